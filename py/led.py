@@ -1,866 +1,6084 @@
 import os
+import sys
 import logging
-import enum
 import ctypes
+import array
+import time
+import re
+import threading
+import json
+import copy
+import math
+import unicodedata
+import textwrap
+import codecs
+import string
+import subprocess
+import shutil
+import socket
+import secrets
+import hmac
 
-from dataclasses import dataclass
-from typing import cast
+from dataclasses import dataclass, field, replace
+from typing import Callable, cast
 
 import sdl2  # pyright: ignore[reportMissingTypeStubs]
 import sdl2.ext  # pyright: ignore[reportMissingTypeStubs]
 import sdl2.sdlttf  # pyright: ignore[reportMissingTypeStubs]
+try:
+    import sdl2.sdlimage as sdlimage  # pyright: ignore[reportMissingTypeStubs]
+except (ImportError, RuntimeError):
+    sdlimage = None  # SDL2_image is optional, image buffers say it is missing
+try:
+    import wgpu  # pyright: ignore[reportMissingImports, reportMissingTypeStubs]
+    import wgpu.backends.wgpu_native  # pyright: ignore[reportMissingImports, reportMissingTypeStubs]
+except (ImportError, RuntimeError, OSError):
+    wgpu = None  # the wgpu renderer is optional, 'sdl' is used without it
+
+from led_core import (ErrorKind, EditorError, SwapStore, LogRing, setup_logging, state_path, cache_path, StyleSpan, ColorTheme, default_color_theme,
+    font_face_paths, UserCommand, EditorConfig, project_config, project_config_name, filetype_detect, language_filetype, language_filetypes,
+    filetype_settings, server_socket_path, load_config, Direction, Mode, Heading, KeyState, decode_text, line_endings, line_ending_name,
+    detect_newline, hex_ascii_col, hex_col, hex_lines, hex_parse, read_lines, Content, is_grapheme_extend, grapheme_next, Frame, Frames, FrameStyle,
+    frame_style, frame_inset, gutter_label, Popup, content_hash, UndoTree, ImageView, dired_name_col, Dired, dired_listing, RemotePath, parse_remote,
+    Sftp, PeerCursor, Buffer, PadView, Pad, ReplEditor, JumpList, Keymap, cheat_sheet, Bookmark, Bookmarks, KeySequence, default_keymap, text_key,
+    key_text, toggle_comment, sort_lines, unique_lines, increment_number, kill_rectangle, Workspaces, layout_tree, load_layout, layout_text,
+    layout_build, tab_labels, tab_at, git_branch, line_diff, defun_bounds, Narrowing, insert_rectangle, auto_pairs, char_class, ModalEditor, Commands,
+    Hooks, Minibuffer, find_project_root, Hunk, diff_hunks, diff_align, DiffView, Conflict, find_conflicts, conflict_side, MergeView, html_export,
+    git_index_lines, grep_project, grep_line_re, RecentFiles, UndoStore, FileWatcher, Snippet, parse_snippet, SnippetLibrary, spell_word_re,
+    SpellChecker, Abbrevs, SnippetSession, path_uri, uri_path, utf16_col, col_from_utf16, lsp_language_ids, severity_names, Diagnostic, ReplProcess,
+    ReplSession, CharId, char_id, text_offset, offset_position, Rga, CollabConnection, CollabPeer, CollabSession, OutlineSymbol, lsp_outline,
+    regex_outline, flatten_outline, LspClient, abbreviate_home, FrameStats, WakeQueue, Notification, Notifications, LayoutSnapshot, client_files,
+    client_send, sound_events)
+
+window_background: tuple[int, int, int, int] = (50, 50, 50, 255)  # around and between the frames' text
+minimap_pitch: int = 2  # drawing units per line of a minimap
+
+class RenderBackend:
+    """Drawing primitives used by FrameRenderer and GlyphAtlas, in drawing units (see set_logical_size());
+    textures are opaque handles that only the backend which created them understands"""
+    def output_size(self) -> tuple[int, int]:
+        """Size of the window in output pixels"""
+        raise NotImplementedError
+
+    def set_logical_size(self, w: int, h: int):
+        """Drawing units covering the window, scaled to its output pixels"""
+        raise NotImplementedError
+
+    def clear(self, color: tuple[int, int, int, int]):
+        raise NotImplementedError
+
+    def fill(self, rect: sdl2.SDL_Rect, color: tuple[int, int, int, int]):
+        raise NotImplementedError
+
+    def draw_rect(self, rect: sdl2.SDL_Rect, color: tuple[int, int, int, int]):
+        """One drawing unit wide outline"""
+        raise NotImplementedError
+
+    def create_texture(self, w: int, h: int, target: bool) -> object | None:
+        """Empty ARGB8888 texture, a render target for set_target() if target is True; None if that fails"""
+        raise NotImplementedError
+
+    def update_texture(self, texture: object, rect: sdl2.SDL_Rect, pixels: ctypes.c_void_p, pitch: int):
+        """Copy ARGB8888 pixels into the rectangle of the texture"""
+        raise NotImplementedError
+
+    def texture_from_surface(self, surface: ctypes.c_void_p) -> object:
+        """Texture with the surface's pixels, the caller still frees the surface"""
+        raise NotImplementedError
+
+    def load_image(self, path: str) -> tuple[object, int, int] | None:
+        """Texture, width and height of an image file decoded by SDL2_image, None if it cannot be"""
+        raise NotImplementedError
+
+    def destroy_texture(self, texture: object):
+        raise NotImplementedError
+
+    def copy(self, texture: object, src: sdl2.SDL_Rect | None, dst: sdl2.SDL_Rect | None):
+        """Draw the src part (None: all) of the texture scaled to dst (None: the whole target)"""
+        raise NotImplementedError
+
+    def set_target(self, texture: object | None, scale: tuple[float, float] = (1.0, 1.0)):
+        """Draw into a render target texture with scale output pixels per drawing unit, None for the window again"""
+        raise NotImplementedError
+
+    def set_clip(self, rect: sdl2.SDL_Rect | None):
+        raise NotImplementedError
+
+    def present(self):
+        raise NotImplementedError
+
+    def destroy(self):
+        """Free the renderer and its textures, before the window it draws into is closed"""
+        raise NotImplementedError
+
+class SdlBackend(RenderBackend):
+    """SDL2's 2D renderer, itself running on OpenGL, Direct3D, Metal or the CPU depending on the platform"""
+    def __init__(self, renderer: sdl2.ext.Renderer):
+        self.renderer: sdl2.ext.Renderer = renderer
+        _ = sdl2.SDL_SetRenderDrawBlendMode(renderer.sdlrenderer, sdl2.SDL_BLENDMODE_BLEND)  # fills with alpha, like inactive_dim, are laid over
+
+    def output_size(self) -> tuple[int, int]:
+        rw, rh = ctypes.c_int(0), ctypes.c_int(0)
+        sdl2.SDL_GetRendererOutputSize(self.renderer.sdlrenderer, rw, rh)  # pyright: ignore[reportUnknownMemberType]
+        return rw.value, rh.value
+
+    def set_logical_size(self, w: int, h: int):
+        self.renderer.logical_size = (w, h)
+
+    def clear(self, color: tuple[int, int, int, int]):
+        self.renderer.clear(color)  # pyright: ignore[reportUnknownMemberType]
+
+    def fill(self, rect: sdl2.SDL_Rect, color: tuple[int, int, int, int]):
+        self.renderer.fill(rect, color=color)  # pyright: ignore[reportUnknownMemberType]
+
+    def draw_rect(self, rect: sdl2.SDL_Rect, color: tuple[int, int, int, int]):
+        self.renderer.draw_rect(rect, color=color)  # pyright: ignore[reportUnknownMemberType]
+
+    def create_texture(self, w: int, h: int, target: bool) -> object | None:
+        access = sdl2.SDL_TEXTUREACCESS_TARGET if target is True else sdl2.SDL_TEXTUREACCESS_STATIC
+        texture = sdl2.SDL_CreateTexture(self.renderer.sdlrenderer, sdl2.SDL_PIXELFORMAT_ARGB8888, access, w, h)  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        return texture if texture else None  # pyright: ignore[reportUnknownVariableType]
+
+    def update_texture(self, texture: object, rect: sdl2.SDL_Rect, pixels: ctypes.c_void_p, pitch: int):
+        sdl2.SDL_UpdateTexture(texture, rect, pixels, pitch)  # pyright: ignore[reportUnknownMemberType]
+
+    def texture_from_surface(self, surface: ctypes.c_void_p) -> object:
+        return sdl2.SDL_CreateTextureFromSurface(self.renderer.sdlrenderer, surface)  # pyright: ignore[reportUnknownVariableType, reportUnknownMemberType]
+
+    def load_image(self, path: str) -> tuple[object, int, int] | None:
+        if sdlimage is None:
+            return None
+        texture = sdlimage.IMG_LoadTexture(self.renderer.sdlrenderer, path.encode('utf-8'))  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        if not texture:
+            return None
+        w, h = ctypes.c_int(0), ctypes.c_int(0)
+        sdl2.SDL_QueryTexture(texture, None, None, w, h)  # pyright: ignore[reportUnknownMemberType]
+        return texture, w.value, h.value  # pyright: ignore[reportUnknownVariableType]
+
+    def destroy_texture(self, texture: object):
+        sdl2.SDL_DestroyTexture(texture)  # pyright: ignore[reportUnknownMemberType]
+
+    def copy(self, texture: object, src: sdl2.SDL_Rect | None, dst: sdl2.SDL_Rect | None):
+        sdl2.SDL_RenderCopy(self.renderer.sdlrenderer, texture, src, dst)  # pyright: ignore[reportUnknownMemberType]
+
+    def set_target(self, texture: object | None, scale: tuple[float, float] = (1.0, 1.0)):
+        # SDL puts the window's logical size back when the target is reset, textures get no logical size
+        sdl2.SDL_SetRenderTarget(self.renderer.sdlrenderer, texture)  # pyright: ignore[reportUnknownMemberType]
+        if texture is not None:
+            sdl2.SDL_RenderSetScale(self.renderer.sdlrenderer, scale[0], scale[1])  # pyright: ignore[reportUnknownMemberType]
+
+    def set_clip(self, rect: sdl2.SDL_Rect | None):
+        sdl2.SDL_RenderSetClipRect(self.renderer.sdlrenderer, rect)  # pyright: ignore[reportUnknownMemberType]
+
+    def present(self):
+        self.renderer.present()
+
+    def destroy(self):
+        self.renderer.destroy()
+
+class OffscreenBackend(SdlBackend):
+    """SDL's software renderer drawing into a surface in memory instead of a window, for tests and --screenshot"""
+    def __init__(self, w: int, h: int):
+        self.surface: ctypes.c_void_p = sdl2.SDL_CreateRGBSurfaceWithFormat(0, w, h, 32, sdl2.SDL_PIXELFORMAT_ARGB8888)  # pyright: ignore[reportUnknownMemberType]
+        if not self.surface:
+            raise RuntimeError(f"Cannot create a {w}x{h} surface: {sdl2.SDL_GetError()}")  # pyright: ignore[reportUnknownMemberType]
+        super().__init__(sdl2.ext.Renderer(self.surface.contents))  # pyright: ignore[reportAttributeAccessIssue]
+
+    def save(self, path: str) -> bool:
+        """Write what was drawn to a PNG, or a BMP if the name says so or SDL2_image is missing"""
+        if path.lower().endswith('.bmp') or sdlimage is None:
+            return sdl2.SDL_SaveBMP(self.surface, path.encode('utf-8')) == 0  # pyright: ignore[reportUnknownMemberType]
+        return sdlimage.IMG_SavePNG(self.surface, path.encode('utf-8')) == 0  # pyright: ignore[reportUnknownMemberType]
+
+    def destroy(self):
+        super().destroy()
+        sdl2.SDL_FreeSurface(self.surface)  # pyright: ignore[reportUnknownMemberType]
 
 @dataclass
-class ColorTheme:
-    background: tuple[int, int, int, int]
-    foreground: tuple[int, int, int, int]
-    border: tuple[int, int, int, int]
-    active_border: tuple[int, int, int, int]
-    cursor: tuple[int, int, int, int]
-
-default_color_theme = ColorTheme((0, 50, 0, 255), (255, 255, 255, 255), (0,0,255,255), (255,0,0,255), (255,255,0,255))
-
-Direction = enum.Enum('Direction', 'NONE HORIZONTAL VERTICAL')
-ContentType = enum.Enum('ContentType', 'NONE CELLARRAY TEXT SCHMEME PYTHON')
-
-class ContentCellArray:  # pyright: ignore[reportRedeclaration]
-    pass
-
-class ContentCellArray:
-    def __init__(self, contents: list[str | ContentCellArray], direction: Direction, content_type: ContentType):
-        self.c_lu: int = 0
-        self.c_rd: int = 0
-        self.direction: Direction = direction
-        self.c_type: ContentType = content_type
-        self.content: list[str | ContentCellArray] = contents
-        self.wx: int = 0
-        self.hy: int = 0
-
-class Content:
-    def __init__(self):
-        self.log: logging.Logger = logging.getLogger("Content")
-        self.cell_arrays: dict[str, ContentCellArray] = {}
-
-    def _tokenize_text(self, text:str) -> ContentCellArray:
-        arrays: ContentCellArray = ContentCellArray([], Direction.VERTICAL, ContentType.CELLARRAY)
-        lines = text.splitlines()
-        text_separators = [" ", ",", ".", ";", ":", "(", ")", "{", "}", "[", "]", "<", ">", "|", "/", "\\", "\t"]
-        for line in lines:
-            tok = ""
-            current_array: ContentCellArray = ContentCellArray([], Direction.HORIZONTAL, ContentType.TEXT)
-            for char in line:
-                if char in text_separators:
-                    if tok:
-                        current_array.content.append(tok)
-                        tok = ""
-                else:
-                    tok += char
-            if tok:
-                current_array.content.append(tok)
-            arrays.content.append(current_array)
-        return arrays
-
-    def get_file_type(self, filename: str) -> ContentType:
-        known_extensions = ['.txt', '.scm', '.py']
-        if not any(filename.endswith(ext) for ext in known_extensions):
-            self.log.error(f"Unknown file type: {filename}")
-            return ContentType.NONE
-        else:
-            if filename.endswith('.txt'):
-                return ContentType.TEXT
-            elif filename.endswith('.scm'):
-                return ContentType.SCHMEME
-            elif filename.endswith('.py'):
-                return ContentType.PYTHON
+class OffscreenWindow:
+    """Stands in for the sdl2.ext.Window when there is none, Led only asks for its size"""
+    size: tuple[int, int]
+    window: None = None
+
+@dataclass
+class GpuTexture:
+    texture: object  # wgpu.GPUTexture
+    view: object
+    bind_group: object  # the view and sampler bound for the quad shader
+    w: int
+    h: int
+
+quad_shader = """
+struct VertexOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+@group(0) @binding(0) var tex: texture_2d<f32>;
+@group(0) @binding(1) var samp: sampler;
+
+@vertex
+fn vs_main(@location(0) pos: vec2<f32>, @location(1) uv: vec2<f32>, @location(2) color: vec4<f32>) -> VertexOut {
+    var out: VertexOut;
+    out.pos = vec4<f32>(pos, 0.0, 1.0);
+    out.uv = uv;
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_main(v: VertexOut) -> @location(0) vec4<f32> {
+    return textureSample(tex, samp, v.uv) * v.color;
+}
+"""
+
+class WgpuBackend(RenderBackend):
+    """Draws through wgpu on Vulkan, Metal or Direct3D 12: fills and texture copies become textured quads,
+    batched by texture and clip rectangle, and each target gets one render pass when it is switched or presented"""
+    def __init__(self, window: sdl2.ext.Window):
+        if wgpu is None:
+            raise RuntimeError("wgpu is not installed")
+        self.log: logging.Logger = logging.getLogger("WgpuBackend")
+        self.window: sdl2.ext.Window = window
+        self.adapter: object = wgpu.gpu.request_adapter_sync(power_preference="high-performance")  # pyright: ignore[reportUnknownMemberType]
+        self.device: object = self.adapter.request_device_sync()  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+        self.context: object = wgpu.backends.wgpu_native.GPUCanvasContext(self, self.get_present_methods())  # pyright: ignore[reportUnknownMemberType]
+        # colors are blended like SDL does, in sRGB values, so the surface must not convert them once more
+        self.screen_format: str = cast(str, self.context.get_preferred_format(self.adapter)).removesuffix("-srgb")  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+        self.texture_format: str = "bgra8unorm"  # SDL's ARGB8888 in little endian memory
+        self.configured: tuple[int, int] = (0, 0)  # output size the surface was configured for
+        shader = self.device.create_shader_module(code=quad_shader)  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType, reportUnknownVariableType]
+        self.layout: object = self.device.create_bind_group_layout(entries=[  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+            {"binding": 0, "visibility": wgpu.ShaderStage.FRAGMENT, "texture": {}},  # pyright: ignore[reportUnknownMemberType]
+            {"binding": 1, "visibility": wgpu.ShaderStage.FRAGMENT, "sampler": {}}])  # pyright: ignore[reportUnknownMemberType]
+        self.sampler: object = self.device.create_sampler(mag_filter="nearest", min_filter="linear")  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+        self.pipelines: dict[str, object] = {format: self.create_pipeline(shader, format) for format in (self.screen_format, self.texture_format)}  # pyright: ignore[reportUnknownArgumentType]
+        self.white: GpuTexture = self.gpu_texture(1, 1, False)  # fills are quads of this tinted by their color
+        self.write(self.white, 0, 0, 1, 1, bytes([255, 255, 255, 255]), 4)
+        self.logical: tuple[int, int] = (1, 1)
+        self.target: GpuTexture | None = None
+        self.scale: tuple[float, float] = (1.0, 1.0)  # target pixels per drawing unit
+        self.clip: tuple[int, int, int, int] | None = None  # in target pixels
+        self.clear_color: tuple[int, int, int, int] | None = None  # the pending pass starts by clearing to this
+        self.batches: list[tuple[GpuTexture, tuple[int, int, int, int] | None, list[float]]] = []
+        self.frame: object | None = None  # surface texture of the frame being drawn
+
+    def get_present_methods(self) -> dict[str, dict[str, object]]:
+        """Native window handles in the form wgpu's canvas context asks for"""
+        info = sdl2.SDL_SysWMinfo()
+        sdl2.SDL_VERSION(info.version)  # pyright: ignore[reportUnknownMemberType]
+        if sdl2.SDL_GetWindowWMInfo(self.window.window, ctypes.byref(info)) == 0:  # pyright: ignore[reportUnknownMemberType]
+            raise RuntimeError(f"No native window handle: {sdl2.SDL_GetError()}")  # pyright: ignore[reportUnknownMemberType]
+        if info.subsystem == sdl2.SDL_SYSWM_X11:
+            screen = {"platform": "x11", "window": int(info.info.x11.window), "display": ctypes.cast(info.info.x11.display, ctypes.c_void_p).value}
+        elif info.subsystem == sdl2.SDL_SYSWM_WAYLAND:
+            screen = {"platform": "wayland", "window": ctypes.cast(info.info.wl.surface, ctypes.c_void_p).value, "display": ctypes.cast(info.info.wl.display, ctypes.c_void_p).value}
+        elif info.subsystem == sdl2.SDL_SYSWM_WINDOWS:
+            screen = {"platform": "windows", "window": ctypes.cast(info.info.win.window, ctypes.c_void_p).value}
+        elif info.subsystem == sdl2.SDL_SYSWM_COCOA:
+            screen = {"platform": "cocoa", "window": ctypes.cast(info.info.cocoa.window, ctypes.c_void_p).value}
+        else:
+            raise RuntimeError(f"Unsupported window system {info.subsystem}")
+        return {"screen": screen}  # pyright: ignore[reportUnknownVariableType]
+
+    def get_physical_size(self) -> tuple[int, int]:
+        return self.output_size()
+
+    def create_pipeline(self, shader: object, format: str) -> object:
+        blend = {"src_factor": "src-alpha", "dst_factor": "one-minus-src-alpha", "operation": "add"}
+        return self.device.create_render_pipeline(  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType, reportUnknownVariableType]
+            layout=self.device.create_pipeline_layout(bind_group_layouts=[self.layout]),  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+            vertex={"module": shader, "entry_point": "vs_main", "buffers": [{"array_stride": 32, "attributes": [
+                {"format": "float32x2", "offset": 0, "shader_location": 0},
+                {"format": "float32x2", "offset": 8, "shader_location": 1},
+                {"format": "float32x4", "offset": 16, "shader_location": 2}]}]},
+            primitive={"topology": "triangle-list"},
+            fragment={"module": shader, "entry_point": "fs_main", "targets": [{"format": format, "blend": {"color": blend, "alpha": blend}}]})
+
+    def gpu_texture(self, w: int, h: int, target: bool) -> GpuTexture:
+        usage = wgpu.TextureUsage.TEXTURE_BINDING | wgpu.TextureUsage.COPY_DST  # pyright: ignore[reportOptionalMemberAccess, reportUnknownMemberType, reportUnknownVariableType]
+        if target is True:
+            usage |= wgpu.TextureUsage.RENDER_ATTACHMENT  # pyright: ignore[reportOptionalMemberAccess, reportUnknownMemberType, reportUnknownVariableType]
+        texture = self.device.create_texture(size=(w, h, 1), format=self.texture_format, usage=usage)  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType, reportUnknownVariableType]
+        view = texture.create_view()  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        bind_group = self.device.create_bind_group(layout=self.layout, entries=[  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+            {"binding": 0, "resource": view}, {"binding": 1, "resource": self.sampler}])
+        return GpuTexture(texture, view, bind_group, w, h)  # pyright: ignore[reportUnknownArgumentType]
+
+    def write(self, texture: GpuTexture, x: int, y: int, w: int, h: int, data: bytes, pitch: int):
+        self.device.queue.write_texture({"texture": texture.texture, "origin": (x, y, 0)}, data, {"bytes_per_row": pitch, "rows_per_image": h}, (w, h, 1))  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+
+    def output_size(self) -> tuple[int, int]:
+        w, h = ctypes.c_int(0), ctypes.c_int(0)
+        if hasattr(sdl2, 'SDL_GetWindowSizeInPixels') is True:
+            sdl2.SDL_GetWindowSizeInPixels(self.window.window, w, h)  # pyright: ignore[reportUnknownMemberType]
+        else:
+            sdl2.SDL_GetWindowSize(self.window.window, w, h)  # pyright: ignore[reportUnknownMemberType]
+        return w.value, h.value
+
+    def set_logical_size(self, w: int, h: int):
+        self.logical = (max(w, 1), max(h, 1))
+        if self.target is None:
+            self.scale = self.screen_scale()
+
+    def screen_scale(self) -> tuple[float, float]:
+        ow, oh = self.output_size()
+        return ow / self.logical[0], oh / self.logical[1]
+
+    def target_size(self) -> tuple[int, int]:
+        return (self.target.w, self.target.h) if self.target is not None else self.output_size()
+
+    def quad(self, texture: GpuTexture, x: float, y: float, w: float, h: float, uv: tuple[float, float, float, float], color: tuple[float, float, float, float]):
+        """Two triangles from drawing units to normalized device coordinates, added to the last batch if it can take them"""
+        tw, th = self.target_size()
+        x0, y0 = x * self.scale[0] / tw * 2 - 1, 1 - y * self.scale[1] / th * 2
+        x1, y1 = (x + w) * self.scale[0] / tw * 2 - 1, 1 - (y + h) * self.scale[1] / th * 2
+        u0, v0, u1, v1 = uv
+        vertices = [x0, y0, u0, v0, *color, x1, y0, u1, v0, *color, x0, y1, u0, v1, *color,
+                    x1, y0, u1, v0, *color, x1, y1, u1, v1, *color, x0, y1, u0, v1, *color]
+        if len(self.batches) > 0 and self.batches[-1][0] is texture and self.batches[-1][1] == self.clip:
+            self.batches[-1][2].extend(vertices)
+        else:
+            self.batches.append((texture, self.clip, vertices))
+
+    def clear(self, color: tuple[int, int, int, int]):
+        self.batches = []
+        self.clear_color = color
+
+    def fill(self, rect: sdl2.SDL_Rect, color: tuple[int, int, int, int]):
+        self.quad(self.white, rect.x, rect.y, rect.w, rect.h, (0.0, 0.0, 1.0, 1.0), (color[0] / 255, color[1] / 255, color[2] / 255, color[3] / 255))
+
+    def draw_rect(self, rect: sdl2.SDL_Rect, color: tuple[int, int, int, int]):
+        for x, y, w, h in ((rect.x, rect.y, rect.w, 1), (rect.x, rect.y + rect.h - 1, rect.w, 1), (rect.x, rect.y, 1, rect.h), (rect.x + rect.w - 1, rect.y, 1, rect.h)):
+            self.fill(sdl2.SDL_Rect(x, y, w, h), color)
+
+    def create_texture(self, w: int, h: int, target: bool) -> object | None:
+        try:
+            return self.gpu_texture(w, h, target)
+        except Exception as e:
+            self.log.error(f"Cannot create {w}x{h} texture: {e}")
+            return None
+
+    def update_texture(self, texture: object, rect: sdl2.SDL_Rect, pixels: ctypes.c_void_p, pitch: int):
+        self.write(cast(GpuTexture, texture), rect.x, rect.y, rect.w, rect.h, ctypes.string_at(pixels, pitch * rect.h), pitch)
+
+    def texture_from_surface(self, surface: ctypes.c_void_p) -> object:
+        converted = sdl2.SDL_ConvertSurfaceFormat(surface, sdl2.SDL_PIXELFORMAT_ARGB8888, 0)  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        w, h, pitch = cast(int, converted.contents.w), cast(int, converted.contents.h), cast(int, converted.contents.pitch)  # pyright: ignore[reportUnknownMemberType]
+        texture = self.gpu_texture(max(w, 1), max(h, 1), False)
+        if w > 0 and h > 0:
+            self.write(texture, 0, 0, w, h, ctypes.string_at(converted.contents.pixels, pitch * h), pitch)  # pyright: ignore[reportUnknownMemberType, reportUnknownArgumentType]
+        sdl2.SDL_FreeSurface(converted)  # pyright: ignore[reportUnknownMemberType]
+        return texture
+
+    def load_image(self, path: str) -> tuple[object, int, int] | None:
+        if sdlimage is None:
+            return None
+        surface = sdlimage.IMG_Load(path.encode('utf-8'))  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        if not surface:
+            return None
+        texture = cast(GpuTexture, self.texture_from_surface(surface))  # pyright: ignore[reportUnknownArgumentType]
+        sdl2.SDL_FreeSurface(surface)  # pyright: ignore[reportUnknownMemberType]
+        return texture, texture.w, texture.h
+
+    def destroy_texture(self, texture: object):
+        cast(GpuTexture, texture).texture.destroy()  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+
+    def copy(self, texture: object, src: sdl2.SDL_Rect | None, dst: sdl2.SDL_Rect | None):
+        texture = cast(GpuTexture, texture)
+        uv = (0.0, 0.0, 1.0, 1.0) if src is None else (src.x / texture.w, src.y / texture.h, (src.x + src.w) / texture.w, (src.y + src.h) / texture.h)
+        if dst is None:
+            tw, th = self.target_size()
+            self.quad(texture, 0, 0, tw / self.scale[0], th / self.scale[1], uv, (1.0, 1.0, 1.0, 1.0))
+        else:
+            self.quad(texture, dst.x, dst.y, dst.w, dst.h, uv, (1.0, 1.0, 1.0, 1.0))
+
+    def set_target(self, texture: object | None, scale: tuple[float, float] = (1.0, 1.0)):
+        self.flush()
+        self.target = cast(GpuTexture | None, texture)
+        self.scale = scale if texture is not None else self.screen_scale()
+        self.clip = None
+
+    def set_clip(self, rect: sdl2.SDL_Rect | None):
+        if rect is None:
+            self.clip = None
+            return
+        tw, th = self.target_size()
+        x0, y0 = min(max(round(rect.x * self.scale[0]), 0), tw), min(max(round(rect.y * self.scale[1]), 0), th)
+        x1, y1 = min(max(round((rect.x + rect.w) * self.scale[0]), x0), tw), min(max(round((rect.y + rect.h) * self.scale[1]), y0), th)
+        self.clip = (x0, y0, x1 - x0, y1 - y0)
+
+    def frame_view(self) -> object:
+        """View of the window's surface texture for this frame, configuring the surface after resizes"""
+        if self.frame is None:
+            size = self.output_size()
+            if size != self.configured:
+                self.context.configure(device=self.device, format=self.screen_format, alpha_mode="opaque")  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+                self.configured = size
+            self.frame = self.context.get_current_texture()  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+        return self.frame.create_view()  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType, reportOptionalMemberAccess]
+
+    def flush(self):
+        """One render pass drawing the batches into the current target"""
+        if len(self.batches) == 0 and self.clear_color is None:
+            return
+        view = self.target.view if self.target is not None else self.frame_view()
+        format = self.texture_format if self.target is not None else self.screen_format
+        attachment: dict[str, object] = {"view": view, "load_op": "load", "store_op": "store"}
+        if self.clear_color is not None:
+            attachment["load_op"] = "clear"
+            attachment["clear_value"] = tuple(c / 255 for c in self.clear_color)
+        encoder = self.device.create_command_encoder()  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType, reportUnknownVariableType]
+        render_pass = encoder.begin_render_pass(color_attachments=[attachment])  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        if len(self.batches) > 0:
+            vertices = array.array('f', [v for _texture, _clip, batch in self.batches for v in batch])
+            buffer = self.device.create_buffer_with_data(data=vertices.tobytes(), usage=wgpu.BufferUsage.VERTEX)  # pyright: ignore[reportAttributeAccessIssue, reportOptionalMemberAccess, reportUnknownMemberType, reportUnknownVariableType]
+            render_pass.set_pipeline(self.pipelines[format])  # pyright: ignore[reportUnknownMemberType]
+            render_pass.set_vertex_buffer(0, buffer)  # pyright: ignore[reportUnknownMemberType]
+            tw, th = self.target_size()
+            first = 0
+            for texture, clip, batch in self.batches:
+                render_pass.set_bind_group(0, texture.bind_group)  # pyright: ignore[reportUnknownMemberType]
+                render_pass.set_scissor_rect(*(clip if clip is not None else (0, 0, tw, th)))  # pyright: ignore[reportUnknownMemberType]
+                count = len(batch) // 8
+                render_pass.draw(count, 1, first, 0)  # pyright: ignore[reportUnknownMemberType]
+                first += count
+        render_pass.end()  # pyright: ignore[reportUnknownMemberType]
+        self.device.queue.submit([encoder.finish()])  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+        self.batches, self.clear_color = [], None
+
+    def present(self):
+        self.flush()
+        if self.frame is not None and hasattr(self.context, 'present') is True:
+            self.context.present()  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+        self.frame = None
+
+    def destroy(self):
+        self.context.unconfigure()  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+        self.device.destroy()  # pyright: ignore[reportAttributeAccessIssue, reportUnknownMemberType]
+
+def create_backend(window: sdl2.ext.Window, name: str) -> RenderBackend:
+    """The configured renderer for the window, SDL's if that is unknown or cannot start"""
+    log = logging.getLogger("Renderer")
+    if name == 'wgpu':
+        try:
+            return WgpuBackend(window)
+        except Exception as e:
+            log.warning(f"Cannot use the wgpu renderer, falling back to sdl: {e}")
+    elif name != 'sdl':
+        log.warning(f"Unknown renderer {name}, using sdl")
+    return SdlBackend(sdl2.ext.Renderer(window, flags=sdl2.SDL_RENDERER_ACCELERATED))
+
+GlyphKey = tuple[int, str, tuple[int, int, int, int], tuple[int, int, int, int]]  # font, grapheme cluster, fg, bg
+
+class GlyphAtlas:
+    """Rendered glyphs packed row by row into large textures, so text is drawn by copying rectangles out of them
+    instead of being rasterized again every frame; when all pages are full it starts over"""
+    def __init__(self, renderer: RenderBackend, size: int = 1024, max_pages: int = 8):
+        self.log: logging.Logger = logging.getLogger("GlyphAtlas")
+        self.renderer: RenderBackend = renderer
+        self.size: int = size
+        self.max_pages: int = max_pages
+        self.pages: list[object] = []
+        self.glyphs: dict[GlyphKey, tuple[int, sdl2.SDL_Rect]] = {}  # page index and rectangle in it
+        self.x: int = 0  # free position in the current row of the last page
+        self.y: int = 0
+        self.row_height: int = 0
+
+    def clear(self):
+        for page in self.pages:
+            self.renderer.destroy_texture(page)
+        self.pages = []
+        self.glyphs = {}
+        self.x, self.y, self.row_height = 0, 0, 0
+
+    def add(self, key: GlyphKey, surface: ctypes.c_void_p) -> tuple[int, sdl2.SDL_Rect] | None:
+        """Copy the glyph surface into the atlas, None if it is larger than a page"""
+        w, h = cast(int, surface.contents.w), cast(int, surface.contents.h)  # pyright: ignore[reportAttributeAccessIssue]
+        if w > self.size or h > self.size:
+            return None
+        if self.x + w > self.size:
+            self.x, self.y, self.row_height = 0, self.y + self.row_height, 0
+        if len(self.pages) == 0 or self.y + h > self.size:
+            if len(self.pages) >= self.max_pages:
+                self.log.info(f"All {self.max_pages} atlas pages full, starting over")
+                self.clear()
+            page = self.renderer.create_texture(self.size, self.size, False)
+            if page is None:
+                return None
+            self.pages.append(page)
+            self.x, self.y, self.row_height = 0, 0, 0
+        converted = sdl2.SDL_ConvertSurfaceFormat(surface, sdl2.SDL_PIXELFORMAT_ARGB8888, 0)  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        rect = sdl2.SDL_Rect(self.x, self.y, w, h)
+        self.renderer.update_texture(self.pages[-1], rect, converted.contents.pixels, converted.contents.pitch)  # pyright: ignore[reportUnknownMemberType, reportUnknownArgumentType]
+        sdl2.SDL_FreeSurface(converted)  # pyright: ignore[reportUnknownMemberType]
+        self.x += w
+        self.row_height = max(self.row_height, h)
+        self.glyphs[key] = (len(self.pages) - 1, rect)
+        return self.glyphs[key]
+
+@dataclass
+class FontSet:
+    """Fonts and cell size of one zoom level"""
+    font: sdl2.sdlttf.TTF_Font
+    fallback_fonts: list[sdl2.sdlttf.TTF_Font]
+    faces: dict[tuple[bool, bool], sdl2.sdlttf.TTF_Font]
+    glyph_fonts: dict[str, int]
+    char_width: int
+    char_height: int
+
+class FrameRenderer:
+    def __init__(self, w:int, h:int, renderer: RenderBackend, font_path:str, theme:ColorTheme=default_color_theme, fallback_paths: list[str] | None = None, ui_scale: float = 1.0,
+                 antialias: str = 'grayscale', subpixel_order: str = 'rgb'):
+        self.log: logging.Logger = logging.getLogger("FrameRenderer")
+        self.theme: ColorTheme = theme
+        self.renderer: RenderBackend = renderer
+        self.atlas: GlyphAtlas = GlyphAtlas(renderer)
+        self.canvas: object | None = None  # render target keeping the tiled frames between presents
+        self.canvas_size: tuple[int, int, int, int] = (0, 0, 0, 0)  # output pixels and drawing units it was made for
+        self.canvas_failed: bool = False  # no render targets, everything is drawn every time
+        self.drawn: dict[int, tuple[tuple[object, ...], list[tuple[object, ...]]]] = {}  # frame id -> frame_key(), line_keys() on the canvas
+        self.copies: int = 0  # RenderCopy calls and canvas lines drawn, for FrameStats
+        self.lines: int = 0
+        self.borders: bool = True  # frame outlines, hidden in zen-mode
+        self.cursor_style: str = 'bar'  # block, bar or underline, set by Led from the config like the two below
+        self.normal_cursor_style: str = 'block'  # ReplEditor.cursor_block is set in modal editing's normal and visual modes
+        self.cursor_on: bool = True  # blink phase
+        self.ruler_overflow: bool = False  # set by Led from the config
+        self.relative_numbers: bool = False  # ReplEditor.relative_numbers, for the rows scrolled in from beyond the canvas
+        self.scroll_ms: int = 0  # smooth scrolling, 0 jumps; set by Led from the config
+        self.scroll_seen: dict[int, tuple[int, int, int]] = {}  # frame id -> pad, id() of its buffer and buf_y last drawn
+        self.scrolls: dict[int, tuple[float, float]] = {}  # frame id -> offset in drawing units and time the animation started with
+        self.layout_ms: int = 0  # transitions of the frame rectangles, 0 jumps; set by Led from the config
+        self.rects_seen: dict[int, tuple[int, int, int, int]] = {}  # frame id -> rectangle of the tiled frames last drawn
+        self.moves: dict[int, tuple[tuple[int, int, int, int], tuple[int, int, int, int], float]] = {}  # frame id -> from, to and start time
+        self.minimaps: dict[int, tuple[tuple[object, ...], list[str], tuple[int, int]]] = {}  # frame id -> key, lines and view rows of the minimap drawn
+        self.style: FrameStyle = FrameStyle()  # set by Led from the config, like Frames.style
+        rw, rh = self.renderer.output_size()
+        self.ui_scale: float = ui_scale  # drawing coordinates are window coordinates divided by this, see logical_size()
+        self.scale: float = (rw / w if rw > 0 else 1.0) * ui_scale  # output pixels per drawing unit
+        if rw != w:
+            widthScale = rw / w
+            heightScale = rh / h
+
+            if widthScale != heightScale:
+                self.log.warning(f"WARNING: width scale {widthScale} != height scale {heightScale}")
+            else:
+                self.log.info(f"Scale: {widthScale}")
+            # self.renderer.set_target(None, (widthScale, heightScale))
+        if os.path.exists(font_path) is False:
+            self.log.error(f"Font {font_path} does not exist")
+        # sdl2.ext.RenderSetScale(self.renderer,2,2)
+        if antialias not in ('grayscale', 'subpixel') or subpixel_order not in ('rgb', 'bgr'):
+            self.log.warning(f"Unknown antialias {antialias} or subpixel_order {subpixel_order}, using grayscale")
+            antialias = 'grayscale'
+        self.subpixel: bool = antialias == 'subpixel'
+        self.bgr: bool = subpixel_order == 'bgr'
+        # glyphs are rasterized at least at output resolution, subpixel ones at exactly that so their color fringes line up with the panel's
+        self.font_mag:int = max(1, math.ceil(self.scale)) if self.subpixel is True else max(2, math.ceil(self.scale))
+        self.dpi:int = 144
+        font_size = 8 * self.font_mag
+        self.font: sdl2.sdlttf.TTF_Font = sdl2.sdlttf.TTF_OpenFontDPI(font_path.encode('utf-8'), font_size, self.dpi, self.dpi)  # pyright: ignore[reportUnknownMemberType] # , reportUnannotatedClassAttribute]
+        sdl2.sdlttf.TTF_SetFontHinting(self.font, sdl2.sdlttf.TTF_HINTING_LIGHT_SUBPIXEL)  # pyright: ignore[reportUnknownMemberType]
+        self.fallback_paths: list[str] = [path for path in fallback_paths or [] if os.path.exists(path) is True]
+        self.fallback_fonts: list[sdl2.sdlttf.TTF_Font] = self.open_fallbacks(font_size)
+        self.glyph_fonts: dict[str, int] = {}  # character -> 0 for the main font, i for fallback_fonts[i - 1]
+        self.font_path: str = font_path
+        self.font_size: int = font_size
+        self.zoom: int = 0
+        self.font_sets: dict[int, FontSet] = {}  # other zoom levels, opened when first used
+        self.faces: dict[tuple[bool, bool], sdl2.sdlttf.TTF_Font] = {(False, False): self.font}  # by (bold, italic)
+        self.char_width: int
+        self.char_height: int
+        self.char_width, self.char_height = self.text_size("a")
+        if self.char_width > 0:
+            self.log.info(f"Char-sizes: {self.char_width}, {self.char_height}")
+        else:
+            self.log.error("Cannot determine character dimensions!")
+        self.line_spacing_extra:int = 0
+        self.images: dict[str, object | None] = {}  # textures by path, None if the file cannot be decoded
+        script = "Tibt".encode('utf-8')
+        sdl2.sdlttf.TTF_SetFontScriptName(self.font, script)  # pyright:ignore[reportUnknownMemberType]
+        self.label_font: sdl2.sdlttf.TTF_Font = sdl2.sdlttf.TTF_OpenFontDPI(font_path.encode('utf-8'), font_size * 6, self.dpi, self.dpi)  # pyright: ignore[reportUnknownMemberType]
+
+    def render_text(self, text:str, x:int, y:int, fg: tuple[int, int, int, int] | None = None, bg: tuple[int, int, int, int] | None = None, font: sdl2.sdlttf.TTF_Font | None = None) -> sdl2.SDL_Rect | None:
+        if text == "":
+            return
+        if fg is None:
+            fg = self.theme.foreground
+        if bg is None:
+            bg = self.theme.background
+        color_fg = sdl2.SDL_Color(fg[0], fg[1], fg[2], fg[3])
+        color_bg = sdl2.SDL_Color(bg[0], bg[1], bg[2], bg[3])
+
+        # Surface = sdl2.sdlttf.TTF_RenderUTF8_Solid(self.font, text.encode(), color)
+        if font is None:
+            font = self.font
+            runs = self.font_runs(text) if len(self.fallback_fonts) > 0 else []
+            if len(runs) > 1 or (len(runs) == 1 and runs[0][2] != 0):
+                return self.render_runs(runs, x, y, color_fg, color_bg)
+        return self.blit_text(font, text, x, y, color_fg, color_bg)
+
+    def open_fallbacks(self, size: int) -> list[sdl2.sdlttf.TTF_Font]:
+        fonts: list[sdl2.sdlttf.TTF_Font] = []
+        for path in self.fallback_paths:
+            fallback = sdl2.sdlttf.TTF_OpenFontDPI(path.encode('utf-8'), size, self.dpi, self.dpi)  # pyright: ignore[reportUnknownMemberType]
+            if not fallback:
+                self.log.error(f"Cannot open fallback font {path}: {sdl2.sdlttf.TTF_GetError()}")  # pyright: ignore[reportUnknownMemberType]
+                continue
+            sdl2.sdlttf.TTF_SetFontHinting(fallback, sdl2.sdlttf.TTF_HINTING_LIGHT_SUBPIXEL)  # pyright: ignore[reportUnknownMemberType]
+            fonts.append(fallback)  # pyright: ignore[reportUnknownArgumentType]
+        return fonts
+
+    def logical_size(self, w: int, h: int) -> tuple[int, int]:
+        """Drawing area for a window size, the renderer scales it up by ui_scale and the display's pixel density"""
+        return (round(w / self.ui_scale), round(h / self.ui_scale))
+
+    def zoom_size(self, zoom: int) -> int:
+        """Font size of a zoom level, one point per step"""
+        return self.font_size + zoom * self.font_mag
+
+    def zoom_valid(self, zoom: int) -> bool:
+        return self.font_size // 2 <= self.zoom_size(zoom) <= self.font_size * 4
+
+    def set_zoom(self, zoom: int):
+        """Switch fonts and cell size to those of a frame's zoom level"""
+        if zoom == self.zoom:
+            return
+        self.font_sets[self.zoom] = FontSet(self.font, self.fallback_fonts, self.faces, self.glyph_fonts, self.char_width, self.char_height)
+        fonts = self.font_sets.get(zoom)
+        if fonts is None:
+            font = sdl2.sdlttf.TTF_OpenFontDPI(self.font_path.encode('utf-8'), self.zoom_size(zoom), self.dpi, self.dpi)  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+            if not font:
+                self.log.error(f"Cannot open {self.font_path} at size {self.zoom_size(zoom)}: {sdl2.sdlttf.TTF_GetError()}")  # pyright: ignore[reportUnknownMemberType]
+                fonts = self.font_sets[self.zoom]
             else:
-                self.log.error(f"Unknown file type: {filename}")
-                return ContentType.NONE
-
-    def tokenize(self, text:str, content_type:ContentType) -> ContentCellArray:
-        if content_type == ContentType.TEXT:
-            return self._tokenize_text(text)
-        elif content_type == ContentType.SCHMEME:
-            # Placeholder for actual SchMeme tokenization
-            self.log.warning("SchMeme tokenization not implemented, using text tokenizer.")
-            return self._tokenize_text(text)
-        elif content_type == ContentType.PYTHON:
-            # Placeholder for actual Python tokenization
-            self.log.warning("Python tokenization not implemented, using text tokenizer.")
-            return self._tokenize_text(text)
-        else:
-            self.log.error(f"Unknown content type: {content_type}")
-            return ContentCellArray([], Direction.VERTICAL, ContentType.NONE)
-
-    def load_text_file(self, filename: str):
+                sdl2.sdlttf.TTF_SetFontHinting(font, sdl2.sdlttf.TTF_HINTING_LIGHT_SUBPIXEL)  # pyright: ignore[reportUnknownMemberType]
+                w, h = self.text_size("a", font)  # pyright: ignore[reportUnknownArgumentType]
+                fonts = FontSet(font, self.open_fallbacks(self.zoom_size(zoom)), {(False, False): font}, {}, w, h)  # pyright: ignore[reportUnknownArgumentType]
+            self.font_sets[zoom] = fonts
+        self.zoom = zoom
+        self.font, self.fallback_fonts, self.faces, self.glyph_fonts = fonts.font, fonts.fallback_fonts, fonts.faces, fonts.glyph_fonts
+        self.char_width, self.char_height = fonts.char_width, fonts.char_height
+
+    def cell_size(self, zoom: int) -> tuple[int, int]:
+        """Character width and line height at a zoom level"""
+        previous = self.zoom
+        self.set_zoom(zoom)
+        size = (self.char_width, self.line_height())
+        self.set_zoom(previous)
+        return size
+
+    def face_font(self, bold: bool, italic: bool) -> sdl2.sdlttf.TTF_Font:
+        """The bold/italic face next to the main font file, or the main font with the style synthesized by SDL_ttf"""
+        if (bold, italic) in self.faces:
+            return self.faces[(bold, italic)]
+        path = next((p for p in font_face_paths(self.font_path, bold, italic) if os.path.exists(p)), None)
+        font = sdl2.sdlttf.TTF_OpenFontDPI((path or self.font_path).encode('utf-8'), self.zoom_size(self.zoom), self.dpi, self.dpi)  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        if not font:
+            self.log.error(f"Cannot open font face {path}: {sdl2.sdlttf.TTF_GetError()}")  # pyright: ignore[reportUnknownMemberType]
+            font = self.font
+        else:
+            sdl2.sdlttf.TTF_SetFontHinting(font, sdl2.sdlttf.TTF_HINTING_LIGHT_SUBPIXEL)  # pyright: ignore[reportUnknownMemberType]
+            if path is None:
+                style = (sdl2.sdlttf.TTF_STYLE_BOLD if bold else 0) | (sdl2.sdlttf.TTF_STYLE_ITALIC if italic else 0)
+                sdl2.sdlttf.TTF_SetFontStyle(font, style)  # pyright: ignore[reportUnknownMemberType]
+        self.faces[(bold, italic)] = font  # pyright: ignore[reportUnknownArgumentType]
+        return font  # pyright: ignore[reportUnknownVariableType]
+
+    def render_styled(self, text: str, x: int, y: int, col: int, spans: list[StyleSpan], bg: tuple[int, int, int, int] | None = None):
+        """Text starting at buffer column col, the parts covered by spans in their faces; later spans win"""
+        if len(spans) == 0:
+            _ = self.render_text(text, x, y, bg=bg)
+            return
+        names: list[str] = [""] * len(text)
+        for span in spans:
+            for i in range(max(span.start - col, 0), min(span.end - col, len(text))):
+                names[i] = span.face
+        start = 0
+        for i in range(1, len(text) + 1):
+            if i < len(text) and names[i] == names[start]:
+                continue
+            style = self.theme.faces.get(names[start]) if names[start] != "" else None
+            rx = x + start * self.char_width
+            if style is None:
+                _ = self.render_text(text[start:i], rx, y, bg=bg)
+            else:
+                font = self.face_font(style.bold, style.italic)
+                _ = self.render_text(text[start:i], rx, y, fg=style.fg, bg=bg, font=None if font is self.font else font)
+                color = style.fg if style.fg is not None else self.theme.foreground
+                w = (i - start) * self.char_width
+                if style.underline is True:
+                    self.renderer.fill(sdl2.SDL_Rect(rx, y + self.char_height - 2, w, 1), color=color)  # pyright: ignore[reportUnknownMemberType]
+                if style.strikethrough is True:
+                    self.renderer.fill(sdl2.SDL_Rect(rx, y + self.char_height // 2, w, 1), color=color)  # pyright: ignore[reportUnknownMemberType]
+            start = i
+
+    def row_spans(self, pad: 'Pad', row: int) -> list[StyleSpan]:
+        """Spans of the buffer row, followed by those of unnecessary and deprecated code reported by the language server"""
+        spans = pad.buffer.styles.get(row, [])
+        for d in pad.buffer.diagnostics:
+            if len(d.tags) > 0 and d.row <= row <= d.end_row:
+                start = d.col if row == d.row else 0
+                end = d.end_col if row == d.end_row else len(pad.buffer[row])
+                spans = spans + [StyleSpan(start, end, 'deprecated' if 2 in d.tags else 'unnecessary')]
+        return spans
+
+    def font_runs(self, text: str) -> list[tuple[int, str, int]]:
+        """Text split into (column, run, font) where font is the first of the main and the fallback fonts with the glyphs"""
+        runs: list[tuple[int, str, int]] = []
+        start, current = 0, 0
+        for i, c in enumerate(text):
+            index = self.glyph_fonts.get(c)
+            if index is None:
+                fonts = [self.font] + self.fallback_fonts
+                index = next((k for k, f in enumerate(fonts) if sdl2.sdlttf.TTF_GlyphIsProvided32(f, ord(c))), 0)  # pyright: ignore[reportUnknownMemberType]
+                self.glyph_fonts[c] = index
+            if i == 0 or is_grapheme_extend(c):
+                current = index if i == 0 else current  # marks and joiners stay with their base character
+                continue
+            if index != current:
+                runs.append((start, text[start:i], current))
+                start, current = i, index
+        runs.append((start, text[start:], current))
+        return runs
+
+    def render_runs(self, runs: list[tuple[int, str, int]], x: int, y: int, color_fg: sdl2.SDL_Color, color_bg: sdl2.SDL_Color) -> sdl2.SDL_Rect:
+        """Each run in its font at its column, fallback glyphs aligned to the main font's baseline"""
+        ascent = sdl2.sdlttf.TTF_FontAscent(self.font)  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        right, bottom = x, y
+        for col, run, index in runs:
+            font = self.font if index == 0 else self.fallback_fonts[index - 1]
+            dy = (ascent - sdl2.sdlttf.TTF_FontAscent(font)) // self.font_mag if index != 0 else 0  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+            rect = self.blit_text(font, run, x + col * self.char_width, y + dy, color_fg, color_bg)  # pyright: ignore[reportUnknownArgumentType]
+            right, bottom = max(right, rect.x + rect.w), max(bottom, y + rect.h)
+        return sdl2.SDL_Rect(x, y, right - x, bottom - y)
+
+    def text_surface(self, font: sdl2.sdlttf.TTF_Font, text: str, color_fg: sdl2.SDL_Color, color_bg: sdl2.SDL_Color) -> ctypes.c_void_p:
+        """Text rasterized with the configured antialiasing, the caller frees the surface"""
+        if self.subpixel is False:
+            return sdl2.sdlttf.TTF_RenderUTF8_Shaded(font, text.encode(), color_fg, color_bg)  # pyright:ignore[reportUnknownMemberType, reportUnknownVariableType]
+        if self.bgr is False:
+            return sdl2.sdlttf.TTF_RenderUTF8_LCD(font, text.encode(), color_fg, color_bg)  # pyright:ignore[reportUnknownMemberType, reportUnknownVariableType]
+        # SDL_ttf filters for RGB panels: render with red and blue swapped, then read the ARGB pixels as ABGR,
+        # which swaps them back and moves each red coverage to the blue channel and vice versa
+        fg = sdl2.SDL_Color(color_fg.b, color_fg.g, color_fg.r, color_fg.a)
+        bg = sdl2.SDL_Color(color_bg.b, color_bg.g, color_bg.r, color_bg.a)
+        surface = sdl2.sdlttf.TTF_RenderUTF8_LCD(font, text.encode(), fg, bg)  # pyright:ignore[reportUnknownMemberType, reportUnknownVariableType]
+        swapped = sdl2.SDL_CreateRGBSurfaceWithFormatFrom(surface.contents.pixels, surface.contents.w, surface.contents.h, 32, surface.contents.pitch, sdl2.SDL_PIXELFORMAT_ABGR8888)  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        converted = sdl2.SDL_ConvertSurfaceFormat(swapped, sdl2.SDL_PIXELFORMAT_ARGB8888, 0)  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+        sdl2.SDL_FreeSurface(swapped)  # pyright: ignore[reportUnknownMemberType]
+        sdl2.SDL_FreeSurface(surface)  # pyright: ignore[reportUnknownMemberType]
+        return converted  # pyright: ignore[reportUnknownVariableType]
+
+    def blit_surface(self, surface: ctypes.c_void_p, x: int, y: int) -> sdl2.SDL_Rect:
+        self.copies += 1
+        texture = self.renderer.texture_from_surface(surface)
+        rect = sdl2.SDL_Rect(x, y, surface.contents.w // self.font_mag, surface.contents.h // self.font_mag)  # pyright: ignore[reportAttributeAccessIssue, reportUnknownArgumentType]
+        sdl2.SDL_FreeSurface(surface)  # pyright: ignore[reportUnknownMemberType]
+        self.renderer.copy(texture, None, rect)
+        self.renderer.destroy_texture(texture)
+        return rect
+
+    def blit_text(self, font: sdl2.sdlttf.TTF_Font, text: str, x: int, y: int, color_fg: sdl2.SDL_Color, color_bg: sdl2.SDL_Color) -> sdl2.SDL_Rect:
+        """Each grapheme cluster copied from the glyph atlas to its column; the huge frame label font bypasses the atlas"""
+        if font is self.label_font:
+            return self.blit_surface(self.text_surface(font, text, color_fg, color_bg), x, y)
+        fg = (color_fg.r, color_fg.g, color_fg.b, color_fg.a)
+        bg = (color_bg.r, color_bg.g, color_bg.b, color_bg.a)
+        self.renderer.fill(sdl2.SDL_Rect(x, y, len(text) * self.char_width, self.char_height), color=bg)  # pyright: ignore[reportUnknownMemberType]
+        right, bottom = x + len(text) * self.char_width, y + self.char_height
+        i = 0
+        while i < len(text):
+            end = grapheme_next(text, i)
+            cluster = text[i:end]
+            if cluster != " ":
+                key = (id(font), cluster, fg, bg)
+                entry = self.atlas.glyphs.get(key)
+                if entry is None:
+                    surface = self.text_surface(font, cluster, color_fg, color_bg)
+                    entry = self.atlas.add(key, surface)
+                    if entry is None:
+                        rect = self.blit_surface(surface, x + i * self.char_width, y)
+                    else:
+                        sdl2.SDL_FreeSurface(surface)  # pyright: ignore[reportUnknownMemberType]
+                if entry is not None:
+                    page, src = entry
+                    rect = sdl2.SDL_Rect(x + i * self.char_width, y, src.w // self.font_mag, src.h // self.font_mag)
+                    self.renderer.copy(self.atlas.pages[page], src, rect)
+                    self.copies += 1
+                right, bottom = max(right, rect.x + rect.w), max(bottom, rect.y + rect.h)  # pyright: ignore[reportPossiblyUnbound]
+            i = end
+        return sdl2.SDL_Rect(x, y, right - x, bottom - y)
+
+    def line_height(self) -> int:
+        return self.char_height + self.line_spacing_extra
+
+    def render_pad(self, frame: Frame, pad: 'Pad', active: bool, block_cursor: bool, show_whitespace: bool = False, spell: 'SpellChecker | None' = None, rows: set[int] | None = None,
+                   offset: int = 0):
+        """The pad's lines and decorations; with rows only those canvas lines, decorations clipped to them"""
+        if offset != 0:
+            self.render_scrolling(frame, pad, active, block_cursor, offset)
+            return
+        x0 = frame.x + self.style.inset()
+        y0 = frame.y + self.style.inset()
+        edge = self.style.inset() - self.style.padding  # inside the thickest outline
+        lh = self.line_height()
+        sel = pad.selection()
+        if rows is not None:
+            top, bottom = min(rows), max(rows) + 1
+            self.renderer.set_clip(sdl2.SDL_Rect(frame.x + edge, y0 + top * lh, frame.wx - 2 * edge - self.style.minimap, (bottom - top) * lh))
+        for i, line in enumerate(pad.canvas):
+            y = y0 + i * lh
+            if rows is not None:
+                if i not in rows:
+                    continue
+                self.renderer.fill(sdl2.SDL_Rect(frame.x + edge, y, frame.wx - 2 * edge - self.style.minimap, lh), color=window_background)  # pyright: ignore[reportUnknownMemberType]
+            self.lines += 1
+            if i >= pad.height:
+                self.render_styled(line, x0, y, 0, pad.status_spans, bg=self.theme.status)
+                continue
+            self.render_line(pad, line, pad.buf_y + i, x0, y, sel)
+        self.render_ruler(x0 + pad.left_border * self.char_width, y0, pad)
+        if show_whitespace is True:
+            self.render_whitespace(x0 + pad.left_border * self.char_width, y0, pad)
+        if len(pad.buffer.hunks) > 0 and pad.left_border > 0:
+            self.render_hunks(x0 + pad.left_border * self.char_width - 4, y0, pad)
+        if len(pad.buffer.diagnostics) > 0:
+            self.render_diagnostics(x0, y0, pad)
+        if len(pad.buffer.peer_cursors) > 0:
+            self.render_peer_cursors(x0 + pad.left_border * self.char_width, y0, pad)
+        if spell is not None and pad.buffer.hex is False:
+            self.render_misspelled(x0 + pad.left_border * self.char_width, y0, pad, spell)
+        self.render_scroll_indicators(x0 + pad.left_border * self.char_width, y0, pad)
+        if active is True and self.cursor_on is True:
+            self.render_cursor(x0 + (pad.left_border + pad.cur_x) * self.char_width, y0 + pad.cur_y * lh, pad, block_cursor)
+        if rows is not None:
+            self.renderer.set_clip(None)
+
+    def render_line(self, pad: 'Pad', line: str, row: int, x0: int, y: int, sel: tuple[tuple[int, int], tuple[int, int]] | None):
+        """A canvas line showing the buffer's row: gutter, highlight, styled text and selection"""
+        _ = self.render_text(line[:pad.left_border], x0, y, bg=self.theme.status)
+        text = line[pad.left_border:]
+        x = x0 + pad.left_border * self.char_width
+        highlight = pad.buffer.highlights.get(row)
+        if highlight is not None:
+            self.renderer.fill(sdl2.SDL_Rect(x, y, pad.width * self.char_width, self.line_height()), color=highlight)  # pyright: ignore[reportUnknownMemberType]
+        spans = self.row_spans(pad, row) if 0 <= row < len(pad.buffer) else []
+        if sel is None or row < sel[0][0] or row > sel[1][0]:
+            self.render_ruled(pad, row, text, x, y, pad.buf_x, spans, bg=highlight)
+            return
+        s_col = sel[0][1] if row == sel[0][0] else 0
+        e_col = sel[1][1] + 1 if row == sel[1][0] else len(pad.buffer[row]) + 1
+        s_col = min(max(s_col - pad.buf_x, 0), len(text))
+        e_col = min(max(e_col - pad.buf_x, 0), len(text))
+        self.render_ruled(pad, row, text[:s_col], x, y, pad.buf_x, spans)
+        self.render_styled(text[s_col:e_col], x + s_col * self.char_width, y, pad.buf_x + s_col, spans, bg=self.theme.selection)
+        self.render_ruled(pad, row, text[e_col:], x + e_col * self.char_width, y, pad.buf_x + e_col, spans)
+
+    def render_ruled(self, pad: 'Pad', row: int, text: str, x: int, y: int, col: int, spans: list[StyleSpan], bg: tuple[int, int, int, int] | None = None):
+        """render_styled(), with ruler_overflow the characters beyond the ruler column on the overflow color"""
+        ruler = pad.buffer.settings.ruler
+        end = min(len(pad.buffer[row]) - col, len(text)) if 0 <= row < len(pad.buffer) else 0
+        start = max(ruler - col, 0)
+        if self.ruler_overflow is False or ruler <= 0 or pad.buffer.hex is True or start >= end:
+            self.render_styled(text, x, y, col, spans, bg=bg)
+            return
+        self.render_styled(text[:start], x, y, col, spans, bg=bg)
+        self.render_styled(text[start:end], x + start * self.char_width, y, col + start, spans, bg=self.theme.ruler_overflow)
+        self.render_styled(text[end:], x + end * self.char_width, y, col + end, spans, bg=bg)
+
+    def render_ruler(self, x: int, y0: int, pad: 'Pad'):
+        """A thin line right of the ruler column of the buffer's file type"""
+        col = pad.buffer.settings.ruler - pad.buf_x
+        if pad.buffer.settings.ruler <= 0 or pad.buffer.hex is True or col <= 0 or col >= pad.width:
+            return
+        self.renderer.fill(sdl2.SDL_Rect(x + col * self.char_width, y0, 1, pad.height * self.line_height()), color=self.theme.ruler)  # pyright: ignore[reportUnknownMemberType]
+
+    def view_line(self, pad: 'Pad', row: int) -> str:
+        """The canvas line the pad would have for a row outside its view"""
+        if row < 0 or row >= len(pad.buffer):
+            return ' ' * (pad.left_border + pad.width)
+        gutter = gutter_label(row, pad.buf_y + pad.cur_y if self.relative_numbers is True else None, pad.buffer.line_offset()) if pad.left_border > 0 else ""
+        return gutter[:pad.left_border].ljust(pad.left_border) + pad.buffer[row][pad.buf_x:pad.buf_x + pad.width].ljust(pad.width)
+
+    def scroll_offset(self, frame: Frame, pad: 'Pad') -> int:
+        """How far the text is still drawn from where the pad's view shows it, while gliding to a new scroll position"""
+        now = time.monotonic()
+        seen = self.scroll_seen.get(frame.id)
+        self.scroll_seen[frame.id] = (frame.pad_id, id(pad.buffer), pad.buf_y)
+        offset = 0.0
+        scroll = self.scrolls.get(frame.id)
+        if scroll is not None:
+            progress = (now - scroll[1]) * 1000 / max(self.scroll_ms, 1)
+            offset = scroll[0] * (1 - progress) ** 3 if progress < 1 else 0.0  # eases out
+        if seen is not None and seen[:2] == (frame.pad_id, id(pad.buffer)) and seen[2] != pad.buf_y and self.scroll_ms > 0 and abs(pad.buf_y - seen[2]) <= 2 * pad.height:
+            offset += (pad.buf_y - seen[2]) * self.line_height()
+            self.scrolls[frame.id] = (offset, now)
+        elif round(offset) == 0:
+            _ = self.scrolls.pop(frame.id, None)
+            return 0
+        return round(offset)
+
+    def animating(self) -> bool:
+        return len(self.scrolls) > 0 or len(self.moves) > 0
+
+    def split_origin(self, frames: Frames, frame: Frame, seen: dict[int, tuple[int, int, int, int]]) -> tuple[int, int, int, int] | None:
+        """Where a new frame starts: the near half of a split at the rectangle of the frame it was split from, the
+        far half as a sliver at that rectangle's far edge"""
+        index = frames.parent_idx(frame.id)
+        while index is not None and frames.frames[index].id not in seen:
+            index = frames.parent_idx(frames.frames[index].id)
+        if index is None:
+            return None
+        ax, ay, aw, ah = seen[frames.frames[index].id]
+        x, w = (ax, aw) if frame.x <= ax else (ax + aw, 0)
+        y, h = (ay, ah) if frame.y <= ay else (ay + ah, 0)
+        return (x, y, w, h)
+
+    def layout_moves(self, frames: Frames, leaves: list[Frame]) -> dict[int, tuple[int, int, int, int]]:
+        """The rectangles the tiled frames are drawn at while they move to where a split, close or resize put them;
+        those that have arrived are left out"""
+        now = time.monotonic()
+        seen, self.rects_seen = self.rects_seen, {fr.id: (fr.x, fr.y, fr.wx, fr.hy) for fr in leaves}
+        shown: dict[int, tuple[int, int, int, int]] = {}
+        moves: dict[int, tuple[tuple[int, int, int, int], tuple[int, int, int, int], float]] = {}
+        for fr in leaves if self.layout_ms > 0 else []:
+            target = (fr.x, fr.y, fr.wx, fr.hy)
+            move = self.moves.get(fr.id)
+            current: tuple[int, int, int, int] | None = None
+            if move is not None:
+                progress = (now - move[2]) * 1000 / self.layout_ms
+                if progress < 1:
+                    eased = 1 - (1 - progress) ** 3
+                    current = (round(move[0][0] + (move[1][0] - move[0][0]) * eased), round(move[0][1] + (move[1][1] - move[0][1]) * eased),
+                               round(move[0][2] + (move[1][2] - move[0][2]) * eased), round(move[0][3] + (move[1][3] - move[0][3]) * eased))
+                if move[1] == target:
+                    if current is not None:
+                        moves[fr.id] = move
+                        shown[fr.id] = current
+                    continue
+            # a new target: from where the frame is shown now, or was drawn last, or the frame it was split from
+            origin = current if current is not None else seen.get(fr.id) or self.split_origin(frames, fr, seen)
+            if origin is not None and origin != target:
+                moves[fr.id] = (origin, target, now)
+                shown[fr.id] = origin
+        self.moves = moves
+        return shown
+
+    def render_scrolling(self, frame: Frame, pad: 'Pad', active: bool, block_cursor: bool, offset: int):
+        """The pad on its way to the view's scroll position, its text offset down (or up) and the rows beyond the canvas
+        taken from the buffer; decorations wait until it has arrived"""
+        x0 = frame.x + self.style.inset()
+        y0 = frame.y + self.style.inset()
+        edge = self.style.inset() - self.style.padding
+        lh = self.line_height()
+        sel = pad.selection()
+        self.renderer.set_clip(sdl2.SDL_Rect(frame.x + edge, y0, frame.wx - 2 * edge, pad.height * lh))
+        for i in range(-math.ceil(max(offset, 0) / lh), pad.height + math.ceil(max(-offset, 0) / lh)):
+            self.lines += 1
+            line = pad.canvas[i] if 0 <= i < pad.height else self.view_line(pad, pad.buf_y + i)
+            self.render_line(pad, line, pad.buf_y + i, x0, y0 + i * lh + offset, sel)
+        self.render_ruler(x0 + pad.left_border * self.char_width, y0, pad)
+        if active is True and self.cursor_on is True:
+            self.render_cursor(x0 + (pad.left_border + pad.cur_x) * self.char_width, y0 + pad.cur_y * lh + offset, pad, block_cursor)
+        self.renderer.set_clip(None)
+        for i in range(pad.height, len(pad.canvas)):
+            self.render_styled(pad.canvas[i], x0, y0 + i * lh, 0, pad.status_spans, bg=self.theme.status)
+
+    def render_cursor(self, cx: int, cy: int, pad: 'Pad', normal: bool):
+        """The cursor in the shape and color of the insert or, in modal editing, the normal mode"""
+        lh = self.line_height()
+        style = self.normal_cursor_style if normal is True else self.cursor_style
+        color = self.theme.normal_cursor if normal is True and self.theme.normal_cursor is not None else self.theme.cursor
+        if style == 'block':
+            rect = sdl2.SDL_Rect(cx, cy, self.char_width, lh)
+            self.renderer.fill(rect, color=color)  # pyright: ignore[reportUnknownMemberType]
+            row = pad.buf_y + pad.cur_y
+            col = pad.buf_x + pad.cur_x
+            if row < len(pad.buffer) and col < len(pad.buffer[row]):
+                _ = self.render_text(pad.buffer[row][col], cx, cy, fg=self.theme.background, bg=color)
+        elif style == 'underline':
+            self.renderer.fill(sdl2.SDL_Rect(cx, cy + lh - 2, self.char_width, 2), color=color)  # pyright: ignore[reportUnknownMemberType]
+        else:
+            self.renderer.fill(sdl2.SDL_Rect(cx, cy, 2, lh), color=color)  # pyright: ignore[reportUnknownMemberType]
+
+    def render_peer_cursors(self, x0: int, y0: int, pad: 'Pad'):
+        """Selections of other participants underlined and their cursors as bars, each in the participant's color"""
+        lh = self.line_height()
+        for peer in pad.buffer.peer_cursors:
+            if peer.anchor is not None:
+                start, end = min(peer.anchor, peer.cursor), max(peer.anchor, peer.cursor)
+                for row in range(max(start[0], pad.buf_y), min(end[0], pad.buf_y + pad.height - 1, len(pad.buffer) - 1) + 1):
+                    s_col = start[1] if row == start[0] else 0
+                    e_col = end[1] + 1 if row == end[0] else len(pad.buffer[row]) + 1
+                    s_col = min(max(s_col - pad.buf_x, 0), pad.width)
+                    e_col = min(max(e_col - pad.buf_x, 0), pad.width)
+                    if e_col > s_col:
+                        rect = sdl2.SDL_Rect(x0 + s_col * self.char_width, y0 + (row - pad.buf_y + 1) * lh - 2, (e_col - s_col) * self.char_width, 2)
+                        self.renderer.fill(rect, color=peer.color)  # pyright: ignore[reportUnknownMemberType]
+            row, col = peer.cursor
+            if pad.buf_y <= row < pad.buf_y + pad.height and pad.buf_x <= col <= pad.buf_x + pad.width:
+                rect = sdl2.SDL_Rect(x0 + (col - pad.buf_x) * self.char_width, y0 + (row - pad.buf_y) * lh, 2, lh)
+                self.renderer.fill(rect, color=peer.color)  # pyright: ignore[reportUnknownMemberType]
+
+    def render_whitespace(self, x0: int, y0: int, pad: 'Pad'):
+        """Spaces as faint dots and tabs as arrows, trailing whitespace on a red background"""
+        lh = self.line_height()
+        for i in range(pad.height):
+            row = pad.buf_y + i
+            if row >= len(pad.buffer):
+                break
+            line = pad.buffer[row]
+            trailing = len(line.rstrip(" \t"))
+            for col in range(pad.buf_x, min(len(line), pad.buf_x + pad.width)):
+                c = line[col]
+                if c != ' ' and c != '\t':
+                    continue
+                bg = self.theme.trailing_whitespace if col >= trailing else self.theme.background
+                _ = self.render_text("·" if c == ' ' else "→", x0 + (col - pad.buf_x) * self.char_width, y0 + i * lh, fg=self.theme.faint, bg=bg)
+
+    def image_texture(self, view: 'ImageView') -> object | None:
+        """Texture of the image file, loaded once; None if it cannot be decoded"""
+        if view.path in self.images:
+            return self.images[view.path]
+        texture = None
+        if sdlimage is not None:
+            image = self.renderer.load_image(view.path)
+            if image is None:
+                self.log.error(f"Cannot load {view.path}: {sdl2.SDL_GetError()}")  # pyright: ignore[reportUnknownMemberType]
+            else:
+                texture, view.width, view.height = image
+        self.images[view.path] = texture
+        return texture
+
+    def forget_image(self, path: str):
+        """Drop the cached texture so a changed file is decoded again"""
+        texture = self.images.pop(path, None)
+        if texture is not None:
+            self.renderer.destroy_texture(texture)
+
+    def render_image(self, frame: Frame, pad: 'Pad', view: 'ImageView'):
+        """The image scaled around its (panned) centre above the pad's status line"""
+        x0 = frame.x + self.style.inset()
+        y0 = frame.y + self.style.inset()
+        lh = self.line_height()
+        wx = pad.width + pad.left_border
+        area = sdl2.SDL_Rect(x0, y0, wx * self.char_width, pad.height * lh)
+        self.renderer.fill(area, color=self.theme.background)  # pyright: ignore[reportUnknownMemberType]
+        texture = self.image_texture(view)
+        if texture is None:
+            reason = "SDL2_image is not available" if sdlimage is None else "cannot decode it"
+            _ = self.render_text(f"Cannot show {os.path.basename(view.path)}: {reason}", x0, y0)
+        else:
+            scale = view.scale(area.w, area.h)
+            w, h = max(int(view.width * scale), 1), max(int(view.height * scale), 1)
+            x = area.x + area.w // 2 - w // 2 - int(view.pan_x * scale)
+            y = area.y + area.h // 2 - h // 2 - int(view.pan_y * scale)
+            self.renderer.set_clip(area)
+            self.renderer.copy(texture, None, sdl2.SDL_Rect(x, y, w, h))
+            self.renderer.set_clip(None)
+        for i, line in enumerate(pad.canvas[pad.height:]):
+            self.render_styled(line, x0, y0 + (pad.height + i) * lh, 0, pad.status_spans, bg=self.theme.status)
+
+    def render_hunks(self, x: int, y0: int, pad: 'Pad'):
+        """A bar at the right of the gutter for added and modified lines, a tick where lines were deleted"""
+        lh = self.line_height()
+        for hunk in pad.buffer.hunks:
+            if hunk.end < pad.buf_y or hunk.start >= pad.buf_y + pad.height:
+                continue
+            if hunk.kind() == 'deleted':
+                y = y0 + (hunk.start - pad.buf_y) * lh
+                rect = sdl2.SDL_Rect(x - self.char_width, y - 1, self.char_width + 3, 3)
+                self.renderer.fill(rect, color=self.theme.git_deleted)  # pyright: ignore[reportUnknownMemberType]
+                continue
+            first = max(hunk.start, pad.buf_y)
+            last = min(hunk.end, pad.buf_y + pad.height)
+            rect = sdl2.SDL_Rect(x, y0 + (first - pad.buf_y) * lh, 3, (last - first) * lh)
+            self.renderer.fill(rect, color=self.theme.git_added if hunk.kind() == 'added' else self.theme.git_modified)  # pyright: ignore[reportUnknownMemberType]
+
+    def severity_color(self, severity: int) -> tuple[int, int, int, int]:
+        return self.theme.error if severity == 1 else self.theme.warning if severity == 2 else self.theme.info
+
+    def render_squiggle(self, x: int, y: int, w: int, color: tuple[int, int, int, int]):
+        for dx in range(0, w, 2):
+            rect = sdl2.SDL_Rect(x + dx, y + (dx // 2) % 2, 2, 1)
+            self.renderer.fill(rect, color=color)  # pyright: ignore[reportUnknownMemberType]
+
+    def render_diagnostics(self, x0: int, y0: int, pad: 'Pad'):
+        """Worst severity per line as gutter mark, each diagnostic range as squiggly underline"""
+        lh = self.line_height()
+        tx = x0 + pad.left_border * self.char_width
+        worst: dict[int, int] = {}
+        for d in pad.buffer.diagnostics:
+            for row in range(max(d.row, pad.buf_y), min(d.end_row, pad.buf_y + pad.height - 1) + 1):
+                worst[row] = min(worst.get(row, 4), d.severity)
+                line = pad.buffer[row] if row < len(pad.buffer) else ""
+                start = d.col if row == d.row else 0
+                end = d.end_col if row == d.end_row else len(line)
+                if end <= start:
+                    end = start + 1  # empty ranges still get a mark
+                start = max(start - pad.buf_x, 0)
+                end = min(end - pad.buf_x, pad.width)
+                if end > start:
+                    self.render_squiggle(tx + start * self.char_width, y0 + (row - pad.buf_y + 1) * lh - 3, (end - start) * self.char_width, self.severity_color(d.severity))
+        if pad.left_border > 0:
+            for row, severity in worst.items():
+                _ = self.render_text("●", x0, y0 + (row - pad.buf_y) * lh, fg=self.severity_color(severity), bg=self.theme.status)
+
+    def render_misspelled(self, x0: int, y0: int, pad: 'Pad', spell: 'SpellChecker'):
+        lh = self.line_height()
+        for i in range(pad.height):
+            row = pad.buf_y + i
+            if row >= len(pad.buffer):
+                break
+            for start, end in spell.misspelled(pad.buffer[row], pad.buffer.settings, pad.buffer.filetype):
+                start = max(start - pad.buf_x, 0)
+                end = min(end - pad.buf_x, pad.width)
+                if end > start:
+                    rect = sdl2.SDL_Rect(x0 + start * self.char_width, y0 + (i + 1) * lh - 2, (end - start) * self.char_width, 1)
+                    self.renderer.fill(rect, color=self.theme.misspelled)  # pyright: ignore[reportUnknownMemberType]
+
+    def render_scroll_indicators(self, x0: int, y0: int, pad: 'Pad'):
+        """< and > at the frame edges for lines that continue left or right of the view"""
+        lh = self.line_height()
+        for i in range(pad.height):
+            row = pad.buf_y + i
+            if row >= len(pad.buffer):
+                break
+            length = len(pad.buffer[row])
+            if pad.buf_x > 0 and length > 0:
+                _ = self.render_text("<", x0, y0 + i * lh, fg=self.theme.faint, bg=self.theme.status)
+            if length > pad.buf_x + pad.width:
+                _ = self.render_text(">", x0 + (pad.width - 1) * self.char_width, y0 + i * lh, fg=self.theme.faint, bg=self.theme.status)
+
+    def render_preedit(self, x: int, y: int, text: str, cursor: int):
+        """Text an input method is composing, underlined over the cursor position until it is committed"""
+        lh = self.line_height()
+        rect = self.render_text(text, x, y)
+        w = rect.w if rect is not None else len(text) * self.char_width
+        self.renderer.fill(sdl2.SDL_Rect(x, y + lh - 2, w, 2), color=self.theme.foreground)  # pyright: ignore[reportUnknownMemberType]
+        self.renderer.fill(sdl2.SDL_Rect(x + min(cursor, len(text)) * self.char_width, y, 2, lh), color=self.theme.cursor)  # pyright: ignore[reportUnknownMemberType]
+
+    def render_tab_bar(self, tabs: list[tuple[int, str]], active: int, wx: int, hy: int):
+        """The workspaces along the top of the window, the one shown highlighted"""
+        self.renderer.fill(sdl2.SDL_Rect(0, 0, wx, hy), color=self.theme.background)  # pyright: ignore[reportUnknownMemberType]
+        for i, (col, label) in enumerate(tabs):
+            x = frame_inset + col * self.char_width
+            rect = sdl2.SDL_Rect(x, frame_inset // 2, len(label) * self.char_width, hy - frame_inset)
+            self.renderer.fill(rect, color=self.theme.selection if i == active else self.theme.status)  # pyright: ignore[reportUnknownMemberType]
+            _ = self.render_text(label, x, frame_inset, bg=self.theme.selection if i == active else self.theme.status)
+
+    def render_minibuffer(self, minibuffer: 'Minibuffer', x: int, y: int, wx: int, hy: int):
+        lh = self.line_height()
+        rect = sdl2.SDL_Rect(x, y, wx, hy)
+        self.renderer.fill(rect, color=self.theme.background)  # pyright: ignore[reportUnknownMemberType]
+        if minibuffer.active is False:
+            _ = self.render_text(minibuffer.current_message(), x + frame_inset, y + frame_inset)
+            return
+        line = minibuffer.prompt + minibuffer.text
+        _ = self.render_text(line, x + frame_inset, y + frame_inset)
+        cursor = sdl2.SDL_Rect(x + frame_inset + len(line) * self.char_width, y + frame_inset, 2, lh)
+        self.renderer.fill(cursor, color=self.theme.cursor)  # pyright: ignore[reportUnknownMemberType]
+        visible, selected = minibuffer.visible_matches()
+        if len(visible) == 0:
+            return
+        list_y = y - len(visible) * lh - frame_inset
+        rect = sdl2.SDL_Rect(x, list_y - frame_inset, wx, len(visible) * lh + 2 * frame_inset)
+        self.renderer.fill(rect, color=self.theme.background)  # pyright: ignore[reportUnknownMemberType]
+        self.renderer.draw_rect(rect, color=self.theme.border)  # pyright: ignore[reportUnknownMemberType]
+        name_width = max(len(m) for m in visible) + 2
+        max_chars = max(1, (wx - 2 * frame_inset) // self.char_width)
+        for i, match in enumerate(visible):
+            entry = match
+            if minibuffer.annotate is not None:
+                entry = f"{match:<{name_width}}{minibuffer.annotate(match)}"
+            bg = self.theme.selection if i == selected else self.theme.background
+            _ = self.render_text(entry[:max_chars], x + frame_inset, list_y + i * lh, bg=bg)
+
+    def text_size(self, text: str, font: sdl2.sdlttf.TTF_Font | None = None) -> tuple[int, int]:
+        if font is None:
+            font = self.font
+        tw: ctypes.c_int = ctypes.c_int(0)
+        th: ctypes.c_int = ctypes.c_int(0)
+        sdl2.sdlttf.TTF_SizeUTF8(font, text.encode(), ctypes.byref(tw), ctypes.byref(th))  # pyright: ignore[reportUnknownMemberType]
+        return (tw.value // self.font_mag, th.value // self.font_mag)
+
+    def render_frame_labels(self, frames: Frames, labels: dict[str, int]):
+        for label, fr_id in labels.items():
+            idx = frames.idx(fr_id)
+            if idx is None:
+                continue
+            fr = frames.frames[idx]
+            text = f" {label} "
+            w, h = self.text_size(text, self.label_font)
+            x = fr.x + (fr.wx - w) // 2
+            y = fr.y + (fr.hy - h) // 2
+            box = sdl2.SDL_Rect(x - frame_inset, y - frame_inset, w + 2 * frame_inset, h + 2 * frame_inset)
+            self.renderer.draw_rect(box, color=self.theme.active_border)  # pyright: ignore[reportUnknownMemberType]
+            _ = self.render_text(text, x, y, fg=self.theme.background, bg=self.theme.cursor, font=self.label_font)
+
+    def popup_rect(self, anchor_x: int, anchor_y: int, lines: list[str], wx: int, hy: int) -> sdl2.SDL_Rect:
+        """Below the anchor cell if it fits, else above; shifted left to stay inside wx x hy"""
+        lh = self.line_height()
+        w = max(len(line) for line in lines) * self.char_width + 2 * frame_inset
+        h = len(lines) * lh + 2 * frame_inset
+        x = anchor_x
+        y = anchor_y + lh
+        if y + h > hy and anchor_y - h >= 0:
+            y = anchor_y - h
+        if x + w > wx:
+            x = wx - w
+        return sdl2.SDL_Rect(max(0, x), max(0, y), min(w, wx), min(h, hy))
+
+    def render_popup(self, frames: Frames, editor: 'ReplEditor', popup: Popup, wx: int, hy: int):
+        fr = frames.get(popup.frame_id)
+        if fr is None or len(popup.lines) == 0:
+            return
+        pad = editor.pad_get(fr.pad_id)
+        if pad is None:
+            return
+        if popup.row < pad.buf_y or popup.row >= pad.buf_y + pad.height or popup.col < pad.buf_x or popup.col > pad.buf_x + pad.width:
+            return  # anchor scrolled out of view
+        lh = self.line_height()
+        ax = fr.x + self.style.inset() + (pad.left_border + popup.col - pad.buf_x) * self.char_width
+        ay = fr.y + self.style.inset() + (popup.row - pad.buf_y) * lh
+        rect = self.popup_rect(ax, ay, popup.lines, wx, hy)
+        self.renderer.fill(rect, color=self.theme.status)  # pyright: ignore[reportUnknownMemberType]
+        self.renderer.draw_rect(rect, color=self.theme.active_border)  # pyright: ignore[reportUnknownMemberType]
+        max_chars = max(1, (rect.w - 2 * frame_inset) // self.char_width)
+        for i, line in enumerate(popup.lines):
+            if (i + 1) * lh > rect.h:
+                break
+            _ = self.render_text(line[:max_chars], rect.x + frame_inset, rect.y + frame_inset + i * lh, bg=self.theme.status)
+
+    def render_toasts(self, toasts: list['Notification'], wx: int, bottom: int):
+        """Newest notification at the bottom right corner, older ones stacked above"""
+        lh = self.line_height()
+        y = bottom
+        max_chars = max(8, (wx // 2) // self.char_width)
+        for toast in reversed(toasts):
+            text = f"{toast.source}: {toast.text}"[:max_chars]
+            w = len(text) * self.char_width + 4 * frame_inset
+            h = lh + 2 * frame_inset
+            y -= h + frame_inset
+            rect = sdl2.SDL_Rect(wx - w - 2 * frame_inset, y, w, h)
+            self.renderer.fill(rect, color=self.theme.status)  # pyright: ignore[reportUnknownMemberType]
+            border = self.theme.active_border if toast.level == "error" else self.theme.border
+            self.renderer.draw_rect(rect, color=border)  # pyright: ignore[reportUnknownMemberType]
+            _ = self.render_text(text, rect.x + 2 * frame_inset, y + frame_inset, bg=self.theme.status)
+
+    def render_stats(self, lines: list[str], wx: int):
+        """Profiling overlay in the top right corner"""
+        lh = self.line_height()
+        w = max(len(line) for line in lines) * self.char_width + 4 * frame_inset
+        rect = sdl2.SDL_Rect(wx - w - 2 * frame_inset, 2 * frame_inset, w, len(lines) * lh + 2 * frame_inset)
+        self.renderer.fill(rect, color=self.theme.status)  # pyright: ignore[reportUnknownMemberType]
+        self.renderer.draw_rect(rect, color=self.theme.border)  # pyright: ignore[reportUnknownMemberType]
+        for i, line in enumerate(lines):
+            _ = self.render_text(line, rect.x + 2 * frame_inset, rect.y + frame_inset + i * lh, bg=self.theme.status)
+
+    def begin_canvas(self, w: int, h: int) -> bool:
+        """Draw into the canvas texture from now on; True if it is new and has to be drawn completely"""
+        if self.canvas_failed is True:
+            self.renderer.clear(window_background)  # pyright: ignore[reportUnknownMemberType]
+            return True
+        ow, oh = self.renderer.output_size()
+        fresh = False
+        if self.canvas is None or self.canvas_size != (ow, oh, w, h):
+            if self.canvas is not None:
+                self.renderer.destroy_texture(self.canvas)
+            self.canvas = self.renderer.create_texture(max(ow, 1), max(oh, 1), True)
+            if self.canvas is None:
+                self.log.warning(f"No render target texture, redrawing everything each frame: {sdl2.SDL_GetError()}")  # pyright: ignore[reportUnknownMemberType]
+                self.canvas_failed = True
+                self.renderer.clear(window_background)  # pyright: ignore[reportUnknownMemberType]
+                return True
+            self.canvas_size = (ow, oh, w, h)
+            fresh = True
+        self.renderer.set_target(self.canvas, (ow / max(w, 1), oh / max(h, 1)))
+        return fresh
+
+    def show_canvas(self):
+        """Back to drawing on the window, starting with a copy of the canvas"""
+        if self.canvas is not None:
+            self.renderer.set_target(None)
+            self.renderer.copy(self.canvas, None, None)
+
+    def frame_key(self, frame: Frame, pad: 'Pad | None', active: bool, editor: 'ReplEditor') -> tuple[object, ...]:
+        """Everything besides the lines that a frame's picture depends on, a change redraws the whole frame"""
+        if pad is None:
+            return (frame.x, frame.y, frame.wx, frame.hy, active, self.borders, self.style)
+        buffer = pad.buffer
+        return (frame.x, frame.y, frame.wx, frame.hy, frame.zoom, active, self.borders, self.style, id(self.theme), editor.cursor_block, self.cursor_style, self.normal_cursor_style, self.ruler_overflow, editor.show_whitespace, editor.spell is not None,
+                pad.buf_y, pad.buf_x, pad.height, pad.width, pad.left_border, buffer.hex, buffer.settings.ruler,
+                [copy.copy(d) for d in buffer.diagnostics], [copy.copy(h) for h in buffer.hunks], [copy.copy(p) for p in buffer.peer_cursors],
+                copy.copy(buffer.image))
+
+    def line_keys(self, pad: 'Pad', active: bool) -> list[tuple[object, ...]]:
+        """What each canvas line shows, compared to find the lines to redraw"""
+        sel = pad.selection()
+        keys: list[tuple[object, ...]] = []
+        for i, line in enumerate(pad.canvas):
+            row = pad.buf_y + i
+            selected = sel if sel is not None and sel[0][0] <= row <= sel[1][0] else None
+            cursor = pad.cur_x if active is True and i == pad.cur_y and self.cursor_on is True else -1
+            keys.append((line, selected, cursor, pad.buffer.highlights.get(row), list(pad.buffer.styles.get(row, []))))
+        return keys
+
+    def render_leaf(self, frame: Frame, pad: 'Pad | None', active: bool, editor: 'ReplEditor', rows: set[int] | None, offset: int = 0):
+        """A tiled frame, or with rows only those canvas lines of it"""
+        rect = sdl2.SDL_Rect(frame.x, frame.y, frame.wx, frame.hy)
+        if rows is None:
+            self.renderer.fill(rect, color=window_background)  # pyright: ignore[reportUnknownMemberType]
+            if self.borders is True:
+                self.render_outline(rect, active)
+        if pad is not None and pad.buffer.image is not None:
+            self.render_image(frame, pad, pad.buffer.image)
+        elif pad is not None:
+            self.render_pad(frame, pad, active, editor.cursor_block, editor.show_whitespace, editor.spell, rows, offset)
+        if active is False and self.style.dim_inactive is True:
+            self.renderer.fill(rect, color=self.theme.inactive_dim)  # pyright: ignore[reportUnknownMemberType]
+
+    def minimap_rect(self, frame: Frame, pad: 'Pad | None') -> sdl2.SDL_Rect | None:
+        """Where a tiled frame's minimap is: right of the text, as tall as the pad's view"""
+        if self.style.minimap <= 0 or pad is None or pad.buffer.image is not None:
+            return None
+        inset = self.style.inset()
+        return sdl2.SDL_Rect(frame.x + frame.wx - inset - self.style.minimap, frame.y + inset, self.style.minimap, pad.height * self.cell_size(frame.zoom)[1])
+
+    def minimap_scale(self, pad: 'Pad', rect: sdl2.SDL_Rect) -> tuple[int, float]:
+        """Rows of the minimap and rows per buffer line, less than one where the buffer would not fit"""
+        rows = rect.h // minimap_pitch
+        return rows, min(1.0, rows / max(len(pad.buffer), 1))
+
+    def minimap_line(self, frame: Frame, pad: 'Pad', y: int) -> int | None:
+        """The buffer line the minimap shows at y, the nearest one above or below it"""
+        rect = self.minimap_rect(frame, pad)
+        if rect is None:
+            return None
+        scale = self.minimap_scale(pad, rect)[1]
+        return min(len(pad.buffer) - 1, max(0, int((y - rect.y) // minimap_pitch / scale)))
+
+    def render_minimap(self, frame: Frame, pad: 'Pad | None', active: bool, full: bool) -> bool:
+        """The frame's minimap, the whole buffer one line per row, a character a drawing unit, or sampled lines where it
+        is longer; unless full only the rows whose line or view indicator changed. True if any was drawn"""
+        rect = self.minimap_rect(frame, pad)
+        if rect is None or pad is None:
+            _ = self.minimaps.pop(frame.id, None)
+            return False
+        rows, scale = self.minimap_scale(pad, rect)
+        lines = [pad.buffer[int(r / scale)] for r in range(min(rows, math.ceil(len(pad.buffer) * scale)))]
+        view = (int(pad.buf_y * scale), math.ceil((pad.buf_y + pad.height) * scale))
+        key = (rect.x, rect.y, rect.w, rect.h, active, id(self.theme), self.style)
+        previous = self.minimaps.get(frame.id)
+        self.minimaps[frame.id] = (key, lines, view)
+        dim = active is False and self.style.dim_inactive is True
+        if full is False and previous is not None and previous[0] == key:
+            def row_key(r: int, lines: list[str], view: tuple[int, int]) -> tuple[str | None, bool]:
+                return (lines[r] if r < len(lines) else None, view[0] <= r < view[1])
+            redraw = [r for r in range(rows) if row_key(r, lines, view) != row_key(r, previous[1], previous[2])]
+            if len(redraw) == 0:
+                return False
+        else:
+            redraw = list(range(rows))
+            self.renderer.fill(rect, color=window_background)  # pyright: ignore[reportUnknownMemberType]
+        for r in redraw:
+            strip = sdl2.SDL_Rect(rect.x, rect.y + r * minimap_pitch, rect.w, minimap_pitch)
+            if full is False:
+                self.renderer.fill(strip, color=window_background)  # pyright: ignore[reportUnknownMemberType]
+            for word in re.finditer(r'\S+', lines[r] if r < len(lines) else ""):
+                if word.start() >= rect.w:
+                    break
+                self.renderer.fill(sdl2.SDL_Rect(rect.x + word.start(), strip.y, min(word.end(), rect.w) - word.start(), max(1, minimap_pitch - 1)), color=self.theme.minimap_text)  # pyright: ignore[reportUnknownMemberType]
+            if view[0] <= r < view[1]:
+                self.renderer.fill(strip, color=self.theme.minimap_view)  # pyright: ignore[reportUnknownMemberType]
+            if dim is True:
+                self.renderer.fill(strip, color=self.theme.inactive_dim)  # pyright: ignore[reportUnknownMemberType]
+        return True
+
+    def render_outline(self, rect: sdl2.SDL_Rect, active: bool):
+        """A frame's outline, thicker for the active one as the style says"""
+        width = self.style.active_border if active is True else self.style.border
+        color = self.theme.active_border if active is True else self.theme.border
+        for i in range(min(width, rect.w // 2, rect.h // 2)):
+            self.renderer.draw_rect(sdl2.SDL_Rect(rect.x + i, rect.y + i, rect.w - 2 * i, rect.h - 2 * i), color=color)  # pyright: ignore[reportUnknownMemberType]
+
+    def render(self, frames:Frames, editor: 'ReplEditor', w: int, h: int) -> bool:
+        """Tiled frames into the canvas, redrawing only the frames and lines that changed since the last call; True if any did"""
+        fresh = self.begin_canvas(w, h)
+        leaves = frames.win_frames()[0]
+        moving = self.layout_moves(frames, leaves)
+        if fresh is False and ({fr.id for fr in leaves} != set(self.drawn.keys()) or len(moving) > 0):
+            self.renderer.clear(window_background)  # pyright: ignore[reportUnknownMemberType]
+            fresh = True
+        if fresh is True:
+            self.drawn = {}
+        changed = fresh
+        for frame in leaves:
+            pad = editor.pad_get(frame.pad_id)
+            active = frame.id == frames.active_id
+            self.set_zoom(frame.zoom)
+            offset = self.scroll_offset(frame, pad) if pad is not None and pad.buffer.image is None else 0
+            if frame.id in moving:
+                # drawn at the rectangle it moves through, clipped to it; not kept, so that it is drawn again once there
+                shown = copy.copy(frame)
+                shown.x, shown.y, shown.wx, shown.hy = moving[frame.id]
+                self.renderer.set_clip(sdl2.SDL_Rect(shown.x, shown.y, shown.wx, shown.hy))
+                self.render_leaf(shown, pad, active, editor, None)
+                self.renderer.set_clip(None)
+                _ = self.drawn.pop(frame.id, None)
+                _ = self.minimaps.pop(frame.id, None)
+                changed = True
+                continue
+            key = self.frame_key(frame, pad, active, editor) + (offset,)
+            lines = self.line_keys(pad, active) if pad is not None and pad.buffer.image is None else []
+            previous = self.drawn.get(frame.id)
+            self.drawn[frame.id] = (key, lines)
+            rows: set[int] | None = None
+            if previous is not None and previous[0] == key and len(previous[1]) == len(lines):
+                rows = {i for i, line in enumerate(lines) if previous[1][i] != line}
+                if len(rows) == 0:
+                    changed = self.render_minimap(frame, pad, active, False) or changed
+                    continue
+                if active is False and self.style.dim_inactive is True:
+                    rows = None  # decorations spanning other lines would get the overlay twice
+            changed = True
+            self.render_leaf(frame, pad, active, editor, rows, offset)
+            _ = self.render_minimap(frame, pad, active, rows is None)
+        self.set_zoom(0)
+        return changed
+
+    def render_floats(self, frames: Frames, editor: 'ReplEditor'):
+        for fr in frames.floats:
+            rect = sdl2.SDL_Rect(fr.x, fr.y, fr.wx, fr.hy)
+            self.renderer.fill(rect, color=self.theme.background)  # pyright: ignore[reportUnknownMemberType]
+            if self.borders is True:
+                self.render_outline(rect, fr.id == frames.active_id)
+            pad = editor.pad_get(fr.pad_id)
+            self.set_zoom(fr.zoom)
+            if pad is not None:
+                self.render_pad(fr, pad, fr.id == frames.active_id, editor.cursor_block, editor.show_whitespace, editor.spell)
+        self.set_zoom(0)
+
+def is_printable_sym(sym: int) -> bool:
+    """Keycodes of character keys are their code point, on any layout; others have SDLK_SCANCODE_MASK set"""
+    return 32 <= sym < (1 << 30) and sym != 127 and chr(sym).isprintable()
+
+def is_text_key(sym: int, modifiers: int) -> bool:
+    """Printable keys without Ctrl/Alt/GUI arrive as SDL_TEXTINPUT, so their KEYDOWN is ignored"""
+    if modifiers & (sdl2.KMOD_CTRL | sdl2.KMOD_ALT | sdl2.KMOD_GUI):
+        return False
+    return is_printable_sym(sym)
+
+class KeyInput:
+    """Turns SDL key and text events into key names: text comes only from SDL_TEXTINPUT, so dead keys, compose
+    sequences and AltGr layouts insert what the system composed instead of the raw keycodes"""
+    def __init__(self):
+        self.held: str | None = None  # AltGr chord that is a command unless text arrives for it
+
+    def key_down(self, sym: int, key_name: str, modifiers: int) -> list[str]:
+        keys = self.flush()
+        if sym == 0 or key_name == "":
+            return keys  # dead and compose keys, their text follows with the next key
+        if is_text_key(sym, modifiers) is True:
+            return keys
+        if modifiers & (sdl2.KMOD_MODE | sdl2.KMOD_RALT) and modifiers & sdl2.KMOD_GUI == 0 and is_printable_sym(sym):
+            self.held = key_description(key_name, modifiers)  # AltGr, reported as RALT or on Windows as LCTRL+RALT
+            return keys
+        return keys + [key_description(key_name, modifiers)]
+
+    def text_input(self, text: str) -> list[str]:
+        self.held = None
+        return [text_key(c) for c in text]
+
+    def flush(self) -> list[str]:
+        """The held chord once no text came for it, called after each batch of events"""
+        keys = [self.held] if self.held is not None else []
+        self.held = None
+        return keys
+
+def key_description(key_name: str, modifiers: int) -> str:
+    prefix = ""
+    if modifiers & sdl2.KMOD_CTRL:
+        prefix += "C-"
+    if modifiers & sdl2.KMOD_ALT:
+        prefix += "M-"
+    if modifiers & sdl2.KMOD_SHIFT:
+        prefix += "S-"
+    if key_name == "Space":
+        key_name = "SPC"
+    elif len(key_name) == 1:
+        key_name = key_name.lower()
+    return prefix + key_name
+
+def sdl_window_id(window: sdl2.ext.Window) -> int:
+    """SDL's id of the window, by which events name the window they happened in; 0 for an OffscreenWindow"""
+    return cast(int, sdl2.SDL_GetWindowID(window.window)) if window.window is not None else 0  # pyright: ignore[reportUnknownMemberType]
+
+class SoundPlayer:
+    """WAV files played for editor events through SDL audio, which is opened when the first one plays"""
+    rate: int = 44100
+
+    def __init__(self, sounds: dict[str, str], volume: int):
+        self.log: logging.Logger = logging.getLogger("SoundPlayer")
+        self.sounds: dict[str, str] = sounds  # event -> file
+        self.volume: int = max(0, min(volume, 100))
+        self.device: int = 0
+        self.failed: bool = False  # no audio, not tried again
+        self.clips: dict[str, bytes | None] = {}  # file -> samples in the device's format, None if it cannot be read
+
+    def play(self, event: str):
+        """The event's sound, cutting short the one still playing; nothing if none is configured"""
+        path = self.sounds.get(event, "")
+        if path == "" or self.open() is False:
+            return
+        if path not in self.clips:
+            self.clips[path] = self.load(os.path.expanduser(path))
+        clip = self.clips[path]
+        if clip is None:
+            return
+        sdl2.SDL_ClearQueuedAudio(self.device)  # pyright: ignore[reportUnknownMemberType]
+        _ = sdl2.SDL_QueueAudio(self.device, clip, len(clip))  # pyright: ignore[reportUnknownMemberType]
+
+    def open(self) -> bool:
+        if self.device != 0 or self.failed is True:
+            return self.failed is False
+        want = sdl2.SDL_AudioSpec(self.rate, sdl2.AUDIO_S16SYS, 2, 1024)  # pyright: ignore[reportUnknownMemberType]
+        if sdl2.SDL_InitSubSystem(sdl2.SDL_INIT_AUDIO) == 0:  # pyright: ignore[reportUnknownMemberType]
+            self.device = cast(int, sdl2.SDL_OpenAudioDevice(None, 0, ctypes.byref(want), None, 0))  # pyright: ignore[reportUnknownMemberType]
+        if self.device == 0:
+            self.log.error(f"No audio: {sdl2.SDL_GetError().decode()}")  # pyright: ignore[reportUnknownMemberType]
+            self.failed = True
+            return False
+        sdl2.SDL_PauseAudioDevice(self.device, 0)  # pyright: ignore[reportUnknownMemberType]
+        return True
+
+    def load(self, path: str) -> bytes | None:
+        """The file's samples converted to the device's format and scaled by the volume"""
+        spec = sdl2.SDL_AudioSpec(0, 0, 0, 0)  # pyright: ignore[reportUnknownMemberType]
+        buf = ctypes.POINTER(ctypes.c_uint8)()
+        length = ctypes.c_uint32(0)
+        if not sdl2.SDL_LoadWAV(path.encode(), ctypes.byref(spec), ctypes.byref(buf), ctypes.byref(length)):  # pyright: ignore[reportUnknownMemberType]
+            self.log.error(f"Cannot load sound {path}: {sdl2.SDL_GetError().decode()}")  # pyright: ignore[reportUnknownMemberType]
+            return None
+        stream = sdl2.SDL_NewAudioStream(spec.format, spec.channels, spec.freq, sdl2.AUDIO_S16SYS, 2, self.rate)  # pyright: ignore[reportUnknownMemberType]
+        _ = sdl2.SDL_AudioStreamPut(stream, buf, length.value)  # pyright: ignore[reportUnknownMemberType]
+        _ = sdl2.SDL_AudioStreamFlush(stream)  # pyright: ignore[reportUnknownMemberType]
+        out = ctypes.create_string_buffer(cast(int, sdl2.SDL_AudioStreamAvailable(stream)))  # pyright: ignore[reportUnknownMemberType]
+        got = cast(int, sdl2.SDL_AudioStreamGet(stream, out, len(out)))  # pyright: ignore[reportUnknownMemberType]
+        sdl2.SDL_FreeAudioStream(stream)  # pyright: ignore[reportUnknownMemberType]
+        sdl2.SDL_FreeWAV(buf)  # pyright: ignore[reportUnknownMemberType]
+        samples = array.array('h', out.raw[:max(got, 0) & ~1])
+        for i in range(len(samples)) if self.volume < 100 else []:
+            samples[i] = samples[i] * self.volume // 100
+        return samples.tobytes()
+
+@dataclass
+class ZenState:
+    """What zen-mode changed in a window, to restore when leaving it"""
+    layout: LayoutSnapshot
+    borders: dict[int, tuple[int, int]] = field(default_factory=dict)  # pad -> gutter and status line size it had
+    fullscreen: bool = False  # zen-mode made the window fullscreen
+
+@dataclass
+class OsWindow:
+    """A top-level window with its own frames; Led's window, renderer, frame_renderer, frames and the frames' state are those of the focused one"""
+    window: sdl2.ext.Window
+    renderer: RenderBackend
+    frame_renderer: FrameRenderer
+    frames: Frames = field(default_factory=Frames)
+    frame_ids: set[int] = field(default_factory=set)  # leaf frames at the last relayout, to tell new ones
+    zoomed: LayoutSnapshot | None = None  # layout to restore when un-zooming
+    zen: ZenState | None = None
+    window_id: int = 0  # see sdl_window_id()
+    workspaces: Workspaces = field(default_factory=Workspaces)
+
+class Led:
+    def __init__(self, window: sdl2.ext.Window, renderer: RenderBackend, frame_renderer: FrameRenderer, config: EditorConfig):
+        self.log: logging.Logger = logging.getLogger("Led")
+        self.window: sdl2.ext.Window = window
+        self.renderer: RenderBackend = renderer
+        self.frame_renderer: FrameRenderer = frame_renderer
+        self.config: EditorConfig = config
+        self.running: bool = True
+        self.editor: ReplEditor = ReplEditor()
+        self.frames: Frames = Frames()
+        self.os_windows: list[OsWindow] = [OsWindow(window, renderer, frame_renderer, self.frames, window_id=sdl_window_id(window))]
+        self.focused: OsWindow = self.os_windows[0]
+        self.open_window: Callable[[], tuple[sdl2.ext.Window, RenderBackend, FrameRenderer]] | None = None  # set by run(), offscreen there is no second window
+        self.keymap: Keymap = default_keymap()
+        self.keys: KeySequence = KeySequence(config.chord_timeout_ms)
+        self.minibuffer: Minibuffer = Minibuffer()
+        self.commands: Commands = Commands()
+        self.hooks: Hooks = Hooks()
+        self.sound: SoundPlayer = SoundPlayer(config.sounds, config.sound_volume)
+        _ = self.hooks.add('after-save', lambda **_args: self.sound.play('save'))
+        self.frame_ids: set[int] = set()  # leaf frames at the last relayout, to tell new ones
+        self.results: WakeQueue[tuple[str, Callable[[], None]]] = WakeQueue()  # background work finished, run in tick()
+        self.grep_origin: int = 0  # frame a project search was started from
+        self.jump_lists: dict[int, JumpList] = {}  # by frame id
+        self.outline: tuple[Buffer, list[OutlineSymbol], list[OutlineSymbol]] | None = None  # source, tree, symbol per line
+        self.outline_origin: int = 0  # frame showing the outlined buffer
+        self.diff_view: DiffView | None = None
+        self.follow: Buffer | None = None  # follow-mode: the frames showing it are consecutive pages, left to right
+        self.scroll_lock: dict[int, int] = {}  # frame id -> top row when last synced, of the frames scrolling together
+        self.merge_view: MergeView | None = None
+        self.repls: dict[str, ReplSession] = {}  # by file type
+        self.collab: CollabSession | None = None
+        self.server: socket.socket | None = None  # unix socket led --client talks to
+        self.preedit: tuple[str, int] = ("", 0)  # input method composition and its cursor, shown until committed as text
+        self.redraw: bool = True  # minibuffer, popups or other things above the frames may have changed
+        self.stats: FrameStats | None = None  # profiling overlay, see toggle-frame-stats
+        self.render_error: str = ""  # last drawing failure shown, not repeated every frame
+        self.last_key_time: float = time.monotonic()  # the cursor blinks from here on
+        self.ime_rect: tuple[int, int, int, int] | None = None  # last cursor rectangle given to SDL_SetTextInputRect
+        self.recent_files: RecentFiles = RecentFiles(config.recent_files_max)
+        self.bookmarks: Bookmarks = Bookmarks()
+        self.layouts: dict[str, LayoutSnapshot] = {}
+        self.golden_ratio: bool = config.golden_ratio
+        self.zoomed: LayoutSnapshot | None = None  # layout to restore when un-zooming
+        self.zen: ZenState | None = None  # set while zen-mode is on
+        self.workspaces: Workspaces = Workspaces()  # tabs of the tab bar, each with its own frames
+        self.frame_style: FrameStyle = frame_style(config)
+        self.minimap_drag: int | None = None  # frame whose minimap is dragged with the left button
+        self.frame_labels: dict[str, int] | None = None  # shown by jump-to-frame until the next key
+        self.popup: Popup | None = None  # dismissed by the next key
+        self.notifications: Notifications = Notifications(config.notification_timeout_ms)
+        self.undo_view: tuple[Buffer, list[int]] | None = None  # buffer shown in *undo-tree*, seq per line
+        self.undo_origin: int = 0  # frame the undo tree was opened from
+        self.snippets: SnippetLibrary = SnippetLibrary()
+        self.lsp_clients: dict[tuple[str, str], LspClient] = {}  # (file type, project root)
+        self.project_configs: dict[str, EditorConfig] = {}  # project root -> config with its .led.toml merged over
+        self.abbrevs: Abbrevs = Abbrevs()
+        self.spell_checker: SpellChecker | None = None  # loaded on first use
+        self.spell_choices: tuple[int, int, int, int, list[str]] | None = None  # pad, row, start, end, suggestions in the popup
+        self.describing: list[str] | None = None  # keys typed after describe-key, until they make a sequence
+        self.snippet: SnippetSession | None = None
+        self.undo_store: UndoStore | None = UndoStore() if config.persistent_undo is True else None
+        self.swap: SwapStore | None = SwapStore() if config.swap_interval_ms > 0 else None
+        self.scratch: Buffer | None = None  # *scratch*, made by open_initial()
+        self.swap_versions: dict[str, int] = {}  # path -> undo version when the swap file was last brought up to date
+        self.swap_due: float = 0.0  # monotonic time of the next swap_sync()
+        self.sftp: Sftp = Sftp()
+        self.watcher: FileWatcher = FileWatcher(lambda path: self.results.put(("file-changed", lambda: self.file_changed(path))), config.file_watch_interval_ms)
+        self.modal: ModalEditor | None = None
+        if config.modal_editing is True:
+            self.modal = ModalEditor(self.editor, config.chord_timeout_ms)
+            self.modal.fallback = self.dispatch
+            self.modal.on_mode_change = lambda mode, previous: self.hooks.emit('mode-changed', mode=mode, previous=previous, pad_id=self.active_pad())
+            self.modal.on_jump = lambda _pad_id: self.jump_push()
+            self.modal.normal_keymap.bind(':', 'command-palette')
+        self.editor.on_message = self.message
+        self.editor.relative_numbers = config.relative_numbers
+        self.editor.status_format = config.status_format
+        self.register_commands()
+        self.user_command_depth: int = 0  # nesting of running user commands, to stop ones that call themselves
+        for name, command in config.commands.items():
+            self.commands.register(name, lambda msg, command=command: self.run_user_command(command, msg), command.doc or f"User command: {', '.join(c for c, _ in command.steps)}")
+        for keys, binding in config.keys.items():
+            cmd, _, msg = binding.strip().partition(" ")
+            self.keymap.bind(keys, cmd, msg)
+
+    def message(self, text: str):
+        """Show text in the echo area for message_timeout_ms"""
+        self.log.info(text)
+        self.minibuffer.show_message(text, self.config.message_timeout_ms / 1000.0)
+        self.messages_append(text)
+
+    def messages_buffer(self) -> Buffer:
+        buffer = self.editor.buffer_get("*Messages*")
+        if buffer is None:
+            buffer = self.editor.buffer_create("*Messages*", [""], undo=False)
+        return buffer
+
+    def messages_append(self, text: str):
+        """Keep a message in *Messages*; pads at its end follow"""
+        buffer = self.messages_buffer()
+        following = [i for i, pad in enumerate(self.editor.pads) if pad.buffer is buffer and self.editor.pad_cursor(i)[0] == len(buffer) - 1]
+        if buffer == [""]:
+            buffer.clear()
+        buffer += text.splitlines() or [""]
+        dropped = buffer[:max(0, len(buffer) - max(1, self.config.messages_max))]
+        del buffer[:len(dropped)]
+        if len(dropped) > 0:
+            self.editor.pads_follow_edit(buffer, 0, dropped, [])  # the others stay on their messages
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer:
+                row, col = self.editor.pad_cursor(i)
+                self.editor.pad_goto(i, len(buffer) - 1 if i in following else min(row, len(buffer) - 1), col if i not in following else 0)
+
+    def register_commands(self):
+        editor_commands = {
+            'nl': "Split the line at the cursor",
+            'bsp': "Delete the character before the cursor",
+            'up': "Move the cursor one line up",
+            'down': "Move the cursor one line down",
+            'left': "Move the cursor one character left",
+            'right': "Move the cursor one character right",
+            'home': "Move the cursor to the start of the line",
+            'end': "Move the cursor to the end of the line",
+            'PgUp': "Scroll one page up",
+            'PgDown': "Scroll one page down",
+            'Start': "Move the cursor to the start of the buffer",
+            'End': "Move the cursor to the end of the buffer",
+        }
+        for name, doc in editor_commands.items():
+            self.commands.register(name, lambda msg, cmd=name: self.editor_command(cmd, msg), doc)
+        self.commands.register('quit', self.cmd_quit, "Exit led")
+        self.commands.register('keyboard-quit', self.cmd_keyboard_quit, "Abort pending keys and the minibuffer")
+        self.commands.register('next-frame', self.cmd_next_frame, "Activate the next frame")
+        self.commands.register('select-frame', self.cmd_select_frame, "Activate the neighbor frame in direction msg (left, right, up, down)")
+        self.commands.register('jump-to-frame', self.cmd_jump_to_frame, "Label all frames and activate the one whose label is typed next")
+        self.commands.register('golden-ratio-mode', self.cmd_golden_ratio_mode, "Toggle enlarging the selected frame to a golden-ratio share")
+        self.commands.register('move-frame', self.cmd_move_frame, "Move the active frame beside its neighbor in direction msg, restructuring the splits")
+        self.commands.register('swap-frame', self.cmd_swap_frame, "Swap the active frame's content with the neighbor in direction msg")
+        self.commands.register('rotate-frames', self.cmd_rotate_frames, "Rotate the frame layout 90 degrees clockwise (msg ccw: counter-clockwise)")
+        self.commands.register('transpose-frames', self.cmd_transpose_frames, "Turn all side by side splits into stacked ones and vice versa")
+        self.commands.register('toggle-split-direction', self.cmd_toggle_split_direction, "Turn the split of the active frame from side by side to stacked or back")
+        self.commands.register('split', self.cmd_split, "Split the active frame (msg: horizontal or vertical)")
+        self.commands.register('split-horizontal', lambda _msg: self.cmd_split('horizontal'), "Split the active frame side by side")
+        self.commands.register('split-vertical', lambda _msg: self.cmd_split('vertical'), "Split the active frame into top and bottom")
+        self.commands.register('enlarge', lambda _msg: self.cmd_size(0.02), "Enlarge the active frame")
+        self.commands.register('shrink', lambda _msg: self.cmd_size(-0.02), "Shrink the active frame")
+        self.commands.register('delete-frame', self.cmd_delete_frame, "Close the active frame")
+        self.commands.register('new-frame', self.cmd_new_frame, "Open another top-level window with its own frames, showing the active buffer")
+        self.commands.register('close-window', self.cmd_close_window, "Close the focused top-level window, led ends with the last one")
+        self.commands.register('open-floating-frame', self.cmd_open_floating_frame, "Show the active buffer in a frame floating above the layout")
+        self.commands.register('close-floating-frame', self.cmd_close_floating_frame, "Close the active floating frame")
+        self.commands.register('delete-other-frames', self.cmd_delete_other_frames, "Close all frames but the active one")
+        self.commands.register('zoom-frame', self.cmd_zoom_frame, "Toggle showing only the active frame, restoring the layout afterwards")
+        self.commands.register('toggle-minimap', self.cmd_toggle_minimap, "Show or hide a miniature of the whole buffer at the right of the frames, click or drag it to scroll")
+        self.commands.register('follow-mode', self.cmd_follow_mode, "Toggle showing the active buffer as consecutive pages in the frames showing it, splitting off one if needed")
+        self.commands.register('set-frame-option', self.cmd_set_frame_option, "Override wrap, line-numbers, font-size (points) or follow in the active frame (msg: name on, off, size or default; or prompted)")
+        self.commands.register('toggle-scroll-lock', self.cmd_toggle_scroll_lock, "Add the active frame to the frames that scroll together line for line, or take it out")
+        self.commands.register('zen-mode', self.cmd_zen_mode, "Toggle distraction-free mode: only the active frame's text, centered, without gutter, status line and border")
+        self.commands.register('command-palette', self.cmd_command_palette, "Run any command by name, with fuzzy completion")
+        self.commands.register('workspace-new', self.cmd_workspace_new, "Open a workspace, a tab with its own frames, showing the active buffer")
+        self.commands.register('workspace-close', lambda _msg: self.workspace_delete(self.workspaces.current), "Close the workspace shown, its buffers stay open")
+        self.commands.register('workspace-delete', self.cmd_workspace_delete, "Delete a workspace, the one shown or another (msg or prompted); its buffers stay open")
+        self.commands.register('workspace-next', lambda _msg: self.workspace_switch((self.workspaces.current + 1) % len(self.workspaces)), "Show the workspace right of this one, wrapping around")
+        self.commands.register('workspace-previous', lambda _msg: self.workspace_switch((self.workspaces.current - 1) % len(self.workspaces)), "Show the workspace left of this one, wrapping around")
+        self.commands.register('workspace-switch', self.cmd_workspace_switch, "Show the workspace of that number or name (msg or prompted)")
+        self.commands.register('workspace-rename', self.cmd_workspace_rename, "Name the workspace shown (msg or prompted)")
+        self.commands.register('layout-write-file', self.cmd_layout_write_file, "Write the frame arrangement and the files shown to a layout file to share (msg or prompted)")
+        self.commands.register('layout-load-file', self.cmd_layout_load_file, "Arrange the frames as a layout file (JSON, or TOML for .toml) says, opening its files (msg or prompted)")
+        self.commands.register('layout-save', self.cmd_layout_save, "Store the frame arrangement under a name (msg or prompted)")
+        self.commands.register('layout-restore', self.cmd_layout_restore, "Restore a frame arrangement stored with layout-save")
+        self.commands.register('notifications', self.cmd_notifications, "List all notifications of background tasks, including dismissed ones")
+        self.commands.register('recover-files', self.cmd_recover_files, "Open a file with unsaved changes left by a crash, to recover them")
+        self.commands.register('show-log', self.cmd_show_log, "Show the recent log entries of this session, the full log is led.log in the state directory")
+        self.commands.register('play-sound', lambda msg: self.sound.play(msg), f"Play the sound configured for an event: {', '.join(sound_events)}")
+        self.commands.register('messages', self.cmd_messages, "Show the messages of the echo area so far")
+        self.commands.register('dismiss-notifications', lambda _msg: self.notifications.dismiss(), "Hide all notification toasts")
+        self.commands.register('revert-buffer', self.cmd_revert_buffer, "Reload the active buffer from its file")
+        self.commands.register('set-buffer-encoding', self.cmd_set_buffer_encoding, "Choose the encoding the active buffer is saved with (msg or prompted)")
+        self.commands.register('set-file-type', self.cmd_set_file_type, "Treat the active buffer as another file type (msg, e.g. py or python, or prompted)")
+        self.commands.register('set-line-ending', self.cmd_set_line_ending, "Convert the active buffer to LF, CRLF or CR line endings on save (msg or prompted)")
+        self.commands.register('recenter', lambda _msg: self.editor.pad_recenter(self.active_pad()) if self.active_pad() >= 0 else None, "Scroll so that the cursor line is in the middle of the frame")
+        self.commands.register('scroll-left', lambda _msg: self.cmd_scroll_sideways('left'), "Scroll the view half a frame width to the left")
+        self.commands.register('scroll-right', lambda _msg: self.cmd_scroll_sideways('right'), "Scroll the view half a frame width to the right")
+        self.commands.register('snippet-expand', self.cmd_snippet_expand, "Expand the snippet named by the word before the cursor; Tab moves between its fields")
+        self.commands.register('snippet-insert', self.cmd_snippet_insert, "Choose a snippet for the buffer's file type and insert it at the cursor")
+        self.commands.register('define-abbrev', self.cmd_define_abbrev, "Define the word before the cursor as abbrev for this file type (msg: global for all)")
+        self.commands.register('define-global-abbrev', lambda _msg: self.cmd_define_abbrev('global'), "Define the word before the cursor as abbrev for all file types")
+        self.commands.register('lsp-definition', self.cmd_lsp_definition, "Go to the definition of the symbol at the cursor, list them if there are several")
+        self.commands.register('lsp-references', self.cmd_lsp_references, "List the references to the symbol at the cursor")
+        self.commands.register('jump-back', self.cmd_jump_back, "Return to where the active frame was before its last jump: opening a file, a search result, a definition, the start or end")
+        self.commands.register('jump-forward', self.cmd_jump_forward, "Go forward again to where jump-back came from")
+        self.commands.register('set-mark', self.cmd_set_mark, "Push the cursor position onto the buffer's mark ring")
+        self.commands.register('pop-mark', self.cmd_pop_mark, "Go to the newest mark of the buffer, repeated it cycles back through the older ones")
+        self.commands.register('outline', self.cmd_outline, "Show the symbols of the buffer as a tree in a side frame")
+        self.commands.register('outline-toggle', self.cmd_outline_toggle, "Collapse or expand the outline entry at the cursor")
+        self.commands.register('outline-jump', self.cmd_outline_jump, "Go to the symbol at the cursor in the source frame (msg show: keep the outline active)")
+        self.commands.register('outline-quit', self.cmd_outline_quit, "Close the outline frame")
+        self.commands.register('next-hunk', self.cmd_next_hunk, "Go to the next change against the git index (msg: previous)")
+        self.commands.register('previous-hunk', lambda _msg: self.cmd_next_hunk('previous'), "Go to the previous change against the git index")
+        self.commands.register('revert-hunk', self.cmd_revert_hunk, "Replace the change at the cursor by the lines staged in git")
+        self.commands.register('diff-with-file', self.cmd_diff_with_file, "Show the active buffer and a file, by default its own on disk, side by side with the differences marked")
+        self.commands.register('diff-buffers', self.cmd_diff_buffers, "Show two buffers side by side with the differences marked")
+        self.commands.register('diff-next', self.cmd_diff_next, "Go to the next change of the diff view (msg: previous)")
+        self.commands.register('diff-quit', self.cmd_diff_quit, "Close the diff view (msg jump: show the source line at the cursor)")
+        self.commands.register('merge', self.cmd_merge, "Show ours and theirs of the conflicts in the buffer above it")
+        self.commands.register('merge-take', self.cmd_merge_take, "Resolve the conflict at the cursor (msg: ours, theirs, both or base)")
+        self.commands.register('merge-next', self.cmd_merge_next, "Go to the next conflict (msg: previous)")
+        self.commands.register('merge-quit', self.cmd_merge_quit, "Close the ours and theirs frames of the merge view")
+        self.commands.register('repl', self.cmd_repl, "Switch to the interpreter for the buffer's file type, starting it if needed")
+        self.commands.register('repl-send', self.cmd_repl_send, "Send the selection or the current line (msg: line, buffer) to the interpreter")
+        self.commands.register('repl-return', self.cmd_repl_return, "Send the input typed in the REPL buffer")
+        self.commands.register('shell-filter', self.cmd_shell_filter, "Replace the selection or the whole buffer by its output through a shell command")
+        self.commands.register('format-buffer', self.cmd_format_buffer, "Pipe the whole buffer through the format_command of its project or the config")
+        self.commands.register('build', self.cmd_build, "Run the build_command of the project in its root, the output goes to *build*")
+        self.commands.register('image-zoom', self.cmd_image_zoom, "Zoom the image (msg: in, out, fit, actual)")
+        self.commands.register('font-zoom', self.cmd_font_zoom, "Change the font size of the active frame (msg: in, out, reset)")
+        self.commands.register('image-pan', self.cmd_image_pan, "Move the view of the image (msg: left, right, up, down)")
+        self.commands.register('export-html', self.cmd_export_html, "Write the selected lines or the buffer as an HTML page in the theme's colors")
+        self.commands.register('dired', self.cmd_dired, "List a directory in an editable buffer")
+        self.commands.register('dired-open', self.cmd_dired_open, "Open the file or directory at the cursor")
+        self.commands.register('dired-mark', self.cmd_dired_mark, "Mark (msg: mark, unmark, none, toggle) entries for the next operation")
+        self.commands.register('dired-delete', self.cmd_dired_delete, "Delete the marked entries or the one at the cursor")
+        self.commands.register('dired-rename', self.cmd_dired_rename, "Rename or move the marked entries or the one at the cursor")
+        self.commands.register('dired-copy', self.cmd_dired_copy, "Copy the marked entries or the one at the cursor")
+        self.commands.register('dired-create', self.cmd_dired_create, "Create a directory (msg file: an empty file)")
+        self.commands.register('dired-refresh', self.cmd_dired_refresh, "Read the directory again")
+        self.commands.register('dired-edit', self.cmd_dired_edit, "Edit the names in the listing (msg: commit, abort)")
+        self.commands.register('collab-share', self.cmd_collab_share, "Share the active buffer with other led instances on [address:]port msg")
+        self.commands.register('collab-join', self.cmd_collab_join, "Edit the buffer shared at host:port/token msg")
+        self.commands.register('collab-leave', self.cmd_collab_leave, "Stop sharing or leave the shared buffer")
+        self.commands.register('collab-participants', self.cmd_collab_participants, "Show who else edits the shared buffer")
+        self.commands.register('next-diagnostic', self.cmd_next_diagnostic, "Go to the next diagnostic of the active buffer (msg: previous)")
+        self.commands.register('previous-diagnostic', lambda _msg: self.cmd_next_diagnostic('previous'), "Go to the previous diagnostic of the active buffer")
+        self.commands.register('list-diagnostics', self.cmd_list_diagnostics, "List the diagnostics of all buffers, errors first")
+        self.commands.register('lsp-complete', self.cmd_lsp_complete, "Complete the word at the cursor with the language server's suggestions")
+        self.commands.register('lsp-hover', self.cmd_lsp_hover, "Show what the language server knows about the symbol at the cursor")
+        self.commands.register('toggle-spell-check', self.cmd_toggle_spell_check, "Underline misspelled words, in code only within comments and strings")
+        self.commands.register('spell-correct', self.cmd_spell_correct, "Offer corrections for the word at the cursor, or add it to the personal dictionary")
+        self.commands.register('toggle-comment', self.cmd_toggle_comment, "Comment or uncomment the current line or the selected lines")
+        self.commands.register('sort-lines', self.cmd_sort_lines, "Sort the selected lines, or else all, by text (msg: numeric by leading number, case ignoring case)")
+        self.commands.register('sort-lines-numeric', lambda _msg: self.cmd_sort_lines('numeric'), "Sort the selected lines, or else all, by their leading number")
+        self.commands.register('sort-lines-case', lambda _msg: self.cmd_sort_lines('case'), "Sort the selected lines, or else all, ignoring case")
+        self.commands.register('reverse-lines', lambda _msg: self.transform_lines(lambda lines: lines[::-1]), "Reverse the order of the selected lines, or else of all")
+        self.commands.register('unique-lines', self.cmd_unique_lines, "Remove the later copies of repeated lines in the selection, or else the buffer")
+        self.commands.register('increment-number', self.cmd_increment_number, "Add msg (a count, 1 if none) to the number at or after the cursor: decimal, negative, zero-padded or 0x hex")
+        self.commands.register('decrement-number', lambda msg: self.cmd_increment_number(msg, -1), "Subtract msg (a count, 1 if none) from the number at or after the cursor")
+        self.commands.register('kill-rectangle', self.cmd_kill_rectangle, "Cut the columns between mark (or the visual selection's start) and cursor on their lines, for yank-rectangle")
+        self.commands.register('yank-rectangle', self.cmd_yank_rectangle, "Insert the last killed rectangle with its top left corner at the cursor")
+        self.commands.register('string-insert-rectangle', self.cmd_string_insert_rectangle, "Insert a string (msg or prompted) at the rectangle's left edge on each of its lines")
+        self.commands.register('open-rectangle', self.cmd_open_rectangle, "Shift the text of the rectangle right by its width, leaving it blank")
+        self.commands.register('narrow-to-region', self.cmd_narrow_to_region, "Show and edit only the selected lines, or those from the mark to the cursor, in the active frame")
+        self.commands.register('narrow-to-defun', self.cmd_narrow_to_defun, "Show and edit only the top-level definition around the cursor in the active frame")
+        self.commands.register('widen', self.cmd_widen, "Show the whole buffer again in a frame narrowed to part of it")
+        self.commands.register('tab-to-tab-stop', self.cmd_tab_to_tab_stop, "Indent to the next tab stop with a tab or spaces, per file type")
+        self.commands.register('toggle-whitespace', self.cmd_toggle_whitespace, "Show or hide spaces, tabs and trailing whitespace")
+        self.commands.register('toggle-relative-numbers', self.cmd_toggle_relative_numbers, "Count the line numbers from the cursor line, or show them absolute again")
+        self.commands.register('toggle-frame-stats', self.cmd_toggle_frame_stats, "Show or hide frames per second, time per stage and draw calls")
+        self.commands.register('hex-mode', self.cmd_hex_mode, "Toggle a hex view of the active buffer; files with NUL bytes open in it")
+        self.commands.register('save-buffer', self.cmd_save_buffer, "Write the active buffer to its file, asking for a name if it has none")
+        self.commands.register('undo', self.cmd_undo, "Undo the last change of the active buffer")
+        self.commands.register('redo', self.cmd_redo, "Redo the change undone last, following the most recent branch")
+        self.commands.register('undo-tree', self.cmd_undo_tree, "Browse the undo history of the active buffer in a frame next to it")
+        self.commands.register('undo-tree-move', self.cmd_undo_tree_move, "Go to the previous (msg: up) or next (msg: down) state in the undo tree")
+        self.commands.register('undo-tree-quit', self.cmd_undo_tree_quit, "Close the undo tree, keeping the selected state")
+        self.commands.register('describe-char', self.cmd_describe_char, "Show code point and name of the character at the cursor")
+        self.commands.register('describe-key', self.cmd_describe_key, "Show which command the next key sequence runs, without running it")
+        self.commands.register('describe-command', self.cmd_describe_command, "Show the documentation and key bindings of a command (msg or chosen)")
+        self.commands.register('key-cheat-sheet', self.cmd_key_cheat_sheet, "List the keys of the active frame by category in a frame below, filtered as you type")
+        self.commands.register('switch-buffer', self.cmd_switch_buffer, "Show another buffer (msg or chosen), like *scratch*, in the active frame")
+        self.commands.register('find-file', self.cmd_find_file, "Open a file (msg or prompted) in the active frame")
+        self.commands.register('find-file-read-only', self.cmd_find_file_read_only, "Open a file (msg or prompted) in the active frame, rejecting edits")
+        self.commands.register('toggle-read-only', self.cmd_toggle_read_only, "Reject or allow edits of the buffer")
+        self.commands.register('recent-files', self.cmd_recent_files, "Reopen a recently opened file, with fuzzy completion")
+        self.commands.register('bookmark-set', self.cmd_bookmark_set, "Remember the cursor position in the file under a name (msg or prompted), kept across sessions")
+        self.commands.register('bookmark-jump', self.cmd_bookmark_jump, "Go to a bookmark (msg, the one at the cursor in *Bookmarks* or chosen)")
+        self.commands.register('bookmark-list', self.cmd_bookmark_list, "List the bookmarks in *Bookmarks* to jump to, delete or rename them")
+        self.commands.register('bookmark-delete', self.cmd_bookmark_delete, "Forget a bookmark (msg, the one at the cursor in *Bookmarks* or chosen)")
+        self.commands.register('bookmark-rename', self.cmd_bookmark_rename, "Give a bookmark (the one at the cursor in *Bookmarks* or chosen) another name")
+        self.commands.register('grep', self.cmd_grep, "Search the project for a string (msg or prompted)")
+        self.commands.register('grep-jump', self.cmd_grep_jump, "Show the file and line of the search result at the cursor")
+
+    def active_pad(self) -> int:
+        active = self.frames.active()
+        if active is None:
+            return -1
+        return active.pad_id
+
+    def window_focus(self, os_window: OsWindow):
+        """Make os_window the one whose frames commands and keys act on"""
+        current = self.focused
+        current.frames, current.frame_ids, current.zoomed, current.zen = self.frames, self.frame_ids, self.zoomed, self.zen
+        current.workspaces = self.workspaces
+        self.focused = os_window
+        self.window, self.renderer, self.frame_renderer = os_window.window, os_window.renderer, os_window.frame_renderer
+        self.frames, self.frame_ids, self.zoomed, self.zen = os_window.frames, os_window.frame_ids, os_window.zoomed, os_window.zen
+        self.workspaces = os_window.workspaces
+
+    def in_window(self, os_window: OsWindow, fn: Callable[[], None]):
+        """Run fn as if os_window were focused, then focus the previous one again"""
+        focused = self.focused
+        self.window_focus(os_window)
         try:
-            with open(filename, 'r') as file:
-                lines = file.read()
-            content_type = self.get_file_type(filename)
-            self.cell_arrays[filename] = self.tokenize(lines, content_type)
+            fn()
+        finally:
+            self.window_focus(focused)
+
+    def window_by_id(self, window_id: int) -> OsWindow | None:
+        return next((os_window for os_window in self.os_windows if os_window.window_id == window_id), None)
+
+    def cmd_new_frame(self, _msg: str):
+        """Open another top-level window with its own frames, showing the active buffer, e.g. for a second screen"""
+        if self.open_window is None:
+            self.message("Cannot open another window here")
+            return
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        buffer = pad.buffer if pad is not None else self.editor.buffer_untitled()
+        row, col = self.editor.pad_cursor(pad_id) if pad is not None else (0, 0)
+        window, renderer, frame_renderer = self.open_window()
+        os_window = OsWindow(window, renderer, frame_renderer, window_id=sdl_window_id(window))
+        root = os_window.frames.active()
+        if root is not None:
+            root.pad_id = self.editor.create_editor(buffer, 1, line_no=True, status_line=True)
+        self.os_windows.append(os_window)
+        self.window_focus(os_window)
+        self.relayout()
+        if root is not None:
+            self.editor.pad_goto(root.pad_id, row, col)
+        self.redraw = True
+
+    def close_window(self, os_window: OsWindow):
+        """Close a top-level window and its frames, the buffers stay open; closing the last one ends led"""
+        if len(self.os_windows) == 1:
+            self.running = False
+            return
+        if os_window is self.focused:
+            self.window_focus(next(other for other in self.os_windows if other is not os_window))
+        self.os_windows.remove(os_window)
+        os_window.renderer.destroy()
+        os_window.window.close()
+        self.redraw = True
+
+    def cmd_close_window(self, _msg: str):
+        self.close_window(self.focused)
+
+    def window_size(self) -> tuple[int, int]:
+        """Size in drawing units, mouse events arrive in these too once the renderer's logical size is set to it"""
+        w, h = cast(tuple[int,int], self.window.size)
+        return self.frame_renderer.logical_size(w, h)
+
+    def minibuffer_height(self) -> int:
+        return self.frame_renderer.line_height() + 2 * frame_inset
+
+    def tab_bar_height(self) -> int:
+        """The tab bar's, 0 while it is hidden: with a single workspace, in zen-mode or by the config"""
+        if self.config.tab_bar is False or len(self.workspaces) < 2 or self.zen is not None:
+            return 0
+        return self.frame_renderer.line_height() + 2 * frame_inset
+
+    def relayout(self):
+        start = time.monotonic()
+        wx, hy = self.window_size()
+        self.frames.style = self.frame_renderer.style = self.frame_style
+        x, w = 0, wx
+        active = self.frames.active()
+        if self.zen is not None and active is not None:
+            w = min(wx, self.config.zen_width * self.frame_renderer.cell_size(active.zoom)[0] + 2 * self.frame_style.inset())
+            x = (wx - w) // 2
+            self.zen_hide_borders()
+        top = self.tab_bar_height()
+        self.frames.geometry(x, top, w, hy - self.minibuffer_height() - top)
+        self.frames.keep_floats_inside(0, top, wx, hy - self.minibuffer_height() - top)
+        self.editor.sync_pads(self.frames, self.frame_renderer.cell_size, self.zen is None)
+        leaves = {fr.id for fr in self.frames.win_frames()[0]}
+        for id in sorted(leaves - self.frame_ids):
+            self.hooks.emit('frame-created', frame_id=id)
+        self.frame_ids = leaves
+        if self.stats is not None:
+            self.stats.add('layout', time.monotonic() - start)
+
+    def cmd_toggle_frame_stats(self, _msg: str):
+        if self.stats is None:
+            self.stats = FrameStats()
+        else:
+            self.stats = None
+        self.redraw = True
+
+    def open_initial(self, filename: str | None):
+        if filename is not None and os.path.isdir(filename):
+            buffer = self.dired_buffer(filename)
+        elif filename is not None and parse_remote(filename) is not None:
+            buffer = self.editor.buffer_untitled()
+            self.open_remote(filename)
+        elif filename is not None:
+            try:
+                buffer = self.editor.buffer_open(filename)
+                self.recent_files.add(filename)
+                self.buffer_opened(buffer)
+            except EditorError as e:
+                self.message(str(e))
+                buffer = self.editor.buffer_untitled()
+        else:
+            buffer = self.editor.buffer_untitled()
+        self.scratch = self.scratch_create()
+        root = self.frames.active()
+        if root is not None:
+            root.pad_id = self.editor.create_editor(buffer, 1, line_no=True, status_line=True)
+        self.relayout()
+        if buffer.dired is not None and root is not None:
+            self.editor.pad_goto(root.pad_id, min(2, len(buffer) - 1), dired_name_col)
+        left = [path for path in self.swap.leftovers() if path != buffer.filename] if self.swap is not None else []
+        if len(left) > 0:
+            self.notifications.post("swap", f"Unsaved changes of {len(left)} files survived a crash, M-x recover-files opens them", "warning")
+
+    def run_user_command(self, command: UserCommand, msg: str):
+        """Run the steps in order, a step's msg {} is replaced by msg; steps that ask in the minibuffer don't wait for the answer"""
+        if self.user_command_depth >= 16:
+            self.message("User commands nested too deep, does one call itself?")
+            return
+        self.user_command_depth += 1
+        try:
+            for cmd, step_msg in command.steps:
+                if self.commands.run(cmd, step_msg.replace("{}", msg)) is False:
+                    self.message(f"Unknown command {cmd}, stopped")
+                    break
+        finally:
+            self.user_command_depth -= 1
+
+    def dispatch(self, cmd: str, msg: str = ""):
+        if self.commands.run(cmd, msg) is False:
+            self.message(f"Unknown command {cmd}")
+
+    def editor_command(self, cmd: str, msg: str):
+        pad_id = self.active_pad()
+        if pad_id >= 0:
+            if cmd in ('Start', 'End'):
+                self.jump_push()
+            self.editor.editor_event(pad_id, cmd, msg)
+
+    def cmd_quit(self, _msg: str):
+        self.running = False
+
+    def scratch_create(self) -> Buffer:
+        """*scratch* for notes that belong to no file, with the text of the last session if scratch_persist is set"""
+        lines = [""]
+        path = state_path("scratch.txt")
+        if os.path.exists(path) is False:
+            path = cache_path("scratch.txt")  # where earlier versions kept it
+        if self.config.scratch_persist is True and os.path.exists(path):
+            try:
+                lines = read_lines(path)
+            except EditorError as e:
+                self.message(str(e))
+        return self.editor.buffer_create("*scratch*", lines)
+
+    def scratch_save(self):
+        if self.scratch is None or self.config.scratch_persist is False:
+            return
+        path = state_path("scratch.txt")
+        try:
+            os.makedirs(os.path.dirname(path), exist_ok=True)
+            with open(path, 'w', encoding='utf-8') as file:
+                _ = file.write("\n".join(self.scratch) + "\n")
+        except OSError as e:
+            self.log.error(f"Cannot write {path}: {e}")
+
+    def cmd_switch_buffer(self, msg: str):
+        """Show a buffer (msg or chosen) in the active frame, the one shown there offered last"""
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        if msg == "":
+            def annotate(name: str) -> str:
+                buffer = self.editor.buffer_get(name)
+                return abbreviate_home(buffer.filename) if buffer is not None and buffer.filename is not None else ""
+
+            names = [b.name for b in self.editor.buffers if b is not pad.buffer] + [pad.buffer.name]
+            self.minibuffer.start("Switch to buffer: ", names, lambda name: self.cmd_switch_buffer(name), annotate)
+            return
+        buffer = self.editor.buffer_get(msg)
+        if buffer is None:
+            self.message(f"No buffer {msg}")
+            return
+        self.jump_push()
+        self.editor.pad_show(self.active_pad(), buffer)
+
+    def cmd_save_buffer(self, _msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        buffer = pad.buffer
+        if buffer.narrowing is not None:
+            self.narrow_sync(buffer, False)
+            buffer = buffer.narrowing.base
+        if buffer.image is not None:
+            self.message("Images are shown read-only")
+            return
+        if buffer is self.scratch:
+            self.message("*scratch* belongs to no file, scratch_persist keeps it between sessions")
+            return
+        if buffer.filename is None:
+            self.minibuffer.start("Save as: ", [], lambda filename: self.save_buffer_as(buffer, filename) if filename != "" else None, require_match=False)
+            return
+        self.save_buffer_as(buffer, buffer.filename)
+
+    def save_buffer_as(self, buffer: Buffer, filename: str):
+        remote = parse_remote(filename)
+        path = filename if remote is not None else os.path.abspath(os.path.expanduser(filename))
+        self.hooks.emit('before-save', buffer=buffer, filename=path)
+        try:
+            if buffer.hex is True:
+                data = hex_parse(buffer)
+            else:
+                data = (buffer.newline.join(buffer) + buffer.newline).encode(buffer.encoding)
+        except UnicodeEncodeError as e:
+            self.message(f"Cannot save as {buffer.encoding}, {e.object[e.start:e.end]!r} has no encoding; try set-buffer-encoding utf-8")
+            return
+        if remote is not None:
+            self.save_remote(buffer, path, remote, data)
+            return
+        try:
+            with open(path, 'wb') as file:
+                _ = file.write(data)
+        except OSError as e:
+            self.message(f"Cannot save {path}: {e}")
+            return
+        if buffer.filename != path:
+            if buffer.filename is not None:
+                self.watcher.unwatch(buffer.filename)
+            buffer.filename = path
+            buffer.remote = None
+            buffer.directory = os.path.dirname(path)
+            self.recent_files.add(path)
+        buffer.disk_hash = content_hash(buffer)
+        buffer.changed_on_disk = False
+        self.watcher.watch(path)
+        self.redisplay_buffer(buffer)
+        if os.path.basename(path) == project_config_name:
+            _ = self.project_configs.pop(os.path.dirname(path), None)  # files opened from now on get the new settings
+        if self.swap is not None and buffer.undo is not None:
+            self.swap.remove(path)
+            self.swap_versions[path] = buffer.undo.version
+        if self.undo_store is not None:
+            self.undo_store.save(buffer)
+        if buffer.lsp is not None:
+            self.lsp_sync()
+            buffer.lsp.notify('textDocument/didSave', {'textDocument': {'uri': path_uri(path)}})
+        self.git_fetch(buffer)
+        self.hooks.emit('after-save', buffer=buffer, filename=path)
+        self.message(f"Wrote {path}")
+
+    def open_remote(self, url: str, row: int = 0, col: int = 0):
+        """Fetch ssh://host/path in the background and show it in the active frame"""
+        remote = parse_remote(url)
+        if remote is None:
+            self.message(f"Cannot parse {url}, expected ssh://[user@]host[:port]/path")
+            return
+        buffer = next((b for b in self.editor.buffers if b.filename == url), None)
+        if buffer is not None:
+            self.editor.pad_show(self.active_pad(), buffer, row, col)
+            return
+        if shutil.which("sftp") is None:
+            self.message("Remote files need the sftp command")
+            return
+        self.notifications.post("sftp", f"Fetching {url}")
+
+        def fetch():
+            data, error = None, ""
+            try:
+                data = self.sftp.read(remote)
+            except FileNotFoundError:
+                error = "new"
+            except OSError as e:
+                error = str(e)
+            self.results.put(("sftp", lambda: self.remote_fetched(url, data, error, row, col)))
+
+        threading.Thread(target=fetch, daemon=True).start()
+
+    def remote_fetched(self, url: str, data: bytes | None, error: str, row: int, col: int):
+        if data is None and error != "new":
+            self.notifications.post("sftp", f"Cannot read {url}: {error}", "error")
+            return
+        buffer = next((b for b in self.editor.buffers if b.filename == url), None)
+        if buffer is None:
+            name = os.path.basename(url.rstrip("/"))
+            buffer = self.editor.buffer_decode(data, name, url) if data is not None else self.editor.buffer_create(name, [""], url)
+            buffer.filetype = filetype_detect(self.config, url, list(buffer))
+            buffer.settings = filetype_settings(self.config, buffer.filetype)
+            self.recent_files.add(url)
+            self.hooks.emit('buffer-opened', buffer=buffer)
+        self.editor.pad_show(self.active_pad(), buffer, row, col)
+        self.message(f"{url} is a new file" if data is None else f"Read {url}, {len(data)} bytes")
+
+    def save_remote(self, buffer: Buffer, url: str, remote: RemotePath, data: bytes):
+        """Upload in the background; the buffer counts as saved with the content it had when saving started"""
+        saved_hash = content_hash(buffer)
+        self.notifications.post("sftp", f"Writing {url}")
+
+        def upload():
+            error = ""
+            try:
+                self.sftp.write(remote, data)
+            except OSError as e:
+                error = str(e)
+            self.results.put(("sftp", lambda: self.remote_saved(buffer, url, remote, saved_hash, error)))
+
+        threading.Thread(target=upload, daemon=True).start()
+
+    def remote_saved(self, buffer: Buffer, url: str, remote: RemotePath, saved_hash: str, error: str):
+        if error != "":
+            self.notifications.post("sftp", f"Cannot write {url}: {error}", "error")
+            return
+        if buffer.filename != url:
+            if buffer.filename is not None and buffer.remote is None:
+                self.watcher.unwatch(buffer.filename)
+            buffer.filename = url
+            buffer.remote = remote
+            buffer.directory = os.getcwd()
+            self.recent_files.add(url)
+        buffer.disk_hash = saved_hash
+        buffer.changed_on_disk = False
+        self.redisplay_buffer(buffer)
+        self.hooks.emit('after-save', buffer=buffer, filename=url)
+        self.message(f"Wrote {url}")
+
+    def cmd_set_buffer_encoding(self, msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        if msg == "":
+            self.minibuffer.start(f"Encoding (now {pad.buffer.encoding}): ", ['utf-8', 'utf-8-sig', 'utf-16', 'cp1252', 'latin-1'], self.cmd_set_buffer_encoding, require_match=False)
+            return
+        try:
+            _ = codecs.lookup(msg)
+        except LookupError:
+            self.message(f"Unknown encoding {msg}")
+            return
+        pad.buffer.encoding = msg
+        self.redisplay_buffer(pad.buffer)
+        self.message(f"{pad.buffer.name} will be saved as {msg}")
+
+    def cmd_set_file_type(self, msg: str):
+        """The buffer's indentation, comments, ruler, language server and REPL follow the new type"""
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        buffer = pad.buffer
+        config = self.buffer_config(buffer)
+        if msg == "":
+            names = sorted(set(config.filetypes) | set(language_filetypes.values()))
+            self.minibuffer.start(f"File type (now {buffer.filetype or 'none'}): ", names, self.cmd_set_file_type, require_match=False)
+            return
+        filetype = language_filetype(config, msg)
+        filetype = filetype if filetype != "" else msg
+        if buffer.lsp is not None and filetype != buffer.filetype and buffer.filename is not None:
+            buffer.lsp.notify('textDocument/didClose', {'textDocument': {'uri': path_uri(buffer.filename)}})
+            buffer.lsp = None
+            buffer.diagnostics = []
+        buffer.filetype = filetype
+        buffer.settings = filetype_settings(config, filetype)
+        self.lsp_attach(buffer)
+        self.redisplay_buffer(buffer)
+        self.message(f"{buffer.name} is now {filetype}{'' if filetype in config.filetypes else ', with default settings'}")
+
+    def cmd_hex_mode(self, _msg: str):
+        """Toggle between the text and a hex dump of the bytes it is saved as"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        buffer = pad.buffer
+        if buffer.hex is False:
+            try:
+                data = (buffer.newline.join(buffer) + buffer.newline).encode(buffer.encoding)
+            except UnicodeEncodeError as e:
+                self.message(f"Cannot encode as {buffer.encoding}: {e}")
+                return
+            buffer[:] = hex_lines(data)
+            buffer.hex = True
+        else:
+            text, buffer.encoding = decode_text(hex_parse(buffer))
+            buffer.newline = detect_newline(text)
+            lines = text.splitlines()
+            buffer[:] = lines if len(lines) > 0 else [""]
+            buffer.hex = False
+        if buffer.undo is not None:
+            buffer.undo.record(buffer, (0, 0), (0, 0))
+        self.redisplay_buffer(buffer)
+
+    def hex_key(self, pad_id: int, key: str) -> bool:
+        """Overwrite the byte at the cursor, as hex digit in the hex columns or as character in the ASCII column"""
+        pad = self.editor.pads[pad_id]
+        row, col = self.editor.pad_cursor(pad_id)
+        line = pad.buffer[row]
+        count = len(line) - hex_ascii_col if len(line) > hex_ascii_col else 0
+        if key == 'Backspace':
+            self.editor.pad_goto(pad_id, row, col - 1)
+            return True
+        if key == 'Return':
+            self.editor.pad_goto(pad_id, row + 1, hex_col(0))
+            return True
+        text = key_text(key)
+        if text is None:
+            return False
+        if col >= hex_ascii_col:
+            i = col - hex_ascii_col
+            if i >= count or ord(text) > 255:
+                return True
+            value, next_col = ord(text), col + 1
+        else:
+            i = next((i for i in range(count) if col <= hex_col(i) + 1), count)
+            if i >= count or text not in string.hexdigits:
+                return True
+            nibble = max(0, col - hex_col(i))
+            digits = line[hex_col(i):hex_col(i)+2]
+            digits = digits[:nibble] + text.lower() + digits[nibble+1:]
+            value = int(digits, 16)
+            next_col = hex_col(i) + 1 if nibble == 0 else hex_col(i + 1)
+        chars = list(line)
+        chars[hex_col(i):hex_col(i)+2] = f"{value:02x}"
+        chars[hex_ascii_col + i] = chr(value) if 32 <= value < 127 else "."
+        pad.buffer[row] = "".join(chars)
+        if next_col >= hex_col(count) and next_col < hex_ascii_col:
+            row, next_col = row + 1, hex_col(0)
+        elif next_col >= hex_ascii_col + count and col >= hex_ascii_col:
+            row, next_col = row + 1, hex_ascii_col
+        self.editor.pad_goto(pad_id, min(row, len(pad.buffer) - 1), next_col)
+        return True
+
+    def snippet_expand(self, pad_id: int, snippet: Snippet, start: tuple[int, int], end: tuple[int, int]):
+        """Replace the text from start to end by the snippet, indenting its lines like the first one"""
+        self.editor.text_delete(pad_id, start, end)
+        row, col = start
+        line = self.editor.pads[pad_id].buffer[row]
+        indent = line[:len(line) - len(line.lstrip())]
+        lines, fields = parse_snippet(snippet.body)
+        _ = self.editor.text_insert(pad_id, start, "\n".join([lines[0]] + [indent + l for l in lines[1:]]))
+        spans: dict[int, list[list[int]]] = {}
+        for num, occurrences in fields.items():
+            spans[num] = [[row + r, (col if r == 0 else len(indent)) + s, (col if r == 0 else len(indent)) + e] for r, s, e in occurrences]
+        order = sorted(n for n in spans if n != 0) + ([0] if 0 in spans else [])
+        if len(order) == 0:
+            last = lines[-1]
+            self.editor.pad_goto(pad_id, row + len(lines) - 1, (col if len(lines) == 1 else len(indent)) + len(last))
+            return
+        self.snippet = SnippetSession(self.editor.pads[pad_id].buffer, order, spans)
+        self.snippet_field(pad_id, 0)
+
+    def snippet_field(self, pad_id: int, index: int):
+        session = self.snippet
+        if session is None:
+            return
+        if index < 0 or index >= len(session.order):
+            self.snippet = None
+            return
+        session.index = index
+        row, start, end = session.spans[session.order[index]][0]
+        session.pending = end > start
+        self.editor.pad_goto(pad_id, row, start)
+        if session.order[index] == 0:
+            self.snippet = None  # the final position ends the session
+
+    def snippet_shift(self, row: int, from_col: int, delta: int, skip: list[int]):
+        if self.snippet is None:
+            return
+        for occurrences in self.snippet.spans.values():
+            for span in occurrences:
+                if span is not skip and span[0] == row and span[1] >= from_col:
+                    span[1] += delta
+                    span[2] += delta
+
+    def snippet_mirror(self):
+        session = self.snippet
+        if session is None:
+            return
+        occurrences = session.spans[session.order[session.index]]
+        row, start, end = occurrences[0]
+        text = session.buffer[row][start:end]
+        for span in occurrences[1:]:
+            line = session.buffer[span[0]]
+            delta = len(text) - (span[2] - span[1])
+            session.buffer[span[0]] = line[:span[1]] + text + line[span[2]:]
+            self.snippet_shift(span[0], span[2], delta, span)
+            span[2] += delta
+
+    def snippet_clear_field(self, pad_id: int):
+        """Drop the placeholder text before the first character typed into a field"""
+        session = self.snippet
+        if session is None:
+            return
+        span = session.spans[session.order[session.index]][0]
+        row, start, end = span
+        line = session.buffer[row]
+        session.buffer[row] = line[:start] + line[end:]
+        self.snippet_shift(row, end, start - end, span)
+        span[2] = start
+        session.pending = False
+        self.snippet_mirror()
+        self.editor.pad_goto(pad_id, row, start)
+
+    def snippet_track(self, pad_id: int, row_before: int, len_before: int, count_before: int):
+        """Follow an edit inside the current field and update its mirrors; leaving the field ends the session"""
+        session = self.snippet
+        if session is None:
+            return
+        pad = self.editor.pad_get(pad_id)
+        row, col = self.editor.pad_cursor(pad_id)
+        if pad is None or pad.buffer is not session.buffer or len(session.buffer) != count_before:
+            self.snippet = None
+            return
+        span = session.spans[session.order[session.index]][0]
+        if row != span[0] or row_before != row:
+            if row != span[0]:
+                self.snippet = None
+            return
+        delta = len(session.buffer[row]) - len_before
+        if col < span[1] or col > span[2] + delta:
+            self.snippet = None
+            return
+        if delta != 0:
+            session.pending = False
+            self.snippet_shift(row, span[2], delta, span)
+            span[2] += delta
+            self.snippet_mirror()
+
+    def cmd_snippet_expand(self, _msg: str):
+        """Expand the snippet whose trigger is the word before the cursor"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        match = re.search(r'\w+$', pad.buffer[row][:col])
+        snippets = self.snippets.snippets(pad.buffer.filetype)
+        if match is None or match.group(0) not in snippets:
+            self.message("No snippet to expand here")
+            return
+        self.snippet_expand(pad_id, snippets[match.group(0)], (row, match.start()), (row, col))
+
+    def cmd_snippet_insert(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        snippets = self.snippets.snippets(pad.buffer.filetype)
+        if len(snippets) == 0:
+            self.message(f"No snippets for {pad.buffer.filetype or 'this buffer'} in {self.snippets.directory}")
+            return
+        cursor = self.editor.pad_cursor(pad_id)
+        self.minibuffer.start("Snippet: ", sorted(snippets.keys()), lambda trigger: self.snippet_expand(pad_id, snippets[trigger], cursor, cursor), annotate=lambda trigger: snippets[trigger].description)
+
+    def word_before_cursor(self, pad_id: int) -> tuple[str, int] | None:
+        row, col = self.editor.pad_cursor(pad_id)
+        match = re.search(r'\w+$', self.editor.pads[pad_id].buffer[row][:col])
+        if match is None:
+            return None
+        return match.group(0), match.start()
+
+    def expand_abbrev(self, pad_id: int):
+        buffer = self.editor.pads[pad_id].buffer
+        if buffer.hex is True:
+            return
+        word = self.word_before_cursor(pad_id)
+        if word is None:
+            return
+        expansion = self.abbrevs.lookup(buffer.filetype, word[0])
+        if expansion is None:
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        line = buffer[row]
+        indent = line[:len(line) - len(line.lstrip())]
+        self.editor.text_delete(pad_id, (row, word[1]), (row, col))
+        end = self.editor.text_insert(pad_id, (row, word[1]), expansion.replace("\n", "\n" + indent))
+        self.editor.pad_goto(pad_id, *end)
+
+    def cmd_define_abbrev(self, msg: str):
+        """Define the word before the cursor as abbrev for the buffer's file type (msg: global for all types)"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        word = self.word_before_cursor(pad_id)
+        if word is None:
+            self.message("No word before the cursor")
+            return
+        filetype = 'all' if msg == 'global' or pad.buffer.filetype == "" else pad.buffer.filetype
+        def define(expansion: str):
+            if expansion == "":
+                return
+            self.abbrevs.define(filetype, word[0], expansion.replace("\\n", "\n"))
+            self.message(f"{word[0]} now expands to {expansion} ({'all file types' if filetype == 'all' else filetype})")
+        self.minibuffer.start(f"Expansion of {word[0]} (\\n for new lines): ", [], define, require_match=False)
+
+    def load_spell_checker(self) -> SpellChecker:
+        if self.spell_checker is None:
+            self.spell_checker = SpellChecker(self.config.spell_dictionary)
+            if self.spell_checker.has_word_list is False:
+                self.message(f"No word list at {self.config.spell_dictionary}, set spell_dictionary in the config")
+        return self.spell_checker
+
+    def cmd_toggle_spell_check(self, _msg: str):
+        if self.editor.spell is not None:
+            self.editor.spell = None
+            self.message("Spell checking off")
+            return
+        self.editor.spell = self.load_spell_checker()
+        if self.spell_checker is not None and self.spell_checker.has_word_list is True:
+            self.message("Spell checking on")
+
+    def cmd_spell_correct(self, _msg: str):
+        """Popup with corrections for the word at the cursor: a digit picks one, a adds the word to the dictionary"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        line = pad.buffer[row]
+        match = next((m for m in spell_word_re.finditer(line) if m.start() <= col <= m.end()), None)
+        if match is None:
+            self.message("No word at the cursor")
+            return
+        checker = self.load_spell_checker()
+        word = match.group(0)
+        if checker.known(word) is True:
+            self.message(f"{word} is spelled correctly")
+            return
+        choices = checker.suggestions(word)
+        self.spell_choices = (pad_id, row, match.start(), match.end(), choices)
+        lines = [f"{i + 1} {c}" for i, c in enumerate(choices)] + [f"a add {word} to dictionary"]
+        self.show_popup(lines, pad_id, (row, match.start()))
+
+    def spell_choose(self, key: str):
+        if self.spell_choices is None:
+            return
+        pad_id, row, start, end, choices = self.spell_choices
+        self.spell_choices = None
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        if key == 'a':
+            self.load_spell_checker().add(pad.buffer[row][start:end])
+            return
+        if key.isdigit() and 1 <= int(key) <= len(choices):
+            self.editor.text_delete(pad_id, (row, start), (row, end))
+            self.editor.pad_goto(pad_id, *self.editor.text_insert(pad_id, (row, start), choices[int(key) - 1]))
+
+    def cmd_toggle_comment(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        settings = pad.buffer.settings
+        if settings.comment == "":
+            self.message(f"No comment syntax known for {pad.buffer.name}")
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        sel = pad.selection()
+        first, last = (sel[0][0], sel[1][0]) if sel is not None else (row, row)
+        pad.buffer[first:last+1] = toggle_comment(pad.buffer[first:last+1], settings.comment, settings.comment_end)
+        if sel is not None and self.modal is not None:
+            self.modal.set_mode(Mode.NORMAL, pad_id)
+        self.editor.pad_goto(pad_id, row, col)
+
+    def transform_lines(self, fn: Callable[[list[str]], list[str]]) -> int:
+        """Replace the selected lines, or else all of the buffer, by fn of them; returns by how many lines that is shorter"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return 0
+        row, col = self.editor.pad_cursor(pad_id)
+        sel = pad.selection()
+        first, last = (sel[0][0], sel[1][0]) if sel is not None else (0, len(pad.buffer) - 1)
+        lines = fn(pad.buffer[first:last+1])
+        removed = last + 1 - first - len(lines)
+        pad.buffer[first:last+1] = lines
+        if len(pad.buffer) == 0:
+            pad.buffer.append("")
+        if sel is not None and self.modal is not None:
+            self.modal.set_mode(Mode.NORMAL, pad_id)
+        self.editor.pad_goto(pad_id, row, col)
+        return removed
+
+    def cmd_sort_lines(self, msg: str):
+        if msg not in ('', 'numeric', 'case'):
+            self.message(f"Unknown sort order {msg}, use numeric or case")
+            return
+        _ = self.transform_lines(lambda lines: sort_lines(lines, msg))
+
+    def cmd_unique_lines(self, _msg: str):
+        removed = self.transform_lines(unique_lines)
+        self.message(f"Removed {removed} duplicate line{'s' if removed != 1 else ''}")
+
+    def cmd_increment_number(self, msg: str, sign: int = 1):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        if msg != "" and msg.isdigit() is False:
+            self.message(f"Not a count: {msg}")
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        result = increment_number(pad.buffer[row], col, sign * (int(msg) if msg != "" else 1))
+        if result is None:
+            self.message("No number at or after the cursor")
+            return
+        pad.buffer[row] = result[0]
+        self.editor.pad_goto(pad_id, row, result[1])
+
+    def rectangle_bounds(self, pad_id: int) -> tuple[int, int, int, int] | None:
+        """First and last row, left and right column of the rectangle between the cursor and the visual selection's start or else the mark"""
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return None
+        corner = pad.sel_anchor if pad.sel_anchor is not None else pad.buffer.marks.marks[-1] if len(pad.buffer.marks.marks) > 0 else None
+        if corner is None:
+            self.message("No mark set, C-SPC sets the rectangle's other corner")
+            return None
+        row, col = self.editor.pad_cursor(pad_id)
+        if pad.sel_anchor is not None and self.modal is not None:
+            self.modal.set_mode(Mode.NORMAL, pad_id)
+        last = len(pad.buffer) - 1  # the mark may be from before lines were deleted
+        r1, r2 = min(row, corner[0], last), min(max(row, corner[0]), last)
+        return (r1, r2, min(col, corner[1]), max(col, corner[1]))
+
+    def cmd_kill_rectangle(self, _msg: str):
+        pad_id = self.active_pad()
+        bounds = self.rectangle_bounds(pad_id)
+        if bounds is None:
+            return
+        r1, r2, c1, c2 = bounds
+        buffer = self.editor.pads[pad_id].buffer
+        buffer[r1:r2 + 1], self.editor.rectangle = kill_rectangle(buffer[r1:r2 + 1], c1, c2)
+        self.editor.pad_goto(pad_id, r1, c1)
+
+    def cmd_yank_rectangle(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        if len(self.editor.rectangle) == 0:
+            self.message("No rectangle killed yet")
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        end = row + len(self.editor.rectangle)
+        pad.buffer[row:end] = insert_rectangle(pad.buffer[row:end], col, self.editor.rectangle)
+        self.editor.pad_goto(pad_id, row, col)
+
+    def cmd_string_insert_rectangle(self, msg: str):
+        pad_id = self.active_pad()
+        if msg == "":
+            if self.rectangle_bounds(pad_id) is not None:
+                self.minibuffer.start("String insert rectangle: ", [], lambda text: self.cmd_string_insert_rectangle(text) if text != "" else None, require_match=False)
+            return
+        self.rectangle_insert(pad_id, lambda width: msg)
+
+    def cmd_open_rectangle(self, _msg: str):
+        self.rectangle_insert(self.active_pad(), lambda width: " " * width)
+
+    def rectangle_insert(self, pad_id: int, piece: Callable[[int], str]):
+        """piece of the rectangle's width inserted at its left edge on each of its lines"""
+        bounds = self.rectangle_bounds(pad_id)
+        if bounds is None:
+            return
+        r1, r2, c1, c2 = bounds
+        buffer = self.editor.pads[pad_id].buffer
+        buffer[r1:r2 + 1] = insert_rectangle(buffer[r1:r2 + 1], c1, [piece(c2 - c1)] * (r2 + 1 - r1))
+        self.editor.pad_goto(pad_id, r1, c1)
+
+    def cmd_narrow_to_region(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        row = self.editor.pad_cursor(pad_id)[0]
+        sel = pad.selection()
+        if sel is not None:
+            if self.modal is not None:
+                self.modal.set_mode(Mode.NORMAL, pad_id)
+            self.narrow(pad_id, sel[0][0], sel[1][0] + 1)
+        elif len(pad.buffer.marks.marks) > 0:
+            mark = min(pad.buffer.marks.marks[-1][0], len(pad.buffer) - 1)
+            self.narrow(pad_id, min(row, mark), max(row, mark) + 1)
+        else:
+            self.message("No selection or mark, C-SPC sets one")
+
+    def cmd_narrow_to_defun(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is not None:
+            self.narrow(pad_id, *defun_bounds(pad.buffer, self.editor.pad_cursor(pad_id)[0]))
+
+    def narrow(self, pad_id: int, start: int, end: int):
+        """Show rows start:end of the pad's buffer as a buffer of their own, whose edits go back to the whole one"""
+        base = self.editor.pads[pad_id].buffer
+        if base.narrowing is not None:
+            self.message("Already narrowed, widen first")
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        buffer = self.editor.buffer_create(f"{base.name}<narrowed>", base[start:end], undo=base.undo is not None)
+        buffer.narrowing = Narrowing(base, start, end, base[start:end], list(base))
+        buffer.directory, buffer.settings, buffer.filetype = base.directory, base.settings, base.filetype
+        buffer.encoding, buffer.newline, buffer.read_only = base.encoding, base.newline, base.read_only
+        self.editor.pad_show(pad_id, buffer, min(max(row - start, 0), end - start - 1), col)
+        self.message(f"Narrowed to lines {start + 1}-{end}, C-x n w widens")
+
+    def narrow_sync(self, buffer: Buffer, merge: bool):
+        """Carry the edits of a narrowed buffer over to its base, or else the base's edits of those lines to it"""
+        narrowing = buffer.narrowing
+        assert narrowing is not None
+        base = narrowing.base
+        narrowing.follow_base()
+        old = list(base) if list(buffer) != narrowing.lines else list(buffer)
+        if list(buffer) != narrowing.lines:
+            target, lines = base, list(buffer)
+            base[narrowing.start:narrowing.end] = lines
+            narrowing.end = narrowing.start + len(lines)
+            narrowing.base_lines = list(base)
+        elif base[narrowing.start:narrowing.end] != narrowing.lines:
+            target, lines = buffer, base[narrowing.start:narrowing.end]
+            buffer[:] = lines if len(lines) > 0 else [""]
+        else:
+            return
+        narrowing.lines = list(buffer)
+        if target.undo is not None:
+            target.undo.record(target, (0, 0), (0, 0), merge)
+        self.views_follow(target, old)
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is target:
+                self.editor.pad_goto(i, *self.editor.pad_cursor(i))
+
+    def cmd_widen(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        buffer = pad.buffer
+        if buffer.narrowing is None:
+            self.message(f"{buffer.name} is not narrowed")
+            return
+        self.narrow_sync(buffer, False)
+        row, col = self.editor.pad_cursor(pad_id)
+        self.editor.pad_show(pad_id, buffer.narrowing.base, buffer.narrowing.start + row, col)
+        if all(p.buffer is not buffer for p in self.editor.pads):
+            self.editor.buffers.remove(buffer)
+
+    def cmd_tab_to_tab_stop(self, _msg: str):
+        """Insert a tab or spaces up to the next multiple of the buffer's tab width"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        settings = pad.buffer.settings
+        row, col = self.editor.pad_cursor(pad_id)
+        text = "\t" if settings.use_tabs is True else " " * (settings.tab_width - col % settings.tab_width)
+        self.editor.pad_goto(pad_id, *self.editor.text_insert(pad_id, (row, col), text))
+
+    def cmd_toggle_relative_numbers(self, _msg: str):
+        self.editor.relative_numbers = not self.editor.relative_numbers
+        for fr in self.frames.win_frames()[0] + self.frames.floats:
+            self.editor.pad_display(fr.pad_id, False)
+        self.message(f"Line numbers {'relative' if self.editor.relative_numbers is True else 'absolute'}")
+
+    def cmd_toggle_whitespace(self, _msg: str):
+        self.editor.show_whitespace = not self.editor.show_whitespace
+        self.message(f"Whitespace {'shown' if self.editor.show_whitespace is True else 'hidden'}")
+
+    def cmd_scroll_sideways(self, msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        step = max(1, pad.width // 2)
+        self.editor.pad_scroll_x(pad_id, -step if msg == 'left' else step)
+
+    def cmd_set_line_ending(self, msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        if msg == "":
+            self.minibuffer.start(f"Line ending (now {line_ending_name(pad.buffer.newline)}): ", list(line_endings.keys()), self.cmd_set_line_ending)
+            return
+        newline = line_endings.get(msg.upper())
+        if newline is None:
+            self.message(f"Unknown line ending {msg}, use one of {', '.join(line_endings.keys())}")
+            return
+        pad.buffer.newline = newline
+        self.redisplay_buffer(pad.buffer)
+        self.message(f"{pad.buffer.name} will be saved with {msg.upper()} line endings")
+
+    def shutdown(self):
+        """Stop background work and drop the swap files; keep the undo history of every file buffer that has no unsaved changes"""
+        self.watcher.stop()
+        for client in self.lsp_clients.values():
+            client.stop()
+        for session in self.repls.values():
+            session.process.stop()
+        self.collab_leave()
+        self.server_stop()
+        self.scratch_save()
+        if self.swap is not None:
+            for path in self.swap_versions:
+                self.swap.remove(path)
+        if self.undo_store is None:
+            return
+        for buffer in self.editor.buffers:
+            try:
+                unchanged = buffer.filename is not None and os.path.exists(buffer.filename) and list(buffer) == read_lines(buffer.filename, buffer.hex)
+            except EditorError:
+                unchanged = False
+            if unchanged is True:
+                self.undo_store.save(buffer)
+
+    def cmd_keyboard_quit(self, _msg: str):
+        self.keys.pending = []
+        self.editor.pending_keys = ""
+        self.minibuffer.cancel()
+
+    def frame_selected(self):
+        """Called whenever the user activates a frame"""
+        if self.golden_ratio is True:
+            self.frames.golden_ratio()
+        self.relayout()
+        self.hooks.emit('frame-selected', frame_id=self.frames.active_id)
+
+    def cmd_next_frame(self, _msg: str):
+        self.frames.next()
+        self.frame_selected()
+
+    def cmd_select_frame(self, msg: str):
+        if msg.upper() not in Heading.__members__:
+            self.message(f"Invalid direction {msg}")
+            return
+        if self.frames.select_frame_in_direction(Heading[msg.upper()]) is False:
+            self.message(f"No frame {msg} of the active frame")
+            return
+        self.frame_selected()
+
+    def cmd_jump_to_frame(self, _msg: str):
+        wfr = sorted(self.frames.win_frames()[0], key=lambda fr: (fr.y, fr.x))
+        names = "123456789abcdefghijklmnopqrstuvwxyz"
+        self.frame_labels = {names[i]: fr.id for i, fr in enumerate(wfr[:len(names)])}
+
+    def cmd_golden_ratio_mode(self, _msg: str):
+        self.golden_ratio = not self.golden_ratio
+        self.message(f"Golden ratio mode {'on' if self.golden_ratio else 'off'}")
+        if self.golden_ratio is True:
+            self.frame_selected()
+
+    def cmd_font_zoom(self, msg: str):
+        """Make the text of the active frame larger or smaller, or reset it to the normal size"""
+        frame = self.frames.active()
+        if frame is None:
+            return
+        if msg not in ('in', 'out', 'reset'):
+            self.message(f"Invalid zoom {msg}, use in, out or reset")
+            return
+        zoom = 0 if msg == 'reset' else frame.zoom + (1 if msg == 'in' else -1)
+        if self.frame_renderer.zoom_valid(zoom) is False:
+            self.message("Font size limit reached")
+            return
+        frame.zoom = zoom
+        self.relayout()
+        self.message(f"Font size {100 * self.frame_renderer.zoom_size(zoom) // self.frame_renderer.font_size}%")
+
+    def mouse_down(self, x: int, y: int):
+        self.redraw = True
+        if y < self.tab_bar_height():
+            index = tab_at(tab_labels(self.workspaces.names()), (x - frame_inset) // self.frame_renderer.char_width)
+            if index is not None:
+                self.workspace_switch(index)
+            return
+        fr_id = self.frames.frame_at(x, y)
+        if fr_id is not None and fr_id != self.frames.active_id:
+            self.frames.active_id = fr_id
+            self.frame_selected()
+        frame = self.minimap_at(x, y)
+        self.minimap_drag = frame.id if frame is not None else None
+        if frame is not None:
+            self.minimap_scroll(frame, y)
+
+    def mouse_drag(self, _x: int, y: int):
+        """The pointer moved with the left button held"""
+        frame = next((fr for fr in self.frames.win_frames()[0] if fr.id == self.minimap_drag), None)
+        if frame is not None:
+            self.minimap_scroll(frame, y)
+
+    def mouse_up(self):
+        self.minimap_drag = None
+
+    def minimap_at(self, x: int, y: int) -> Frame | None:
+        """The tiled frame whose minimap is at the position"""
+        fr_id = self.frames.frame_at(x, y)
+        frame = next((fr for fr in self.frames.win_frames()[0] if fr.id == fr_id), None)
+        rect = self.frame_renderer.minimap_rect(frame, self.editor.pad_get(frame.pad_id)) if frame is not None else None
+        if rect is None or not (rect.x <= x < rect.x + rect.w and rect.y <= y < rect.y + rect.h):
+            return None
+        return frame
+
+    def minimap_scroll(self, frame: Frame, y: int):
+        """Center the frame's view on the line its minimap shows at y"""
+        pad = self.editor.pad_get(frame.pad_id)
+        line = self.frame_renderer.minimap_line(frame, pad, y) if pad is not None else None
+        if pad is None or line is None:
+            return
+        self.redraw = True
+        self.editor.pad_scroll_y(frame.pad_id, line - pad.height // 2 - pad.buf_y)
+
+    def mouse_wheel(self, x: int, y: int, lines: int):
+        """Scroll the frame under the pointer, lines > 0 towards the start of the buffer"""
+        fr_id = self.frames.frame_at(x, y)
+        frame = next((fr for fr in self.frames.win_frames()[0] + self.frames.floats if fr.id == fr_id), None) if fr_id is not None else None
+        if frame is None or self.editor.pad_get(frame.pad_id) is None:
+            return
+        self.redraw = True
+        self.editor.pad_scroll_y(frame.pad_id, -lines)
+
+    def drop_file(self, path: str, x: int | None, y: int | None):
+        """A file dropped onto the window opens in the frame under the pointer, else in the active one"""
+        self.redraw = True
+        fr_id = self.frames.frame_at(x, y) if x is not None and y is not None else None
+        if fr_id is not None and fr_id != self.frames.active_id:
+            self.frames.active_id = fr_id
+            self.frame_selected()
+        _ = self.guarded(f"Opening {path}", lambda: self.open_file(path))
+
+    def cmd_move_frame(self, msg: str):
+        if msg.upper() not in Heading.__members__:
+            self.message(f"Invalid direction {msg}")
+            return
+        if self.frames.move(Heading[msg.upper()]) is False:
+            self.message("The only frame cannot be moved")
+        self.relayout()
+
+    def cmd_swap_frame(self, msg: str):
+        if msg.upper() not in Heading.__members__:
+            self.message(f"Invalid direction {msg}")
+            return
+        if self.frames.swap_with_neighbor(Heading[msg.upper()]) is False:
+            self.message(f"No frame {msg} of the active frame")
+        self.relayout()
+
+    def cmd_rotate_frames(self, msg: str):
+        self.frames.rotate(clockwise=msg != 'ccw')
+        self.relayout()
+
+    def cmd_transpose_frames(self, _msg: str):
+        self.frames.transpose()
+        self.relayout()
+
+    def cmd_toggle_split_direction(self, _msg: str):
+        if self.frames.toggle_split_direction() is False:
+            self.message("The active frame is not split")
+        self.relayout()
+
+    def cmd_split(self, msg: str):
+        _ = self.frames.split(direction=Direction.VERTICAL if msg == 'vertical' else Direction.HORIZONTAL)
+        self.relayout()
+
+    def cmd_size(self, delta: float):
+        self.frames.size(delta=delta)
+        self.relayout()
+
+    def cmd_delete_frame(self, _msg: str):
+        if self.frames.close_floating() is False:
+            _ = self.frames.delete()
+        self.relayout()
+
+    def cmd_open_floating_frame(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        wx, hy = self.window_size()
+        hy -= self.minibuffer_height()
+        row, col = self.editor.pad_cursor(pad_id)
+        float_pad = self.editor.create_editor(pad.buffer, 1, status_line=True)
+        _ = self.frames.create_floating(wx // 5, hy // 5, wx * 3 // 5, hy * 3 // 5, float_pad)
+        self.relayout()
+        self.editor.pad_goto(float_pad, row, col)
+
+    def cmd_close_floating_frame(self, _msg: str):
+        if self.frames.close_floating() is False:
+            self.message("The active frame is not floating")
+        self.relayout()
+
+    def cmd_delete_other_frames(self, _msg: str):
+        self.zoomed = None
+        _ = self.frames.keep_only()
+        self.relayout()
+
+    def cmd_zoom_frame(self, _msg: str):
+        if self.zoomed is not None:
+            snapshot = self.zoomed
+            self.zoomed = None
+            self.layout_return(snapshot)
+            return
+        if len(self.frames.win_frames()[0]) < 2:
+            self.message("Only one frame")
+            return
+        self.zoomed = self.layout_snapshot()
+        _ = self.frames.keep_only()
+        self.relayout()
+
+    def cmd_zen_mode(self, _msg: str):
+        """Toggle showing only the active frame's text, without line numbers, status line and border, in a centered column"""
+        if self.zen is not None:
+            zen, self.zen = self.zen, None
+            for pad_id, (left, bottom) in zen.borders.items():
+                pad = self.editor.pad_get(pad_id)
+                if pad is not None:
+                    pad.left_border, pad.bottom_border = left, bottom
+            self.frame_renderer.borders = True
+            if zen.fullscreen is True:
+                _ = sdl2.SDL_SetWindowFullscreen(self.window.window, 0)  # pyright: ignore[reportUnknownMemberType]
+            self.layout_return(zen.layout)
+            return
+        self.zen = ZenState(self.layout_snapshot())
+        _ = self.frames.keep_only()
+        self.frame_renderer.borders = False
+        if self.config.zen_fullscreen is True and self.window.window is not None:
+            self.zen.fullscreen = sdl2.SDL_SetWindowFullscreen(self.window.window, sdl2.SDL_WINDOW_FULLSCREEN_DESKTOP) == 0  # pyright: ignore[reportUnknownMemberType]
+        self.relayout()
+
+    def cmd_toggle_minimap(self, _msg: str):
+        """Show or hide the miniature of the whole buffer at the right of the tiled frames"""
+        self.frame_style = replace(self.frame_style, minimap=0 if self.frame_style.minimap > 0 else max(1, self.config.minimap_width))
+        for os_window in self.os_windows:
+            self.in_window(os_window, self.relayout)
+
+    def zen_hide_borders(self):
+        """Take the gutter and status line from the pads shown, also those that appear while zen-mode is on"""
+        if self.zen is None:
+            return
+        for fr in self.frames.win_frames()[0] + self.frames.floats:
+            pad = self.editor.pad_get(fr.pad_id)
+            if pad is not None and fr.pad_id not in self.zen.borders:
+                self.zen.borders[fr.pad_id] = (pad.left_border, pad.bottom_border)
+                pad.left_border, pad.bottom_border = 0, 0
+
+    def command_summary(self, name: str) -> str:
+        command = self.commands.get(name)
+        return command.doc if command is not None else ""
+
+    def cmd_command_palette(self, _msg: str):
+        self.minibuffer.start("M-x ", self.commands.names(), lambda name: self.dispatch(name), self.command_summary)
+
+    def layout_snapshot(self) -> LayoutSnapshot:
+        views: dict[int, PadView] = {}
+        for fr in self.frames.win_frames()[0]:
+            if self.editor.pad_get(fr.pad_id) is not None:
+                views[fr.id] = self.editor.pad_view(fr.pad_id)
+        return LayoutSnapshot(self.frames.snapshot(), views)
+
+    def layout_return(self, snapshot: LayoutSnapshot):
+        """Back to the layout a single frame was shown instead of, keeping what was done in that frame"""
+        active = self.frames.active()
+        if active is not None and active.id in snapshot.views:
+            snapshot.views[active.id] = self.editor.pad_view(active.pad_id)
+            snapshot.layout.active_id = active.id
+        self.layout_restore(snapshot)
+
+    def layout_restore(self, snapshot: LayoutSnapshot):
+        self.frames.restore(snapshot.layout)
+        self.relayout()
+        for fr in self.frames.win_frames()[0]:
+            view = snapshot.views.get(fr.id)
+            if view is not None:
+                self.editor.pad_restore_view(fr.pad_id, view)
+
+    def workspace_switch(self, index: int, keep: bool = True):
+        """Show workspace index instead of the current one, whose layout is kept for coming back unless keep is False"""
+        workspaces = self.workspaces
+        if index == workspaces.current or not 0 <= index < len(workspaces):
+            return
+        if self.zen is not None:
+            self.message("Leave zen-mode first")
+            return
+        if keep is True:
+            current = workspaces.shown()
+            current.snapshot, current.zoomed = self.layout_snapshot(), self.zoomed
+        workspaces.current = index
+        target = workspaces.shown()
+        self.zoomed = target.zoomed
+        if target.snapshot is not None:
+            self.layout_restore(target.snapshot)
+        target.snapshot = target.zoomed = None
+        self.frame_selected()
+        self.redraw = True
+        self.message(f"Workspace {workspaces.labels()[index]}")
+
+    def cmd_workspace_new(self, _msg: str):
+        if self.zen is not None:
+            self.message("Leave zen-mode first")
+            return
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        view = self.editor.pad_view(pad_id) if pad is not None else None
+        current = self.workspaces.shown()
+        current.snapshot, current.zoomed = self.layout_snapshot(), self.zoomed
+        self.workspaces.current, self.zoomed = self.workspaces.add(), None
+        _ = self.frames.fresh(self.editor.pad_clone(pad_id) if pad is not None else -1)
+        self.relayout()
+        if view is not None:
+            self.editor.pad_restore_view(self.active_pad(), view)
+        self.frame_selected()
+        self.message(f"Workspace {self.workspaces.labels()[self.workspaces.current]}")
+
+    def workspace_delete(self, index: int):
+        """Delete a workspace; deleting the one shown shows its left neighbor, or the right one for the first"""
+        workspaces = self.workspaces
+        if len(workspaces) < 2:
+            self.message("The only workspace stays")
+            return
+        label = workspaces.labels()[index]
+        if index == workspaces.current:
+            self.workspace_switch(index - 1 if index > 0 else 1, keep=False)
+        if workspaces.remove(index) is False:
+            return
+        self.relayout()
+        self.redraw = True
+        self.message(f"Deleted workspace {label}")
+
+    def cmd_workspace_delete(self, msg: str):
+        if msg == "":
+            self.minibuffer.start("Delete workspace: ", self.workspaces.labels(), self.cmd_workspace_delete)
+            return
+        index = self.workspaces.find(msg)
+        if index is None:
+            self.message(f"No workspace {msg}")
+            return
+        self.workspace_delete(index)
+
+    def cmd_workspace_switch(self, msg: str):
+        if msg == "":
+            self.minibuffer.start("Workspace: ", self.workspaces.labels(), self.cmd_workspace_switch)
+            return
+        index = self.workspaces.find(msg)
+        if index is None:
+            self.message(f"No workspace {msg}")
+            return
+        if index == self.workspaces.current:
+            self.message(f"Workspace {msg} is shown")
+            return
+        self.workspace_switch(index)
+
+    def cmd_workspace_rename(self, msg: str):
+        shown = self.workspaces.shown()
+        if msg == "":
+            self.minibuffer.start(f"Rename workspace {shown.name} to: ", [], lambda name: self.cmd_workspace_rename(name) if name != "" else None, require_match=False)
+            return
+        if self.workspaces.rename(self.workspaces.current, msg) is False:
+            self.message(f"Another workspace is named {msg.strip()}")
+            return
+        self.redraw = True
+
+    def layout_leaf(self, frame: Frame, directory: str) -> dict[str, object]:
+        """A leaf of a layout file: the file shown, relative to the layout file's directory if below it, or else the buffer name"""
+        pad = self.editor.pad_get(frame.pad_id)
+        if pad is None:
+            return {}
+        leaf: dict[str, object] = {}
+        filename = pad.buffer.filename
+        if filename is not None:
+            leaf['file'] = os.path.relpath(filename, directory) if filename.startswith(directory + os.sep) else abbreviate_home(filename)
+        else:
+            leaf['buffer'] = pad.buffer.name
+        row = self.editor.pad_cursor(frame.pad_id)[0]
+        if row > 0:
+            leaf['row'] = row
+        if frame.id == self.frames.active_id:
+            leaf['active'] = True
+        return leaf
+
+    def cmd_layout_write_file(self, msg: str):
+        if msg == "":
+            self.minibuffer.start("Write layout to: ", [], lambda path: self.cmd_layout_write_file(path) if path != "" else None, require_match=False)
+            return
+        path = os.path.abspath(os.path.expanduser(msg))
+        directory = os.path.dirname(path)
+        tree = layout_tree(self.frames, self.frames.root_id, lambda fr: self.layout_leaf(fr, directory))
+        try:
+            with open(path, 'w', encoding='utf-8') as file:
+                _ = file.write(layout_text(tree, path))
+        except OSError as e:
+            self.message(f"Cannot write {abbreviate_home(path)}: {e}")
+            return
+        self.message(f"Wrote layout {abbreviate_home(path)}")
+
+    def cmd_layout_load_file(self, msg: str):
+        if msg == "":
+            self.minibuffer.start("Load layout file: ", [], lambda path: self.cmd_layout_load_file(path) if path != "" else None, require_match=False)
+            return
+        if self.zen is not None:
+            self.message("Leave zen-mode first")
+            return
+        path = os.path.abspath(os.path.expanduser(msg))
+        root, error = load_layout(path)
+        if error != "":
+            self.message(f"Cannot load layout {abbreviate_home(path)}: {error}")
+            return
+        pad_id = self.active_pad()
+        self.zoomed = None
+        _ = self.frames.fresh(self.editor.pad_clone(pad_id) if self.editor.pad_get(pad_id) is not None else -1)
+        leaves = layout_build(self.frames, self.frames.root_id, root)
+        self.relayout()
+        active, missing = leaves[0][0], []
+        for frame_id, leaf in leaves:
+            self.frames.active_id = frame_id
+            row = cast(int, leaf.get('row', 0))
+            name = leaf.get('file')
+            if isinstance(name, str):
+                self.open_file(os.path.join(os.path.dirname(path), os.path.expanduser(name)), row)
+            elif isinstance(leaf.get('buffer'), str):
+                buffer = self.editor.buffer_get(cast(str, leaf['buffer']))
+                if buffer is not None:
+                    self.editor.pad_show(self.active_pad(), buffer, row)
+                else:
+                    missing.append(cast(str, leaf['buffer']))
+            if leaf.get('active') is True:
+                active = frame_id
+        self.frames.active_id = active
+        self.frame_selected()
+        self.message(f"Loaded layout {abbreviate_home(path)}" + (f", no buffer {', '.join(missing)}" if len(missing) > 0 else ""))
+
+    def cmd_layout_save(self, msg: str):
+        if msg == "":
+            self.minibuffer.start("Save layout as: ", sorted(self.layouts.keys()), lambda name: self.cmd_layout_save(name) if name != "" else None, require_match=False)
+            return
+        self.layouts[msg] = self.layout_snapshot()
+        self.message(f"Saved layout {msg}")
+
+    def cmd_layout_restore(self, msg: str):
+        if msg == "":
+            if len(self.layouts) == 0:
+                self.message("No saved layouts")
+                return
+            self.minibuffer.start("Restore layout: ", sorted(self.layouts.keys()), self.cmd_layout_restore)
+            return
+        snapshot = self.layouts.get(msg)
+        if snapshot is None:
+            self.message(f"No layout named {msg}")
+            return
+        self.layout_restore(snapshot)
+
+    def show_popup(self, lines: list[str], pad_id: int = -1, pos: tuple[int, int] | None = None):
+        """Popup at pos (default: cursor) in the frame showing pad_id (default: active)"""
+        if pad_id < 0:
+            pad_id = self.active_pad()
+        frame_id = self.frames.active_id
+        for fr in self.frames.win_frames()[0] + self.frames.floats:
+            if fr.pad_id == pad_id:
+                frame_id = fr.id
+        if pos is None:
+            pos = self.editor.pad_cursor(pad_id)
+        self.popup = Popup(lines, frame_id, pos[0], pos[1])
+
+    def cmd_show_log(self, _msg: str):
+        ring = next((h for h in logging.getLogger().handlers if isinstance(h, LogRing)), None)
+        buffer = self.editor.buffer_get("*log*")
+        if buffer is None:
+            buffer = self.editor.buffer_create("*log*", [""], undo=False)
+        lines = list(ring.lines) if ring is not None else []
+        buffer[:] = lines if len(lines) > 0 else [f"Nothing logged yet, see {abbreviate_home(state_path('led.log'))}"]
+        self.editor.pad_show(self.active_pad(), buffer, len(buffer) - 1)
+
+    def cmd_messages(self, _msg: str):
+        buffer = self.messages_buffer()
+        self.editor.pad_show(self.active_pad(), buffer, len(buffer) - 1)
+
+    def cmd_notifications(self, _msg: str):
+        buffer = self.editor.buffer_get("*Notifications*")
+        if buffer is None:
+            buffer = self.editor.buffer_create("*Notifications*", [""], undo=False)
+        lines = self.notifications.lines()
+        buffer[:] = lines if len(lines) > 0 else ["No notifications"]
+        self.editor.pad_show(self.active_pad(), buffer, len(buffer) - 1)
+
+    def undo_moved(self, pad_id: int, buffer: Buffer, cursor: tuple[int, int] | None):
+        if cursor is None:
+            self.message("No further undo information")
+            return
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer and i != pad_id:
+                row, col = self.editor.pad_cursor(i)
+                self.editor.pad_goto(i, row, col)
+        self.editor.pad_goto(pad_id, *cursor)
+        if self.undo_view is not None and self.undo_view[0] is buffer:
+            _ = self.show_undo_tree(buffer)
+
+    def cmd_undo(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None or pad.buffer.undo is None or self.refuse_read_only(pad.buffer) is True:
+            return
+        self.undo_moved(pad_id, pad.buffer, pad.buffer.undo.undo(pad.buffer))
+
+    def cmd_redo(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None or pad.buffer.undo is None or self.refuse_read_only(pad.buffer) is True:
+            return
+        self.undo_moved(pad_id, pad.buffer, pad.buffer.undo.redo(pad.buffer))
+
+    def undo_tree_keymap(self) -> Keymap:
+        return Keymap("undo-tree", {
+            'Up': ('undo-tree-move', 'up'), 'k': ('undo-tree-move', 'up'),
+            'Down': ('undo-tree-move', 'down'), 'j': ('undo-tree-move', 'down'),
+            'Return': ('undo-tree-quit', ''), 'q': ('undo-tree-quit', ''),
+            })
+
+    def show_undo_tree(self, target: Buffer) -> Buffer | None:
+        if target.undo is None:
+            return None
+        buffer = self.editor.buffer_get("*undo-tree*")
+        if buffer is None:
+            buffer = self.editor.buffer_create("*undo-tree*", [""], undo=False)
+            buffer.keymap = self.undo_tree_keymap()
+        lines, seqs = target.undo.visualize()
+        buffer[:] = lines
+        self.undo_view = (target, seqs)
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer:
+                self.editor.pad_goto(i, seqs.index(target.undo.current), 0)
+        return buffer
+
+    def cmd_undo_tree(self, _msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None or pad.buffer.undo is None:
+            self.message("No undo history here")
+            return
+        buffer = self.show_undo_tree(pad.buffer)
+        if buffer is None:
+            return
+        self.undo_origin = self.frames.active_id
+        if self.frames.split(direction=Direction.HORIZONTAL) is True:
+            parent_idx = self.frames.parent_idx(self.frames.active_id)
+            if parent_idx is not None:
+                self.undo_origin = self.frames.active_id
+                self.frames.active_id = self.frames.frames[parent_idx].c_rd
+            self.relayout()
+        self.editor.pad_show(self.active_pad(), buffer)
+        _ = self.show_undo_tree(pad.buffer)
+
+    def cmd_undo_tree_move(self, msg: str):
+        if self.undo_view is None:
+            return
+        target, seqs = self.undo_view
+        if target.undo is None:
+            return
+        row, _ = self.editor.pad_cursor(self.active_pad())
+        row = min(max(row + (-1 if msg == 'up' else 1), 0), len(seqs) - 1)
+        cursor = target.undo.goto(target, seqs[row])
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is target:
+                r, c = cursor if cursor is not None else self.editor.pad_cursor(i)
+                self.editor.pad_goto(i, r, c)
+        _ = self.show_undo_tree(target)
+
+    def cmd_undo_tree_quit(self, _msg: str):
+        self.undo_view = None
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None or pad.buffer.name != "*undo-tree*":
+            return
+        if self.frames.is_leaf(self.undo_origin) and self.undo_origin != self.frames.active_id:
+            _ = self.frames.delete()
+            self.frames.active_id = self.undo_origin
+            self.relayout()
+
+    def cmd_describe_char(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        line = pad.buffer[row]
+        if col >= len(line):
+            self.show_popup(["end of line"])
+            return
+        c = line[col]
+        self.show_popup([f"{c!r}  U+{ord(c):04X}", unicodedata.name(c, "unnamed"), f"category {unicodedata.category(c)}"])
+
+    def active_keymaps(self) -> list[Keymap]:
+        """Where keys are looked up, in this order: the buffer's keymap, modal editing's outside insert mode, the global one"""
+        pad = self.editor.pad_get(self.active_pad())
+        keymaps = [pad.buffer.keymap] if pad is not None and pad.buffer.keymap is not None else []
+        if self.modal is not None and self.modal.mode != Mode.INSERT:
+            keymaps.append(self.modal.normal_keymap)
+        return keymaps + [self.keymap]
+
+    def cmd_describe_key(self, _msg: str):
+        self.describing = []
+        self.message("Describe key: ")
+
+    def describe_key_feed(self, key: str):
+        """Collect the keys of a sequence for describe-key and describe what it runs once complete"""
+        assert self.describing is not None
+        self.describing.append(key)
+        seq = " ".join(self.describing)
+        for keymap in self.active_keymaps():
+            binding = keymap.lookup(seq)
+            if binding is not None:
+                self.describing = None
+                cmd, msg = binding
+                where = [] if keymap is self.keymap else [f"in the {keymap.name} keymap"]
+                self.show_popup([f"{seq} runs {cmd}{' ' + msg if msg != '' else ''}"] + where + self.command_doc(cmd))
+                return
+        if any(keymap.is_prefix(seq) for keymap in self.active_keymaps()):
+            self.message(f"Describe key: {seq} -")
+            return
+        self.describing = None
+        text = key_text(key)
+        self.show_popup([f"{seq} inserts {text!r}" if text is not None and seq == key else f"{seq} is undefined"])
+
+    def command_doc(self, name: str) -> list[str]:
+        command = self.commands.get(name)
+        if command is None:
+            return ["a step of modal editing"]
+        return textwrap.wrap(command.doc, 72) if command.doc != "" else ["not documented"]
+
+    def cmd_describe_command(self, msg: str):
+        if msg == "":
+            self.minibuffer.start("Describe command: ", self.commands.names(), self.cmd_describe_command, self.command_summary)
+            return
+        if self.commands.get(msg) is None:
+            self.message(f"Unknown command {msg}")
+            return
+        keys = [keys for keymap in self.active_keymaps() for keys in keymap.keys_for(msg)]
+        bound = f"bound to {', '.join(keys)}" if len(keys) > 0 else f"not bound to a key, M-x {msg} runs it"
+        self.show_popup([msg] + self.command_doc(msg) + [bound])
+
+    def cheat_sheet_entries(self) -> list[tuple[str, str, str]]:
+        """Every key sequence of the active keymaps as (keys, command and msg, doc), leaving out those shadowed by an earlier keymap"""
+        entries: list[tuple[str, str, str]] = []
+        seen: set[str] = set()
+        for keymap in self.active_keymaps():
+            for keys, (cmd, msg) in keymap.bindings.items():
+                if keys in seen:
+                    continue
+                seen.add(keys)
+                entries.append((keys, f"{cmd} {msg}" if msg != "" else cmd, self.command_summary(cmd)))
+        return entries
+
+    def cmd_key_cheat_sheet(self, _msg: str):
+        entries = self.cheat_sheet_entries()  # of the frame it was asked in, not the one showing them
+        buffer = self.editor.buffer_get("*Keys*")
+        if buffer is None:
+            buffer = self.editor.buffer_create("*Keys*", [""], undo=False)
+        buffer[:] = cheat_sheet(entries)
+        _ = self.show_below(buffer, 0)
+
+        def refilter(pattern: str):
+            buffer[:] = cheat_sheet(entries, pattern)
+            for i, pad in enumerate(self.editor.pads):
+                if pad.buffer is buffer:
+                    self.editor.pad_goto(i, 0, 0)
+
+        self.minibuffer.start("Filter keys: ", [], lambda _pattern: None, require_match=False, on_change=refilter)
+
+    def buffer_opened(self, buffer: Buffer):
+        if buffer.filename is None or buffer.remote is not None:
+            return
+        if buffer.image is not None:
+            buffer.keymap = self.image_keymap()
+            self.watcher.watch(buffer.filename)
+            self.hooks.emit('buffer-opened', buffer=buffer)
+            return
+        config = self.buffer_config(buffer)
+        buffer.filetype = filetype_detect(config, buffer.filename, list(buffer))
+        buffer.settings = filetype_settings(config, buffer.filetype)
+        self.lsp_attach(buffer)
+        self.git_fetch(buffer)
+        if self.undo_store is not None:
+            _ = self.undo_store.restore(buffer)
+        self.swap_offer(buffer)
+        conflicts = find_conflicts(buffer)
+        if len(conflicts) > 0:
+            self.notifications.post("merge", f"{buffer.name} has {len(conflicts)} merge conflicts, C-c ^ m shows ours, theirs and the result", "warning")
+        self.watcher.watch(buffer.filename)
+        self.hooks.emit('buffer-opened', buffer=buffer)
+
+    def buffer_config(self, buffer: Buffer) -> EditorConfig:
+        """The config with the .led.toml of the buffer's project merged over it, read once per project"""
+        if buffer.remote is not None:
+            return self.config
+        root = find_project_root(buffer.directory)
+        if root not in self.project_configs:
+            self.project_configs[root] = project_config(self.config, root)
+        return self.project_configs[root]
+
+    def swap_offer(self, buffer: Buffer):
+        """Ask whether to recover the unsaved changes a crashed led left for the buffer's file"""
+        if self.swap is None or buffer.filename is None or buffer.undo is None:
+            return
+        self.swap_versions[buffer.filename] = buffer.undo.version  # keep the swap file until answered
+        data = self.swap.leftover(buffer.filename)
+        if data is None:
+            return
+        lines = cast(list[str], data.get('lines', []))
+        if lines == list(buffer):
+            self.swap.remove(buffer.filename)
+            return
+        when = time.strftime("%Y-%m-%d %H:%M", time.localtime(cast(float, data.get('time', 0.0))))
+        changed = ", the file changed since" if data.get('disk_hash') != buffer.disk_hash else ""
+        filename = buffer.filename
+
+        def answer(choice: str):
+            if choice == "yes":
+                buffer[:] = lines
+                if buffer.undo is not None:
+                    buffer.undo.record(buffer, (0, 0), (0, 0))
+                self.redisplay_buffer(buffer)
+                self.message(f"Recovered {buffer.name}, save it to keep the changes")
+            elif self.swap is not None:
+                self.swap.remove(filename)
+        self.minibuffer.start(f"{buffer.name} has unsaved changes from {when}{changed}, recover? ", ["yes", "no"], answer)
+
+    def swap_sync(self):
+        """Every swap_interval_ms, rewrite the swap files of buffers changed since and drop those of saved ones"""
+        if self.swap is None or time.monotonic() < self.swap_due:
+            return
+        self.swap_due = time.monotonic() + self.config.swap_interval_ms / 1000
+        for buffer in self.editor.buffers:
+            if buffer.filename is None or buffer.remote is not None or buffer.undo is None or self.swap_versions.get(buffer.filename) == buffer.undo.version:
+                continue
+            self.swap_versions[buffer.filename] = buffer.undo.version
+            if buffer.modified() is True:
+                self.swap.write(buffer)
+            else:
+                self.swap.remove(buffer.filename)
+
+    def swap_pending(self) -> bool:
+        return self.swap is not None and any(buffer.filename is not None and buffer.remote is None and buffer.undo is not None and
+                                             self.swap_versions.get(buffer.filename) != buffer.undo.version for buffer in self.editor.buffers)
+
+    def cmd_recover_files(self, _msg: str):
+        if self.swap is None:
+            self.message("Swap files are off, see swap_interval_ms")
+            return
+        files = [abbreviate_home(path) for path in self.swap.leftovers()]
+        if len(files) == 0:
+            self.message("No unsaved changes left by a crash")
+            return
+        self.minibuffer.start("Recover: ", files, lambda filename: self.open_file(os.path.expanduser(filename)) if filename != "" else None)
+
+    def git_fetch(self, buffer: Buffer):
+        """Read the staged version of the file and the branch in the background, the hunks follow in git_update()"""
+        filename = buffer.filename
+        if filename is None or buffer.hex is True or shutil.which("git") is None:
+            return
+
+        def fetch():
+            base = git_index_lines(filename)
+            branch = git_branch(filename)
+
+            def done():
+                if branch != buffer.git_branch:
+                    buffer.git_branch = branch
+                    self.redisplay_buffer(buffer)
+                buffer.git_base = base
+                buffer.git_version = -1
+                if base is None:
+                    buffer.hunks = []
+                    self.redisplay_buffer(buffer)
+
+            self.results.put(("git", done))
+
+        threading.Thread(target=fetch, daemon=True).start()
+
+    def git_update(self):
+        """Diff buffers edited since their last diff against the git index, one background job per buffer at a time"""
+        for buffer in self.editor.buffers:
+            base = buffer.git_base
+            if base is None or buffer.git_pending is True:
+                continue
+            version = buffer.undo.version if buffer.undo is not None else 0
+            if version == buffer.git_version:
+                continue
+            buffer.git_version = version
+            buffer.git_pending = True
+            lines = list(buffer)
+
+            def diff(buffer: Buffer = buffer, base: list[str] = base, lines: list[str] = lines, version: int = version):
+                hunks = diff_hunks(base, lines)
+
+                def done():
+                    buffer.git_pending = False
+                    if buffer.git_version == version:
+                        buffer.hunks = hunks
+                        self.redisplay_buffer(buffer)
+
+                self.results.put(("git", done))
+
+            threading.Thread(target=diff, daemon=True).start()
+
+    def diff_keymap(self) -> Keymap:
+        return Keymap("diff", {
+            'n': ('diff-next', ''), 'p': ('diff-next', 'previous'), ']': ('diff-next', ''), '[': ('diff-next', 'previous'),
+            'Return': ('diff-quit', 'jump'), 'q': ('diff-quit', ''),
+            })
+
+    def show_diff(self, a: list[str], b: list[str], names: tuple[str, str], sources: tuple[Buffer | None, Buffer | None]):
+        """Two aligned views side by side in the active frame, scrolling together"""
+        if self.diff_view is not None:
+            self.cmd_diff_quit('')
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        origin = pad.buffer
+        theme = self.frame_renderer.theme
+        rows = diff_align(a, b)
+        views: list[Buffer] = []
+        for side, (lines, name) in enumerate(zip((a, b), names)):
+            view = self.editor.buffer_get(f"*diff {name}*")
+            if view is None:
+                view = self.editor.buffer_create(f"*diff {name}*", [""], undo=False)
+            view.keymap = self.diff_keymap()
+            view[:] = [lines[row[side]] if row[side] is not None else "" for row in rows] if len(rows) > 0 else [""]
+            view.highlights = {}
+            for i, row in enumerate(rows):
+                if row[side] is None:
+                    view.highlights[i] = theme.diff_filler
+                elif row[2] is True:
+                    view.highlights[i] = theme.diff_changed if row[1 - side] is not None else theme.diff_removed if side == 0 else theme.diff_added
+            views.append(view)
+        if self.frames.split(direction=Direction.HORIZONTAL) is False:
+            self.message("Cannot split this frame")
+            return
+        parent_idx = self.frames.parent_idx(self.frames.active_id)
+        if parent_idx is None:
+            return
+        parent = self.frames.frames[parent_idx]
+        self.diff_view = DiffView(views[0], views[1], sources, rows, parent.c_lu, parent.c_rd, origin)
+        self.relayout()
+        for frame_id, view in ((parent.c_rd, views[1]), (parent.c_lu, views[0])):
+            self.frames.active_id = frame_id
+            self.editor.pad_show(self.active_pad(), view)
+        changes = self.diff_view.changes()
+        if len(changes) > 0:
+            self.editor.pad_goto(self.active_pad(), changes[0], 0)
+        self.message(f"{len(changes)} changes" if len(changes) > 0 else "No differences")
+
+    def cmd_diff_with_file(self, msg: str):
+        """Diff the active buffer with a file, by default its own file on disk"""
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        buffer = pad.buffer
+        if msg == "":
+            default = buffer.filename if buffer.filename is not None else ""
+            self.minibuffer.start("Diff with file (default: on disk): ", [], lambda path: self.cmd_diff_with_file(os.path.expanduser(path) if path != "" else default) if path != "" or default != "" else None)
+            return
+        if os.path.isfile(msg) is False:
+            self.message(f"No file {msg}")
+            return
+        name = f"{buffer.name} on disk" if buffer.filename is not None and os.path.samefile(msg, buffer.filename) else os.path.basename(msg)
+        self.show_diff(read_lines(msg), list(buffer), (name, buffer.name), (None, buffer))
+
+    def cmd_diff_buffers(self, _msg: str):
+        names = [b.name for b in self.editor.buffers if b.name.startswith("*") is False]
+
+        def second(first: str):
+            a = self.editor.buffer_get(first)
+            if a is None:
+                return
+
+            def accept(name: str):
+                b = self.editor.buffer_get(name)
+                if b is not None:
+                    self.show_diff(list(a), list(b), (a.name, b.name), (a, b))
+
+            self.minibuffer.start(f"Diff {first} with buffer: ", [n for n in names if n != first], accept)
+
+        self.minibuffer.start("Diff buffer: ", names, second)
+
+    def diff_sync(self):
+        """Keep the other side of the diff view on the same rows as the active one"""
+        view = self.diff_view
+        pad = self.editor.pad_get(self.active_pad())
+        if view is None or pad is None or (pad.buffer is not view.left and pad.buffer is not view.right):
+            return
+        other = view.right if pad.buffer is view.left else view.left
+        for i, p in enumerate(self.editor.pads):
+            if p.buffer is other and (p.buf_y != pad.buf_y or p.buf_x != pad.buf_x or p.cur_y != pad.cur_y):
+                p.buf_y = min(pad.buf_y, max(len(other) - 1, 0))
+                p.buf_x = pad.buf_x
+                self.editor.pad_goto(i, pad.buf_y + pad.cur_y, p.buf_x + p.cur_x)
+
+    def cmd_follow_mode(self, _msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        if self.follow is pad.buffer:
+            self.follow = None
+            self.message("Follow mode off")
+            return
+        self.follow = pad.buffer
+        if len(self.follow_frames()) < 2:
+            self.cmd_split('horizontal')
+        self.message(f"Follow mode: {len(self.follow_frames())} frames show {pad.buffer.name} page after page")
+
+    def follow_frames(self) -> list[Frame]:
+        """The tiled frames showing the follow-mode buffer, in reading order; a frame's follow option takes it in or out"""
+        target = self.follow
+        active = self.frames.active()
+        if active is not None and active.follow is True:
+            pad = self.editor.pad_get(active.pad_id)
+            target = pad.buffer if pad is not None else target
+        shown: list[Frame] = []
+        for fr in self.frames.win_frames()[0]:
+            pad = self.editor.pad_get(fr.pad_id)
+            if target is None or pad is None or pad.buffer is not target:
+                continue
+            if fr.follow is True or (fr.follow is None and self.follow is target):
+                shown.append(fr)
+        return sorted(shown, key=lambda fr: (fr.x, fr.y))
+
+    def follow_sync(self):
+        """Scroll the other follow-mode frames so that each continues where the one before it ends, around the active one"""
+        frames = self.follow_frames()
+        if len(frames) < 2:
+            return
+        active = next((i for i, fr in enumerate(frames) if fr.id == self.frames.active_id), 0)
+        pads = [self.editor.pads[fr.pad_id] for fr in frames]
+        tops = [0] * len(pads)
+        tops[active] = pads[active].buf_y
+        for i in range(active + 1, len(pads)):
+            tops[i] = tops[i - 1] + pads[i - 1].height
+        for i in range(active - 1, -1, -1):
+            tops[i] = max(0, tops[i + 1] - pads[i].height)
+        for i, (fr, pad) in enumerate(zip(frames, pads)):
+            if i != active and pad.buf_y != tops[i]:
+                pad.buf_y, pad.cur_y = min(tops[i], max(len(pad.buffer) - 1, 0)), 0
+                self.editor.pad_goto(fr.pad_id, pad.buf_y, pad.buf_x + pad.cur_x)
+                self.redraw = True
+
+    def cmd_set_frame_option(self, msg: str):
+        frame = self.frames.active()
+        if frame is None:
+            return
+        options = frame.options()
+        if msg == "":
+            def value(name: str):
+                choices = ['default'] if name == 'font-size' else ['on', 'off', 'default']
+                self.minibuffer.start(f"{name} in this frame (now {options[name]}): ", choices, lambda v: self.cmd_set_frame_option(f"{name} {v}"), require_match=name != 'font-size')
+            self.minibuffer.start("Frame option: ", list(options), value, annotate=lambda name: options.get(name, ""))
+            return
+        name, _, value = msg.partition(" ")
+        value = value.strip()
+        if name not in options:
+            self.message(f"Unknown frame option {name}, use one of {', '.join(options)}")
+            return
+        if name == 'font-size':
+            if value != 'default' and value.isdigit() is False:
+                self.message(f"Invalid font size {value}, use points or default")
+                return
+            zoom = 0 if value == 'default' else round((int(value) - self.frame_renderer.font_size) / self.frame_renderer.font_mag)
+            if self.frame_renderer.zoom_valid(zoom) is False:
+                self.message(f"Font size {value} is out of range")
+                return
+            frame.zoom = zoom
+        else:
+            flags = {'on': True, 'off': False, 'default': None}
+            if value not in flags:
+                self.message(f"Invalid value {value} for {name}, use on, off or default")
+                return
+            setattr(frame, name.replace("-", "_"), flags[value])
+        self.relayout()
+        self.redraw = True
+        self.message(f"{name} {frame.options()[name]} in this frame")
+
+    def cmd_toggle_scroll_lock(self, _msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        if self.frames.active_id in self.scroll_lock:
+            del self.scroll_lock[self.frames.active_id]
+            self.message("Frame scrolls on its own")
+            return
+        self.scroll_lock[self.frames.active_id] = pad.buf_y
+        count = len(self.scroll_lock)
+        self.message(f"{count} frames scroll together" if count > 1 else "Frame scroll-locked, lock another one to scroll them together")
+
+    def scroll_lock_sync(self):
+        """Scroll the locked frames by as many lines as the one that scrolled, the active one first"""
+        pads: dict[int, int] = {}
+        for frame_id in list(self.scroll_lock):
+            fr = self.frames.get(frame_id)
+            if fr is None or self.frames.is_leaf(frame_id) is False or self.editor.pad_get(fr.pad_id) is None:
+                del self.scroll_lock[frame_id]  # closed
+            else:
+                pads[frame_id] = fr.pad_id
+        moved = [frame_id for frame_id, top in self.scroll_lock.items() if self.editor.pads[pads[frame_id]].buf_y != top]
+        if len(moved) == 0:
+            return
+        leader = self.frames.active_id if self.frames.active_id in moved else moved[0]
+        delta = self.editor.pads[pads[leader]].buf_y - self.scroll_lock[leader]
+        for frame_id, pad_id in pads.items():
+            pad = self.editor.pads[pad_id]
+            if frame_id != leader:
+                pad.buf_y = min(max(self.scroll_lock[frame_id] + delta, 0), max(len(pad.buffer) - 1, 0))
+                self.editor.pad_goto(pad_id, min(pad.buf_y + pad.cur_y, len(pad.buffer) - 1), pad.buf_x + pad.cur_x)
+            self.scroll_lock[frame_id] = pad.buf_y
+        self.redraw = True
+
+    def cmd_diff_next(self, msg: str):
+        """Go to the next (msg: previous) changed region of the diff view, wrapping around"""
+        view = self.diff_view
+        pad_id = self.active_pad()
+        if view is None:
+            return
+        changes = view.changes()
+        if len(changes) == 0:
+            self.message("No differences")
+            return
+        row, _ = self.editor.pad_cursor(pad_id)
+        if msg == 'previous':
+            target = next((start for start in reversed(changes) if start < row), changes[-1])
+        else:
+            target = next((start for start in changes if start > row), changes[0])
+        self.editor.pad_goto(pad_id, target, 0)
+        self.message(f"Change {changes.index(target) + 1} of {len(changes)}")
+
+    def cmd_diff_quit(self, msg: str):
+        """Close the diff view, msg jump: at the line of the source under the cursor"""
+        view = self.diff_view
+        if view is None:
+            return
+        self.diff_view = None
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        target: tuple[Buffer, int] | None = None
+        if msg == 'jump' and pad is not None and (pad.buffer is view.left or pad.buffer is view.right):
+            row, _ = self.editor.pad_cursor(pad_id)
+            side = 0 if pad.buffer is view.left else 1
+            source = view.sources[side]
+            lines = [r[side] for r in view.rows[:row + 1] if r[side] is not None]
+            if source is not None:
+                target = (source, lines[-1] if len(lines) > 0 else 0)
+        if self.frames.is_leaf(view.right_frame) and self.frames.is_leaf(view.left_frame):
+            self.frames.active_id = view.right_frame
+            _ = self.frames.delete()
+            self.frames.active_id = view.left_frame
+            self.relayout()
+        buffer, row = target if target is not None else (view.origin, 0)
+        pad_id = self.active_pad()
+        if pad_id >= 0:
+            self.editor.pad_show(pad_id, buffer, row)
+
+    def cmd_merge(self, _msg: str):
+        """Ours and theirs side by side above the buffer being resolved"""
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        buffer = pad.buffer
+        if len(find_conflicts(buffer)) == 0:
+            self.message("No conflict markers in this buffer")
+            return
+        if self.merge_view is not None:
+            self.cmd_merge_quit('')
+        views = [self.editor.buffer_get(name) or self.editor.buffer_create(name, [""], undo=False) for name in (f"*ours {buffer.name}*", f"*theirs {buffer.name}*")]
+        if self.frames.split(direction=Direction.VERTICAL) is False:
+            self.message("Cannot split this frame")
+            return
+        parent_idx = self.frames.parent_idx(self.frames.active_id)
+        if parent_idx is None:
+            return
+        top, result = self.frames.frames[parent_idx].c_lu, self.frames.frames[parent_idx].c_rd
+        self.frames.active_id = top
+        _ = self.frames.split(direction=Direction.HORIZONTAL)
+        top_idx = self.frames.idx(top)
+        if top_idx is None:
+            return
+        ours, theirs = self.frames.frames[top_idx].c_lu, self.frames.frames[top_idx].c_rd
+        self.relayout()
+        for frame_id, shown in ((ours, views[0]), (theirs, views[1]), (result, buffer)):
+            self.frames.active_id = frame_id
+            self.editor.pad_show(self.active_pad(), shown)
+        self.merge_view = MergeView(buffer, views[0], views[1], ours, theirs, result)
+        self.merge_refresh()
+        self.cmd_merge_next('')
+
+    def merge_refresh(self):
+        """Rebuild the ours and theirs views and mark the conflicts left in the result"""
+        view = self.merge_view
+        if view is None:
+            return
+        theme = self.frame_renderer.theme
+        conflicts = find_conflicts(view.buffer)
+        for side, shown in (('ours', view.ours), ('theirs', view.theirs)):
+            shown[:], regions = conflict_side(list(view.buffer), conflicts, side)
+            shown.highlights = {row: theme.diff_changed for start, end in regions for row in range(start, end)}
+        view.buffer.highlights = {}
+        for conflict in conflicts:
+            for row in range(conflict.start, conflict.end + 1):
+                marker = row in (conflict.start, conflict.base, conflict.middle, conflict.end)
+                view.buffer.highlights[row] = theme.diff_filler if marker else theme.diff_changed if row < conflict.middle else theme.diff_added
+        view.synced = (-1, -1)
+        self.redisplay_buffer(view.ours)
+        self.redisplay_buffer(view.theirs)
+        self.redisplay_buffer(view.buffer)
+
+    def merge_sync(self):
+        """Show the conflict at the result's cursor in the ours and theirs views"""
+        view = self.merge_view
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if view is None or pad is None or pad.buffer is not view.buffer:
+            return
+        row, _ = self.editor.pad_cursor(pad_id)
+        version = view.buffer.undo.version if view.buffer.undo is not None else 0
+        if view.synced == (version, row):
+            return
+        if view.synced[0] != version:
+            self.merge_refresh()
+        view.synced = (version, row)
+        conflicts = find_conflicts(view.buffer)
+        index = max((i for i, conflict in enumerate(conflicts) if conflict.start <= row), default=0)
+        if len(conflicts) == 0:
+            return
+        for side, shown in (('ours', view.ours), ('theirs', view.theirs)):
+            _, regions = conflict_side(list(view.buffer), conflicts, side)
+            for i, p in enumerate(self.editor.pads):
+                if p.buffer is shown:
+                    p.buf_y = max(regions[index][0] - 2, 0)
+                    self.editor.pad_goto(i, regions[index][0], 0)
+
+    def conflict_at(self, buffer: Buffer, row: int) -> Conflict | None:
+        """The conflict containing row, else the next one after it, else the last one"""
+        conflicts = find_conflicts(buffer)
+        if len(conflicts) == 0:
+            return None
+        return next((c for c in conflicts if c.start <= row <= c.end), next((c for c in conflicts if c.start > row), conflicts[-1]))
+
+    def cmd_merge_take(self, msg: str):
+        """Resolve the conflict at the cursor to ours, theirs, both or (diff3) base"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        buffer = self.merge_view.buffer if self.merge_view is not None and pad.buffer is not self.merge_view.buffer else pad.buffer
+        result_pad = pad_id if pad.buffer is buffer else next((i for i, p in enumerate(self.editor.pads) if p.buffer is buffer), pad_id)
+        row, _ = self.editor.pad_cursor(result_pad)
+        conflict = self.conflict_at(buffer, row)
+        if conflict is None:
+            self.message("No conflicts left")
+            return
+        lines = {'ours': conflict.ours(buffer), 'theirs': conflict.theirs(buffer), 'base': conflict.base_lines(buffer),
+                 'both': conflict.ours(buffer) + conflict.theirs(buffer)}.get(msg)
+        if lines is None:
+            return
+        buffer[conflict.start:conflict.end + 1] = lines
+        if len(buffer) == 0:
+            buffer.append("")
+        if buffer.undo is not None and pad.buffer is not buffer:
+            buffer.undo.record(buffer, (conflict.start, 0), (conflict.start, 0))
+        self.merge_refresh()
+        left = len(find_conflicts(buffer))
+        for i, p in enumerate(self.editor.pads):
+            if p.buffer is buffer:
+                self.editor.pad_goto(i, min(conflict.start, len(buffer) - 1), 0)
+        self.message(f"Took {msg}, {left} conflicts left" if left > 0 else f"Took {msg}, all conflicts resolved")
+
+    def cmd_merge_next(self, msg: str):
+        """Go to the next (msg: previous) conflict in the buffer, wrapping around"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        starts = [c.start for c in find_conflicts(pad.buffer)]
+        if len(starts) == 0:
+            self.message("No conflicts")
+            return
+        row, _ = self.editor.pad_cursor(pad_id)
+        if msg == 'previous':
+            target = next((start for start in reversed(starts) if start < row), starts[-1])
+        else:
+            target = next((start for start in starts if start > row), starts[0])
+        self.editor.pad_goto(pad_id, target, 0)
+        self.message(f"Conflict {starts.index(target) + 1} of {len(starts)}")
+
+    def cmd_merge_quit(self, _msg: str):
+        view = self.merge_view
+        if view is None:
+            return
+        self.merge_view = None
+        view.buffer.highlights = {}
+        for frame_id in (view.theirs_frame, view.ours_frame):
+            if self.frames.is_leaf(frame_id):
+                self.frames.active_id = frame_id
+                _ = self.frames.delete()
+        if self.frames.is_leaf(view.result_frame):
+            self.frames.active_id = view.result_frame
+        self.relayout()
+
+    def repl_keymap(self) -> Keymap:
+        return Keymap("repl", {'Return': ('repl-return', '')})
+
+    def repl_session(self, filetype: str) -> ReplSession | None:
+        """The interpreter for the file type, started and shown below the active frame if needed"""
+        session = self.repls.get(filetype)
+        if session is None:
+            command = self.config.repls.get(filetype, [])
+            if len(command) == 0:
+                self.message(f"No REPL configured for .{filetype} files" if filetype != "" else "No REPL for this buffer")
+                return None
+            pad = self.editor.pad_get(self.active_pad())
+            directory = pad.buffer.directory if pad is not None else os.getcwd()
+            process = ReplProcess(command, directory, lambda done: self.results.put(("repl", done)))
+            if process.start() is False:
+                self.message(f"Cannot start {command[0]}")
+                return None
+            name = f"*repl {filetype}*"
+            buffer = self.editor.buffer_get(name) or self.editor.buffer_create(name, [""], undo=False)
+            buffer.keymap = self.repl_keymap()
+            buffer.filetype = filetype
+            buffer.directory = directory
+            session = ReplSession(process, buffer, (len(buffer) - 1, len(buffer[-1])))
+            process.on_output = lambda text: self.repl_output(session, text)
+            process.on_exit = lambda: self.repl_exited(session)
+            self.repls[filetype] = session
+        if any(self.editor.pads[fr.pad_id].buffer is session.buffer for fr in self.frames.win_frames()[0] if fr.pad_id >= 0) is False:
+            origin = self.frames.active_id
+            if self.frames.split(direction=Direction.VERTICAL) is True:
+                parent_idx = self.frames.parent_idx(self.frames.active_id)
+                if parent_idx is not None:
+                    origin = self.frames.active_id
+                    self.frames.active_id = self.frames.frames[parent_idx].c_rd
+                self.relayout()
+            self.editor.pad_show(self.active_pad(), session.buffer, *session.input_start)
+            self.frames.active_id = origin
+        return session
+
+    def repl_output(self, session: ReplSession, text: str):
+        """Insert output before any input typed but not yet sent"""
+        buffer = session.buffer
+        row, col = session.input_start
+        pending = [buffer[row][col:]] + buffer[row + 1:]
+        del buffer[row + 1:]
+        buffer[row] = buffer[row][:col]
+        lines = text.split("\n")
+        buffer[-1] += lines[0]
+        buffer.extend(lines[1:])
+        session.input_start = (len(buffer) - 1, len(buffer[-1]))
+        buffer[-1] += pending[0]
+        buffer.extend(pending[1:])
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer:
+                self.editor.pad_goto(i, len(buffer) - 1, len(buffer[-1]))
+
+    def repl_exited(self, session: ReplSession):
+        self.repl_output(session, "\n[process exited]\n")
+        if self.repls.get(session.buffer.filetype) is session:
+            del self.repls[session.buffer.filetype]
+
+    def repl_send(self, session: ReplSession, text: str):
+        """Show text as typed input and send it, multi-line blocks end with an empty line for indentation-aware interpreters"""
+        if "\n" in text.strip("\n"):
+            text = text.rstrip("\n") + "\n\n"
+        elif text.endswith("\n") is False:
+            text += "\n"
+        buffer = session.buffer
+        row, col = session.input_start
+        del buffer[row + 1:]
+        buffer[row] = buffer[row][:col]
+        lines = text.split("\n")
+        buffer[-1] += lines[0]
+        buffer.extend(lines[1:])
+        session.input_start = (len(buffer) - 1, len(buffer[-1]))
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer:
+                self.editor.pad_goto(i, len(buffer) - 1, len(buffer[-1]))
+        session.process.send(text)
+
+    def cmd_repl(self, _msg: str):
+        """Switch to the REPL for the active buffer's file type, starting it if needed"""
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        session = next((s for s in self.repls.values() if s.buffer is pad.buffer), None) or self.repl_session(pad.buffer.filetype)
+        if session is None:
+            return
+        for fr in self.frames.win_frames()[0]:
+            if fr.pad_id >= 0 and self.editor.pads[fr.pad_id].buffer is session.buffer:
+                self.frames.active_id = fr.id
+                self.editor.pad_goto(fr.pad_id, len(session.buffer) - 1, len(session.buffer[-1]))
+                return
+
+    def cmd_repl_send(self, msg: str):
+        """Send the selection or else the current line (msg: line, buffer) to the REPL of the buffer's file type"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        buffer = pad.buffer
+        row, col = self.editor.pad_cursor(pad_id)
+        sel = pad.selection()
+        if msg == 'buffer':
+            text = "\n".join(buffer)
+        elif sel is not None and msg != 'line':
+            start, end = sel
+            end = (end[0], min(end[1] + 1, len(buffer[end[0]])))
+            text = self.editor.text_get(pad_id, start, end)
+            if self.modal is not None:
+                self.modal.set_mode(Mode.NORMAL, pad_id)
+        else:
+            text = buffer[row]
+            if msg == 'line' or sel is None:
+                self.editor.pad_goto(pad_id, min(row + 1, len(buffer) - 1), col)
+        session = self.repl_session(buffer.filetype)
+        if session is not None:
+            self.repl_send(session, text)
+
+    def cmd_repl_return(self, _msg: str):
+        """In a REPL buffer send the input after the last output"""
+        pad = self.editor.pad_get(self.active_pad())
+        session = next((s for s in self.repls.values() if pad is not None and s.buffer is pad.buffer), None)
+        if session is None:
+            self.message("The interpreter is not running")
+            return
+        row, col = session.input_start
+        text = "\n".join([session.buffer[row][col:]] + session.buffer[row + 1:])
+        self.repl_send(session, text)
+
+    def cmd_shell_filter(self, msg: str, whole: bool = False):
+        """Replace the selection, or else (or with whole) the whole buffer, by its output through the shell command msg"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        if self.refuse_read_only(pad.buffer) is True:
+            return
+        if msg == "":
+            self.minibuffer.start("Filter through: ", [], lambda command: self.cmd_shell_filter(command) if command != "" else None)
+            return
+        buffer = pad.buffer
+        sel = pad.selection() if whole is False else None
+        if sel is not None:
+            start, end = sel
+            end = (end[0], min(end[1] + 1, len(buffer[end[0]])))
+            text = self.editor.text_get(pad_id, start, end)
+            if self.modal is not None:
+                self.modal.set_mode(Mode.NORMAL, pad_id)
+        else:
+            start, end = (0, 0), (len(buffer) - 1, len(buffer[-1]))
+            text = "\n".join(buffer) + "\n"
+        version = buffer.undo.version if buffer.undo is not None else 0
+        cursor = self.editor.pad_cursor(pad_id)
+        self.message(f"Running {msg}")
+
+        def run():
+            try:
+                result = subprocess.run(msg, shell=True, input=text.encode(buffer.encoding, errors='replace'), capture_output=True, cwd=buffer.directory, timeout=60)
+                output, errors, status = result.stdout.decode(buffer.encoding, errors='replace'), result.stderr.decode('utf-8', errors='replace'), result.returncode
+            except (OSError, subprocess.TimeoutExpired) as e:
+                output, errors, status = "", str(e), -1
+            self.results.put(("filter", lambda: self.shell_filter_done(buffer, version, msg, start, end, text, cursor, output, errors, status)))
+
+        threading.Thread(target=run, daemon=True).start()
+
+    def shell_filter_done(self, buffer: Buffer, version: int, command: str, start: tuple[int, int], end: tuple[int, int], text: str,
+                          cursor: tuple[int, int], output: str, errors: str, status: int):
+        self.sound.play('build-finished')
+        if status != 0:
+            first = errors.strip().splitlines()[0] if errors.strip() != "" else f"exit status {status}"
+            self.notifications.post("filter", f"{command}: {first}", "error")
+            return
+        if (buffer.undo.version if buffer.undo is not None else 0) != version:
+            self.notifications.post("filter", f"{buffer.name} changed while {command} ran, output discarded", "warning")
+            return
+        if text.endswith("\n") is False and output.endswith("\n"):
+            output = output[:-1]
+        (r1, c1), (r2, c2) = start, end
+        whole = start == (0, 0) and end == (len(buffer) - 1, len(buffer[-1])) and text.endswith("\n")
+        if whole is True:
+            lines = output.split("\n")
+            if len(lines) > 1 and lines[-1] == "":
+                lines.pop()
+        else:
+            lines = (buffer[r1][:c1] + output + buffer[r2][c2:]).split("\n")
+        before = len(buffer)
+        if whole is True:
+            buffer[:] = lines if len(lines) > 0 else [""]
+        else:
+            buffer[r1:r2 + 1] = lines
+        if buffer.undo is not None:
+            buffer.undo.record(buffer, cursor, start)
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer:
+                row, col = self.editor.pad_cursor(i)
+                self.editor.pad_goto(i, min(row, len(buffer) - 1), col)
+        delta = len(buffer) - before
+        self.message(f"Filtered through {command}" + (f", {delta:+d} lines" if delta != 0 else ""))
+
+    def image_keymap(self) -> Keymap:
+        return Keymap("image", {
+            '+': ('image-zoom', 'in'), '=': ('image-zoom', 'in'), '-': ('image-zoom', 'out'), '0': ('image-zoom', 'fit'), '1': ('image-zoom', 'actual'),
+            'Left': ('image-pan', 'left'), 'h': ('image-pan', 'left'), 'Right': ('image-pan', 'right'), 'l': ('image-pan', 'right'),
+            'Up': ('image-pan', 'up'), 'k': ('image-pan', 'up'), 'Down': ('image-pan', 'down'), 'j': ('image-pan', 'down'),
+            })
+
+    def active_image(self) -> tuple[ImageView, int, int] | None:
+        """Image of the active frame with the pixel size of its picture area"""
+        pad = self.editor.pad_get(self.active_pad())
+        frame = self.frames.active()
+        if pad is None or pad.buffer.image is None or frame is None:
+            return None
+        char_width, line_height = self.frame_renderer.cell_size(frame.zoom)
+        return pad.buffer.image, (pad.width + pad.left_border) * char_width, pad.height * line_height
+
+    def cmd_image_zoom(self, msg: str):
+        """Zoom the image in or out by a quarter, to fit the frame or to one screen pixel per image pixel"""
+        active = self.active_image()
+        if active is None:
+            return
+        view, wx, hy = active
+        scale = view.scale(wx, hy)
+        if msg == 'fit':
+            view.zoom, view.pan_x, view.pan_y = 0.0, 0, 0
+        elif msg == 'actual':
+            view.zoom = 1.0
+        else:
+            view.zoom = min(max(scale * (1.25 if msg == 'in' else 0.8), 0.01), 64.0)
+        self.message(f"{view.width}x{view.height} at {round(view.scale(wx, hy) * 100)}%" if view.width > 0 else "")
+
+    def cmd_image_pan(self, msg: str):
+        """Move the view a tenth of the frame"""
+        active = self.active_image()
+        if active is None:
+            return
+        view, wx, hy = active
+        scale = view.scale(wx, hy)
+        dx, dy = {'left': (-1, 0), 'right': (1, 0), 'up': (0, -1), 'down': (0, 1)}.get(msg, (0, 0))
+        view.pan_x = min(max(view.pan_x + int(dx * wx / 10 / scale), -view.width // 2), view.width // 2)
+        view.pan_y = min(max(view.pan_y + int(dy * hy / 10 / scale), -view.height // 2), view.height // 2)
+
+    def cmd_export_html(self, msg: str):
+        """Write the selected lines, or else the whole buffer, to the HTML file msg"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        buffer = pad.buffer
+        if msg == "":
+            default = (buffer.filename if buffer.filename is not None else os.path.join(buffer.directory, buffer.name.strip("*"))) + ".html"
+            self.minibuffer.start(f"Export HTML to (default {abbreviate_home(default)}): ", [],
+                                  lambda path: self.cmd_export_html(os.path.expanduser(path) if path != "" else default), require_match=False)
+            return
+        sel = pad.selection()
+        first, last = (sel[0][0], sel[1][0]) if sel is not None else (0, len(buffer) - 1)
+        try:
+            with open(msg, 'w', encoding='utf-8') as file:
+                _ = file.write(html_export(buffer, self.frame_renderer.theme, first, last))
+        except OSError as e:
+            self.message(f"Cannot write {msg}: {e}")
+            return
+        if sel is not None and self.modal is not None:
+            self.modal.set_mode(Mode.NORMAL, pad_id)
+        self.message(f"Exported {last - first + 1} lines to {abbreviate_home(msg)}")
+
+    def dired_keymap(self) -> Keymap:
+        return Keymap("dired", {
+            'Return': ('dired-open', ''), 'f': ('dired-open', ''), '^': ('dired-open', '..'),
+            'm': ('dired-mark', 'mark'), 'u': ('dired-mark', 'unmark'), 'U': ('dired-mark', 'none'), 't': ('dired-mark', 'toggle'),
+            'd': ('dired-delete', ''), 'R': ('dired-rename', ''), 'C': ('dired-copy', ''),
+            '+': ('dired-create', 'directory'), 'n': ('dired-create', 'file'), 'g': ('dired-refresh', ''),
+            'C-x C-q': ('dired-edit', ''),
+            })
+
+    def dired_buffer(self, directory: str) -> Buffer:
+        """The listing of directory, created or refreshed"""
+        directory = os.path.abspath(os.path.expanduser(directory))
+        buffer = next((b for b in self.editor.buffers if b.dired is not None and b.dired.directory == directory), None)
+        if buffer is None:
+            name = os.path.basename(directory) + "/" if os.path.basename(directory) != "" else directory
+            if self.editor.buffer_get(name) is not None:
+                name = abbreviate_home(directory) + "/"
+            buffer = self.editor.buffer_create(name, [""], undo=False)
+            buffer.dired = Dired(directory, [])
+            buffer.directory = directory
+            buffer.keymap = self.dired_keymap()
+        dired = buffer.dired
+        if dired is not None:
+            buffer[:] = dired_listing(dired)
+        return buffer
+
+    def show_dired(self, directory: str):
+        pad_id = self.active_pad()
+        if pad_id < 0:
+            return
+        buffer = self.dired_buffer(directory)
+        self.editor.pad_show(pad_id, buffer, min(2, len(buffer) - 1), dired_name_col)
+
+    def dired_refresh(self, buffer: Buffer):
+        if buffer.dired is None:
+            return
+        buffer[:] = dired_listing(buffer.dired)
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer:
+                row, _ = self.editor.pad_cursor(i)
+                self.editor.pad_goto(i, min(row, len(buffer) - 1), dired_name_col)
+
+    def dired_at_point(self) -> tuple[Buffer, Dired, int] | None:
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None or pad.buffer.dired is None:
+            return None
+        row, _ = self.editor.pad_cursor(pad_id)
+        return pad.buffer, pad.buffer.dired, row
+
+    def dired_targets(self) -> tuple[Buffer, Dired, list[str]] | None:
+        """The marked entries, else the one at the cursor"""
+        at = self.dired_at_point()
+        if at is None:
+            return None
+        buffer, dired, row = at
+        if len(dired.marked) > 0:
+            return buffer, dired, sorted(dired.marked)
+        name = dired.entries[row] if row < len(dired.entries) else ""
+        if name in ("", ".."):
+            self.message("No file here")
+            return None
+        return buffer, dired, [name]
+
+    def cmd_dired(self, msg: str):
+        if msg == "":
+            pad = self.editor.pad_get(self.active_pad())
+            default = pad.buffer.directory if pad is not None else os.getcwd()
+            self.minibuffer.start(f"Directory (default {abbreviate_home(default)}): ", [], lambda path: self.show_dired(path if path != "" else default), require_match=False)
+            return
+        self.show_dired(msg)
+
+    def cmd_dired_open(self, msg: str):
+        at = self.dired_at_point()
+        if at is None:
+            return
+        _, dired, row = at
+        name = msg if msg != "" else dired.entries[row] if row < len(dired.entries) else ""
+        if name == "":
+            return
+        path = os.path.normpath(os.path.join(dired.directory, name))
+        if os.path.isdir(path):
+            origin = dired.directory
+            self.show_dired(path)
+            if name == "..":
+                # land on the directory we came from
+                parent = self.editor.pads[self.active_pad()].buffer.dired
+                if parent is not None and os.path.basename(origin) in parent.entries:
+                    self.editor.pad_goto(self.active_pad(), parent.entries.index(os.path.basename(origin)), dired_name_col)
+            return
+        self.open_file(path)
+
+    def cmd_dired_mark(self, msg: str):
+        at = self.dired_at_point()
+        if at is None:
+            return
+        buffer, dired, row = at
+        name = dired.entries[row] if row < len(dired.entries) else ""
+        if msg == 'none':
+            dired.marked.clear()
+        elif msg == 'toggle':
+            dired.marked = set(dired.entries[2:]) - dired.marked
+        elif name not in ("", ".."):
+            if msg == 'mark':
+                dired.marked.add(name)
+            else:
+                dired.marked.discard(name)
+        self.dired_refresh(buffer)
+        if msg in ('mark', 'unmark'):
+            self.editor.pad_goto(self.active_pad(), min(row + 1, len(buffer) - 1), dired_name_col)
+
+    def dired_moved(self, old: str, new: str):
+        """Buffers visiting a renamed file or a file below a renamed directory follow it"""
+        for buffer in self.editor.buffers:
+            if buffer.filename is None or (buffer.filename != old and buffer.filename.startswith(old + os.sep) is False):
+                continue
+            self.watcher.unwatch(buffer.filename)
+            buffer.filename = new + buffer.filename[len(old):]
+            buffer.name = os.path.basename(buffer.filename)
+            buffer.directory = os.path.dirname(buffer.filename)
+            self.watcher.watch(buffer.filename)
+
+    def cmd_dired_delete(self, _msg: str):
+        targets = self.dired_targets()
+        if targets is None:
+            return
+        buffer, dired, names = targets
+
+        def delete(answer: str):
+            if answer != "yes":
+                return
+            for name in names:
+                path = os.path.join(dired.directory, name)
+                try:
+                    if os.path.isdir(path) and not os.path.islink(path):
+                        shutil.rmtree(path)
+                    else:
+                        os.remove(path)
+                except OSError as e:
+                    self.notifications.post("dired", f"Cannot delete {name}: {e.strerror}", "error")
+                dired.marked.discard(name)
+            self.dired_refresh(buffer)
+            self.message(f"Deleted {len(names)} entries" if len(names) > 1 else f"Deleted {names[0]}")
+
+        what = f"{len(names)} marked entries" if len(names) > 1 else names[0]
+        self.minibuffer.start(f"Delete {what}? ", ["no", "yes"], delete)
+
+    def dired_transfer(self, verb: str, action: Callable[[str, str], object]):
+        """Rename or copy the targets: one entry to a new name, several into a directory"""
+        targets = self.dired_targets()
+        if targets is None:
+            return
+        buffer, dired, names = targets
+
+        def accept(target: str):
+            if target == "":
+                return
+            target = os.path.join(dired.directory, os.path.expanduser(target))
+            if len(names) > 1 and os.path.isdir(target) is False:
+                self.message(f"{target} is not a directory")
+                return
+            done = 0
+            for name in names:
+                source = os.path.join(dired.directory, name)
+                destination = os.path.join(target, name) if os.path.isdir(target) else target
+                try:
+                    _ = action(source, destination)
+                    done += 1
+                except OSError as e:
+                    self.notifications.post("dired", f"Cannot {verb.lower()} {name}: {e.strerror}", "error")
+            dired.marked.clear()
+            self.dired_refresh(buffer)
+            self.message(f"{verb} {done} of {len(names)} entries" if len(names) > 1 else f"{verb} {names[0]}" if done == 1 else "")
+
+        prompt = f"{verb} {len(names)} entries into: " if len(names) > 1 else f"{verb} {names[0]} to: "
+        self.minibuffer.start(prompt, [], accept, require_match=False)
+
+    def cmd_dired_rename(self, _msg: str):
+        def move(source: str, destination: str):
+            _ = shutil.move(source, destination)
+            self.dired_moved(os.path.abspath(source), os.path.abspath(destination))
+
+        self.dired_transfer("Renamed", move)
+
+    def cmd_dired_copy(self, _msg: str):
+        self.dired_transfer("Copied", lambda source, destination: shutil.copytree(source, destination) if os.path.isdir(source) else shutil.copy2(source, destination))
+
+    def cmd_dired_create(self, msg: str):
+        """Create a directory (msg: file, an empty file that is then opened)"""
+        at = self.dired_at_point()
+        if at is None:
+            return
+        buffer, dired, _ = at
+
+        def create(name: str):
+            if name == "":
+                return
+            path = os.path.join(dired.directory, os.path.expanduser(name))
+            try:
+                if msg == 'file':
+                    with open(path, 'x'):
+                        pass
+                else:
+                    os.makedirs(path)
+            except OSError as e:
+                self.message(f"Cannot create {name}: {e.strerror}")
+                return
+            self.dired_refresh(buffer)
+            if msg == 'file':
+                self.open_file(path)
+
+        self.minibuffer.start("Create file: " if msg == 'file' else "Create directory: ", [], create, require_match=False)
+
+    def cmd_dired_refresh(self, _msg: str):
+        at = self.dired_at_point()
+        if at is not None:
+            self.dired_refresh(at[0])
+
+    def cmd_dired_edit(self, msg: str):
+        """Make the names editable; msg commit renames the entries whose names were changed, abort restores the listing"""
+        at = self.dired_at_point()
+        if at is None:
+            return
+        buffer, dired, _ = at
+        if msg == "":
+            buffer.keymap = Keymap("dired-edit", {'C-c C-c': ('dired-edit', 'commit'), 'C-c C-k': ('dired-edit', 'abort')})
+            self.message("Edit names, C-c C-c renames, C-c C-k aborts")
+            return
+        if msg == 'commit':
+            renamed = 0
+            if len(buffer) != len(dired.entries):
+                self.message("Lines were added or removed, only names can be edited")
+                return
+            for row, name in enumerate(dired.entries):
+                if name in ("", ".."):
+                    continue
+                new = buffer[row][dired_name_col:].rstrip("/") if len(buffer[row]) > dired_name_col else ""
+                if new == "" or new == name:
+                    continue
+                try:
+                    source, destination = os.path.join(dired.directory, name), os.path.join(dired.directory, new)
+                    if os.path.exists(destination):
+                        raise FileExistsError(17, "File exists")
+                    os.rename(source, destination)
+                    self.dired_moved(source, destination)
+                    renamed += 1
+                except OSError as e:
+                    self.notifications.post("dired", f"Cannot rename {name} to {new}: {e.strerror}", "error")
+            self.message(f"Renamed {renamed} entries" if renamed != 1 else "Renamed 1 entry")
+        buffer.keymap = self.dired_keymap()
+        self.dired_refresh(buffer)
+
+    def collab_connection(self, sock: socket.socket) -> CollabConnection:
+        return CollabConnection(sock, lambda connection, message: self.results.put(("collab", lambda: self.collab_message(connection, message))),
+                                lambda connection: self.results.put(("collab", lambda: self.collab_closed(connection))))
+
+    def collab_peer(self, site: int, name: str) -> CollabPeer:
+        return CollabPeer(name, self.frame_renderer.theme.peers[site % len(self.frame_renderer.theme.peers)])
+
+    def cmd_collab_share(self, msg: str):
+        """Share the active buffer with other led instances on [address:]port msg, only this machine's without an address"""
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        if self.collab is not None:
+            self.message(f"Already collaborating on {self.collab.buffer.name}, collab-leave ends it")
+            return
+        if msg == "":
+            self.minibuffer.start("Share on [address:]port (default 127.0.0.1:7808): ", [], lambda port: self.cmd_collab_share(port or "7808"), require_match=False)
+            return
+        address, _, port = msg.rpartition(":")
+        if port.isdigit() is False:
+            self.message(f"Not a port: {port}")
+            return
+        address = address if address != "" else "127.0.0.1"
+        try:
+            server = socket.create_server((address, int(port)))
+        except OSError as e:
+            self.message(f"Cannot listen on {msg}: {e.strerror}")
+            return
+        buffer = pad.buffer
+        session = CollabSession(buffer, Rga(1, "\n".join(buffer)), secrets.token_urlsafe(16), server)
+        self.collab = session
+
+        def accept():
+            while True:
+                try:
+                    sock, _ = server.accept()
+                except OSError:
+                    break  # closed by collab-leave
+                self.results.put(("collab", lambda sock=sock: self.collab_accepted(session, sock)))
+
+        threading.Thread(target=accept, daemon=True).start()
+        host = socket.gethostname() if address == "0.0.0.0" else address
+        self.message(f"Sharing {buffer.name}, others join with {host}:{port}/{session.token}")
+
+    def collab_accepted(self, session: CollabSession, sock: socket.socket):
+        if self.collab is not session:
+            sock.close()
+            return
+        connection = self.collab_connection(sock)
+        session.connections.append(connection)
+        connection.start()
+
+    def cmd_collab_join(self, msg: str):
+        """Edit the buffer shared at host:port/token msg"""
+        if self.collab is not None:
+            self.message(f"Already collaborating on {self.collab.buffer.name}, collab-leave ends it")
+            return
+        if msg == "":
+            self.minibuffer.start("Join host:port/token: ", [], lambda address: self.cmd_collab_join(address) if address != "" else None, require_match=False)
+            return
+        address, _, token = msg.partition("/")
+        host, _, port = address.rpartition(":")
+        if host == "" or port.isdigit() is False:
+            self.message(f"Expected host:port/token, not {msg}")
+            return
+        self.message(f"Connecting to {address}")
+
+        def connect():
+            try:
+                sock = socket.create_connection((host, int(port)), timeout=10)
+                sock.settimeout(None)
+            except OSError as e:
+                self.notifications.post("collab", f"Cannot connect to {address}: {e.strerror or e}", "error")
+                return
+            self.results.put(("collab", lambda: self.collab_connected(sock, host, token)))
+
+        threading.Thread(target=connect, daemon=True).start()
+
+    def collab_connected(self, sock: socket.socket, host: str, token: str):
+        if self.collab is not None:
+            sock.close()
+            return
+        buffer = self.editor.buffer_create(f"*joining {host}*", [""])
+        session = CollabSession(buffer, Rga(0), token)
+        connection = self.collab_connection(sock)
+        session.connections.append(connection)
+        self.collab = session
+        connection.start()
+        connection.send({'type': 'hello', 'token': token, 'name': os.environ.get("USER", "led")})
+
+    def collab_message(self, connection: CollabConnection, message: dict[str, object]):
+        session = self.collab
+        if session is None or connection not in session.connections:
+            return
+        kind = message.get('type')
+        host = session.server is not None
+        if host is True and kind != 'hello' and connection.site == 0:
+            return
+        if host is True:
+            message['site'] = connection.site  # participants only speak for themselves
+        site = cast(int, message.get('site', 0))
+        if kind == 'hello' and host is True:
+            if hmac.compare_digest(str(message.get('token', "")).encode(), session.token.encode()) is False:
+                connection.send({'type': 'refused', 'reason': "wrong token"})
+                connection.close()
+                return
+            connection.site = session.next_site
+            session.next_site += 1
+            name = str(message.get('name', f"site {connection.site}"))
+            peers: dict[str, object] = {str(s): p.name for s, p in session.peers.items()}
+            peers['1'] = os.environ.get("USER", "led")
+            connection.send({'type': 'welcome', 'site': connection.site, 'name': session.buffer.name, 'chars': session.rga.to_list(), 'peers': peers})
+            session.broadcast({'type': 'joined', 'site': connection.site, 'name': name}, exclude=connection)
+            session.peers[connection.site] = self.collab_peer(connection.site, name)
+            session.cursor_at = None
+            self.notifications.post("collab", f"{name} joined {session.buffer.name}")
+        elif kind == 'welcome' and host is False:
+            session.rga = Rga.from_list(site, cast(list[list[object]], message.get('chars', [])))
+            session.text = session.rga.text()
+            buffer = session.buffer
+            buffer.name = f"{message.get('name', 'shared')}@{connection.sock.getpeername()[0]}"
+            buffer[:] = session.text.split("\n")
+            buffer.undo = UndoTree(buffer)
+            session.version = buffer.undo.version
+            for peer_site, name in cast(dict[str, str], message.get('peers', {})).items():
+                session.peers[int(peer_site)] = self.collab_peer(int(peer_site), name)
+            self.editor.pad_show(self.active_pad(), buffer)
+            self.message(f"Joined {buffer.name} with {', '.join(p.name for p in session.peers.values())}")
+        elif kind == 'refused':
+            self.notifications.post("collab", f"The host refused to share: {message.get('reason', '')}", "error")
+            self.collab_leave()
+        elif kind == 'ops':
+            if host is True:
+                session.broadcast(message, exclude=connection)
+            self.collab_apply(session, cast(list[list[object]], message.get('ops', [])))
+        elif kind == 'cursor':
+            if host is True:
+                session.broadcast(message, exclude=connection)
+            peer = session.peers.get(site)
+            if peer is not None:
+                peer.cursor, peer.anchor = char_id(message.get('cursor')), char_id(message.get('anchor'))
+                peer.selecting, peer.placed = message.get('selecting') is True, True
+                self.collab_place_peers(session)
+        elif kind == 'joined':
+            session.peers[site] = self.collab_peer(site, str(message.get('name', "")))
+            self.notifications.post("collab", f"{session.peers[site].name} joined {session.buffer.name}")
+        elif kind == 'left':
+            peer = session.peers.pop(site, None)
+            if peer is not None:
+                self.notifications.post("collab", f"{peer.name} left {session.buffer.name}")
+                self.collab_place_peers(session)
+
+    def collab_closed(self, connection: CollabConnection):
+        session = self.collab
+        if session is None or connection not in session.connections:
+            return
+        session.connections.remove(connection)
+        if session.server is None:
+            self.notifications.post("collab", f"The host stopped sharing {session.buffer.name}", "warning")
+            self.collab_leave()
+            return
+        peer = session.peers.pop(connection.site, None)
+        if peer is not None:
+            session.broadcast({'type': 'left', 'site': connection.site})
+            self.notifications.post("collab", f"{peer.name} left {session.buffer.name}")
+            self.collab_place_peers(session)
+
+    def collab_leave(self):
+        session = self.collab
+        if session is None:
+            return
+        self.collab = None
+        if session.server is not None:
+            session.server.close()
+        for connection in session.connections:
+            connection.close()
+        session.buffer.peer_cursors = []
+
+    def cmd_collab_leave(self, _msg: str):
+        """Stop sharing, or leave the shared buffer; the text stays as an ordinary buffer"""
+        if self.collab is None:
+            self.message("Not collaborating")
+            return
+        name = self.collab.buffer.name
+        self.collab_leave()
+        self.message(f"Left {name}")
+
+    def cmd_collab_participants(self, _msg: str):
+        """Show who else edits the shared buffer"""
+        if self.collab is None:
+            self.message("Not collaborating")
+            return
+        names = [f"{p.name} (site {site})" for site, p in sorted(self.collab.peers.items())]
+        self.show_popup([f"{self.collab.buffer.name}:"] + (names if len(names) > 0 else ["nobody else yet"]))
+
+    def collab_send_edits(self, session: CollabSession):
+        buffer = session.buffer
+        version = buffer.undo.version if buffer.undo is not None else 0
+        if version == session.version:
+            return
+        session.version = version
+        text = "\n".join(buffer)
+        if text == session.text:
+            return
+        ops = session.rga.edit(session.text, text)
+        session.text = text
+        session.broadcast({'type': 'ops', 'site': session.rga.site, 'ops': ops})
+        self.collab_place_peers(session)
+
+    def collab_sync(self):
+        """Send the edits and the cursor of the shared buffer's active pad since the last sync"""
+        session = self.collab
+        if session is None or session.rga.site == 0:
+            return
+        self.collab_send_edits(session)
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None or pad.buffer is not session.buffer:
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        at = (session.version, row, col, pad.sel_anchor)
+        if at == session.cursor_at:
+            return
+        session.cursor_at = at
+        anchor = session.rga.id_before(text_offset(pad.buffer, *pad.sel_anchor)) if pad.sel_anchor is not None else None
+        session.broadcast({'type': 'cursor', 'site': session.rga.site, 'cursor': session.rga.id_before(text_offset(pad.buffer, row, col)),
+                           'anchor': anchor, 'selecting': pad.sel_anchor is not None})
+
+    def collab_apply(self, session: CollabSession, ops: list[list[object]]):
+        """Integrate remote operations and put the text into the buffer, keeping the local cursors at their characters"""
+        self.collab_send_edits(session)
+        buffer = session.buffer
+        rga = session.rga
+        marks: list[tuple[int, CharId | None, CharId | None]] = []
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer:
+                anchor = rga.id_before(text_offset(buffer, *pad.sel_anchor)) if pad.sel_anchor is not None else None
+                marks.append((i, rga.id_before(text_offset(buffer, *self.editor.pad_cursor(i))), anchor))
+        for op in ops:
+            if rga.apply(op) is False:
+                self.log.warning(f"Dropping collaboration operation {op} on an unknown character")
+        text = rga.text()
+        if text == session.text:
+            return
+        session.text = text
+        before = self.editor.pad_cursor(marks[0][0]) if len(marks) > 0 else (0, 0)
+        buffer[:] = text.split("\n")
+        for i, cursor, anchor in marks:
+            pad = self.editor.pads[i]
+            if pad.sel_anchor is not None:
+                pad.sel_anchor = offset_position(text, rga.offset_after(anchor))
+            self.editor.pad_goto(i, *offset_position(text, rga.offset_after(cursor)))
+        if buffer.undo is not None:
+            buffer.undo.record(buffer, before, self.editor.pad_cursor(marks[0][0]) if len(marks) > 0 else (0, 0))
+            session.version = buffer.undo.version
+        self.collab_place_peers(session)
+
+    def collab_place_peers(self, session: CollabSession):
+        text = session.text
+        session.buffer.peer_cursors = []
+        for peer in session.peers.values():
+            if peer.placed is False:
+                continue
+            cursor = offset_position(text, session.rga.offset_after(peer.cursor))
+            anchor = offset_position(text, session.rga.offset_after(peer.anchor)) if peer.selecting is True else None
+            session.buffer.peer_cursors.append(PeerCursor(peer.name, peer.color, cursor, anchor))
+
+    def hunk_at(self, buffer: Buffer, row: int) -> Hunk | None:
+        for hunk in buffer.hunks:
+            if hunk.start <= row < hunk.end or (hunk.start == hunk.end and (row == hunk.start or (hunk.start == len(buffer) and row == hunk.start - 1))):
+                return hunk
+        return None
+
+    def cmd_next_hunk(self, msg: str):
+        """Go to the start of the next (msg: previous) changed region against git, wrapping around"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None or len(pad.buffer.hunks) == 0:
+            self.message("No changes against git")
+            return
+        row, _ = self.editor.pad_cursor(pad_id)
+        starts = [min(hunk.start, len(pad.buffer) - 1) for hunk in pad.buffer.hunks]
+        if msg == 'previous':
+            target = next((start for start in reversed(starts) if start < row), starts[-1])
+        else:
+            target = next((start for start in starts if start > row), starts[0])
+        self.editor.pad_goto(pad_id, target, 0)
+        hunk = self.hunk_at(pad.buffer, target)
+        if hunk is not None:
+            self.message(f"{hunk.kind()}: {max(hunk.end - hunk.start, hunk.base_end - hunk.base_start)} lines")
+
+    def cmd_revert_hunk(self, _msg: str):
+        """Replace the changed region at the cursor by the staged lines"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        buffer = pad.buffer
+        row, _ = self.editor.pad_cursor(pad_id)
+        hunk = self.hunk_at(buffer, row)
+        if hunk is None or buffer.git_base is None or buffer.git_version != (buffer.undo.version if buffer.undo is not None else 0):
+            self.message("No change against git here")
+            return
+        buffer[hunk.start:hunk.end] = buffer.git_base[hunk.base_start:hunk.base_end]
+        if len(buffer) == 0:
+            buffer.append("")
+        self.editor.pad_goto(pad_id, min(hunk.start, len(buffer) - 1), 0)
+        self.redisplay_buffer(buffer)
+        self.message(f"Reverted {hunk.kind()} lines")
+
+    def lsp_attach(self, buffer: Buffer):
+        command = self.config.language_servers.get(buffer.filetype, [])
+        if buffer.filename is None or buffer.lsp is not None or len(command) == 0 or buffer.hex is True:
+            return
+        root = find_project_root(buffer.directory)
+        client = self.lsp_clients.get((buffer.filetype, root))
+        if client is None:
+            client = LspClient(command, root, lambda done: self.results.put(("lsp", done)))
+            if client.start() is False:
+                self.log.info(f"No language server {command[0]} for {buffer.name}")
+                return
+            client.on_notification = self.lsp_notification
+            self.lsp_clients[(buffer.filetype, root)] = client
+            self.notifications.post("lsp", f"Started {command[0]} in {abbreviate_home(root)}")
+        buffer.lsp = client
+        buffer.lsp_version = buffer.undo.version if buffer.undo is not None else 0
+        client.notify('textDocument/didOpen', {'textDocument': {
+            'uri': path_uri(buffer.filename), 'languageId': lsp_language_ids.get(buffer.filetype, buffer.filetype),
+            'version': buffer.lsp_version, 'text': "\n".join(buffer) + "\n"}})
+
+    def lsp_sync(self):
+        """Send the full text of buffers changed since the last sync"""
+        for buffer in self.editor.buffers:
+            if buffer.lsp is None or buffer.undo is None or buffer.filename is None or buffer.undo.version == buffer.lsp_version:
+                continue
+            buffer.lsp_version = buffer.undo.version
+            buffer.lsp.notify('textDocument/didChange', {
+                'textDocument': {'uri': path_uri(buffer.filename), 'version': buffer.lsp_version},
+                'contentChanges': [{'text': "\n".join(buffer) + "\n"}]})
 
-        except FileNotFoundError:
-            self.log.error(f"File {filename} not found.")
-        except Exception as e:
-            self.log.error(f"An error occurred: {e}")
-
-class Frame:
-    def __init__(self, id:int, content: Content | None = None):
-        self.c_lu: int = 0
-        self.c_rd: int = 0
-        self.direction: Direction = Direction.NONE
-        self.ratio:float = 0.0
-        self.id: int = id
-        self.x:int = 0
-        self.y:int = 0
-        self.wx:int = 0
-        self.hy:int = 0
-        self.content: Content | None = content
-
-class Frames:
-    def __init__(self, theme:ColorTheme = default_color_theme):
-        self.log: logging.Logger = logging.getLogger("Frames")
-        self.fr_id:int = 0
-        self.frames: list[Frame] = []
-        self.root_id:int = self.create()
-        self.active_id:int = self.root_id
-        self.theme: ColorTheme = theme
+    def lsp_notification(self, method: str, params: dict[str, object]):
+        if method == 'textDocument/publishDiagnostics':
+            path = uri_path(cast(str, params.get('uri', "")))
+            buffer = next((b for b in self.editor.buffers if b.filename == path), None)
+            if buffer is None:
+                return
+            buffer.diagnostics = []
+            for d in cast(list[dict[str, object]], params.get('diagnostics', [])):
+                rng = cast(dict[str, dict[str, int]], d['range'])
+                row, end_row = rng['start']['line'], rng['end']['line']
+                line = buffer[row] if row < len(buffer) else ""
+                end_line = buffer[end_row] if end_row < len(buffer) else ""
+                buffer.diagnostics.append(Diagnostic(row, col_from_utf16(line, rng['start']['character']), end_row, col_from_utf16(end_line, rng['end']['character']),
+                                                     cast(int, d.get('severity', 1)), cast(str, d.get('message', "")), cast(str, d.get('source', "")),
+                                                     tuple(cast(list[int], d.get('tags', [])))))
+            self.redisplay_buffer(buffer)
+        elif method == 'window/showMessage':
+            self.notifications.post("lsp", cast(str, params.get('message', "")), "error" if params.get('type') == 1 else "info")
 
-    def get_id(self) -> int:
-        self.fr_id += 1
-        return self.fr_id
+    def lsp_position(self, pad_id: int) -> tuple[Buffer, dict[str, object]] | None:
+        """Buffer at the active pad and the textDocument/position params for its cursor, once the buffer is synced"""
+        pad = self.editor.pad_get(pad_id)
+        if pad is None or pad.buffer.lsp is None or pad.buffer.filename is None:
+            self.message("No language server for this buffer")
+            return None
+        self.lsp_sync()
+        row, col = self.editor.pad_cursor(pad_id)
+        return pad.buffer, {'textDocument': {'uri': path_uri(pad.buffer.filename)}, 'position': {'line': row, 'character': utf16_col(pad.buffer[row], col)}}
 
-    def create(self, content: Content | None = None) -> int:
-        id:int = self.get_id()
-        fr:Frame = Frame(id, content)
-        self.frames.append(fr)
-        return id
+    def cmd_next_diagnostic(self, msg: str):
+        """Go to the next (msg: previous) diagnostic of the active buffer, wrapping around"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None or len(pad.buffer.diagnostics) == 0:
+            self.message("No diagnostics")
+            return
+        cursor = self.editor.pad_cursor(pad_id)
+        ordered = sorted(pad.buffer.diagnostics, key=lambda d: (d.row, d.col))
+        if msg == 'previous':
+            target = next((d for d in reversed(ordered) if (d.row, d.col) < cursor), ordered[-1])
+        else:
+            target = next((d for d in ordered if (d.row, d.col) > cursor), ordered[0])
+        self.editor.pad_goto(pad_id, target.row, target.col)
+        first = target.message.splitlines()[0] if target.message != "" else ""
+        self.message(f"{severity_names[target.severity]}: {first}")
 
-    def idx(self, id:int) -> int | None:
-        for index, fr in enumerate(self.frames):
-            if fr.id == id:
-                return index
-        return None
+    def cmd_list_diagnostics(self, _msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        root = find_project_root(pad.buffer.directory if pad is not None else os.getcwd())
+        lines: list[str] = []
+        for buffer in self.editor.buffers:
+            if buffer.filename is None:
+                continue
+            name = os.path.relpath(buffer.filename, root)
+            for d in sorted(buffer.diagnostics, key=lambda d: (d.severity, d.row, d.col)):
+                source = f" [{d.source}]" if d.source != "" else ""
+                lines.append(f"{name}:{d.row + 1}:{d.col + 1}: {severity_names[d.severity]}: {d.message.splitlines()[0] if d.message != '' else ''}{source}")
+        self.grep_origin = self.frames.active_id
+        self.show_results("*diagnostics*", root, f"{len(lines)} diagnostics", sorted(lines, key=lambda l: 0 if ": error:" in l else 1))
 
-    def parent_idx(self, id:int) -> int | None:
-        for index, fr in enumerate(self.frames):
-            if fr.c_lu == id or fr.c_rd == id:
-                return index
-        return None
+    def cmd_lsp_complete(self, _msg: str):
+        pad_id = self.active_pad()
+        target = self.lsp_position(pad_id)
+        if target is None or target[0].lsp is None:
+            return
+        buffer, params = target
+        row, col = self.editor.pad_cursor(pad_id)
 
-    def delete(self, id:int=0) -> bool:
-        if id == 0:
-            id = self.active_id
-        active:bool = False
-        if id == self.active_id:
-            active = True
-        lc = self.idx(id)
-        if lc is None:
-            return False
-        fr = self.frames[lc]
-        if fr.c_lu != 0 or fr.c_rd != 0:
-            return False
-        if fr.id == self.root_id:
-            return False
-            
-        p_idx = self.parent_idx(id)
-        if p_idx is None:
-            self.log.error("Illegal state in delete (1)")
-            return False
+        def show(result: object):
+            items = cast(list[dict[str, object]], result.get('items', []) if isinstance(result, dict) else result if isinstance(result, list) else [])
+            if len(items) == 0:
+                self.message("No completions")
+                return
+            labels = {cast(str, item.get('insertText', item['label'])): cast(str, item.get('detail', "")) for item in items}
+            match = re.search(r'\w+$', buffer[row][:col])
+            start = match.start() if match is not None else col
 
-        p_fr = self.frames[p_idx]
-        if p_fr.id == self.root_id:
-            if p_fr.c_lu == id:
-                self.root_id = p_fr.c_rd
-            elif p_fr.c_rd == id:
-                self.root_id = p_fr.c_lu
-            else:
-                self.log.error("Illegal state in delete (2)")
-                return False
-            self.frames.remove(p_fr)
-            self.frames.remove(fr)
-            if active:
-                self.next()
-            return True
-        else:
-            pp_idx = self.parent_idx(p_fr.id)
-            if pp_idx is None:
-                self.log.error("Illegal state in delete (3)")
-                return False
-            pp_fr = self.frames[pp_idx]
-            if p_fr.c_lu == id:
-                if pp_fr.c_lu == p_fr.id:
-                    pp_fr.c_lu = p_fr.c_rd
-                elif pp_fr.c_rd == p_fr.id:
-                    pp_fr.c_rd = p_fr.c_rd
-                else:
-                    self.log.error("Illegal state in delete (4)")
-                    return False
-                self.frames.remove(p_fr)
-                self.frames.remove(fr)
-            elif p_fr.c_rd == id:
-                if pp_fr.c_lu == p_fr.id:
-                    pp_fr.c_lu = p_fr.c_lu
-                elif pp_fr.c_rd == p_fr.id:
-                    pp_fr.c_rd = p_fr.c_lu
-                else:
-                    self.log.error("Illegal state in delete (5)")
-                    return False
-                self.frames.remove(p_fr)
-                self.frames.remove(fr)
-            else:
-                print("Illegal state in delete (6)")
-                return False
-            if active:
-                self.next()
-        return True
+            def accept(text: str):
+                if self.editor.pads[pad_id].buffer is not buffer:
+                    return
+                self.editor.text_delete(pad_id, (row, start), (row, col))
+                self.editor.pad_goto(pad_id, *self.editor.text_insert(pad_id, (row, start), text))
 
-    def split(self, id: int=0, direction: Direction = Direction.HORIZONTAL) -> bool:
-        if id == 0:
-            id = self.active_id
-        idx = self.idx(id)
-        if idx is None:
-            return False
-        fr = self.frames[idx]
-        if fr.c_lu != 0 or fr.c_rd != 0:
-            return False
-        fr.direction = direction
-        fr.ratio = 0.5
-        fr.c_lu = self.create(fr.content)
-        fr.c_rd = self.create(content=fr.content)
-        fr.content = None  # Clear content as it is now split into two frames
-        if fr.id == self.active_id:
-            self.active_id = fr.c_lu
-        return True
+            self.minibuffer.start("Complete: ", list(labels.keys()), accept, annotate=lambda label: labels.get(label, ""))
+            if match is not None:
+                self.minibuffer.text = match.group(0)
+                self.minibuffer.update_matches()
 
-    def size(self, id:int=0, delta:float=0.0):
-        if id==0:
-            id = self.active_id
-        if id == self.root_id:
+        buffer.lsp.request('textDocument/completion', params, show)
+
+    def lsp_locations(self, result: object) -> list[tuple[str, int, int]]:
+        """file, row and utf-16 column of a Location, Location[] or LocationLink[] result"""
+        items = cast(list[dict[str, object]], result if isinstance(result, list) else [result] if isinstance(result, dict) else [])
+        locations: list[tuple[str, int, int]] = []
+        for item in items:
+            uri = cast(str, item.get('targetUri', item.get('uri', "")))
+            span = cast(dict[str, dict[str, int]], item.get('targetSelectionRange', item.get('range', {})))
+            start = span.get('start', {})
+            if uri.startswith("file:"):
+                locations.append((uri_path(uri), start.get('line', 0), start.get('character', 0)))
+        return locations
+
+    def show_locations(self, what: str, locations: list[tuple[str, int, int]]):
+        """Jump to a single location, list several in a results buffer"""
+        if len(locations) == 0:
+            self.message(f"No {what} found")
             return
-        if delta == 0.0:
+        lines: dict[str, list[str]] = {}
+        for filename, _, _ in locations:
+            if filename not in lines:
+                buffer = next((b for b in self.editor.buffers if b.filename == filename), None)
+                lines[filename] = list(buffer) if buffer is not None else read_lines(filename) if os.path.exists(filename) else []
+        resolved: list[tuple[str, int, int]] = []
+        for filename, row, units in locations:
+            text = lines[filename][row] if row < len(lines[filename]) else ""
+            resolved.append((filename, row, col_from_utf16(text, units)))
+        if len(resolved) == 1:
+            filename, row, col = resolved[0]
+            self.open_file(filename, row, col)
             return
-        p_idx = self.parent_idx(id)
-        if p_idx is None:
+        root = find_project_root(os.path.dirname(resolved[0][0]))
+        results = [f"{os.path.relpath(f, root)}:{row + 1}:{col + 1}: {lines[f][row].strip() if row < len(lines[f]) else ''}" for f, row, col in sorted(set(resolved))]
+        self.grep_origin = self.frames.active_id
+        self.show_results(f"*{what}*", root, f"{len(results)} {what}", results)
+
+    def cmd_lsp_definition(self, _msg: str):
+        target = self.lsp_position(self.active_pad())
+        if target is None or target[0].lsp is None:
             return
-        p_fr = self.frames[p_idx]
-        if p_fr.c_lu == id:
-            if delta > 0:
-                if p_fr.ratio + delta < 0.8:
-                    p_fr.ratio += delta
-            else:
-                if p_fr.ratio + delta > 0.2:
-                    p_fr.ratio += delta
-        elif p_fr.c_rd == id:
-            if delta > 0:
-                if p_fr.ratio - delta > 0.2:
-                    p_fr.ratio -= delta
-            else:
-                if p_fr.ratio - delta < 0.8:
-                    p_fr.ratio -= delta
-
-    def geometry(self, x: int, y: int, wx:int, hy:int):
-        def _geometry(id: int, x: int, y:int, wx:int, hy: int, level:int):
-            f_idx = self.idx(id)
-            if f_idx is None:
-                print("Internal error (1) in geometry")
+        buffer, params = target
+        buffer.lsp.request('textDocument/definition', params, lambda result: self.show_locations("definitions", self.lsp_locations(result)))
+
+    def cmd_lsp_references(self, _msg: str):
+        target = self.lsp_position(self.active_pad())
+        if target is None or target[0].lsp is None:
+            return
+        buffer, params = target
+        params['context'] = {'includeDeclaration': True}
+        buffer.lsp.request('textDocument/references', params, lambda result: self.show_locations("references", self.lsp_locations(result)))
+
+    def jump_push(self):
+        """Remember where the active frame is before it jumps elsewhere"""
+        pad_id = self.active_pad()
+        if self.editor.pad_get(pad_id) is None:
+            return
+        if self.frames.active_id not in self.jump_lists:
+            self.jump_lists[self.frames.active_id] = JumpList()
+        self.jump_lists[self.frames.active_id].push(self.editor.pad_view(pad_id))
+
+    def cmd_set_mark(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        pad.buffer.marks.push(self.editor.pad_cursor(pad_id))
+        self.message("Mark set")
+
+    def cmd_pop_mark(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        position = pad.buffer.marks.pop()
+        if position is None:
+            self.message("No mark set in this buffer")
+            return
+        self.editor.pad_goto(pad_id, *position)
+
+    def cmd_jump_back(self, _msg: str):
+        pad_id = self.active_pad()
+        jumps = self.jump_lists.get(self.frames.active_id)
+        view = jumps.back(self.editor.pad_view(pad_id)) if jumps is not None and self.editor.pad_get(pad_id) is not None else None
+        if view is None:
+            self.message("No earlier position")
+            return
+        self.editor.pad_restore_view(pad_id, view)
+
+    def cmd_jump_forward(self, _msg: str):
+        jumps = self.jump_lists.get(self.frames.active_id)
+        view = jumps.forward() if jumps is not None else None
+        if view is None:
+            self.message("No later position")
+            return
+        self.editor.pad_restore_view(self.active_pad(), view)
+
+    def outline_keymap(self) -> Keymap:
+        return Keymap("outline", {
+            'Return': ('outline-jump', ''), 'SPC': ('outline-jump', 'show'), 'Tab': ('outline-toggle', ''),
+            'g': ('outline', 'refresh'), 'q': ('outline-quit', ''),
+            })
+
+    def show_outline(self, source: Buffer, symbols: list[OutlineSymbol]):
+        buffer = self.editor.buffer_get("*outline*")
+        if buffer is None:
+            buffer = self.editor.buffer_create("*outline*", [""], undo=False)
+            buffer.keymap = self.outline_keymap()
+        lines, shown = flatten_outline(symbols)
+        buffer[:] = lines if len(lines) > 0 else [f"No symbols in {source.name}"]
+        self.outline = (source, symbols, shown)
+        for fr in self.frames.win_frames()[0]:
+            pad = self.editor.pad_get(fr.pad_id)
+            if pad is not None and pad.buffer is buffer:
+                row, _ = self.editor.pad_cursor(fr.pad_id)
+                self.editor.pad_goto(fr.pad_id, min(row, len(buffer) - 1), 0)
                 return
-            fr: Frame = self.frames[f_idx]
-            fr.x = x
-            fr.y = y
-            fr.wx = wx
-            fr.hy = hy
-            if fr.c_lu != 0 and fr.c_rd != 0:
-                if fr.direction == Direction.HORIZONTAL:
-                    _geometry(fr.c_lu, fr.x, fr.y, int(fr.wx * fr.ratio), fr.hy, level+1 )
-                    _geometry(fr.c_rd, fr.x+int(fr.wx*fr.ratio), fr.y, int(fr.wx * (1-fr.ratio)), fr.hy, level+1 )
-                elif fr.direction == Direction.VERTICAL:
-                    _geometry(fr.c_lu, fr.x, fr.y, fr.wx, int(fr.hy * fr.ratio), level+1)
-                    _geometry(fr.c_rd, fr.x, fr.y+int(fr.hy*fr.ratio), fr.wx, int(fr.hy*(1-fr.ratio)), level+1)
-            else:
-                if fr.c_lu !=0 or fr.c_rd !=0:
-                    self.log.error("Illegal state: incomplete sub-tree-node in geometry!")
-                    return
+        self.outline_origin = self.frames.active_id
+        if self.frames.split(direction=Direction.HORIZONTAL) is True:
+            parent_idx = self.frames.parent_idx(self.frames.active_id)
+            if parent_idx is not None:
+                self.frames.frames[parent_idx].ratio = 0.7
+                self.outline_origin = self.frames.active_id
+                self.frames.active_id = self.frames.frames[parent_idx].c_rd
+            self.relayout()
+        self.editor.pad_show(self.active_pad(), buffer)
+
+    def cmd_outline(self, msg: str):
+        """Symbols of the buffer in the active frame (msg refresh: of the outlined one) in a side frame"""
+        if msg == 'refresh' and self.outline is not None:
+            source = self.outline[0]
+        else:
+            pad = self.editor.pad_get(self.active_pad())
+            if pad is None:
+                return
+            source = pad.buffer
+        if source.lsp is None or source.filename is None:
+            self.show_outline(source, regex_outline(list(source)))
+            return
+        self.lsp_sync()
+        source.lsp.request('textDocument/documentSymbol', {'textDocument': {'uri': path_uri(source.filename)}},
+                           lambda result: self.show_outline(source, lsp_outline(result)))
+
+    def cmd_outline_toggle(self, _msg: str):
+        if self.outline is None:
+            return
+        source, symbols, shown = self.outline
+        row, _ = self.editor.pad_cursor(self.active_pad())
+        if row < len(shown) and len(shown[row].children) > 0:
+            shown[row].collapsed = not shown[row].collapsed
+            self.show_outline(source, symbols)
+
+    def cmd_outline_jump(self, msg: str):
+        """Move the cursor of the source frame to the symbol at the cursor, and there unless msg is show"""
+        if self.outline is None:
+            return
+        source, _, shown = self.outline
+        row, _ = self.editor.pad_cursor(self.active_pad())
+        if row >= len(shown):
+            return
+        symbol = shown[row]
+        outline_id = self.frames.active_id
+        if self.frames.is_leaf(self.outline_origin) is False or self.outline_origin == outline_id:
+            return
+        self.frames.active_id = self.outline_origin
+        self.jump_push()
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is not None and pad.buffer is not source:
+            self.editor.pad_show(pad_id, source)
+        line = source[symbol.row] if symbol.row < len(source) else ""
+        col = col_from_utf16(line, symbol.col) if source.lsp is not None else symbol.col
+        self.editor.pad_goto(pad_id, symbol.row, col)
+        if msg == 'show':
+            self.frames.active_id = outline_id
+
+    def cmd_outline_quit(self, _msg: str):
+        self.outline = None
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None or pad.buffer.name != "*outline*":
+            return
+        if self.frames.is_leaf(self.outline_origin) and self.outline_origin != self.frames.active_id:
+            _ = self.frames.delete()
+            self.frames.active_id = self.outline_origin
+            self.relayout()
 
-        _geometry(self.root_id, x, y, wx, hy, 0)
+    def cmd_lsp_hover(self, _msg: str):
+        pad_id = self.active_pad()
+        target = self.lsp_position(pad_id)
+        if target is None or target[0].lsp is None:
+            return
+        buffer, params = target
+        cursor = self.editor.pad_cursor(pad_id)
 
-    def display_geometry(self):
-        def _display_geometry(id:int, level:int):
-            f_idx = self.idx(id)
-            if f_idx is None:
-                print("Internal error (1) in geometry")
+        def show(result: object):
+            if isinstance(result, dict) is False:
+                self.message("Nothing known here")
                 return
-            fr: Frame = self.frames[f_idx]
-            print(f"{" "*level} id={fr.id} ratio={fr.ratio}, [{fr.x},{fr.y}] {fr.wx}x{fr.hy} ", end="")
-            if fr.id == self.active_id:
-                print("* ", end="")
-            if fr.c_lu != 0 and fr.c_rd != 0:
-                print("->")
-                _display_geometry(fr.c_lu, level+1)
-                _display_geometry(fr.c_rd, level+1)
-            else:
-                if fr.c_lu !=0 or fr.c_rd !=0:
-                    print("Illegal state: incomplete sub-tree-node!")
-                    return
-                print("[w]")
-
-        _display_geometry(self.root_id, 0)
-
-    def win_frames(self) -> tuple[list[Frame], int]:
-        wfr: list[Frame] = []
-        a_idx = -1
-        for fr in self.frames:
-            if fr.c_lu == 0 and fr.c_rd == 0:
-                wfr.append(fr)
-                if fr.id == self.active_id:
-                    a_idx = len(wfr) - 1
-        return (wfr, a_idx)
-
-    def next(self):
-        wt: tuple[list[Frame], int] = self.win_frames()
-        wfr: list[Frame]
-        a_idx:int
-        wfr, a_idx = wt
-        if a_idx<len(wfr) - 1:
-            self.active_id = wfr[a_idx+1].id
-        else:
-            self.active_id = wfr[0].id
- 
-class FrameRenderer:
-    def __init__(self, w:int, h:int, renderer: sdl2.ext.Renderer, font_path:str, theme:ColorTheme=default_color_theme):
-        self.log: logging.Logger = logging.getLogger("FrameRenderer")
-        self.theme: ColorTheme = theme
-        self.renderer: sdl2.ext.Renderer = renderer
-        rw: ctypes.c_int = ctypes.c_int(0)
-        rh: ctypes.c_int = ctypes.c_int(0)
-        sdl2.SDL_GetRendererOutputSize(self.renderer.sdlrenderer, rw, rh);  # pyright: ignore[reportUnknownMemberType]
-        if rw.value != w:
-            widthScale = rw.value / w
-            heightScale = rh.value / h
+            contents = cast(dict[str, object], result).get('contents')
+            parts = contents if isinstance(contents, list) else [contents]
+            text = "\n".join(cast(str, p['value']) if isinstance(p, dict) else str(p) for p in cast(list[object], parts) if p is not None)
+            lines = [l for l in text.splitlines() if l.strip() != "" and l.strip() != "```" and l.startswith("```") is False]
+            if len(lines) > 0 and self.editor.pads[pad_id].buffer is buffer:
+                self.show_popup(lines[:12], pad_id, cursor)
 
-            if widthScale != heightScale:
-                self.log.warning(f"WARNING: width scale {widthScale} != height scale {heightScale}")
-            else:
-                self.log.info(f"Scale: {widthScale}")
-            # sdl2.SDL_RenderSetScale(self.renderer.sdlrenderer, widthScale, heightScale);
-        if os.path.exists(font_path) is False:
-            self.log.error(f"Font {font_path} does not exist")
-        # sdl2.ext.RenderSetScale(self.renderer,2,2)
-        self.font_mag:int = 2
-        self.dpi:int = 144
-        font_size = 8 * self.font_mag
-        self.font: sdl2.sdlttf.TTF_Font = sdl2.sdlttf.TTF_OpenFontDPI(font_path.encode('utf-8'), font_size, self.dpi, self.dpi)  # pyright: ignore[reportUnknownMemberType] # , reportUnannotatedClassAttribute]
-        sdl2.sdlttf.TTF_SetFontHinting(self.font, sdl2.sdlttf.TTF_HINTING_LIGHT_SUBPIXEL)  # pyright: ignore[reportUnknownMemberType]
-        rect = self.render_text("a", 0, 0)
-        if rect is not None:
-            self.char_width: int = rect.w
-            self.char_height: int = rect.h
-            self.log.info(f"Char-sizes: {self.char_width}, {self.char_height}")
+        buffer.lsp.request('textDocument/hover', params, show)
+
+    def file_changed(self, path: str):
+        buffer = next((b for b in self.editor.buffers if b.filename == path), None)
+        if buffer is None:
+            return
+        if buffer.image is not None:
+            self.frame_renderer.forget_image(path)
+            return
+        if os.path.exists(path) is False:
+            self.notifications.post("files", f"{abbreviate_home(path)} was deleted", "warning")
+            return
+        if content_hash(read_lines(path, buffer.hex)) == buffer.disk_hash:
+            return  # touched or rewritten with the same content
+        if self.config.auto_revert is True and buffer.modified() is False:
+            self.revert_buffer(buffer)
+            self.notifications.post("files", f"Reverted {buffer.name}, it changed on disk")
+            return
+        buffer.changed_on_disk = True
+        self.redisplay_buffer(buffer)
+        self.notifications.post("files", f"{buffer.name} changed on disk", "warning")
+        if self.minibuffer.active is False:
+            self.minibuffer.start(f"{buffer.name} changed on disk, reload? ", ["yes", "no"], lambda answer: self.revert_buffer(buffer) if answer == "yes" else None)
+
+    def redisplay_buffer(self, buffer: Buffer):
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer:
+                row, col = self.editor.pad_cursor(i)
+                self.editor.pad_goto(i, row, col)
+
+    def revert_buffer(self, buffer: Buffer):
+        """Replace the contents by the file on disk, as an undoable change"""
+        if buffer.filename is None:
+            return
+        if buffer.hex is True:
+            lines = read_lines(buffer.filename, True)
         else:
-            self.log.error("Cannot determine character dimensions!")
-        self.line_spacing_extra:int = 0
-        script = "Tibt".encode('utf-8')
-        sdl2.sdlttf.TTF_SetFontScriptName(self.font, script)  # pyright:ignore[reportUnknownMemberType]
+            lines, buffer.encoding, buffer.newline = Content().load_text(buffer.filename)
+        buffer[:] = lines
+        buffer.disk_hash = content_hash(lines)
+        buffer.changed_on_disk = False
+        if buffer.undo is not None:
+            buffer.undo.record(buffer, (0, 0), (0, 0))
+        self.redisplay_buffer(buffer)
 
-    def render_text(self, text:str, x:int, y:int) -> sdl2.SDL_Rect | None:
-        if text == "":
+    def cmd_revert_buffer(self, _msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None or pad.buffer.filename is None:
+            self.message("No file to revert from")
             return
-        color_fg = sdl2.SDL_Color(self.theme.foreground[0], self.theme.foreground[1], self.theme.foreground[2], self.theme.foreground[3])
-        color_bg = sdl2.SDL_Color(self.theme.background[0], self.theme.background[1], self.theme.background[2], self.theme.background[3])
+        self.revert_buffer(pad.buffer)
+        self.message(f"Reverted {pad.buffer.name}")
 
-        # Surface = sdl2.sdlttf.TTF_RenderUTF8_Solid(self.font, text.encode(), color)
-        surface = sdl2.sdlttf.TTF_RenderUTF8_LCD(self.font, text.encode(), color_fg, color_bg)  # pyright:ignore[reportUnknownMemberType, reportUnknownVariableType]
-        texture = sdl2.SDL_CreateTextureFromSurface(self.renderer.sdlrenderer, surface)  # pyright: ignore[reportUnknownVariableType, reportUnknownMemberType]
-        rect = sdl2.SDL_Rect(x, y, surface.contents.w // self.font_mag, surface.contents.h // self.font_mag)  # pyright: ignore[reportUnknownMemberType, reportUnknownArgumentType]
-        sdl2.SDL_FreeSurface(surface)  # pyright: ignore[reportUnknownMemberType]
-        sdl2.SDL_RenderCopy(self.renderer.sdlrenderer, texture, None, rect)  # pyright: ignore[reportUnknownMemberType]
-        sdl2.SDL_DestroyTexture(texture)  # pyright: ignore[reportUnknownMemberType]
-        return rect
+    def open_file(self, filename: str, row: int = 0, col: int = 0):
+        pad_id = self.active_pad()
+        if pad_id < 0:
+            return
+        self.jump_push()
+        if parse_remote(filename) is not None:
+            self.open_remote(filename, row, col)
+            return
+        if os.path.isdir(filename):
+            self.show_dired(filename)
+            return
+        known = len(self.editor.buffers)
+        buffer = self.editor.buffer_open(filename)
+        if len(self.editor.buffers) > known:
+            self.buffer_opened(buffer)
+        elif self.swap is not None and buffer.modified() is False:
+            self.swap_offer(buffer)
+        self.editor.pad_show(pad_id, buffer, row, col)
+        self.recent_files.add(filename)
 
-    def render(self, frames:Frames):
-        def _render(id:int, frames: Frames):
-            idx: int | None = frames.idx(id)
-            if idx is None:
+    def server_start(self, path: str | None = None):
+        """Accept led --client requests on the unix socket, unless another instance is serving it"""
+        path = path if path is not None else server_socket_path()
+        if os.path.exists(path):
+            try:
+                with socket.socket(socket.AF_UNIX) as probe:
+                    probe.connect(path)
+                self.log.info(f"Another led serves {path}")
                 return
-            frame = frames.frames[idx]
-            rect = sdl2.SDL_Rect(frame.x, frame.y, frame.wx, frame.hy)
-            if frame.id == frames.active_id:
-                self.renderer.draw_rect(rect, color=self.theme.active_border)  # pyright: ignore[reportUnknownMemberType]
+            except OSError:
+                os.unlink(path)  # left behind by an instance that crashed
+        try:
+            os.makedirs(os.path.dirname(path), mode=0o700, exist_ok=True)
+            server = socket.socket(socket.AF_UNIX)
+            server.bind(path)
+            os.chmod(path, 0o600)
+            server.listen()
+        except OSError as e:
+            self.log.error(f"Cannot serve {path}: {e}")
+            return
+        self.server = server
+
+        def serve(connection: socket.socket):
+            try:
+                with connection.makefile('rb') as file:
+                    line = file.readline()
+                request = json.loads(line) if line.strip() != b"" else None
+            except (OSError, json.JSONDecodeError) as e:
+                self.log.warning(f"Ignoring client request: {e}")
+                request = None
+            if isinstance(request, dict):
+                self.results.put(("client", lambda: self.client_request(connection, cast(dict[str, object], request))))
             else:
-                self.renderer.draw_rect(rect, color=self.theme.border)  # pyright: ignore[reportUnknownMemberType]
-            if frame.c_lu!=0 and frame.c_rd!=0:
-                _render(frame.c_lu, frames)
-                _render(frame.c_rd, frames)
-
-        _render(frames.root_id, frames)
-
-@dataclass()
-class Pad:
-    screen_pos_x: int
-    screen_pos_y: int
-    width: int
-    height: int
-    left_border: int
-    bottom_border: int
-    cur_x: int
-    cur_y: int
-    buffer: list[str]
-    buf_x: int
-    buf_y: int
-    screen: list[str]
-    color_theme: ColorTheme
-
-
-class ReplEditor():
-    def __init__(self, color_theme:ColorTheme=default_color_theme):
-        # ANSI Escapes https://gist.github.com/fnky/458719343aabd01cfb17a3a4f7296797
-        self.log: logging.Logger = logging.getLogger("ReplEditor")
-        self.color_theme: ColorTheme = color_theme
-        self.editor_esc: bool = False
-        self.pads: list[Pad] = []
-
-    def canvas_print_at(self, msg: str, y:int, x:int, flush:bool = False, scroll:bool=False):
-        # _ = self.render_text(msg, x*self.char_width, y*(self.char_height + self.line_spacing_extra))
-        # self.cur_pos_x = x + len(msg)
-        # self.cur_pos_y = y
-        pass
-
-    def pad_print_at(self, pad_index:int, msg: str, y:int, x:int, flush:bool = False, scroll:bool=False, border:bool=False):
-        if pad_index >= len(self.pads):
-            return
-        _pad = self.pads[pad_index]
-        if border is False:
+                connection.close()
+
+        def accept():
+            while True:
+                try:
+                    connection, _ = server.accept()
+                except OSError:
+                    break  # closed by server_stop()
+                threading.Thread(target=serve, args=(connection,), daemon=True).start()
+
+        threading.Thread(target=accept, daemon=True).start()
+
+    def server_stop(self):
+        if self.server is None:
+            return
+        path = cast(str, self.server.getsockname())
+        self.server.close()
+        self.server = None
+        try:
+            os.unlink(path)
+        except OSError:
             pass
-            # self.repl.canvas_print_at(msg, y+pad.screen_pos_y, x+pad.screen_pos_x, flush=flush, scroll=scroll)
-        else:
+
+    def client_frame(self, path: str) -> int:
+        """Leaf frame for a file sent by led --client, by the first rule in config.client_frames that applies"""
+        leaves = [fr for fr in self.frames.win_frames()[0] if fr.pad_id >= 0]
+        for rule in self.config.client_frames:
+            if rule == 'showing':
+                showing = next((fr for fr in leaves if self.editor.pads[fr.pad_id].buffer.filename == path), None)
+                if showing is not None:
+                    return showing.id
+            elif rule == 'largest' and len(leaves) > 0:
+                return max(leaves, key=lambda fr: fr.wx * fr.hy).id
+            elif rule == 'active':
+                return self.frames.active_id
+            elif rule == 'split' and self.frames.split(direction=Direction.VERTICAL) is True:
+                parent_idx = self.frames.parent_idx(self.frames.active_id)
+                self.relayout()
+                if parent_idx is not None:
+                    return self.frames.frames[parent_idx].c_rd
+            elif rule not in ('showing', 'largest', 'active', 'split'):
+                self.log.warning(f"Ignoring unknown client_frames rule {rule}")
+        return self.frames.active_id
+
+    def client_request(self, connection: socket.socket, request: dict[str, object]):
+        """Open the files of a led --client request, each as [path, row, col], and tell the client what happened"""
+        opened: list[str] = []
+        for entry in cast(list[list[object]], request.get('files', [])):
+            path, row, col = cast(str, entry[0]), cast(int, entry[1]), cast(int, entry[2])
+            self.frames.active_id = self.client_frame(path)
+            self.frame_selected()
+            self.open_file(path, row, col)
+            opened.append(abbreviate_home(path))
+        sdl2.SDL_RaiseWindow(self.window.window)  # pyright: ignore[reportUnknownMemberType]
+        if len(opened) > 0:
+            self.message(f"Opened {', '.join(opened)} for a client")
+        try:
+            connection.sendall((json.dumps({'opened': len(opened)}) + "\n").encode('utf-8'))
+        except OSError:
             pass
-            # self.repl.canvas_print_at(msg, y+pad.screen_pos_y, x+pad.screen_pos_x-pad.left_border, flush=flush, scroll=scroll)
-
-    def pad_create(self, buffer:list[str], height: int, width:int, offset_y:int, offset_x:int, left_border:int, bottom_border:int, color_theme: ColorTheme | None) -> int:
-        if color_theme is None:
-            color_theme = self.color_theme
-        cur_x_offset, cur_y_offset = 0, 0, #  self.repl.cursor_start_offset_get()
-        pad: Pad = Pad(
-            screen_pos_x = cur_x_offset + offset_x + left_border,
-            screen_pos_y = cur_y_offset + offset_y,
-            width = width-left_border,
-            height = height-bottom_border,
-            left_border = left_border,
-            bottom_border = bottom_border,
-            cur_x = 0,
-            cur_y = 0,
-            color_theme = color_theme,
-            screen = [' ' * width] * height,
-            buffer = buffer,
-            buf_x = 0,
-            buf_y = 0
-            )
-        self.pads.append(pad)
-        pad_index = len(self.pads)-1
-        self.pad_display(pad_index)
-        return pad_index
-    
-    def pad_get(self, padIndex: int) -> Pad | None:
-        if padIndex >=0 and padIndex<len(self.pads):
-            return self.pads[padIndex]
-        else:
-            self.log.error(f"Invalid padIndex: {padIndex}")
+        connection.close()
+
+    def cmd_recent_files(self, _msg: str):
+        files = [abbreviate_home(f) for f in self.recent_files.existing()]
+        if len(files) == 0:
+            self.message("No recent files")
+            return
+        self.minibuffer.start("Recent file: ", files, lambda filename: self.open_file(os.path.expanduser(filename)))
+
+    def cmd_bookmark_set(self, msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        if pad.buffer.filename is None:
+            self.message("Only positions in files can be bookmarked")
+            return
+        if msg == "":
+            default = pad.buffer.name
+            self.minibuffer.start(f"Set bookmark (default {default}): ", [], lambda name: self.cmd_bookmark_set(name if name != "" else default), require_match=False)
+            return
+        row, col = self.editor.pad_cursor(pad_id)
+        moved = msg in self.bookmarks.marks
+        self.bookmarks.set(msg, Bookmark(pad.buffer.filename, row, col, pad.buffer[row]))
+        self.bookmarks_refresh()
+        self.message(f"Bookmark {msg} {'moved to' if moved is True else 'set at'} line {row + 1}")
+
+    def bookmark_at_cursor(self) -> str | None:
+        """Name of the bookmark on the cursor line when *Bookmarks* is shown in the active frame"""
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None or pad.buffer.name != "*Bookmarks*":
             return None
+        row = self.editor.pad_cursor(pad_id)[0]
+        names = self.bookmarks.names()
+        return names[row - 1] if 0 < row <= len(names) else None
 
-    def pad_display(self, pad_index:int, set_cursor:bool = True, update_from_buffer:bool=True):
-        if pad_index >= len(self.pads):
+    def bookmark_choose(self, prompt: str, msg: str, action: Callable[[str], None]):
+        """action with the bookmark named by msg, at the cursor in *Bookmarks* or chosen in the minibuffer"""
+        name = msg if msg != "" else self.bookmark_at_cursor()
+        if name is not None:
+            if name not in self.bookmarks.marks:
+                self.message(f"No bookmark {name}")
+                return
+            action(name)
             return
-        pad = self.pads[pad_index]
-        # self.repl.color_set(self.schema['fg'], self.schema['bg'])
-        if update_from_buffer is True:
-            for i in range(pad.height):
-                if i+pad.buf_y < len(pad.buffer):
-                    pad.screen[i] = " " # buffer[i+pad.buf_y][pad.buf_x:pad.buf_x+pad.width]
-                    pad.screen[i] += ' ' * (pad.width - len(pad.screen[i]))
-                else:
-                    pad.screen[i] = ' ' * pad.width
-        for i in range(pad.height):
-            self.pad_print_at(pad_index, pad.screen[i], i, 0)
-        if pad.left_border > 0:
-            # self.repl.color_set(self.schema['fg'], self.schema['lb'])
-            for i in range(pad.height):
-                self.pad_print_at(pad_index, f"  {i+pad.buf_y:3d} ", i, 0, border=True)
-        if pad.bottom_border > 0:
-            # self.repl.color_set(self.schema['fg'], self.schema['bb'])
-            for i in range(pad.height, pad.height+pad.bottom_border):
-                status_msg = ' ' * pad.left_border + f"Doms editor ({pad.cur_y+pad.buf_y},{pad.cur_x+pad.buf_x})"
-                gl = pad.left_border + pad.width
-                status_msg = status_msg[:gl]
-                status_msg += ' ' * (gl - len(status_msg))
-                self.pad_print_at(pad_index, status_msg, i, 0, border=True)
-        if set_cursor is True:
-            self.pad_print_at(pad_index, "", pad.cur_y, pad.cur_x)
-        # self.repl.canvas_render_show()
-
-    def pad_move(self, pad_id:int, dx:int | None = None, dy:int | None = None, x:int | None = None, y: int | None = None) -> bool:
-        changed: bool = False
-        if pad_id>= len(self.pads):
-            return changed
-        pad = self.pads[pad_id]
-        if x is None:
-            if dx is not None:
-                if dx < 0:
-                    if pad.cur_x + dx >=0:
-                        pad.cur_x += dx
-                        changed=True
-                    elif pad.buf_x + pad.cur_x + dx >= 0:
-                        pad.buf_x += dx
-                        if pad.buf_x < 0:
-                            pad.buf_x = 0
-                            pad.cur_x = 0
-                        changed = True
-                    else:
-                        pad.buf_x = 0
-                        pad.cur_x = 0
-                        changed = True
-                elif dx > 0:
-                    len_x = len(pad.buffer[pad.buf_y+pad.cur_y])
-                    if pad.buf_x + pad.cur_x < len_x:
-                        if pad.cur_x < pad.width:
-                            pad.cur_x += dx
-                        else:
-                            pad.buf_x += dx
-                        changed = True
-                    else:
-                        pass  # EOL, don't expand
-        else:
-            if x == -1:
-                len_x = len(pad.buffer[pad.buf_y+pad.cur_y])
-                len_w = len_x - pad.width
-                if len_w < 0:
-                    len_w = 0
-                pad.buf_x = len_w
-                pad.cur_x = len_x - pad.buf_x
-                changed = True
-            elif x == 0:
-                pad.buf_x = 0
-                pad.cur_x = 0
-                changed = True
-            else:
-                len_x = len(pad.buffer[pad.buf_y+pad.cur_y])
-                if x > len_x:
-                    x= len_x
-                if x <= pad.width:
-                    pad.buf_x = 0
-                    pad.cur_x = x
-                else:
-                    pad.buf_x = x
-                    pad.cur_x = 0
-                changed = True
-        if y is None:
-            if dy is not None:
-                if dy < 0:
-                    if pad.cur_y + dy >=0:
-                        pad.cur_y += dy
-                        changed=True
-                    elif pad.buf_y + pad.cur_y + dy >= 0:
-                        pad.buf_y += dy
-                        if pad.buf_y < 0:
-                            pad.buf_y = 0
-                            pad.cur_y = 0
-                        changed = True
-                    else:
-                        pad.buf_y = 0
-                        pad.cur_y = 0
-                        changed = True
-                elif dy > 0:
-                    if pad.buf_y + pad.cur_y < len(pad.buffer) - dy:
-                        if pad.cur_y < pad.height - 1:
-                            pad.cur_y += dy
-                        else:
-                            pad.buf_y += dy
-                        changed = True
-        else:
-            if y == -1:
-                len_y = len(pad.buffer)
-                len_h = len_y - pad.height
-                if len_h < 0:
-                    len_h = 0
-                pad.buf_y = len_h
-                pad.cur_y = len_y - pad.buf_y
-                changed = True
-            elif y == 0:
-                pad.buf_y = 0
-                pad.cur_y = 0
-                changed = True
-            else:
-                len_y = len(pad.buffer)
-                if y > len_y:
-                    y= len_y
-                if y <= pad.height:
-                    pad.buf_y = 0
-                    pad.cur_y = y
-                else:
-                    pad.buf_y = y
-                    pad.cur_y = 0
-                changed = True
-        len_x = len(pad.buffer[pad.buf_y+pad.cur_y])
-        delta = len_x - (pad.buf_x + pad.cur_x)
-        if delta < 0:
-            if pad.cur_x + delta >= 0:
-                pad.cur_x += delta
-            else:
-                pad.buf_x += delta
-                if pad.buf_x < 0:
-                    pad.buf_x = 0
-                    pad.cur_x = 0
-            changed = True
-        if pad.cur_x == pad.width:
-            pad.buf_x += 1
-            pad.cur_x -= 1
-            changed = True
-        while pad.cur_y >= pad.height:
-            pad.buf_y += 1
-            if pad.cur_y > 0:
-                pad.cur_y -= 1
-        if pad.cur_y >= pad.height:
-            print(f"Pad_y: {pad.cur_y} error")
-            exit(1)
-        return changed
+        if len(self.bookmarks.marks) == 0:
+            self.message("No bookmarks, C-x r m sets one")
+            return
+        self.minibuffer.start(prompt, self.bookmarks.names(), action, self.bookmarks.describe)
 
-    def create_editor(self, buffer: list[str], height: int, width:int = 0, offset_y:int =0, offset_x:int =0, color_theme: ColorTheme | None=None, line_no:bool=False, status_line:bool=False, debug:bool=False) -> int:
-        # tinp: InputEvent | None
-        left_border:int = 0
-        bottom_border:int = 0
-        if line_no is True:
-            left_border = 6
-        if status_line is True:
-            bottom_border = 1
-        pad_id = self.pad_create(buffer, height, width, offset_y, offset_x, left_border, bottom_border, color_theme)
-        # self.repl.cursor_show()
-        self.editor_esc = False
-        return pad_id
-
-    def editor_event(self, pad_id: int, cmd: str, msg: str, debug: bool = False):
-        if debug is True:
-            hex_msg = f"{bytearray(msg, encoding='utf-8')}"
-            print(f"[{cmd},{msg},{hex_msg}]")
-            # self.input_queue.task_done()
-        else:
-            pad = self.pad_get(pad_id)
-            if pad is None:
-                print(f"Pad with id {pad_id} not found")
+    def cmd_bookmark_jump(self, msg: str):
+        self.bookmark_choose("Jump to bookmark: ", msg, self.bookmark_jump)
+
+    def bookmark_jump(self, name: str):
+        mark = self.bookmarks.marks[name]
+        if parse_remote(mark.filename) is not None:
+            self.open_file(mark.filename, mark.row, mark.col)
+            return
+        if os.path.exists(mark.filename) is False:
+            self.message(f"The file of bookmark {name}, {abbreviate_home(mark.filename)}, is gone")
+            return
+        self.open_file(mark.filename)
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is not None:
+            self.editor.pad_show(pad_id, pad.buffer, mark.locate(pad.buffer), mark.col)
+
+    def cmd_bookmark_delete(self, msg: str):
+        def delete(name: str):
+            _ = self.bookmarks.delete(name)
+            self.bookmarks_refresh()
+            self.message(f"Bookmark {name} deleted")
+
+        self.bookmark_choose("Delete bookmark: ", msg, delete)
+
+    def cmd_bookmark_rename(self, msg: str):
+        def rename(name: str):
+            def to(new_name: str):
+                if new_name == "" or new_name == name:
+                    return
+                if self.bookmarks.rename(name, new_name) is False:
+                    self.message(f"There is a bookmark {new_name} already")
+                    return
+                self.bookmarks_refresh()
+
+            self.minibuffer.start(f"Rename bookmark {name} to: ", [], to, require_match=False)
+
+        self.bookmark_choose("Rename bookmark: ", msg, rename)
+
+    def cmd_bookmark_list(self, _msg: str):
+        buffer = self.editor.buffer_get("*Bookmarks*")
+        if buffer is None:
+            buffer = self.editor.buffer_create("*Bookmarks*", [""], undo=False)
+            buffer.keymap = Keymap("bookmarks", {
+                'Return': ('bookmark-jump', ''), 'd': ('bookmark-delete', ''), 'R': ('bookmark-rename', ''), 'g': ('bookmark-list', ''),
+                })
+        self.bookmarks_refresh()
+        self.editor.pad_show(self.active_pad(), buffer, min(1, len(buffer) - 1))
+
+    def bookmarks_refresh(self):
+        """Update *Bookmarks* if it was listed, keeping the cursors on their lines where there still are some"""
+        buffer = self.editor.buffer_get("*Bookmarks*")
+        if buffer is None:
+            return
+        buffer[:] = self.bookmarks.lines() if len(self.bookmarks.marks) > 0 else ["No bookmarks, C-x r m sets one"]
+        for i, pad in enumerate(self.editor.pads):
+            if pad.buffer is buffer:
+                row, col = self.editor.pad_cursor(i)
+                self.editor.pad_goto(i, row, col)
+
+    def cmd_find_file(self, msg: str):
+        if msg != "":
+            self.open_file(msg)
+            return
+        self.minibuffer.start("Find file: ", [], lambda filename: self.open_file(os.path.expanduser(filename)) if filename != "" else None)
+
+    def cmd_find_file_read_only(self, msg: str):
+        if msg == "":
+            self.minibuffer.start("Find file read-only: ", [], lambda filename: self.cmd_find_file_read_only(os.path.expanduser(filename)) if filename != "" else None)
+            return
+        self.open_file(msg)
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is not None and pad.buffer.filename is not None:
+            pad.buffer.read_only = True
+            self.editor.pad_display(self.active_pad(), False)
+
+    def refuse_read_only(self, buffer: Buffer) -> bool:
+        """True, telling so, if the buffer rejects edits"""
+        if buffer.read_only is False:
+            return False
+        self.message(f"{buffer.name} is read-only, C-x C-q makes it editable")
+        self.sound.play('bell')
+        return True
+
+    def cmd_toggle_read_only(self, _msg: str):
+        """Allow or reject edits of the buffer, also of a file without write permission, which then may fail to save"""
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        pad.buffer.read_only = not pad.buffer.read_only
+        self.editor.pad_display(self.active_pad(), False)
+        self.message(f"{pad.buffer.name} is {'read-only' if pad.buffer.read_only is True else 'editable'}")
+
+    def cmd_format_buffer(self, _msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        if pad is None:
+            return
+        command = self.buffer_config(pad.buffer).format_command
+        if command == "":
+            self.message(f"No format_command for {pad.buffer.name}, set it in {project_config_name} or the config")
+            return
+        self.cmd_shell_filter(command, whole=True)
+
+    def cmd_build(self, _msg: str):
+        pad = self.editor.pad_get(self.active_pad())
+        directory = pad.buffer.directory if pad is not None else os.getcwd()
+        root = find_project_root(directory)
+        command = self.buffer_config(pad.buffer).build_command if pad is not None else self.config.build_command
+        if command == "":
+            self.message(f"No build_command for {root}, set it in {project_config_name} or the config")
+            return
+        self.grep_origin = self.frames.active_id
+        self.message(f"Building in {root}: {command}")
+
+        def run():
+            try:
+                result = subprocess.run(command, shell=True, capture_output=True, cwd=root, timeout=600)
+                output, status = (result.stdout + result.stderr).decode('utf-8', errors='replace'), result.returncode
+            except (OSError, subprocess.TimeoutExpired) as e:
+                output, status = str(e), -1
+            self.results.put(("build", lambda: self.build_done(root, command, output, status)))
+
+        threading.Thread(target=run, daemon=True).start()
+
+    def build_done(self, root: str, command: str, output: str, status: int):
+        """The output in *build*, where Return jumps to the file:line: of an error"""
+        self.sound.play('build-finished')
+        outcome = "finished" if status == 0 else f"failed with exit status {status}"
+        self.notifications.post("build", f"{command} {outcome}", "info" if status == 0 else "error")
+        self.show_results("*build*", root, f"{command} in {root} {outcome}", output.splitlines())
+
+    def grep_keymap(self) -> Keymap:
+        return Keymap("grep", {'Return': ('grep-jump', '')})
+
+    def cmd_grep(self, msg: str):
+        if msg == "":
+            self.minibuffer.start("Grep: ", [], lambda pattern: self.cmd_grep(pattern) if pattern != "" else None)
+            return
+        pad = self.editor.pad_get(self.active_pad())
+        root = find_project_root(pad.buffer.directory if pad is not None else os.getcwd())
+        config = self.buffer_config(pad.buffer) if pad is not None else self.config
+        self.grep_origin = self.frames.active_id
+        self.message(f"Searching {root} for {msg}")
+
+        def search():
+            results = grep_project(root, msg, exclude=config.exclude)
+            self.notifications.post("grep", f"{len(results)} matches for {msg}")
+            self.results.put(("grep", lambda: self.show_grep_results(root, msg, results)))
+
+        threading.Thread(target=search, daemon=True).start()
+
+    def show_grep_results(self, root: str, pattern: str, results: list[str]):
+        self.show_results("*grep*", root, f"{len(results)} matches for {pattern} in {root}", results)
+
+    def show_results(self, name: str, root: str, header: str, results: list[str]):
+        """file:line: lines in a frame below the origin, Return jumps to the one at the cursor"""
+        buffer = self.editor.buffer_get(name)
+        if buffer is None:
+            buffer = self.editor.buffer_create(name, [""], undo=False)
+            buffer.keymap = self.grep_keymap()
+        buffer.directory = root
+        buffer[:] = [header] + results
+        origin = self.show_below(buffer, 1 if len(results) > 0 else 0)
+        if origin is not None:
+            self.grep_origin = origin
+
+    def show_below(self, buffer: Buffer, row: int) -> int | None:
+        """Buffer in the frame already showing it or else in a new lower half of the active one; returns the frame split"""
+        for fr in self.frames.win_frames()[0]:
+            pad = self.editor.pad_get(fr.pad_id)
+            if pad is not None and pad.buffer is buffer:
+                self.frames.active_id = fr.id
+                self.editor.pad_show(fr.pad_id, buffer, row)
+                return None
+        origin = None
+        if self.frames.split(direction=Direction.VERTICAL) is True:
+            # the new lower half shows the buffer, the upper half keeps the origin
+            parent_idx = self.frames.parent_idx(self.frames.active_id)
+            if parent_idx is not None:
+                origin = self.frames.active_id
+                self.frames.active_id = self.frames.frames[parent_idx].c_rd
+            self.relayout()
+        self.editor.pad_show(self.active_pad(), buffer, row)
+        return origin
+
+    def cmd_grep_jump(self, _msg: str):
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id)
+        if pad is None:
+            return
+        row, _ = self.editor.pad_cursor(pad_id)
+        match = grep_line_re.match(pad.buffer[row])
+        if match is None:
+            return
+        filename = os.path.join(pad.buffer.directory, match.group(1))
+        if self.frames.is_leaf(self.grep_origin):
+            self.frames.active_id = self.grep_origin
+        self.open_file(filename, int(match.group(2)) - 1, int(match.group(3)) - 1 if match.group(3) is not None else 0)
+
+    def guarded(self, what: str, fn: Callable[[], None]) -> bool:
+        """Run fn; an EditorError or a bug in it is shown in the echo area instead of ending led. False if one happened"""
+        try:
+            fn()
+            return True
+        except EditorError as e:
+            self.message(str(e))
+        except Exception as e:
+            self.log.exception(f"{what} failed")
+            self.message(f"{what} failed: {e}")
+        return False
+
+    def handle_key(self, key: str):
+        """process_key() plus recording the changes it made in the buffer's undo tree"""
+        self.redraw = True
+        self.last_key_time = time.monotonic()
+        pad_id = self.active_pad()
+        pad = self.editor.pad_get(pad_id) if pad_id >= 0 else None
+        if pad is None:
+            _ = self.guarded(f"Key {key}", lambda: self.process_key(key))
+            return
+        buffer = pad.buffer
+        typing = key_text(key) is not None and (self.modal is None or self.modal.mode == Mode.INSERT)
+        if self.snippet is not None and self.snippet.pending is True and typing is True and self.minibuffer.active is False:
+            self.snippet_clear_field(pad_id)
+        before = self.editor.pad_cursor(pad_id)
+        len_before, count_before = len(buffer[before[0]]), len(buffer)
+        lines_before = buffer.undo.shadow if buffer.undo is not None else None  # record() replaces rather than changes it
+        was_insert = self.modal is not None and self.modal.mode == Mode.INSERT
+        session = self.snippet
+        _ = self.guarded(f"Key {key}", lambda: self.process_key(key))
+        if session is not None and self.snippet is session:
+            self.snippet_track(pad_id, before[0], len_before, count_before)
+        if buffer.undo is None:
+            return
+        if buffer.read_only is True and buffer != buffer.undo.shadow:
+            buffer[:] = buffer.undo.shadow
+            if pad_id < len(self.editor.pads) and self.editor.pads[pad_id].buffer is buffer:
+                self.editor.pad_goto(pad_id, *before)
+            _ = self.refuse_read_only(buffer)
+            return
+        if self.modal is not None:
+            merge = was_insert and self.modal.mode == Mode.INSERT
+        else:
+            merge = key_text(key) is not None
+        after = self.editor.pad_cursor(pad_id) if pad_id < len(self.editor.pads) and self.editor.pads[pad_id].buffer is buffer else before
+        buffer.undo.record(buffer, before, after, merge)
+        if lines_before is not None:
+            self.views_follow(buffer, lines_before, pad_id)
+        for other in self.editor.buffers:
+            if other.narrowing is not None and (other is buffer or other.narrowing.base is buffer):
+                self.narrow_sync(other, merge)
+
+    def views_follow(self, buffer: Buffer, old: list[str], source: int = -1):
+        """The other frames showing buffer keep their cursors and scroll positions on the same text after it changed from old"""
+        if all(pad.buffer is not buffer for i, pad in enumerate(self.editor.pads) if i != source) or old == buffer:
+            return
+        start, removed, inserted = line_diff(old, buffer)
+        self.editor.pads_follow_edit(buffer, start, removed, inserted, source)
+
+    def process_key(self, key: str):
+        self.popup = None
+        if self.describing is not None:
+            self.describe_key_feed(key)
+            return
+        if self.spell_choices is not None:
+            choosing = key == 'a' or key.isdigit()
+            self.spell_choose(key)
+            if choosing is True:
                 return
-            if cmd == "bsp":
-                if pad.cur_x + pad.buf_x > 0:
-                    _ = self.pad_move(pad_id, dx = -1)
-                    pad.buffer[pad.buf_y+pad.cur_y] = pad.buffer[pad.buf_y+pad.cur_y][:pad.buf_x+pad.cur_x] + pad.buffer[pad.buf_y+pad.cur_y][pad.buf_x+pad.cur_x+1:]
-                else:
-                    if pad.cur_y + pad.buf_y > 0:
-                        cur_idx = pad.cur_y+pad.buf_y
-                        cur_line = pad.buffer[cur_idx]
-                        _ = self.pad_move(pad_id, dy = -1)
-                        _ = self.pad_move(pad_id, x = -1)
-                        cur_idx_new = pad.cur_y+pad.buf_y
-                        pad.buffer[cur_idx_new] += cur_line
-                        del pad.buffer[cur_idx]
-                self.pad_display(pad_id)
-            elif cmd == 'exit':
-                self.editor_esc = True
-            elif cmd == "nl":
-                cur_ind = pad.cur_y+pad.buf_y
-                cur_pos = pad.cur_x + pad.buf_x
-                if cur_ind < len(pad.buffer):
-                    cur_line: str = pad.buffer[cur_ind]
-                else:
-                    print("error cur_line invl")
-                    cur_line = ""
-                    exit(1)
-                left = cur_line[:cur_pos]
-                right = cur_line[cur_pos:]
-                pad.buffer[cur_ind]=left
-                if cur_ind == len(pad.buffer) -1:
-                    pad.buffer.append(right)
-                else:
-                    pad.buffer.insert(cur_ind+1, right)
-                _ = self.pad_move(pad_id, dy=1, x=0)
-                self.pad_display(pad_id)
-            elif cmd == "up":
-                _ = self.pad_move(pad_id, dy = -1)
-                self.pad_display(pad_id)
-            elif cmd == "down":
-                _ = self.pad_move(pad_id, dy = 1)
-                self.pad_display(pad_id)
-            elif cmd == "left":
-                _ = self.pad_move(pad_id, dx = -1)
-                self.pad_display(pad_id)
-            elif cmd == "right":
-                _ = self.pad_move(pad_id, dx = 1)
-                self.pad_display(pad_id)
-            elif cmd == "home":
-                _ = self.pad_move(pad_id, x=0)
-                self.pad_display(pad_id)
-            elif cmd == "end":
-                _ = self.pad_move(pad_id, x= -1)
-                self.pad_display(pad_id)
-            elif cmd == "PgUp":
-                _ = self.pad_move(pad_id, dy = -pad.height)
-                self.pad_display(pad_id)
-            elif cmd == "PgDown":
-                _ = self.pad_move(pad_id, dy = pad.height)
-                self.pad_display(pad_id)
-            elif cmd == "Start":
-                _ = self.pad_move(pad_id, x=0, y=0)
-                self.pad_display(pad_id)
-            elif cmd == "End":
-                llen = len(pad.buffer) - 1
-                y = llen + pad.height
-                if y > llen:
-                    y = llen
-                _ = self.pad_move(pad_id, y=y)
-                _ = self.pad_move(pad_id, x= -1)
-                self.pad_display(pad_id)
-            elif cmd == "err":
-                print()
-                print(f"msg: {msg} [Illegal command in editor]")
+        if self.frame_labels is not None:
+            fr_id = self.frame_labels.get(key)
+            self.frame_labels = None
+            if fr_id is not None:
+                self.frames.active_id = fr_id
+                self.frame_selected()
+            return
+        if self.minibuffer.feed(key) is True:
+            return
+        pad_id = self.active_pad()
+        if pad_id < 0:
+            return
+        text = key_text(key)
+        if self.config.abbrev_mode is True and (key == 'Return' or (text is not None and char_class(text) != 1)) and (self.modal is None or self.modal.mode == Mode.INSERT):
+            self.expand_abbrev(pad_id)
+        if self.snippet is not None and (key == 'Tab' or key == 'S-Tab'):
+            self.snippet_field(pad_id, self.snippet.index + (1 if key == 'Tab' else -1))
+            return
+        pad = self.editor.pad_get(pad_id)
+        if pad is not None and pad.buffer.hex is True and len(self.keys.pending) == 0 and (self.modal is None or self.modal.mode == Mode.INSERT):
+            if self.hex_key(pad_id, key) is True:
                 return
-            elif cmd == "char":
-                cur_ind = pad.cur_y+pad.buf_y
-                cur_line = pad.buffer[cur_ind]
-                if ord(msg[0]) >= 32:
-                    left = cur_line[:pad.buf_x+pad.cur_x]
-                    right = cur_line[pad.buf_x+pad.cur_x:]
-                    pad.buffer[cur_ind] = left + msg + right
-                    _ = self.pad_move(pad_id, dx = 1)
-                self.pad_display(pad_id)
-            else:
-                print(f"Bad state: cmd={cmd}, msg={msg}")
+        if pad is not None and pad.buffer.keymap is not None:
+            keys = " ".join(self.keys.pending + [key])
+            binding = pad.buffer.keymap.lookup(keys)
+            if binding is not None:
+                self.keys.pending = []
+                self.editor.pending_keys = ""
+                self.dispatch(*binding)
+                return
+            if pad.buffer.keymap.is_prefix(keys):
+                self.keys.pending.append(key)
+                self.keys.last_key_time = time.monotonic()
+                self.editor.pending_keys = keys
                 return
-            # self.input_queue.task_done()
-                    
-            self.pad_display(pad_id, False)
-        return
-
-def translate_key_event(event: sdl2.SDL_Event) -> tuple[str, str]:
-    key_name = cast(str, sdl2.SDL_GetKeyName(event.key.keysym.sym).decode())  # pyright: ignore[reportUnknownMemberType, reportAny]
-    modifiers = cast(int, sdl2.SDL_GetModState())  # pyright: ignore[reportUnknownMemberType]
-    if key_name == 'Return':
-        return ('nl', '')
-    elif key_name == 'Backspace':
-        return ('bsp', '')
-    elif key_name == 'Escape':
-        return ('exit', '')
-    elif key_name == 'Up':
-        return ('up', '')
-    elif key_name == 'Down':
-        return ('down', '')
-    elif key_name == 'Left':
-        return ('left', '')
-    elif key_name == 'Right':
-        return ('right', '')
-    elif key_name == 'Home':
-        return ('home', '')
-    elif key_name == 'End':
-        return ('end', '')
-    elif key_name == 'PageUp':
-        return ('PgUp', '')
-    elif key_name == 'PageDown':
-        return ('PgDown', '')
-    elif key_name == 'Tab':
-        return ('tab', '')
-    elif key_name == 'Return' and (modifiers & sdl2.KMOD_LCTRL or modifiers & sdl2.KMOD_RCTRL):
-        return ('Start', '')
-    elif key_name == 'Return' and (modifiers & sdl2.KMOD_LSHIFT or modifiers & sdl2.KMOD_RSHIFT):
-        return ('End', '')
-    else:
-        if len(key_name) > 1:
-            return ('err', f"Unknown key: {key_name}")
-        else:
-            return ('char', key_name)
+        if self.config.auto_pairs is True and key == 'Backspace' and (self.modal is None or self.modal.mode == Mode.INSERT):
+            if self.delete_pair(pad_id) is True:
+                return
+        if self.config.auto_pairs is True and self.modal is not None and self.modal.mode == Mode.VISUAL and key in auto_pairs:
+            self.wrap_selection(pad_id, key)
+            return
+        if self.modal is not None and self.modal.feed(pad_id, key) is True:
+            return
+        state, binding, seq = self.keys.feed(self.keymap, key)
+        self.editor.pending_keys = " ".join(self.keys.pending)
+        if state == KeyState.PENDING:
+            return
+        if binding is not None:
+            self.dispatch(*binding)
+            return
+        text = key_text(key)
+        if text is not None and seq == key:
+            self.insert_char(pad_id, text)
+        else:
+            self.message(f"{seq} is undefined")
+            self.sound.play('bell')
+
+    def insert_char(self, pad_id: int, text: str):
+        """Typed text, with auto_pairs: closing pairs and stepping over the closer that is already there"""
+        pad = self.editor.pads[pad_id]
+        row, col = self.editor.pad_cursor(pad_id)
+        line = pad.buffer[row]
+        after = line[col] if col < len(line) else ""
+        if self.config.auto_pairs is False:
+            self.editor.editor_event(pad_id, 'char', text)
+            return
+        if text in auto_pairs.values() and after == text:
+            self.editor.pad_goto(pad_id, row, col + 1)
+            return
+        closer = auto_pairs.get(text)
+        if closer is not None and (after == "" or after.isspace() or after in auto_pairs.values()):
+            before = line[col-1] if col > 0 else ""
+            if closer != text or before.isalnum() is False:
+                _ = self.editor.text_insert(pad_id, (row, col), text + closer)
+                self.editor.pad_goto(pad_id, row, col + 1)
+                return
+        self.editor.editor_event(pad_id, 'char', text)
+
+    def delete_pair(self, pad_id: int) -> bool:
+        row, col = self.editor.pad_cursor(pad_id)
+        line = self.editor.pads[pad_id].buffer[row]
+        if col == 0 or col >= len(line) or auto_pairs.get(line[col-1]) != line[col]:
+            return False
+        self.editor.text_delete(pad_id, (row, col - 1), (row, col + 1))
+        return True
+
+    def wrap_selection(self, pad_id: int, opener: str):
+        pad = self.editor.pads[pad_id]
+        sel = pad.selection()
+        if sel is None or self.modal is None:
+            return
+        start, end = sel
+        end = (end[0], min(end[1] + 1, len(pad.buffer[end[0]])))
+        _ = self.editor.text_insert(pad_id, end, auto_pairs[opener])
+        _ = self.editor.text_insert(pad_id, start, opener)
+        self.modal.set_mode(Mode.NORMAL, pad_id)
+        self.editor.pad_goto(pad_id, *start)
+
+    def tick(self):
+        if self.stats is not None:
+            start = time.monotonic()
+            self.tick_stages()
+            self.stats.add('tick', time.monotonic() - start)
+            if self.stats.update() is True:
+                self.redraw = True
+        else:
+            self.tick_stages()
+
+    def tick_stages(self):
+        if self.notifications.update() is True:
+            self.redraw = True
+        while self.results.empty() is False:
+            what, done = self.results.get_nowait()
+            _ = self.guarded(what, done)
+            self.redraw = True
+        message = self.minibuffer.message
+        if self.minibuffer.current_message() != message:
+            self.redraw = True
+        self.lsp_sync()
+        self.git_update()
+        self.diff_sync()
+        self.follow_sync()
+        self.scroll_lock_sync()
+        self.merge_sync()
+        self.collab_sync()
+        self.swap_sync()
+        if self.keys.expire() is True or (self.modal is not None and self.modal.keys.expire() is True):
+            self.editor.pending_keys = ""
+            self.redraw = True
+
+    def wait_timeout_ms(self) -> int:
+        """How long the main loop may sleep until a key sequence, message or toast times out, -1 for until the next event"""
+        deadlines = [toast.until for toast in self.notifications.toasts]
+        if self.minibuffer.message != "":
+            deadlines.append(self.minibuffer.message_until)
+        for keys in (self.keys, self.modal.keys if self.modal is not None else None):
+            if keys is not None and len(keys.pending) > 0:
+                deadlines.append(keys.last_key_time + keys.timeout)
+        if self.stats is not None:
+            deadlines.append(self.stats.deadline())
+        if self.swap_pending() is True:
+            deadlines.append(self.swap_due)
+        if any(os_window.frame_renderer.animating() for os_window in self.os_windows):
+            deadlines.append(time.monotonic() + 1 / 60)
+        blink = self.cursor_blink()[1]
+        if blink is not None:
+            deadlines.append(blink)
+        if len(deadlines) == 0:
+            return -1
+        return max(1, math.ceil((min(deadlines) - time.monotonic()) * 1000))
+
+    def cursor_blink(self) -> tuple[bool, float | None]:
+        """Whether the cursor is shown now, and when that changes; it stays on while keys come faster than it blinks"""
+        interval = self.config.cursor_blink_ms / 1000
+        if interval <= 0:
+            return True, None
+        phase = int((time.monotonic() - self.last_key_time) / interval)
+        if phase >= 2 * self.config.cursor_blinks:
+            return True, None
+        return phase % 2 == 0, self.last_key_time + (phase + 1) * interval
+
+    def text_editing(self, text: str, start: int):
+        """SDL_TEXTEDITING: the input method's pre-edit string changed, an empty one ends the composition"""
+        self.preedit = (text, start)
+        self.redraw = True
+
+    def cursor_rect(self) -> tuple[int, int, int, int] | None:
+        """Screen rectangle of the cursor that takes text, where input method candidates are placed"""
+        lh = self.frame_renderer.line_height()
+        if self.minibuffer.active is True:
+            _, hy = self.window_size()
+            return (frame_inset + len(self.minibuffer.prompt + self.minibuffer.text) * self.frame_renderer.char_width,
+                    hy - self.minibuffer_height() + frame_inset, self.frame_renderer.char_width, lh)
+        frame = self.frames.active()
+        pad = self.editor.pad_get(self.active_pad())
+        if frame is None or pad is None:
+            return None
+        char_width, lh = self.frame_renderer.cell_size(frame.zoom)
+        return (frame.x + self.frame_style.inset() + (pad.left_border + pad.cur_x) * char_width,
+                frame.y + self.frame_style.inset() + pad.cur_y * lh, char_width, lh)
+
+    def render(self):
+        """render_window() of every top-level window, the overlays that follow the keyboard only in the focused one"""
+        focused, redraw = self.focused, self.redraw
+        for os_window in self.os_windows:
+            self.redraw = redraw
+            self.in_window(os_window, lambda: self.render_guarded(os_window is focused))
+        self.redraw = False
+
+    def render_guarded(self, focused: bool):
+        """render_window(); a drawing failure is shown in the echo area, once while it repeats, and the next frame is drawn from scratch"""
+        try:
+            self.render_window(focused)
+            self.render_error = ""
+        except Exception as e:
+            error = e if isinstance(e, EditorError) else EditorError(ErrorKind.RENDER, f"Drawing failed: {e}")
+            self.frame_renderer.drawn = {}
+            if str(error) != self.render_error:
+                self.log.exception("Drawing failed")
+                self.render_error = str(error)
+                self.message(str(error))
+
+    def render_window(self, focused: bool = True):
+        """Present the window if the frames or anything drawn over them changed"""
+        start = time.monotonic()
+        wx, hy = self.window_size()
+        self.frame_renderer.cursor_style, self.frame_renderer.normal_cursor_style = self.config.cursor_style, self.config.normal_cursor_style
+        self.frame_renderer.cursor_on = self.cursor_blink()[0] or focused is False
+        self.frame_renderer.ruler_overflow = self.config.ruler_overflow
+        self.frame_renderer.relative_numbers = self.editor.relative_numbers
+        self.frame_renderer.scroll_ms = 0 if self.config.reduce_motion is True else self.config.smooth_scroll_ms
+        self.frame_renderer.layout_ms = 0 if self.config.reduce_motion is True else self.config.layout_animation_ms
+        changed = self.frame_renderer.render(self.frames, self.editor, wx, hy)
+        if changed is False and self.redraw is False:
+            return
+        self.redraw = False
+        drawn = time.monotonic()
+        self.frame_renderer.show_canvas()
+        if self.tab_bar_height() > 0:
+            self.frame_renderer.render_tab_bar(tab_labels(self.workspaces.names()), self.workspaces.current, wx, self.tab_bar_height())
+        self.frame_renderer.render_floats(self.frames, self.editor)
+        if self.frame_labels is not None and focused is True:
+            self.frame_renderer.render_frame_labels(self.frames, self.frame_labels)
+        if self.popup is not None and focused is True:
+            self.frame_renderer.render_popup(self.frames, self.editor, self.popup, wx, hy)
+        self.frame_renderer.render_toasts(self.notifications.toasts, wx, hy - self.minibuffer_height())
+        self.frame_renderer.render_minibuffer(self.minibuffer, 0, hy - self.minibuffer_height(), wx, self.minibuffer_height())
+        rect = self.cursor_rect() if focused is True else None
+        if rect is not None and self.preedit[0] != "":
+            self.frame_renderer.render_preedit(rect[0], rect[1], *self.preedit)
+        if rect is not None and rect != self.ime_rect:
+            self.ime_rect = rect
+            scale = self.frame_renderer.ui_scale
+            sdl2.SDL_SetTextInputRect(sdl2.SDL_Rect(*(round(v * scale) for v in rect)))  # pyright: ignore[reportUnknownMemberType]
+        if self.stats is not None:
+            self.frame_renderer.render_stats(self.stats.summary, wx)
+        self.renderer.present()
+        if self.stats is not None:
+            self.stats.frames += 1
+            self.stats.add('render', drawn - start)
+            self.stats.add('present', time.monotonic() - drawn)
+            self.stats.copies += self.frame_renderer.copies
+            self.stats.lines += self.frame_renderer.lines
+        self.frame_renderer.copies, self.frame_renderer.lines = 0, 0
+
+def drop_event_path(event: sdl2.SDL_Event) -> str:
+    """File name of an SDL_DROPFILE event, freeing SDL's copy of it"""
+    pointer = ctypes.c_void_p.from_buffer(event.drop, sdl2.SDL_DropEvent.file.offset)  # pyright: ignore[reportUnknownMemberType, reportUnknownArgumentType]
+    path = ctypes.string_at(pointer.value).decode('utf-8', errors='replace') if pointer.value else ""
+    sdl2.SDL_free(pointer)  # pyright: ignore[reportUnknownMemberType]
+    return path
+
+def drop_position(window: sdl2.ext.Window, frame_renderer: 'FrameRenderer') -> tuple[int | None, int | None]:
+    """Pointer position in drawing units while something is dropped, None outside the window; the pointer
+    need not have entered the window as far as mouse focus is concerned, so it is taken from the desktop"""
+    gx, gy, wx, wy = ctypes.c_int(0), ctypes.c_int(0), ctypes.c_int(0), ctypes.c_int(0)
+    _ = sdl2.SDL_GetGlobalMouseState(ctypes.byref(gx), ctypes.byref(gy))  # pyright: ignore[reportUnknownMemberType]
+    sdl2.SDL_GetWindowPosition(window.window, ctypes.byref(wx), ctypes.byref(wy))  # pyright: ignore[reportUnknownMemberType]
+    x, y = gx.value - wx.value, gy.value - wy.value
+    w, h = cast(tuple[int, int], window.size)
+    if x < 0 or y < 0 or x >= w or y >= h:
+        return None, None
+    return frame_renderer.logical_size(x, y)
+
+def display_ui_scale(window: sdl2.ext.Window, renderer: RenderBackend) -> float:
+    """UI scale from the display DPI in quarter steps, 1 where the system already scales windows (Retina, Wayland)"""
+    if renderer.output_size()[0] > cast(tuple[int, int], window.size)[0]:
+        return 1.0
+    ddpi, hdpi, vdpi = ctypes.c_float(0), ctypes.c_float(0), ctypes.c_float(0)
+    display = sdl2.SDL_GetWindowDisplayIndex(window.window)  # pyright: ignore[reportUnknownMemberType, reportUnknownVariableType]
+    if display < 0 or sdl2.SDL_GetDisplayDPI(display, ctypes.byref(ddpi), ctypes.byref(hdpi), ctypes.byref(vdpi)) != 0 or ddpi.value <= 0:  # pyright: ignore[reportUnknownMemberType]
+        return 1.0
+    return max(1.0, round(ddpi.value / 96 * 4) / 4)
+
+def screenshot(args: list[str], font_path: str) -> int:
+    """led --screenshot out.png [--size WxH] [--split horizontal|vertical] [+line[:col]] file...: draw the files,
+    each further one split off the previous frame, into an image without opening a window; the exit status"""
+    log = logging.getLogger("Screenshot")
+    out, size, direction = args[0] if len(args) > 0 else "", (1024, 768), Direction.HORIZONTAL
+    args = args[1:]
+    while len(args) > 1 and args[0] in ('--size', '--split'):
+        option, value, args = args[0], args[1], args[2:]
+        m = re.fullmatch(r'(\d+)x(\d+)', value)
+        if option == '--size' and m is not None and int(m.group(1)) > 0 and int(m.group(2)) > 0:
+            size = (int(m.group(1)), int(m.group(2)))
+        elif option == '--split' and value in ('horizontal', 'vertical'):
+            direction = Direction[value.upper()]
+        else:
+            log.error(f"Invalid {option} {value}")
+            return 2
+    if out == "" or out.startswith('--'):
+        log.error("Usage: led --screenshot out.png [--size WxH] [--split horizontal|vertical] [+line[:col]] file...")
+        return 2
+    sdl2.sdlttf.TTF_Init()
+    config = load_config()
+    renderer = OffscreenBackend(*size)
+    frame_renderer = FrameRenderer(size[0], size[1], renderer, font_path, fallback_paths=config.fallback_fonts, ui_scale=config.ui_scale if config.ui_scale > 0 else 1.0,
+                                   antialias=config.antialias, subpixel_order=config.subpixel_order)
+    renderer.set_logical_size(*frame_renderer.logical_size(*size))
+    led = Led(cast(sdl2.ext.Window, OffscreenWindow(size)), renderer, frame_renderer, config)
+    files = client_files(args)
+    led.open_initial(files[0][0] if len(files) > 0 else None)
+    for i, (path, row, col) in enumerate(files):
+        if i > 0:
+            _ = led.frames.split(direction=direction)
+            led.relayout()
+        led.open_file(path, row, col)
+    led.relayout()
+    led.tick()
+    led.render()
+    saved = renderer.save(out)
+    if saved is False:
+        log.error(f"Cannot write {out}: {sdl2.SDL_GetError()}")  # pyright: ignore[reportUnknownMemberType]
+    led.shutdown()
+    renderer.destroy()
+    sdl2.sdlttf.TTF_Quit()
+    return 0 if saved is True else 1
 
 def run():
+    args = sys.argv[1:]
+    verbose = '--verbose' in args or '-v' in args
+    args = [arg for arg in args if arg not in ('--verbose', '-v')]
+    _ = setup_logging(verbose)
+    if len(args) > 0 and args[0] == '--client':
+        args = args[1:]
+        if client_send(client_files(args)) is True:
+            return
     # get path to script:
     script_path:str = os.path.dirname(os.path.abspath(__file__))
     # get path to font at ../Resources/IosevkaNerdFontMono-Regular.ttf
     font_path = os.path.join(script_path, "../Resources/IosevkaNerdFontMono-Regular.ttf")
+    if len(args) > 0 and args[0] == '--screenshot':
+        sys.exit(screenshot(args[1:], font_path))
 
+    _ = sdl2.SDL_SetHint(sdl2.SDL_HINT_IME_SHOW_UI, b"1")  # pyright: ignore[reportUnknownMemberType]
     sdl2.ext.init()
     sdl2.sdlttf.TTF_Init()
 
-    window = sdl2.ext.Window("Resizable Window", size=(800, 600), flags=(sdl2.SDL_WINDOW_RESIZABLE | sdl2.SDL_WINDOW_ALLOW_HIGHDPI |  sdl2.SDL_RENDERER_ACCELERATED))
-    window.show()
-    renderer = sdl2.ext.Renderer(window, flags=sdl2.SDL_RENDERER_ACCELERATED)
+    config = load_config()
 
-    frame_renderer = FrameRenderer(800, 600, renderer, font_path)
+    def open_window() -> tuple[sdl2.ext.Window, RenderBackend, FrameRenderer]:
+        """A window with its own renderer and fonts, scaled for the display it opens on"""
+        window = sdl2.ext.Window("Resizable Window", size=(800, 600), flags=(sdl2.SDL_WINDOW_RESIZABLE | sdl2.SDL_WINDOW_ALLOW_HIGHDPI |  sdl2.SDL_RENDERER_ACCELERATED))
+        window.show()
+        renderer = create_backend(window, config.renderer)
+        ui_scale = config.ui_scale if config.ui_scale > 0 else display_ui_scale(window, renderer)
+        frame_renderer = FrameRenderer(800, 600, renderer, font_path, fallback_paths=config.fallback_fonts, ui_scale=ui_scale,
+                                       antialias=config.antialias, subpixel_order=config.subpixel_order)
+        renderer.set_logical_size(*frame_renderer.logical_size(800, 600))
+        return window, renderer, frame_renderer
 
-    frames = Frames()
-    frames.geometry(0, 0, 800, 600)
+    led = Led(*open_window(), config)
+    led.open_window = open_window
+    files = client_files(args)
+    led.open_initial(files[0][0] if len(files) > 0 else None)
+    if config.server is True:
+        led.server_start()
+    frames = led.frames
     _ = frames.split(direction=Direction.HORIZONTAL)
-    frames.geometry(0, 0, 800, 600)
+    led.relayout()
     _ = frames.split(direction=Direction.VERTICAL)
-    frames.geometry(0, 0, 800, 600)
+    led.relayout()
     _ = frames.split(direction=Direction.HORIZONTAL)
-    frames.geometry(0, 0, 800, 600)
+    led.relayout()
     _ = frames.delete()
-    frames.geometry(0, 0, 800, 600)
+    led.relayout()
 
-    running = True
-    while running:
-        events = sdl2.ext.get_events()  # pyright: ignore[reportUnknownVariableType]
+    sdl2.SDL_StartTextInput()  # pyright: ignore[reportUnknownMemberType]
+    key_input = KeyInput()
+    wake_type = cast(int, sdl2.SDL_RegisterEvents(1))  # pyright: ignore[reportUnknownMemberType]
+
+    def wake():
+        event = sdl2.SDL_Event()
+        event.type = wake_type
+        _ = sdl2.SDL_PushEvent(ctypes.byref(event))  # pyright: ignore[reportUnknownMemberType]
+
+    led.results.wake = wake
+    led.notifications.incoming.wake = wake
+    while led.running:
+        led.tick()
+        led.render()
+        # sleep until input, a window event, finished background work (wake) or the next timeout
+        event = sdl2.SDL_Event()
+        timeout = led.wait_timeout_ms()
+        if (sdl2.SDL_WaitEvent(ctypes.byref(event)) if timeout < 0 else sdl2.SDL_WaitEventTimeout(ctypes.byref(event), timeout)) == 0:  # pyright: ignore[reportUnknownMemberType]
+            continue
+        events = [event] + sdl2.ext.get_events()  # pyright: ignore[reportUnknownVariableType, reportOperatorIssue]
+        start = time.monotonic()
         for event in events:  # pyright: ignore[reportUnknownVariableType]
             if event.type == sdl2.SDL_QUIT:
-                running = False
+                led.running = False
                 break
             if event.type == sdl2.SDL_WINDOWEVENT:
-                if event.window.event == sdl2.SDL_WINDOWEVENT_RESIZED:
+                led.redraw = True  # exposed, resized or moved to another display
+                os_window = led.window_by_id(cast(int, event.window.windowID))
+                if os_window is not None and event.window.event == sdl2.SDL_WINDOWEVENT_FOCUS_GAINED:
+                    led.window_focus(os_window)
+                if os_window is not None and event.window.event == sdl2.SDL_WINDOWEVENT_CLOSE:
+                    led.close_window(os_window)  # with one window SDL_QUIT follows
+                if os_window is not None and event.window.event == sdl2.SDL_WINDOWEVENT_RESIZED:
                     new_width: int = cast(int, event.window.data1)
                     new_height: int = cast(int, event.window.data2)
                     print(f"Window resized to: {new_width}x{new_height}")
-                    window.size = (new_width, new_height)
-                    frames.geometry(0, 0, new_width, new_height)
+                    os_window.window.size = (new_width, new_height)
+                    led.in_window(os_window, led.relayout)
 
                     # Update the renderer's logical size to match the new window size
-                    renderer.logical_size = (new_width, new_height)
+                    os_window.renderer.set_logical_size(*os_window.frame_renderer.logical_size(new_width, new_height))
+            if event.type == sdl2.SDL_DROPFILE:
+                path = drop_event_path(event)
+                os_window = led.window_by_id(cast(int, event.drop.windowID))
+                if path != "" and os_window is not None:
+                    led.window_focus(os_window)
+                    led.drop_file(path, *drop_position(os_window.window, os_window.frame_renderer))
+            if event.type == sdl2.SDL_MOUSEWHEEL:
+                os_window = led.window_by_id(cast(int, event.wheel.windowID))
+                if os_window is not None:
+                    mx, my = ctypes.c_int(0), ctypes.c_int(0)
+                    _ = sdl2.SDL_GetMouseState(ctypes.byref(mx), ctypes.byref(my))  # pyright: ignore[reportUnknownMemberType]
+                    flipped = -1 if event.wheel.direction == sdl2.SDL_MOUSEWHEEL_FLIPPED else 1
+                    led.window_focus(os_window)
+                    led.mouse_wheel(*os_window.frame_renderer.logical_size(mx.value, my.value), 3 * flipped * cast(int, event.wheel.y))
+            if event.type == sdl2.SDL_MOUSEBUTTONDOWN:
+                os_window = led.window_by_id(cast(int, event.button.windowID))
+                if event.button.button == sdl2.SDL_BUTTON_LEFT and os_window is not None:
+                    led.window_focus(os_window)
+                    led.mouse_down(*os_window.frame_renderer.logical_size(cast(int, event.button.x), cast(int, event.button.y)))
+            if event.type == sdl2.SDL_MOUSEBUTTONUP and event.button.button == sdl2.SDL_BUTTON_LEFT:
+                led.mouse_up()
+            if event.type == sdl2.SDL_MOUSEMOTION and event.motion.state & sdl2.SDL_BUTTON_LMASK:
+                os_window = led.window_by_id(cast(int, event.motion.windowID))
+                if os_window is not None and os_window is led.focused:
+                    led.mouse_drag(*os_window.frame_renderer.logical_size(cast(int, event.motion.x), cast(int, event.motion.y)))
             if event.type == sdl2.SDL_KEYDOWN:
                 key_name = cast(str, sdl2.SDL_GetKeyName(event.key.keysym.sym).decode())  # pyright: ignore[reportUnknownMemberType]
                 modifiers = cast(int, sdl2.SDL_GetModState())  # pyright: ignore[reportUnknownMemberType]
-                wx: int; hy: int
-                wx, hy = cast(tuple[int,int], window.size)
-                if key_name == 'X' and (modifiers & sdl2.KMOD_LCTRL or modifiers & sdl2.KMOD_RCTRL):
-                    print("Ctrl+X pressed, exiting.")
-                    running = False
-                    break
-                if key_name == 'Tab':
-                    frames.next()
-                    frames.geometry(0,0,wx, hy)
-                    break
-                if key_name == 'H':
-                    _ = frames.split(direction=Direction.HORIZONTAL)
-                    frames.geometry(0,0,wx, hy)
-                    break
-                if key_name == 'V':
-                    _ = frames.split(direction=Direction.VERTICAL)
-                    frames.geometry(0,0,wx, hy)
-                    break
-                if key_name == '=':
-                    frames.size(delta=0.02)
-                    frames.geometry(0,0,wx, hy)
-                    break
-                if key_name == '-':
-                    frames.size(delta= -0.02)
-                    frames.geometry(0,0,wx, hy)
-                    break 
-                if key_name == 'C':
-                    _ = frames.delete()
-                    frames.geometry(0,0,wx, hy)
-                    break
-
-                else:
-                    print(f"Key pressed: {key_name}")
+                if led.preedit[0] == "":  # while composing the input method owns the keys
+                    for key in key_input.key_down(cast(int, event.key.keysym.sym), key_name, modifiers):
+                        led.handle_key(key)
+            if event.type == sdl2.SDL_TEXTEDITING:  # pyright: ignore[reportUnknownMemberType]
+                led.text_editing(cast(str, event.edit.text.decode('utf-8', errors='replace')), cast(int, event.edit.start))  # pyright: ignore[reportUnknownMemberType]
             if event.type == sdl2.SDL_TEXTINPUT:  # pyright: ignore[reportUnknownMemberType]
                 text_char:str = cast(str, event.text.text.decode('utf-8'))  # pyright: ignore[reportUnknownMemberType]
-                text_type:int = cast(int, event.text.type)  # pyright: ignore[reportUnknownMemberType]
-                print(f"Text {text_char}, type: {text_type}")
-
-        renderer.clear((50, 50, 50))  # pyright: ignore[reportUnknownMemberType]
-        frame_renderer.render(frames)
-        renderer.present()
-        sdl2.SDL_Delay(10)  # pyright: ignore[reportUnknownMemberType]
+                led.preedit = ("", 0)
+                for key in key_input.text_input(text_char):
+                    led.handle_key(key)
+            if led.running is False:
+                break
+        for key in key_input.flush():
+            led.handle_key(key)
+        if led.stats is not None:
+            led.stats.add('events', time.monotonic() - start)
 
+    led.shutdown()
     sdl2.ext.quit()
 
 if __name__ == "__main__":
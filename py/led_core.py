@@ -0,0 +1,4693 @@
+"""Editor core without SDL: frame layout, buffers, undo, key maps, commands, projects and language tooling; led.py draws and runs it"""
+import os
+import logging
+import logging.handlers
+import enum
+import collections
+import time
+import re
+import fnmatch
+import queue
+import threading
+import concurrent.futures
+import json
+import copy
+import unicodedata
+import tomllib
+import hashlib
+import codecs
+import string
+import subprocess
+import shutil
+import pathlib
+import urllib.parse
+import difflib
+import html
+import tempfile
+import socket
+
+from dataclasses import dataclass, field, asdict
+from typing import Callable, cast
+
+@dataclass(frozen=True)
+class TextStyle:
+    bold: bool = False
+    italic: bool = False
+    underline: bool = False
+    strikethrough: bool = False
+    fg: tuple[int, int, int, int] | None = None  # None keeps the theme's foreground
+
+@dataclass
+class StyleSpan:
+    """Columns start..end (exclusive) of a row drawn in the theme face of that name"""
+    start: int
+    end: int
+    face: str
+
+def default_faces() -> dict[str, TextStyle]:
+    return {
+        'keyword': TextStyle(bold=True),
+        'comment': TextStyle(italic=True, fg=(150, 175, 150, 255)),
+        'string': TextStyle(fg=(230, 200, 120, 255)),
+        'link': TextStyle(underline=True, fg=(100, 170, 255, 255)),
+        'emphasis': TextStyle(italic=True),
+        'strong': TextStyle(bold=True),
+        'deprecated': TextStyle(strikethrough=True),
+        'unnecessary': TextStyle(fg=(130, 150, 130, 255)),
+        'status-name': TextStyle(bold=True),  # the {@face} sections of the default status_format
+        'status-error': TextStyle(fg=(255, 120, 120, 255)),
+        'status-mode': TextStyle(bold=True),
+        }
+
+@dataclass
+class ColorTheme:
+    background: tuple[int, int, int, int]
+    foreground: tuple[int, int, int, int]
+    border: tuple[int, int, int, int]
+    active_border: tuple[int, int, int, int]
+    cursor: tuple[int, int, int, int]
+    selection: tuple[int, int, int, int] = (40, 80, 160, 255)
+    status: tuple[int, int, int, int] = (0, 0, 120, 255)
+    faint: tuple[int, int, int, int] = (110, 140, 110, 255)  # scroll indicators and other hints drawn over the text
+    trailing_whitespace: tuple[int, int, int, int] = (110, 40, 40, 255)
+    misspelled: tuple[int, int, int, int] = (230, 60, 60, 255)
+    error: tuple[int, int, int, int] = (255, 70, 70, 255)
+    warning: tuple[int, int, int, int] = (240, 190, 40, 255)
+    info: tuple[int, int, int, int] = (100, 170, 255, 255)
+    git_added: tuple[int, int, int, int] = (80, 190, 90, 255)
+    git_modified: tuple[int, int, int, int] = (90, 150, 230, 255)
+    git_deleted: tuple[int, int, int, int] = (220, 80, 80, 255)
+    diff_added: tuple[int, int, int, int] = (30, 70, 35, 255)
+    diff_removed: tuple[int, int, int, int] = (80, 30, 30, 255)
+    diff_changed: tuple[int, int, int, int] = (35, 50, 85, 255)
+    diff_filler: tuple[int, int, int, int] = (45, 45, 45, 255)
+    normal_cursor: tuple[int, int, int, int] | None = None  # cursor in modal editing's normal and visual modes, None: cursor
+    inactive_dim: tuple[int, int, int, int] = (0, 0, 0, 90)  # laid over inactive frames with dim_inactive, the alpha is how much
+    ruler: tuple[int, int, int, int] = (75, 75, 75, 255)
+    ruler_overflow: tuple[int, int, int, int] = (80, 40, 40, 255)  # behind the characters beyond the ruler, with ruler_overflow
+    minimap_text: tuple[int, int, int, int] = (120, 120, 120, 255)
+    minimap_view: tuple[int, int, int, int] = (255, 255, 255, 35)  # laid over the lines of the minimap the frame shows
+    peers: tuple[tuple[int, int, int, int], ...] = ((255, 140, 60, 255), (200, 110, 255, 255), (60, 220, 220, 255), (255, 110, 180, 255), (170, 230, 80, 255))
+    faces: dict[str, TextStyle] = field(default_factory=default_faces)  # styles StyleSpans refer to by name
+
+default_color_theme = ColorTheme((0, 50, 0, 255), (255, 255, 255, 255), (0,0,255,255), (255,0,0,255), (255,255,0,255))
+
+@dataclass
+class FileTypeSettings:
+    tab_width: int = 4
+    use_tabs: bool = False
+    wrap: bool = False
+    comment: str = ""  # line comment prefix, or block comment start if comment_end is set
+    comment_end: str = ""
+    ruler: int = 0  # column of the right-margin line, 0 without
+
+def default_language_servers() -> dict[str, list[str]]:
+    return {
+        'py': ["pylsp"],
+        'rs': ["rust-analyzer"],
+        'c': ["clangd"],
+        'h': ["clangd"],
+        'go': ["gopls"],
+        'js': ["typescript-language-server", "--stdio"],
+        'ts': ["typescript-language-server", "--stdio"],
+        }
+
+def default_repls() -> dict[str, list[str]]:
+    return {
+        'py': ["python3", "-i", "-u", "-q"],
+        'jl': ["julia", "-i", "--color=no", "--banner=no"],
+        'lisp': ["sbcl", "--noinform"],
+        'scm': ["guile"],
+        'rb': ["irb", "--noreadline", "--noprompt"],
+        'sh': ["sh", "-i"],
+        }
+
+def font_face_paths(font_path: str, bold: bool, italic: bool) -> list[str]:
+    """Usual file names of a font family's styled faces, e.g. Mono-BoldItalic.ttf next to Mono-Regular.ttf"""
+    stem, ext = os.path.splitext(font_path)
+    for regular in ("-Regular", "Regular", "-Book", "-Roman"):
+        if stem.endswith(regular):
+            stem = stem[:-len(regular)]
+            break
+    names = ["BoldItalic", "BoldOblique", "Bold-Italic"] if bold and italic else ["Bold"] if bold else ["Italic", "Oblique"]
+    return [f"{stem}{sep}{name}{ext}" for name in names for sep in ("-", "")]
+
+def default_fallback_fonts() -> list[str]:
+    """Common places of fonts covering CJK, symbols and other scripts; missing ones are skipped"""
+    return [
+        "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+        "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+        "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+        "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+        "/usr/share/fonts/truetype/ancient-scripts/Symbola_hint.ttf",
+        "/System/Library/Fonts/Hiragino Sans GB.ttc",
+        "/System/Library/Fonts/Apple Symbols.ttf",
+        "/Library/Fonts/Arial Unicode.ttf",
+        "C:/Windows/Fonts/msgothic.ttc",
+        "C:/Windows/Fonts/seguisym.ttf",
+        ]
+
+def default_filetypes() -> dict[str, FileTypeSettings]:
+    """Keyed by extension without the dot, or by file name for files like Makefile"""
+    return {
+        'py': FileTypeSettings(4, False, False, "#", ruler=80),
+        'rs': FileTypeSettings(4, False, False, "//", ruler=100),
+        'c': FileTypeSettings(8, True, False, "//", ruler=80),
+        'h': FileTypeSettings(8, True, False, "//", ruler=80),
+        'go': FileTypeSettings(8, True, False, "//", ruler=100),
+        'js': FileTypeSettings(2, False, False, "//", ruler=100),
+        'ts': FileTypeSettings(2, False, False, "//", ruler=100),
+        'json': FileTypeSettings(2, False, False),
+        'toml': FileTypeSettings(4, False, False, "#"),
+        'scm': FileTypeSettings(2, False, False, ";;"),
+        'html': FileTypeSettings(2, False, False, "<!--", "-->"),
+        'xml': FileTypeSettings(2, False, False, "<!--", "-->"),
+        'css': FileTypeSettings(2, False, False, "/*", "*/"),
+        'sh': FileTypeSettings(4, False, False, "#", ruler=80),
+        'md': FileTypeSettings(4, False, True, "<!--", "-->"),
+        'txt': FileTypeSettings(4, False, True),
+        'Makefile': FileTypeSettings(8, True, False, "#"),
+        }
+
+default_status_format = ("{@status-name}{name}{@}[ {ro}][ {narrowed}] ({row},{col})  {ending}  {indent}[ {wrap}][  {encoding}]"
+                         "[  {@status-error}E:{errors}{@} W:{warnings}][  {changed}][  {@status-mode}-- {mode} --{@}][  {pending}-]")
+
+@dataclass
+class StatusPiece:
+    kind: str  # text, field, face or segment
+    value: str = ""
+    pieces: list['StatusPiece'] = field(default_factory=list)  # of a segment
+
+def status_parse(template: str, start: int = 0, nested: bool = False) -> tuple[list[StatusPiece], int]:
+    """The pieces of a status line template up to the end, or of a segment up to its ], and where they end"""
+    pieces: list[StatusPiece] = []
+    text = ""
+    i = start
+    while i < len(template):
+        c = template[i]
+        if c == '\\' and i + 1 < len(template):
+            text += template[i + 1]
+            i += 2
+            continue
+        if c == ']' and nested is True:
+            i += 1
+            break
+        end = template.find('}', i) if c == '{' else -1
+        if c != '[' and end < 0:
+            text += c
+            i += 1
+            continue
+        if text != "":
+            pieces.append(StatusPiece('text', text))
+            text = ""
+        if c == '[':
+            segment, i = status_parse(template, i + 1, True)
+            pieces.append(StatusPiece('segment', pieces=segment))
+        else:
+            name = template[i + 1:end]
+            pieces.append(StatusPiece('face', name[1:]) if name.startswith('@') else StatusPiece('field', name))
+            i = end + 1
+    if text != "":
+        pieces.append(StatusPiece('text', text))
+    return pieces, i
+
+def status_format(template: str, fields: dict[str, str]) -> tuple[str, list[StyleSpan]]:
+    """The status line and its face spans: {field} is replaced by its value, text in [ ] is left out if a field in it is empty,
+    {@face} draws what follows in that theme face up to {@}; a backslash takes the next character as it is"""
+    def expand(pieces: list[StatusPiece], face: str) -> tuple[list[tuple[str, str]], str, bool]:
+        chunks: list[tuple[str, str]] = []
+        complete = True
+        for piece in pieces:
+            if piece.kind == 'text':
+                chunks.append((piece.value, face))
+            elif piece.kind == 'face':
+                face = piece.value
+            elif piece.kind == 'segment':
+                inner, inner_face, inner_complete = expand(piece.pieces, face)
+                if inner_complete is True:
+                    chunks += inner
+                    face = inner_face
+            else:
+                value = fields.get(piece.value)
+                if value == "":
+                    complete = False
+                chunks.append((value if value is not None else f"{{{piece.value}}}", face))  # unknown fields stay visible
+        return chunks, face, complete
+
+    text = ""
+    spans: list[StyleSpan] = []
+    for chunk, face in expand(status_parse(template)[0], "")[0]:
+        if face != "" and chunk != "":
+            if len(spans) > 0 and spans[-1].face == face and spans[-1].end == len(text):
+                spans[-1].end += len(chunk)
+            else:
+                spans.append(StyleSpan(len(text), len(text) + len(chunk), face))
+        text += chunk
+    return text, spans
+
+@dataclass
+class UserCommand:
+    doc: str
+    steps: list[tuple[str, str]]  # (command, msg) run in order
+
+def parse_steps(steps: list[str]) -> list[tuple[str, str]]:
+    """'split vertical' -> ('split', 'vertical'): the command name, then its msg"""
+    return [(cmd, msg) for cmd, _, msg in (step.strip().partition(" ") for step in steps)]
+
+@dataclass
+class EditorConfig:
+    modal_editing: bool = False
+    relative_numbers: bool = False  # the gutter counts lines from the cursor line, for count-prefixed motions
+    chord_timeout_ms: int = 1500
+    recent_files_max: int = 50
+    message_timeout_ms: int = 3000
+    messages_max: int = 1000  # lines of *Messages* kept, the oldest go first
+    notification_timeout_ms: int = 6000
+    golden_ratio: bool = False
+    border_width: int = 1  # outline of the frames, in drawing units
+    active_border_width: int = 2  # outline of the active frame
+    frame_padding: int = 1  # between a frame's outline and its text
+    frame_gap: int = 0  # between neighboring frames
+    dim_inactive: bool = False  # darken the frames that are not active by the theme's inactive_dim
+    ruler_overflow: bool = False  # mark the characters beyond the ruler column of the file type
+    minimap: bool = False  # a miniature of the whole buffer at the right of the tiled frames, toggled by toggle-minimap
+    minimap_width: int = 60  # in drawing units, one per character
+    cursor_style: str = 'bar'  # block, bar or underline
+    normal_cursor_style: str = 'block'  # in modal editing's normal and visual modes
+    cursor_blink_ms: int = 530  # on and off time of the blinking cursor, 0 keeps it steady; each key starts it over, on
+    cursor_blinks: int = 10  # then it stays on, so that an idle led sleeps
+    smooth_scroll_ms: int = 120  # how long the view glides to a new scroll position
+    layout_animation_ms: int = 100  # how long frames move to their new rectangles after a split, close or resize
+    reduce_motion: bool = False  # no animations, the view and the frames jump
+    status_format: str = default_status_format  # see status_format(): fields as in status_fields(), [ ] segments, {@face} sections
+    tab_bar: bool = True  # show the workspaces at the top of the window while there are two or more
+    zen_width: int = 100  # text columns of zen-mode, centered in the window
+    zen_fullscreen: bool = False  # zen-mode also fills the screen, without window decorations
+    persistent_undo: bool = True
+    scratch_persist: bool = False  # keep the text of the *scratch* buffer between sessions, in the state directory
+    auto_revert: bool = False  # reload files changed on disk without asking if the buffer has no changes
+    file_watch_interval_ms: int = 1000
+    swap_interval_ms: int = 2000  # how often unsaved changes are written to the state directory for crash recovery, 0 turns it off
+    auto_pairs: bool = False  # close brackets and quotes as they are typed
+    abbrev_mode: bool = True  # expand abbrevs when a word is finished
+    spell_dictionary: str = "/usr/share/dict/words"  # one word per line
+    format_command: str = ""  # format-buffer pipes the buffer through it, usually set per project in .led.toml
+    build_command: str = ""  # build runs it in the project root, the output goes to *build*
+    exclude: list[str] = field(default_factory=list)  # gitignore-style patterns grep skips, e.g. "target/"
+    filetypes: dict[str, FileTypeSettings] = field(default_factory=default_filetypes)  # [filetypes.py] tables in the config
+    associations: dict[str, str] = field(default_factory=dict)  # [associations] glob -> file type, e.g. "*.tpl" = "html", before any detection
+    language_servers: dict[str, list[str]] = field(default_factory=default_language_servers)  # file type -> command, [] disables
+    repls: dict[str, list[str]] = field(default_factory=default_repls)  # file type -> interpreter reading stdin
+    server: bool = True  # open files sent by led --client in this window
+    fallback_fonts: list[str] = field(default_factory=default_fallback_fonts)  # tried in order for characters the main font lacks
+    ui_scale: float = 0.0  # size of text, borders and gaps, e.g. 1.5; 0 derives it from the display DPI
+    antialias: str = 'grayscale'  # or 'subpixel': LCD rendering, sharper on standard DPI monitors
+    subpixel_order: str = 'rgb'  # 'bgr' for panels with the blue subpixel on the left
+    renderer: str = 'sdl'  # or 'wgpu': Vulkan, Metal or Direct3D 12 through wgpu, falls back to 'sdl' if unavailable
+    client_frames: list[str] = field(default_factory=lambda: ['showing', 'active'])  # first rule that applies: showing, largest, active, split
+    commands: dict[str, UserCommand] = field(default_factory=dict)  # [commands.name] tables with doc and steps
+    keys: dict[str, str] = field(default_factory=dict)  # key sequence -> "command msg", added to the global keymap
+    sounds: dict[str, str] = field(default_factory=dict)  # [sounds] table: event in sound_events -> WAV file, none by default
+    sound_volume: int = 80  # percent
+
+sound_events = ('bell', 'save', 'build-finished')  # build-finished: a shell command run in the background is done
+
+def filetype_of(config: EditorConfig, filename: str) -> str:
+    """Key into config.filetypes: the file name if listed (Makefile), else the extension"""
+    base = os.path.basename(filename)
+    if base in config.filetypes:
+        return base
+    return os.path.splitext(base)[1][1:]
+
+language_filetypes = {  # interpreter of a #! line or language of a modeline -> key into config.filetypes
+    'python': 'py', 'rust': 'rs', 'go': 'go', 'javascript': 'js', 'node': 'js', 'typescript': 'ts', 'deno': 'ts',
+    'scheme': 'scm', 'guile': 'scm', 'racket': 'scm', 'sh': 'sh', 'bash': 'sh', 'zsh': 'sh', 'dash': 'sh', 'ksh': 'sh', 'shell-script': 'sh',
+    'markdown': 'md', 'text': 'txt', 'make': 'Makefile', 'makefile': 'Makefile', 'nxml': 'xml',
+    'ruby': 'rb', 'julia': 'jl', 'sbcl': 'lisp', 'perl': 'pl', 'lua': 'lua',
+    }
+vim_modeline_re = re.compile(r'(?:^|\s)(?:vim?|ex):(?:.*?[\s:])?(?:ft|filetype|syn|syntax)=([\w+-]+)')
+emacs_modeline_re = re.compile(r'-\*-(.*?)-\*-')
+
+def language_filetype(config: EditorConfig, name: str) -> str:
+    """python3.11 or python-mode -> py; a name that is a file type key itself stays, unknown ones give an empty string"""
+    name = name.lower().removesuffix('-mode')
+    if name in config.filetypes or name in language_filetypes.values():
+        return name
+    name = re.sub(r'[\d.]+$', '', name)
+    return language_filetypes.get(name, name if name in config.filetypes else "")
+
+def modeline_filetype(config: EditorConfig, lines: list[str]) -> str:
+    """From vim: ft=... in the first or last five lines, or from -*- mode: ... -*- in the first two"""
+    for line in lines[:2]:
+        m = emacs_modeline_re.search(line)
+        if m is not None:
+            spec = m.group(1).strip()
+            if ':' in spec:
+                mode = re.search(r'(?:^|;)\s*mode:\s*([\w+-]+)', spec, re.I)
+                spec = mode.group(1) if mode is not None else ""
+            if language_filetype(config, spec) != "":
+                return language_filetype(config, spec)
+    for line in lines[:5] + lines[max(5, len(lines) - 5):]:
+        m = vim_modeline_re.search(line)
+        if m is not None and language_filetype(config, m.group(1)) != "":
+            return language_filetype(config, m.group(1))
+    return ""
+
+def content_filetype(config: EditorConfig, lines: list[str]) -> str:
+    """From the #! line's interpreter, or markers like an XML prolog at the start"""
+    first = next((line.strip() for line in lines[:10] if line.strip() != ""), "")
+    if first.startswith('#!'):
+        words = first[2:].split()
+        if len(words) > 0 and os.path.basename(words[0]) == 'env':
+            words = [w for w in words[1:] if w.startswith('-') is False and '=' not in w]
+        return language_filetype(config, os.path.basename(words[0])) if len(words) > 0 else ""
+    if first.startswith('<?xml'):
+        return 'xml'
+    if first.lower().startswith(('<!doctype html', '<html')):
+        return 'html'
+    return ""
+
+def association_filetype(config: EditorConfig, filename: str) -> str:
+    """The file type of the first [associations] glob matching the file name; globs with a / match the end of the path, or all of it from a leading /"""
+    for pattern, filetype in config.associations.items():
+        if '/' not in pattern:
+            matched = fnmatch.fnmatchcase(os.path.basename(filename), pattern)
+        else:
+            matched = fnmatch.fnmatchcase(filename, pattern if pattern.startswith('/') else '*/' + pattern)
+        if matched is True:
+            return filetype
+    return ""
+
+def filetype_detect(config: EditorConfig, filename: str, lines: list[str]) -> str:
+    """The config's associations win, then a modeline, a listed file name or extension and the content; else the unlisted extension as filetype_of()"""
+    filetype = association_filetype(config, filename)
+    if filetype != "":
+        return filetype
+    filetype = modeline_filetype(config, lines)
+    if filetype != "":
+        return filetype
+    filetype = filetype_of(config, filename)
+    if filetype in config.filetypes:
+        return filetype
+    detected = content_filetype(config, lines)
+    return detected if detected != "" else filetype
+
+def filetype_settings(config: EditorConfig, filetype: str) -> FileTypeSettings:
+    settings = config.filetypes.get(filetype, FileTypeSettings())
+    return copy.copy(settings)
+
+def config_path() -> str:
+    config_home = os.environ.get("XDG_CONFIG_HOME", os.path.expanduser("~/.config"))
+    return os.path.join(config_home, "led", "config.toml")
+
+def state_path(name: str) -> str:
+    state_home = os.environ.get("XDG_STATE_HOME", os.path.expanduser("~/.local/state"))
+    return os.path.join(state_home, "led", name)
+
+def cache_path(name: str) -> str:
+    cache_home = os.environ.get("XDG_CACHE_HOME", os.path.expanduser("~/.cache"))
+    return os.path.join(cache_home, "led", name)
+
+def server_socket_path() -> str:
+    runtime = os.environ.get("XDG_RUNTIME_DIR", os.path.join(tempfile.gettempdir(), f"led-{os.getuid()}"))
+    return os.path.join(runtime, "led", "server")
+
+project_config_name = ".led.toml"  # in a project root, merged over the user config for the files below it
+project_keys = ('filetypes', 'associations', 'format_command', 'build_command', 'exclude')  # a repository cannot start language servers or bind commands
+
+def load_config(path: str | None = None) -> EditorConfig:
+    log = logging.getLogger("Config")
+    config = EditorConfig()
+    if path is None:
+        path = config_path()
+    if os.path.exists(path) is False:
+        return config
+    try:
+        with open(path, 'rb') as file:
+            data = tomllib.load(file)
+    except (OSError, tomllib.TOMLDecodeError) as e:
+        log.error(f"Cannot read config {path}: {e}")
+        return config
+    config_apply(config, data, log)
+    return config
+
+def project_config(config: EditorConfig, root: str) -> EditorConfig:
+    """config with the root's .led.toml merged over it, or config itself if there is none"""
+    log = logging.getLogger("Config")
+    path = os.path.join(root, project_config_name)
+    if os.path.exists(path) is False:
+        return config
+    try:
+        with open(path, 'rb') as file:
+            data = tomllib.load(file)
+    except (OSError, tomllib.TOMLDecodeError) as e:
+        log.error(f"Cannot read project config {path}: {e}")
+        return config
+    merged = copy.deepcopy(config)
+    config_apply(merged, data, log, project_keys)
+    return merged
+
+def config_apply(config: EditorConfig, data: dict[str, object], log: logging.Logger, keys: tuple[str, ...] | None = None):
+    """Set config from the tables of a config file; keys limits which entries it may set"""
+    for key, value in data.items():
+        if keys is not None and key not in keys:
+            log.warning(f"Ignoring config entry {key}, a project config cannot set it")
+        elif key == 'filetypes' and isinstance(value, dict):
+            for name, table in cast(dict[str, object], value).items():
+                settings = config.filetypes.setdefault(name, FileTypeSettings())
+                if isinstance(table, dict) is False:
+                    log.warning(f"Ignoring invalid config entry filetypes.{name}={table}")
+                    continue
+                for k, v in cast(dict[str, object], table).items():
+                    if hasattr(settings, k) and type(v) is type(getattr(settings, k)):
+                        setattr(settings, k, v)
+                    else:
+                        log.warning(f"Ignoring invalid config entry filetypes.{name}.{k}={v}")
+        elif key == 'commands' and isinstance(value, dict):
+            for name, table in cast(dict[str, object], value).items():
+                steps = cast(dict[str, object], table).get('steps') if isinstance(table, dict) else None
+                if isinstance(steps, list) and len(cast(list[object], steps)) > 0 and all(isinstance(step, str) for step in cast(list[object], steps)):
+                    doc = cast(dict[str, object], table).get('doc', "")
+                    config.commands[name] = UserCommand(doc if isinstance(doc, str) else "", parse_steps(cast(list[str], steps)))
+                else:
+                    log.warning(f"Ignoring invalid config entry commands.{name}={table}, it needs steps = [\"command msg\", ...]")
+        elif key == 'associations' and isinstance(value, dict):
+            for pattern, filetype in cast(dict[str, object], value).items():
+                if isinstance(filetype, str) and filetype != "":
+                    config.associations[pattern] = filetype
+                else:
+                    log.warning(f"Ignoring invalid config entry associations.{pattern}={filetype}")
+        elif key == 'keys' and isinstance(value, dict):
+            for sequence, binding in cast(dict[str, object], value).items():
+                if isinstance(binding, str) and binding.strip() != "":
+                    config.keys[sequence] = binding
+                else:
+                    log.warning(f"Ignoring invalid config entry keys.{sequence}={binding}")
+        elif key == 'sounds' and isinstance(value, dict):
+            for event, sound in cast(dict[str, object], value).items():
+                if event in sound_events and isinstance(sound, str):
+                    config.sounds[event] = sound
+                else:
+                    log.warning(f"Ignoring invalid config entry sounds.{event}={sound}, events are {', '.join(sound_events)}")
+        elif key in ('language_servers', 'repls') and isinstance(value, dict):
+            commands = cast(dict[str, list[str]], getattr(config, key))
+            for name, command in cast(dict[str, object], value).items():
+                if isinstance(command, list) and all(isinstance(c, str) for c in cast(list[object], command)):
+                    commands[name] = cast(list[str], command)
+                else:
+                    log.warning(f"Ignoring invalid config entry {key}.{name}={command}")
+        elif key == 'ui_scale' and isinstance(value, (int, float)) and not isinstance(value, bool) and 0 <= value <= 8:
+            config.ui_scale = float(value)
+        elif key == 'exclude' and isinstance(value, list) and all(isinstance(p, str) for p in cast(list[object], value)):
+            config.exclude = cast(list[str], value)
+        elif key != 'exclude' and hasattr(config, key) and type(value) is type(getattr(config, key)):
+            setattr(config, key, value)
+        else:
+            log.warning(f"Ignoring invalid config entry {key}={value}")
+
+Direction = enum.Enum('Direction', 'NONE HORIZONTAL VERTICAL')
+ErrorKind = enum.Enum('ErrorKind', 'IO DECODE RENDER COMMAND')
+ContentType = enum.Enum('ContentType', 'NONE CELLARRAY TEXT SCHMEME PYTHON')
+Mode = enum.Enum('Mode', 'NORMAL INSERT VISUAL')
+Heading = enum.Enum('Heading', 'LEFT RIGHT UP DOWN')
+KeyState = enum.Enum('KeyState', 'PENDING BOUND UNBOUND')
+
+class ContentCellArray:  # pyright: ignore[reportRedeclaration]
+    pass
+
+class ContentCellArray:
+    def __init__(self, contents: list[str | ContentCellArray], direction: Direction, content_type: ContentType):
+        self.c_lu: int = 0
+        self.c_rd: int = 0
+        self.direction: Direction = direction
+        self.c_type: ContentType = content_type
+        self.content: list[str | ContentCellArray] = contents
+        self.wx: int = 0
+        self.hy: int = 0
+
+class EditorError(Exception):
+    """A failure the user is told about in the echo area; led keeps running"""
+    def __init__(self, kind: ErrorKind, message: str):
+        super().__init__(message)
+        self.kind: ErrorKind = kind
+
+def decode_text(data: bytes) -> tuple[str, str]:
+    """Text and its encoding: by BOM, else UTF-8, else Windows-1252, else Latin-1 which accepts any bytes"""
+    for bom, encoding in ((codecs.BOM_UTF8, 'utf-8-sig'), (codecs.BOM_UTF16_LE, 'utf-16'), (codecs.BOM_UTF16_BE, 'utf-16')):
+        if data.startswith(bom):
+            try:
+                return data.decode(encoding), encoding
+            except UnicodeDecodeError:
+                break
+    for encoding in ('utf-8', 'cp1252'):
+        try:
+            return data.decode(encoding), encoding
+        except UnicodeDecodeError:
+            pass
+    return data.decode('latin-1'), 'latin-1'
+
+line_endings = {'LF': '\n', 'CRLF': '\r\n', 'CR': '\r'}
+
+def line_ending_name(newline: str) -> str:
+    return next(name for name, nl in line_endings.items() if nl == newline)
+
+def detect_newline(text: str) -> str:
+    crlf = text.count("\r\n")
+    cr = text.count("\r") - crlf
+    lf = text.count("\n") - crlf
+    if crlf > lf and crlf >= cr:
+        return "\r\n"
+    if cr > lf and cr > crlf:
+        return "\r"
+    return "\n"
+
+def is_binary(data: bytes) -> bool:
+    return b'\0' in data[:8192]
+
+hex_ascii_col = 60  # lines look like: offset, two groups of 8 hex bytes, the bytes as ASCII
+
+def hex_col(i: int) -> int:
+    """Column of the first hex digit of byte i in a hex line"""
+    return 10 + 3 * i + (1 if i >= 8 else 0)
+
+def hex_line(offset: int, chunk: bytes) -> str:
+    hexes = " ".join(f"{b:02x}" for b in chunk[:8])
+    if len(chunk) > 8:
+        hexes += "  " + " ".join(f"{b:02x}" for b in chunk[8:])
+    text = "".join(chr(b) if 32 <= b < 127 else "." for b in chunk)
+    return f"{offset:08x}  {hexes:<48}  {text}"
+
+def hex_lines(data: bytes) -> list[str]:
+    return [hex_line(offset, data[offset:offset+16]) for offset in range(0, max(len(data), 1), 16)]
+
+def hex_parse(lines: list[str]) -> bytes:
+    """Inverse of hex_lines(), the hex digits count, invalid pairs are skipped"""
+    data = bytearray()
+    for line in lines:
+        for token in line[10:hex_ascii_col-2].split():
+            if len(token) == 2 and all(c in string.hexdigits for c in token):
+                data.append(int(token, 16))
+    return bytes(data)
+
+def read_lines(path: str, as_hex: bool = False) -> list[str]:
+    if as_hex is False:
+        return Content().load_lines(path)
+    try:
+        with open(path, 'rb') as file:
+            return hex_lines(file.read())
+    except FileNotFoundError:
+        return [""]
+    except OSError as e:
+        raise EditorError(ErrorKind.IO, f"Cannot read {path}: {e.strerror or e}")
+
+class Content:
+    def __init__(self):
+        self.log: logging.Logger = logging.getLogger("Content")
+        self.cell_arrays: dict[str, ContentCellArray] = {}
+
+    def _tokenize_text(self, text:str) -> ContentCellArray:
+        arrays: ContentCellArray = ContentCellArray([], Direction.VERTICAL, ContentType.CELLARRAY)
+        lines = text.splitlines()
+        text_separators = [" ", ",", ".", ";", ":", "(", ")", "{", "}", "[", "]", "<", ">", "|", "/", "\\", "\t"]
+        for line in lines:
+            tok = ""
+            current_array: ContentCellArray = ContentCellArray([], Direction.HORIZONTAL, ContentType.TEXT)
+            for char in line:
+                if char in text_separators:
+                    if tok:
+                        current_array.content.append(tok)
+                        tok = ""
+                else:
+                    tok += char
+            if tok:
+                current_array.content.append(tok)
+            arrays.content.append(current_array)
+        return arrays
+
+    def get_file_type(self, filename: str) -> ContentType:
+        known_extensions = ['.txt', '.scm', '.py']
+        if not any(filename.endswith(ext) for ext in known_extensions):
+            self.log.error(f"Unknown file type: {filename}")
+            return ContentType.NONE
+        else:
+            if filename.endswith('.txt'):
+                return ContentType.TEXT
+            elif filename.endswith('.scm'):
+                return ContentType.SCHMEME
+            elif filename.endswith('.py'):
+                return ContentType.PYTHON
+            else:
+                self.log.error(f"Unknown file type: {filename}")
+                return ContentType.NONE
+
+    def tokenize(self, text:str, content_type:ContentType) -> ContentCellArray:
+        if content_type == ContentType.TEXT:
+            return self._tokenize_text(text)
+        elif content_type == ContentType.SCHMEME:
+            # Placeholder for actual SchMeme tokenization
+            self.log.warning("SchMeme tokenization not implemented, using text tokenizer.")
+            return self._tokenize_text(text)
+        elif content_type == ContentType.PYTHON:
+            # Placeholder for actual Python tokenization
+            self.log.warning("Python tokenization not implemented, using text tokenizer.")
+            return self._tokenize_text(text)
+        else:
+            self.log.error(f"Unknown content type: {content_type}")
+            return ContentCellArray([], Direction.VERTICAL, ContentType.NONE)
+
+    def load_text_file(self, filename: str):
+        try:
+            with open(filename, 'r') as file:
+                lines = file.read()
+            content_type = self.get_file_type(filename)
+            self.cell_arrays[filename] = self.tokenize(lines, content_type)
+
+        except FileNotFoundError:
+            self.log.error(f"File {filename} not found.")
+        except Exception as e:
+            self.log.error(f"An error occurred: {e}")
+
+    def load_text(self, filename: str) -> tuple[list[str], str, str]:
+        """Lines, the encoding they were decoded with and the dominant line ending; one empty line for a new file,
+        EditorError if the file exists but cannot be read, so that it is not shown (and saved) empty"""
+        encoding = 'utf-8'
+        newline = '\n'
+        try:
+            with open(filename, 'rb') as file:
+                text, encoding = decode_text(file.read())
+            newline = detect_newline(text)
+            lines = text.splitlines()
+        except FileNotFoundError:
+            self.log.info(f"File {filename} not found, new file")
+            lines = []
+        except OSError as e:
+            raise EditorError(ErrorKind.IO, f"Cannot read {filename}: {e.strerror or e}")
+        except Exception as e:
+            raise EditorError(ErrorKind.DECODE, f"Cannot decode {filename}: {e}")
+        if len(lines) == 0:
+            lines = [""]
+        return lines, encoding, newline
+
+    def load_lines(self, filename: str) -> list[str]:
+        return self.load_text(filename)[0]
+
+def is_grapheme_extend(c: str) -> bool:
+    """Characters that belong to the one before them: combining marks, joiners, variation selectors, skin tones, tags"""
+    o = ord(c)
+    return unicodedata.category(c) in ('Mn', 'Me', 'Mc') or o in (0x200C, 0x200D) or 0xFE00 <= o <= 0xFE0F or 0x1F3FB <= o <= 0x1F3FF or 0xE0020 <= o <= 0xE007F
+
+def is_regional_indicator(c: str) -> bool:
+    return 0x1F1E6 <= ord(c) <= 0x1F1FF
+
+def grapheme_next(line: str, col: int) -> int:
+    """Column after the grapheme cluster starting at col: a base with its marks, a ZWJ emoji sequence, a flag or CR LF"""
+    n = len(line)
+    if col >= n:
+        return n
+    i = col + 1
+    if line[col] == "\r" and i < n and line[i] == "\n":
+        return i + 1
+    if is_regional_indicator(line[col]) and i < n and is_regional_indicator(line[i]):
+        i += 1
+    while i < n and is_grapheme_extend(line[i]):
+        i += 2 if line[i] == "\u200d" and i + 1 < n else 1  # a zero width joiner glues the next character on
+    return i
+
+def grapheme_floor(line: str, col: int) -> int:
+    """Start of the grapheme cluster containing col"""
+    if col <= 0 or col >= len(line):
+        return max(col, 0)
+    if is_grapheme_extend(line[col]) is False and is_regional_indicator(line[col]) is False and line[col - 1] != "\u200d":
+        return col
+    i = 0
+    while True:
+        end = grapheme_next(line, i)
+        if end > col:
+            return i
+        i = end
+
+def grapheme_prev(line: str, col: int) -> int:
+    """Start of the grapheme cluster before col"""
+    return grapheme_floor(line, min(col, len(line)) - 1) if col > 0 else 0
+
+def grapheme_count(text: str) -> int:
+    count, i = 0, 0
+    while i < len(text):
+        i = grapheme_next(text, i)
+        count += 1
+    return count
+
+class Frame:
+    def __init__(self, id:int, content: Content | None = None):
+        self.c_lu: int = 0
+        self.c_rd: int = 0
+        self.direction: Direction = Direction.NONE
+        self.ratio:float = 0.0
+        self.id: int = id
+        self.x:int = 0
+        self.y:int = 0
+        self.wx:int = 0
+        self.hy:int = 0
+        self.content: Content | None = content
+        self.pad_id: int = -1
+        self.zoom: int = 0  # font size steps of this frame's text, see FrameRenderer.set_zoom()
+        self.wrap: bool | None = None  # overrides of the buffer's or global setting in this frame, None keeps it; set-frame-option
+        self.line_numbers: bool | None = None
+        self.follow: bool | None = None  # in or out of follow-mode with the other frames showing its buffer
+
+    def options(self) -> dict[str, str]:
+        """The frame-local options by their set-frame-option names, 'default' where none overrides"""
+        def name(value: bool | None) -> str:
+            return 'default' if value is None else 'on' if value is True else 'off'
+        return {'wrap': name(self.wrap), 'line-numbers': name(self.line_numbers), 'font-size': str(self.zoom) if self.zoom != 0 else 'default', 'follow': name(self.follow)}
+
+    def copy_options(self, other: 'Frame'):
+        self.zoom, self.wrap, self.line_numbers, self.follow = other.zoom, other.wrap, other.line_numbers, other.follow
+
+@dataclass
+class FrameLayout:
+    frames: list[Frame]
+    root_id: int
+    active_id: int
+
+@dataclass
+class FrameStyle:
+    """Outlines, padding and gaps of the frames, in drawing units"""
+    border: int = 1
+    active_border: int = 2
+    padding: int = 1  # between the outline and the text
+    gap: int = 0  # between neighboring tiled frames
+    dim_inactive: bool = False
+    minimap: int = 0  # width of the minimap right of the text of tiled frames, 0 without
+
+    def inset(self) -> int:
+        """From a frame's edge to its text, the same whether it is active or not so that the text stays put"""
+        return max(self.border, self.active_border) + self.padding
+
+def frame_style(config: EditorConfig) -> FrameStyle:
+    return FrameStyle(max(0, config.border_width), max(0, config.active_border_width), max(0, config.frame_padding), max(0, config.frame_gap), config.dim_inactive,
+                      max(1, config.minimap_width) if config.minimap is True else 0)
+
+class Frames:
+    def __init__(self, theme:ColorTheme = default_color_theme):
+        self.log: logging.Logger = logging.getLogger("Frames")
+        self.fr_id:int = 0
+        self.frames: list[Frame] = []
+        self.root_id:int = self.create()
+        self.active_id:int = self.root_id
+        self.floats: list[Frame] = []  # above the tiled tree, explicit geometry, last is topmost
+        self.float_return: dict[int, int] = {}  # float id -> frame active before it opened
+        self.theme: ColorTheme = theme
+        self.style: FrameStyle = FrameStyle()
+
+    def get_id(self) -> int:
+        self.fr_id += 1
+        return self.fr_id
+
+    def create(self, content: Content | None = None) -> int:
+        id:int = self.get_id()
+        fr:Frame = Frame(id, content)
+        self.frames.append(fr)
+        return id
+
+    def idx(self, id:int) -> int | None:
+        for index, fr in enumerate(self.frames):
+            if fr.id == id:
+                return index
+        return None
+
+    def parent_idx(self, id:int) -> int | None:
+        for index, fr in enumerate(self.frames):
+            if fr.c_lu == id or fr.c_rd == id:
+                return index
+        return None
+
+    def delete(self, id:int=0) -> bool:
+        if id == 0:
+            id = self.active_id
+        active:bool = False
+        if id == self.active_id:
+            active = True
+        lc = self.idx(id)
+        if lc is None:
+            return False
+        fr = self.frames[lc]
+        if fr.c_lu != 0 or fr.c_rd != 0:
+            return False
+        if fr.id == self.root_id:
+            return False
+            
+        p_idx = self.parent_idx(id)
+        if p_idx is None:
+            self.log.error("Illegal state in delete (1)")
+            return False
+
+        p_fr = self.frames[p_idx]
+        if p_fr.id == self.root_id:
+            if p_fr.c_lu == id:
+                self.root_id = p_fr.c_rd
+            elif p_fr.c_rd == id:
+                self.root_id = p_fr.c_lu
+            else:
+                self.log.error("Illegal state in delete (2)")
+                return False
+            self.frames.remove(p_fr)
+            self.frames.remove(fr)
+            if active:
+                self.next()
+            return True
+        else:
+            pp_idx = self.parent_idx(p_fr.id)
+            if pp_idx is None:
+                self.log.error("Illegal state in delete (3)")
+                return False
+            pp_fr = self.frames[pp_idx]
+            if p_fr.c_lu == id:
+                if pp_fr.c_lu == p_fr.id:
+                    pp_fr.c_lu = p_fr.c_rd
+                elif pp_fr.c_rd == p_fr.id:
+                    pp_fr.c_rd = p_fr.c_rd
+                else:
+                    self.log.error("Illegal state in delete (4)")
+                    return False
+                self.frames.remove(p_fr)
+                self.frames.remove(fr)
+            elif p_fr.c_rd == id:
+                if pp_fr.c_lu == p_fr.id:
+                    pp_fr.c_lu = p_fr.c_lu
+                elif pp_fr.c_rd == p_fr.id:
+                    pp_fr.c_rd = p_fr.c_lu
+                else:
+                    self.log.error("Illegal state in delete (5)")
+                    return False
+                self.frames.remove(p_fr)
+                self.frames.remove(fr)
+            else:
+                print("Illegal state in delete (6)")
+                return False
+            if active:
+                self.next()
+        return True
+
+    def split(self, id: int=0, direction: Direction = Direction.HORIZONTAL) -> bool:
+        if id == 0:
+            id = self.active_id
+        idx = self.idx(id)
+        if idx is None:
+            return False
+        fr = self.frames[idx]
+        if fr.c_lu != 0 or fr.c_rd != 0:
+            return False
+        fr.direction = direction
+        fr.ratio = 0.5
+        fr.c_lu = self.create(fr.content)
+        fr.c_rd = self.create(content=fr.content)
+        fr.content = None  # Clear content as it is now split into two frames
+        for child_id in (fr.c_lu, fr.c_rd):
+            c_idx = self.idx(child_id)
+            if c_idx is not None:
+                self.frames[c_idx].pad_id = fr.pad_id  # duplicates get their own pad in ReplEditor.sync_pads()
+                self.frames[c_idx].copy_options(fr)
+        fr.pad_id = -1
+        fr.copy_options(Frame(fr.id))
+        if fr.id == self.active_id:
+            self.active_id = fr.c_lu
+        return True
+
+    def size(self, id:int=0, delta:float=0.0):
+        if id==0:
+            id = self.active_id
+        if id == self.root_id:
+            return
+        if delta == 0.0:
+            return
+        p_idx = self.parent_idx(id)
+        if p_idx is None:
+            return
+        p_fr = self.frames[p_idx]
+        if p_fr.c_lu == id:
+            if delta > 0:
+                if p_fr.ratio + delta < 0.8:
+                    p_fr.ratio += delta
+            else:
+                if p_fr.ratio + delta > 0.2:
+                    p_fr.ratio += delta
+        elif p_fr.c_rd == id:
+            if delta > 0:
+                if p_fr.ratio - delta > 0.2:
+                    p_fr.ratio -= delta
+            else:
+                if p_fr.ratio - delta < 0.8:
+                    p_fr.ratio -= delta
+
+    def geometry(self, x: int, y: int, wx:int, hy:int):
+        def _geometry(id: int, x: int, y:int, wx:int, hy: int, level:int):
+            f_idx = self.idx(id)
+            if f_idx is None:
+                print("Internal error (1) in geometry")
+                return
+            fr: Frame = self.frames[f_idx]
+            fr.x = x
+            fr.y = y
+            fr.wx = wx
+            fr.hy = hy
+            if fr.c_lu != 0 and fr.c_rd != 0:
+                lu_gap, rd_gap = self.style.gap // 2, self.style.gap - self.style.gap // 2  # taken from either side of the split
+                if fr.direction == Direction.HORIZONTAL:
+                    _geometry(fr.c_lu, fr.x, fr.y, int(fr.wx * fr.ratio) - lu_gap, fr.hy, level+1 )
+                    _geometry(fr.c_rd, fr.x+int(fr.wx*fr.ratio) + rd_gap, fr.y, int(fr.wx * (1-fr.ratio)) - rd_gap, fr.hy, level+1 )
+                elif fr.direction == Direction.VERTICAL:
+                    _geometry(fr.c_lu, fr.x, fr.y, fr.wx, int(fr.hy * fr.ratio) - lu_gap, level+1)
+                    _geometry(fr.c_rd, fr.x, fr.y+int(fr.hy*fr.ratio) + rd_gap, fr.wx, int(fr.hy*(1-fr.ratio)) - rd_gap, level+1)
+            else:
+                if fr.c_lu !=0 or fr.c_rd !=0:
+                    self.log.error("Illegal state: incomplete sub-tree-node in geometry!")
+                    return
+
+        _geometry(self.root_id, x, y, wx, hy, 0)
+
+    def display_geometry(self):
+        def _display_geometry(id:int, level:int):
+            f_idx = self.idx(id)
+            if f_idx is None:
+                print("Internal error (1) in geometry")
+                return
+            fr: Frame = self.frames[f_idx]
+            print(f"{" "*level} id={fr.id} ratio={fr.ratio}, [{fr.x},{fr.y}] {fr.wx}x{fr.hy} ", end="")
+            if fr.id == self.active_id:
+                print("* ", end="")
+            if fr.c_lu != 0 and fr.c_rd != 0:
+                print("->")
+                _display_geometry(fr.c_lu, level+1)
+                _display_geometry(fr.c_rd, level+1)
+            else:
+                if fr.c_lu !=0 or fr.c_rd !=0:
+                    print("Illegal state: incomplete sub-tree-node!")
+                    return
+                print("[w]")
+
+        _display_geometry(self.root_id, 0)
+
+    def win_frames(self) -> tuple[list[Frame], int]:
+        wfr: list[Frame] = []
+        a_idx = -1
+        for fr in self.frames:
+            if fr.c_lu == 0 and fr.c_rd == 0:
+                wfr.append(fr)
+                if fr.id == self.active_id:
+                    a_idx = len(wfr) - 1
+        return (wfr, a_idx)
+
+    def neighbor(self, id:int, heading: Heading) -> int | None:
+        idx = self.idx(id)
+        if idx is None:
+            return None
+        cur = self.frames[idx]
+        wfr, _ = self.win_frames()
+        best: int | None = None
+        best_score: tuple[int, int] | None = None
+        for fr in wfr:
+            if fr.id == cur.id:
+                continue
+            if heading == Heading.LEFT:
+                gap = cur.x - (fr.x + fr.wx)
+            elif heading == Heading.RIGHT:
+                gap = fr.x - (cur.x + cur.wx)
+            elif heading == Heading.UP:
+                gap = cur.y - (fr.y + fr.hy)
+            else:
+                gap = fr.y - (cur.y + cur.hy)
+            if heading == Heading.LEFT or heading == Heading.RIGHT:
+                overlap = min(cur.y + cur.hy, fr.y + fr.hy) - max(cur.y, fr.y)
+            else:
+                overlap = min(cur.x + cur.wx, fr.x + fr.wx) - max(cur.x, fr.x)
+            if gap < -2 or overlap <= 0:  # 2px tolerance for rounding in geometry()
+                continue
+            score = (abs(gap), -overlap)
+            if best_score is None or score < best_score:
+                best = fr.id
+                best_score = score
+        return best
+
+    def select_frame_in_direction(self, heading: Heading) -> bool:
+        fr_id = self.neighbor(self.active_id, heading)
+        if fr_id is None:
+            return False
+        self.active_id = fr_id
+        return True
+
+    def snapshot(self) -> FrameLayout:
+        return FrameLayout([copy.copy(fr) for fr in self.frames], self.root_id, self.active_id)
+
+    def restore(self, layout: FrameLayout):
+        self.frames = [copy.copy(fr) for fr in layout.frames]
+        self.root_id = layout.root_id
+        self.active_id = layout.active_id
+        self.fr_id = max([self.fr_id] + [fr.id for fr in self.frames])
+
+    def is_leaf(self, id:int) -> bool:
+        idx = self.idx(id)
+        if idx is None:
+            return False
+        return self.frames[idx].c_lu == 0 and self.frames[idx].c_rd == 0
+
+    def split_nodes(self) -> list[Frame]:
+        return [fr for fr in self.frames if fr.c_lu != 0 and fr.c_rd != 0]
+
+    def flip_split(self, fr: Frame, swap_children: bool):
+        fr.direction = Direction.VERTICAL if fr.direction == Direction.HORIZONTAL else Direction.HORIZONTAL
+        if swap_children is True:
+            fr.c_lu, fr.c_rd = fr.c_rd, fr.c_lu
+            fr.ratio = 1.0 - fr.ratio
+
+    def transpose(self):
+        """Mirror the layout at the diagonal: every left/right split becomes top/bottom and vice versa"""
+        for fr in self.split_nodes():
+            self.flip_split(fr, False)
+
+    def rotate(self, clockwise: bool = True):
+        """Rotate the layout by 90 degrees; left/top children keep their place in reading order where possible"""
+        for fr in self.split_nodes():
+            # clockwise: left -> top, top -> right; counter-clockwise: left -> bottom, top -> left
+            if clockwise is True:
+                self.flip_split(fr, fr.direction == Direction.VERTICAL)
+            else:
+                self.flip_split(fr, fr.direction == Direction.HORIZONTAL)
+
+    def toggle_split_direction(self, id:int=0) -> bool:
+        """Turn the split containing frame id (default: active) from side by side to stacked or back"""
+        if id == 0:
+            id = self.active_id
+        p_idx = self.parent_idx(id)
+        if p_idx is None:
+            return False
+        self.flip_split(self.frames[p_idx], False)
+        return True
+
+    def fresh(self, pad_id: int) -> int:
+        """Replace the tiled frames by a single new one showing pad_id, as a new workspace starts"""
+        fr = Frame(self.get_id())
+        fr.pad_id = pad_id
+        self.frames = [fr]
+        self.root_id = self.active_id = fr.id
+        return fr.id
+
+    def keep_only(self, id:int=0) -> bool:
+        """Make leaf frame id (default: active) the only frame"""
+        if id == 0:
+            id = self.active_id
+        idx = self.idx(id)
+        if idx is None or self.is_leaf(id) is False:
+            return False
+        fr = self.frames[idx]
+        self.frames = [fr]
+        self.root_id = fr.id
+        self.active_id = fr.id
+        return True
+
+    def golden_ratio(self, id:int=0, share:float=0.618):
+        """Give frame id (default: active) the golden share of every split above it"""
+        if id == 0:
+            id = self.active_id
+        child = id
+        p_idx = self.parent_idx(child)
+        while p_idx is not None:
+            p_fr = self.frames[p_idx]
+            p_fr.ratio = share if p_fr.c_lu == child else 1.0 - share
+            child = p_fr.id
+            p_idx = self.parent_idx(child)
+
+    def create_floating(self, x:int, y:int, wx:int, hy:int, pad_id:int = -1, focus:bool = True) -> int:
+        fr = Frame(self.get_id())
+        fr.x, fr.y, fr.wx, fr.hy = x, y, wx, hy
+        fr.pad_id = pad_id
+        self.floats.append(fr)
+        self.float_return[fr.id] = self.active_id
+        if focus is True:
+            self.active_id = fr.id
+        return fr.id
+
+    def float_idx(self, id:int) -> int | None:
+        for index, fr in enumerate(self.floats):
+            if fr.id == id:
+                return index
+        return None
+
+    def close_floating(self, id:int=0) -> bool:
+        if id == 0:
+            id = self.active_id
+        f_idx = self.float_idx(id)
+        if f_idx is None:
+            return False
+        del self.floats[f_idx]
+        back = self.float_return.pop(id, self.root_id)
+        if self.active_id == id:
+            if self.is_leaf(back) is True or self.float_idx(back) is not None:
+                self.active_id = back
+            else:
+                self.active_id = self.win_frames()[0][0].id
+        return True
+
+    def keep_floats_inside(self, x:int, y:int, wx:int, hy:int):
+        for fr in self.floats:
+            fr.wx = min(fr.wx, wx)
+            fr.hy = min(fr.hy, hy)
+            fr.x = min(max(fr.x, x), x + wx - fr.wx)
+            fr.y = min(max(fr.y, y), y + hy - fr.hy)
+
+    def frame_at(self, x:int, y:int) -> int | None:
+        for fr in reversed(self.floats):
+            if fr.x <= x < fr.x + fr.wx and fr.y <= y < fr.y + fr.hy:
+                return fr.id
+        for fr in self.win_frames()[0]:
+            if fr.x <= x < fr.x + fr.wx and fr.y <= y < fr.y + fr.hy:
+                return fr.id
+        return None
+
+    def detach(self, id:int) -> Frame | None:
+        """Take leaf frame id out of the tree, its sibling takes the place of their parent"""
+        idx = self.idx(id)
+        p_idx = self.parent_idx(id)
+        if idx is None or p_idx is None or self.is_leaf(id) is False:
+            return None
+        fr = self.frames[idx]
+        p_fr = self.frames[p_idx]
+        sibling = p_fr.c_rd if p_fr.c_lu == id else p_fr.c_lu
+        pp_idx = self.parent_idx(p_fr.id)
+        if pp_idx is None:
+            self.root_id = sibling
+        else:
+            pp_fr = self.frames[pp_idx]
+            if pp_fr.c_lu == p_fr.id:
+                pp_fr.c_lu = sibling
+            else:
+                pp_fr.c_rd = sibling
+        self.frames.remove(p_fr)
+        self.frames.remove(fr)
+        return fr
+
+    def attach(self, fr: Frame, target_id:int, heading: Heading):
+        """Insert detached frame fr beside frame target_id, on the side given by heading"""
+        node = Frame(self.get_id())
+        node.direction = Direction.HORIZONTAL if heading in (Heading.LEFT, Heading.RIGHT) else Direction.VERTICAL
+        node.ratio = 0.5
+        if heading in (Heading.LEFT, Heading.UP):
+            node.c_lu, node.c_rd = fr.id, target_id
+        else:
+            node.c_lu, node.c_rd = target_id, fr.id
+        p_idx = self.parent_idx(target_id)
+        if p_idx is None:
+            self.root_id = node.id
+        else:
+            p_fr = self.frames[p_idx]
+            if p_fr.c_lu == target_id:
+                p_fr.c_lu = node.id
+            else:
+                p_fr.c_rd = node.id
+        self.frames.append(node)
+        self.frames.append(fr)
+
+    def move(self, heading: Heading) -> bool:
+        """Move the active frame past its neighbor in heading, or to that edge of the window"""
+        target = self.neighbor(self.active_id, heading)
+        fr = self.detach(self.active_id)
+        if fr is None:
+            return False
+        if target is None:
+            target = self.root_id
+        self.attach(fr, target, heading)
+        self.active_id = fr.id
+        return True
+
+    def swap_with_neighbor(self, heading: Heading) -> bool:
+        """Exchange what the active frame and its neighbor show; the active frame moves with its content"""
+        fr_id = self.neighbor(self.active_id, heading)
+        a_idx = self.idx(self.active_id)
+        if fr_id is None or a_idx is None:
+            return False
+        n_idx = self.idx(fr_id)
+        if n_idx is None:
+            return False
+        a_fr = self.frames[a_idx]
+        n_fr = self.frames[n_idx]
+        a_fr.pad_id, n_fr.pad_id = n_fr.pad_id, a_fr.pad_id
+        a_fr.content, n_fr.content = n_fr.content, a_fr.content
+        self.active_id = n_fr.id
+        return True
+
+    def get(self, id:int) -> Frame | None:
+        """Tiled or floating frame id"""
+        idx = self.idx(id)
+        if idx is not None:
+            return self.frames[idx]
+        f_idx = self.float_idx(id)
+        if f_idx is not None:
+            return self.floats[f_idx]
+        return None
+
+    def active(self) -> Frame | None:
+        return self.get(self.active_id)
+
+    def next(self):
+        wt: tuple[list[Frame], int] = self.win_frames()
+        wfr: list[Frame]
+        a_idx:int
+        wfr, a_idx = wt
+        if a_idx<len(wfr) - 1:
+            self.active_id = wfr[a_idx+1].id
+        else:
+            self.active_id = wfr[0].id
+ 
+def gutter_label(row: int, cursor: int | None = None, offset: int = 0) -> str:
+    """Line number column of a pad's canvas; relative to the cursor line if given, which keeps its own number, left aligned.
+    offset is added to the numbers of a narrowed buffer, which are those of the whole one"""
+    if cursor is None:
+        return f"  {row + offset:3d} "
+    if row == cursor:
+        return f"  {row + offset:<3d} "
+    return f"  {abs(row - cursor):3d} "
+
+frame_inset: int = 2  # pixels between the border and the text of the minibuffer, popups and toasts
+line_number_width: int = 6  # columns of the gutter
+
+@dataclass
+class Popup:
+    """Small bordered box of text anchored to a buffer position shown in a frame"""
+    lines: list[str]
+    frame_id: int
+    row: int
+    col: int
+
+def content_hash(lines: list[str]) -> str:
+    return hashlib.sha256("\n".join(lines).encode('utf-8')).hexdigest()
+
+def line_diff(old: list[str], new: list[str]) -> tuple[int, list[str], list[str]]:
+    """(start, removed, inserted) such that old[start:start+len(removed)] = inserted gives new"""
+    n = min(len(old), len(new))
+    start = 0
+    while start < n and old[start] == new[start]:
+        start += 1
+    end = 0
+    while end < n - start and old[len(old)-1-end] == new[len(new)-1-end]:
+        end += 1
+    return start, old[start:len(old)-end], new[start:len(new)-end]
+
+def shift_row(row: int, start: int, removed: int, inserted: int) -> int:
+    """Where row is after the lines start:start+removed became inserted lines; rows within them stay inside the new ones"""
+    if row < start:
+        return row
+    if row >= start + removed:
+        return row + inserted - removed
+    return start + min(row - start, max(inserted - 1, 0))
+
+def shift_col(col: int, old: str, new: str) -> int:
+    """Where col of line old is in new, the line after an edit; like shift_row, columns within the changed text stay inside it"""
+    prefix = 0
+    while prefix < min(len(old), len(new)) and old[prefix] == new[prefix]:
+        prefix += 1
+    suffix = 0
+    while suffix < min(len(old), len(new)) - prefix and old[len(old)-1-suffix] == new[len(new)-1-suffix]:
+        suffix += 1
+    if col >= len(old) - suffix:
+        return col + len(new) - len(old)
+    if col <= prefix:
+        return col
+    return min(col, len(new) - suffix)
+
+@dataclass
+class UndoNode:
+    seq: int
+    parent: int  # -1 for the root
+    start: int  # line the change begins at
+    removed: list[str]
+    inserted: list[str]
+    before: tuple[int, int]  # cursor before and after the change
+    after: tuple[int, int]
+    time: float
+    children: list[int] = field(default_factory=list)
+    last_child: int = -1  # branch redo follows
+
+class UndoTree:
+    """Undo history of a buffer as a tree: editing after an undo starts a new branch instead of dropping the redo states"""
+    def __init__(self, lines: list[str]):
+        self.nodes: list[UndoNode] = [UndoNode(0, -1, 0, [], [], (0, 0), (0, 0), time.time())]
+        self.current: int = 0
+        self.shadow: list[str] = list(lines)  # buffer contents at the current node
+        self.version: int = 0  # counts changes in either direction, for watchers like the LSP sync
+        self.sealed: bool = True  # next change starts a new node even if merging was asked for
+
+    def record(self, lines: list[str], before: tuple[int, int], after: tuple[int, int], merge: bool = False):
+        """Compare lines with the state at the current node and add a node for the difference"""
+        if lines == self.shadow:
+            self.sealed = True
+            return
+        head = self.nodes[self.current]
+        if merge is True and self.sealed is False and self.current != 0 and len(head.children) == 0:
+            base = list(self.shadow)
+            base[head.start:head.start+len(head.inserted)] = head.removed
+            head.start, head.removed, head.inserted = line_diff(base, lines)
+            head.after = after
+        else:
+            start, removed, inserted = line_diff(self.shadow, lines)
+            node = UndoNode(len(self.nodes), self.current, start, removed, inserted, before, after, time.time())
+            self.nodes.append(node)
+            head.children.append(node.seq)
+            head.last_child = node.seq
+            self.current = node.seq
+        self.shadow = list(lines)
+        self.sealed = False
+        self.version += 1
+
+    def step_up(self, lines: list[str]) -> tuple[int, int]:
+        node = self.nodes[self.current]
+        lines[node.start:node.start+len(node.inserted)] = node.removed
+        self.current = node.parent
+        self.version += 1
+        return node.before
+
+    def step_down(self, lines: list[str], seq: int) -> tuple[int, int]:
+        node = self.nodes[seq]
+        lines[node.start:node.start+len(node.removed)] = node.inserted
+        self.nodes[node.parent].last_child = seq
+        self.current = seq
+        self.version += 1
+        return node.after
+
+    def undo(self, lines: list[str]) -> tuple[int, int] | None:
+        if self.current == 0:
+            return None
+        cursor = self.step_up(lines)
+        self.shadow = list(lines)
+        self.sealed = True
+        return cursor
+
+    def redo(self, lines: list[str]) -> tuple[int, int] | None:
+        seq = self.nodes[self.current].last_child
+        if seq < 0:
+            return None
+        cursor = self.step_down(lines, seq)
+        self.shadow = list(lines)
+        self.sealed = True
+        return cursor
+
+    def path_to_root(self, seq: int) -> list[int]:
+        path: list[int] = []
+        while seq >= 0:
+            path.append(seq)
+            seq = self.nodes[seq].parent
+        return path
+
+    def goto(self, lines: list[str], seq: int) -> tuple[int, int] | None:
+        """Undo up to the common ancestor, then redo down to seq"""
+        if seq < 0 or seq >= len(self.nodes) or seq == self.current:
+            return None
+        target = self.path_to_root(seq)
+        on_path = set(target)
+        cursor = (0, 0)
+        while self.current not in on_path:
+            cursor = self.step_up(lines)
+        for s in reversed(target[:target.index(self.current)]):
+            cursor = self.step_down(lines, s)
+        self.shadow = list(lines)
+        self.sealed = True
+        return cursor
+
+    def to_dict(self) -> dict[str, object]:
+        return {'current': self.current, 'nodes': [asdict(node) for node in self.nodes]}
+
+    @classmethod
+    def from_dict(cls, data: dict[str, object], lines: list[str]) -> 'UndoTree':
+        """Inverse of to_dict(); lines must be the buffer contents at the stored current node"""
+        tree = cls(lines)
+        nodes = cast(list[dict[str, object]], data['nodes'])
+        tree.nodes = [UndoNode(**{**node, 'before': tuple(cast(list[int], node['before'])), 'after': tuple(cast(list[int], node['after']))}) for node in nodes]  # pyright: ignore[reportArgumentType]
+        tree.current = cast(int, data['current'])
+        return tree
+
+    def describe(self, node: UndoNode) -> str:
+        if node.seq == 0:
+            return "original"
+        stamp = time.strftime('%H:%M:%S', time.localtime(node.time))
+        return f"{stamp} line {node.start + 1} +{len(node.inserted)} -{len(node.removed)}"
+
+    def visualize(self) -> tuple[list[str], list[int]]:
+        """Text lines in chronological order, side branches indented before the newest one; plus the seq of each line"""
+        lines: list[str] = []
+        seqs: list[int] = []
+
+        def show(seq: int, depth: int):
+            while seq >= 0:
+                node = self.nodes[seq]
+                marker = "*" if seq == self.current else "o"
+                lines.append(f"{'| ' * depth}{marker} {seq:>4} {self.describe(node)}")
+                seqs.append(seq)
+                children = sorted(node.children)
+                for child in children[:-1]:
+                    show(child, depth + 1)
+                seq = children[-1] if len(children) > 0 else -1
+
+        show(0, 0)
+        return lines, seqs
+
+image_extensions = ('png', 'jpg', 'jpeg', 'gif', 'bmp', 'webp', 'tga')
+
+@dataclass
+class ImageView:
+    path: str
+    zoom: float = 0.0  # screen pixels per image pixel, 0 fits the image into the frame
+    pan_x: int = 0  # image pixels the centre is moved from the image's centre
+    pan_y: int = 0
+    width: int = 0  # known once the renderer loaded it
+    height: int = 0
+
+    def scale(self, wx: int, hy: int) -> float:
+        if self.zoom > 0.0 or self.width == 0 or self.height == 0:
+            return self.zoom if self.zoom > 0.0 else 1.0
+        return min(wx / self.width, hy / self.height)
+
+dired_name_col = 32  # where names start in a directory listing line
+
+@dataclass
+class Dired:
+    directory: str
+    entries: list[str]  # name per listing row, "" for the header
+    marked: set[str] = field(default_factory=set)
+
+def dired_line(directory: str, name: str, marked: bool) -> str:
+    path = os.path.join(directory, name)
+    try:
+        st = os.stat(path)
+        size = "<dir>" if os.path.isdir(path) else f"{st.st_size}"
+        mtime = time.strftime("%Y-%m-%d %H:%M", time.localtime(st.st_mtime))
+    except OSError:
+        size, mtime = "?", "?"
+    suffix = "/" if os.path.isdir(path) else ""
+    return f"{'*' if marked else ' '} {size:>10}  {mtime:16}  {name}{suffix}"
+
+def dired_listing(dired: Dired) -> list[str]:
+    """Header and one line per entry, directories first, refreshing dired.entries"""
+    try:
+        names = os.listdir(dired.directory)
+    except OSError as e:
+        dired.entries = [""]
+        return [f"  {abbreviate_home(dired.directory)}: {e.strerror}"]
+    names.sort(key=lambda n: (not os.path.isdir(os.path.join(dired.directory, n)), n.lower()))
+    dired.entries = ["", ".."] + names
+    dired.marked &= set(names)
+    return [f"  {abbreviate_home(dired.directory)}:"] + [dired_line(dired.directory, n, n in dired.marked) for n in dired.entries[1:]]
+
+@dataclass
+class RemotePath:
+    host: str  # [user@]host as passed to ssh
+    port: int | None
+    path: str  # absolute, or relative to the login directory
+
+def parse_remote(url: str) -> RemotePath | None:
+    """ssh://[user@]host[:port]/path; ssh://host/~/path is relative to the home directory"""
+    if url.startswith("ssh://") is False:
+        return None
+    parts = urllib.parse.urlsplit(url)
+    try:
+        port = parts.port
+    except ValueError:
+        return None
+    if parts.hostname is None:
+        return None
+    host = (parts.username + "@" if parts.username is not None else "") + parts.hostname
+    path = urllib.parse.unquote(parts.path) or "/"
+    if path == "/~" or path.startswith("/~/"):
+        path = path[3:] or "."
+    return RemotePath(host, port, path)
+
+def sftp_quote(path: str) -> str:
+    return '"' + path.replace('\\', '\\\\').replace('"', '\\"') + '"'
+
+class Sftp:
+    """File transfers with the sftp command; transfers to a host share one ssh master connection"""
+    def __init__(self, control_dir: str | None = None, timeout: float = 60.0):
+        self.log: logging.Logger = logging.getLogger("Sftp")
+        self.control_dir: str = control_dir if control_dir is not None else cache_path("ssh")
+        self.timeout: float = timeout
+
+    def run(self, remote: RemotePath, batch: str):
+        """Run the sftp commands in batch, raising FileNotFoundError or OSError with sftp's message"""
+        os.makedirs(self.control_dir, mode=0o700, exist_ok=True)
+        command = ["sftp", "-q", "-b", "-", "-o", "BatchMode=yes", "-o", "ControlMaster=auto",
+                   "-o", f"ControlPath={os.path.join(self.control_dir, '%C')}", "-o", "ControlPersist=300"]
+        if remote.port is not None:
+            command += ["-P", str(remote.port)]
+        try:
+            result = subprocess.run(command + [remote.host], input=batch.encode('utf-8'), capture_output=True, timeout=self.timeout)
+        except subprocess.TimeoutExpired:
+            raise OSError(f"{remote.host} did not answer within {self.timeout:.0f}s")
+        if result.returncode != 0:
+            error = result.stderr.decode('utf-8', errors='replace').strip()
+            self.log.warning(f"sftp {remote.host}: {error}")
+            if "not found" in error or "No such file" in error:
+                raise FileNotFoundError(error)
+            raise OSError(error.splitlines()[-1] if error != "" else f"sftp exit status {result.returncode}")
+
+    def read(self, remote: RemotePath) -> bytes:
+        with tempfile.TemporaryDirectory() as directory:
+            local = os.path.join(directory, "get")
+            self.run(remote, f"get {sftp_quote(remote.path)} {sftp_quote(local)}\n")
+            with open(local, 'rb') as file:
+                return file.read()
+
+    def write(self, remote: RemotePath, data: bytes):
+        with tempfile.TemporaryDirectory() as directory:
+            local = os.path.join(directory, "put")
+            with open(local, 'wb') as file:
+                _ = file.write(data)
+            self.run(remote, f"put {sftp_quote(local)} {sftp_quote(remote.path)}\n")
+
+@dataclass
+class PeerCursor:
+    name: str
+    color: tuple[int, int, int, int]
+    cursor: tuple[int, int]
+    anchor: tuple[int, int] | None  # other end of the selection, which includes both ends
+
+class MarkRing:
+    """Positions set with set-mark in a buffer; pop goes to the newest and keeps it as the oldest, cycling through them"""
+    def __init__(self, max_marks: int = 16):
+        self.max_marks: int = max_marks
+        self.marks: list[tuple[int, int]] = []  # newest last
+
+    def push(self, position: tuple[int, int]):
+        if len(self.marks) > 0 and self.marks[-1] == position:
+            return
+        self.marks.append(position)
+        del self.marks[:max(0, len(self.marks) - self.max_marks)]
+
+    def pop(self) -> tuple[int, int] | None:
+        if len(self.marks) == 0:
+            return None
+        position = self.marks.pop()
+        self.marks.insert(0, position)
+        return position
+
+@dataclass
+class Narrowing:
+    base: 'Buffer'
+    start: int  # rows start:end of base are the narrowed buffer's lines
+    end: int
+    lines: list[str]  # what both had there when last synced
+    base_lines: list[str] = field(default_factory=list)  # all of base then
+
+    def follow_base(self):
+        """Move start:end with the base's edits since the last sync: shifted by those above, grown or shrunk by those within"""
+        start, removed, inserted = line_diff(self.base_lines, list(self.base))
+        delta = len(inserted) - len(removed)
+        if start + len(removed) <= self.start:
+            self.start, self.end = self.start + delta, self.end + delta
+        elif start < self.end:
+            self.start, self.end = min(self.start, start), max(self.end, start + len(removed)) + delta
+        self.base_lines = list(self.base)
+
+class Buffer(list[str]):
+    """Lines of text plus the metadata of where they came from; shared by all pads showing it"""
+    def __init__(self, lines: list[str], name: str, filename: str | None = None, undo: bool = True):
+        super().__init__(lines if len(lines) > 0 else [""])
+        self.name: str = name
+        self.filename: str | None = filename
+        self.remote: RemotePath | None = parse_remote(filename) if filename is not None else None  # filename is an ssh:// url
+        self.directory: str = os.path.dirname(os.path.abspath(filename)) if filename is not None and self.remote is None else os.getcwd()
+        self.keymap: 'Keymap | None' = None  # buffer-local bindings, looked up before the global keymap
+        self.undo: UndoTree | None = UndoTree(self) if undo is True else None  # None for generated buffers
+        self.disk_hash: str = content_hash(self) if filename is not None else ""  # contents when last read or written
+        self.changed_on_disk: bool = False
+        self.encoding: str = 'utf-8'  # used when writing the file
+        self.lsp: 'LspClient | None' = None
+        self.lsp_version: int = -1  # undo.version last sent to the server
+        self.diagnostics: list['Diagnostic'] = []
+        self.git_base: list[str] | None = None  # file in the git index, None if not tracked
+        self.git_version: int = -1  # undo.version the hunks were computed for
+        self.git_pending: bool = False
+        self.git_branch: str = ""  # checked out where the file is
+        self.hunks: list['Hunk'] = []
+        self.highlights: dict[int, tuple[int, int, int, int]] = {}  # background per row, e.g. of diff views
+        self.styles: dict[int, list[StyleSpan]] = {}  # faces per row, e.g. from syntax highlighting
+        self.image: ImageView | None = None  # shown as picture instead of the lines
+        self.dired: Dired | None = None  # set for directory listings
+        self.peer_cursors: list[PeerCursor] = []  # other participants of a shared buffer
+        self.newline: str = '\n'  # likewise, one of line_endings
+        self.hex: bool = False  # lines are hex_lines() of the file's bytes
+        self.settings: FileTypeSettings = FileTypeSettings()
+        self.filetype: str = ""  # see filetype_of()
+        self.read_only: bool = False  # edits are undone with a message, set for files without write permission
+        self.marks: MarkRing = MarkRing()
+        self.narrowing: Narrowing | None = None  # set when the lines are part of another buffer, see narrow-to-region
+
+    def line_offset(self) -> int:
+        """Row of the base buffer where a narrowed buffer's lines start"""
+        return self.narrowing.start if self.narrowing is not None else 0
+
+    def modified(self) -> bool:
+        return self.filename is not None and content_hash(self) != self.disk_hash
+
+@dataclass
+class PadView:
+    buffer: Buffer
+    row: int
+    col: int
+    buf_y: int
+    buf_x: int
+
+class JumpList:
+    """Where a frame jumped away from, browsed back and forward like a browser's history"""
+    def __init__(self, max_jumps: int = 100):
+        self.max_jumps: int = max_jumps
+        self.jumps: list[PadView] = []
+        self.index: int = 0  # of the current place in jumps, len(jumps) while that isn't one of them
+
+    @staticmethod
+    def same(a: PadView, b: PadView) -> bool:
+        return a.buffer is b.buffer and a.row == b.row
+
+    def push(self, view: PadView):
+        """Record the place left by a jump; the places gone back from are dropped"""
+        del self.jumps[self.index:]
+        if len(self.jumps) == 0 or self.same(self.jumps[-1], view) is False:
+            self.jumps.append(view)
+        del self.jumps[:max(0, len(self.jumps) - self.max_jumps)]
+        self.index = len(self.jumps)
+
+    def back(self, here: PadView) -> PadView | None:
+        if self.index >= len(self.jumps):
+            if len(self.jumps) == 0 or self.same(self.jumps[-1], here) is False:
+                self.jumps.append(here)  # to come forward to again
+            self.index = len(self.jumps) - 1
+        if self.index == 0:
+            return None
+        self.index -= 1
+        return self.jumps[self.index]
+
+    def forward(self) -> PadView | None:
+        if self.index >= len(self.jumps) - 1:
+            return None
+        self.index += 1
+        return self.jumps[self.index]
+
+@dataclass()
+class Pad:
+    screen_pos_x: int
+    screen_pos_y: int
+    width: int
+    height: int
+    left_border: int
+    bottom_border: int
+    cur_x: int
+    cur_y: int
+    buffer: Buffer
+    buf_x: int
+    buf_y: int
+    screen: list[str]
+    color_theme: ColorTheme
+    canvas: list[str] = field(default_factory=list)  # screen incl. line numbers and status line, as rendered
+    sel_anchor: tuple[int, int] | None = None  # (row, col) of visual selection start
+    wrap: bool | None = None  # the frame's override of the buffer's wrap setting
+    status_spans: list[StyleSpan] = field(default_factory=list)  # faces of the status line, as rendered
+
+    def selection(self) -> tuple[tuple[int, int], tuple[int, int]] | None:
+        if self.sel_anchor is None:
+            return None
+        cursor = (self.buf_y + self.cur_y, self.buf_x + self.cur_x)
+        start, (row, col) = min(self.sel_anchor, cursor), max(self.sel_anchor, cursor)
+        line = self.buffer[row] if row < len(self.buffer) else ""
+        if col < len(line):
+            col = grapheme_next(line, col) - 1  # the end is inclusive, make it include the whole last cluster
+        return (start, (row, col))
+
+
+class ReplEditor():
+    def __init__(self, color_theme:ColorTheme=default_color_theme):
+        # ANSI Escapes https://gist.github.com/fnky/458719343aabd01cfb17a3a4f7296797
+        self.log: logging.Logger = logging.getLogger("ReplEditor")
+        self.color_theme: ColorTheme = color_theme
+        self.editor_esc: bool = False
+        self.pads: list[Pad] = []
+        self.buffers: list[Buffer] = []
+        self.register: tuple[str, bool] = ("", False)  # (text, linewise)
+        self.rectangle: list[str] = []  # last killed rectangle, a string per line
+        self.mode_label: str = ""
+        self.pending_keys: str = ""
+        self.cursor_block: bool = False
+        self.show_whitespace: bool = False
+        self.relative_numbers: bool = False  # gutters count from the cursor line
+        self.status_format: str = default_status_format
+        self.spell: 'SpellChecker | None' = None  # set while spell checking is on
+        self.on_message: Callable[[str], None] | None = None  # user-visible errors, set by Led
+
+    def message(self, text: str):
+        self.log.warning(text)
+        if self.on_message is not None:
+            self.on_message(text)
+
+    def canvas_print_at(self, pad: Pad, msg: str, y:int, x:int):
+        if y < 0 or y >= len(pad.canvas) or msg == "":
+            return
+        line = pad.canvas[y]
+        pad.canvas[y] = (line[:x] + msg + line[x+len(msg):])[:len(line)]
+
+    def pad_print_at(self, pad_index:int, msg: str, y:int, x:int, flush:bool = False, scroll:bool=False, border:bool=False):
+        if pad_index >= len(self.pads):
+            return
+        pad = self.pads[pad_index]
+        if border is False:
+            self.canvas_print_at(pad, msg, y, x+pad.left_border)
+        else:
+            self.canvas_print_at(pad, msg, y, x)
+
+    def pad_create(self, buffer:Buffer, height: int, width:int, offset_y:int, offset_x:int, left_border:int, bottom_border:int, color_theme: ColorTheme | None) -> int:
+        if color_theme is None:
+            color_theme = self.color_theme
+        cur_x_offset, cur_y_offset = 0, 0, #  self.repl.cursor_start_offset_get()
+        pad: Pad = Pad(
+            screen_pos_x = cur_x_offset + offset_x + left_border,
+            screen_pos_y = cur_y_offset + offset_y,
+            width = width-left_border,
+            height = height-bottom_border,
+            left_border = left_border,
+            bottom_border = bottom_border,
+            cur_x = 0,
+            cur_y = 0,
+            color_theme = color_theme,
+            screen = [' ' * width] * height,
+            buffer = buffer,
+            buf_x = 0,
+            buf_y = 0,
+            canvas = [' ' * width] * height
+            )
+        self.pads.append(pad)
+        pad_index = len(self.pads)-1
+        self.pad_display(pad_index)
+        return pad_index
+    
+    def pad_resize(self, pad_index:int, height:int, width:int):
+        pad = self.pad_get(pad_index)
+        if pad is None:
+            return
+        pad.width = max(1, width - pad.left_border)
+        pad.height = max(1, height - pad.bottom_border)
+        pad.screen = [' ' * pad.width] * pad.height
+        pad.canvas = [' ' * (pad.left_border + pad.width)] * (pad.height + pad.bottom_border)
+        row, col = self.pad_cursor(pad_index)
+        self.pad_goto(pad_index, row, col)
+
+    def pad_clone(self, pad_index:int) -> int:
+        pad = self.pads[pad_index]
+        clone = Pad(**{f: getattr(pad, f) for f in pad.__dataclass_fields__})
+        clone.screen = list(pad.screen)
+        clone.canvas = list(pad.canvas)
+        clone.sel_anchor = None
+        self.pads.append(clone)
+        return len(self.pads) - 1
+
+    def sync_pads(self, frames: Frames, cell_size: Callable[[int], tuple[int, int]], gutters: bool = True):
+        """Give each shown frame a pad of its size; gutters False leaves the line numbers as they are, as zen-mode hides them"""
+        used: set[int] = set()
+        wfr, _ = frames.win_frames()
+        for fr in wfr + frames.floats:
+            if fr.pad_id < 0 or fr.pad_id >= len(self.pads):
+                fr.pad_id = self.create_editor(self.buffer_untitled(), 1, line_no=True, status_line=True)
+            elif fr.pad_id in used:
+                fr.pad_id = self.pad_clone(fr.pad_id)
+            used.add(fr.pad_id)
+            pad = self.pads[fr.pad_id]
+            pad.wrap = fr.wrap
+            if gutters is True:
+                numbers = fr.line_numbers if fr.line_numbers is not None else fr in wfr  # floating frames go without
+                pad.left_border = line_number_width if numbers is True else 0
+            char_width, line_height = cell_size(fr.zoom)
+            rows = max(0, (fr.hy - 2 * frames.style.inset()) // line_height)
+            cols = max(0, (fr.wx - 2 * frames.style.inset() - (frames.style.minimap if fr in wfr else 0)) // char_width)
+            self.pad_resize(fr.pad_id, rows, cols)
+
+    def buffer_get(self, name: str) -> Buffer | None:
+        for buffer in self.buffers:
+            if buffer.name == name:
+                return buffer
+        return None
+
+    def buffer_create(self, name: str, lines: list[str], filename: str | None = None, undo: bool = True) -> Buffer:
+        unique = name
+        n = 2
+        while self.buffer_get(unique) is not None:
+            unique = f"{name}<{n}>"
+            n += 1
+        buffer = Buffer(lines, unique, filename, undo)
+        self.buffers.append(buffer)
+        return buffer
+
+    def buffer_untitled(self) -> Buffer:
+        buffer = self.buffer_get("*untitled*")
+        if buffer is None:
+            buffer = self.buffer_create("*untitled*", [""])
+        return buffer
+
+    def buffer_open(self, filename: str) -> Buffer:
+        path = os.path.abspath(filename)
+        for buffer in self.buffers:
+            if buffer.filename == path:
+                return buffer
+        if os.path.splitext(path)[1][1:].lower() in image_extensions and os.path.isfile(path):
+            buffer = self.buffer_create(os.path.basename(path), [f"image {abbreviate_home(path)}"], path, undo=False)
+            buffer.image = ImageView(path)
+            return buffer
+        lines, encoding, newline = [""], 'utf-8', '\n'
+        binary = False
+        if os.path.exists(path):
+            try:
+                with open(path, 'rb') as file:
+                    binary = is_binary(file.read(8192))
+            except OSError:
+                pass
+            if binary is True:
+                lines = read_lines(path, True)
+            else:
+                lines, encoding, newline = Content().load_text(path)
+        buffer = self.buffer_create(os.path.basename(path), lines, path)
+        buffer.encoding = encoding
+        buffer.newline = newline
+        buffer.hex = binary
+        buffer.read_only = os.path.exists(path) and os.access(path, os.W_OK) is False
+        return buffer
+
+    def buffer_decode(self, data: bytes, name: str, filename: str) -> Buffer:
+        """Buffer of file content read by other means than open(), e.g. from a remote host"""
+        if is_binary(data[:8192]) is True:
+            buffer = self.buffer_create(name, hex_lines(data), filename)
+            buffer.hex = True
+            return buffer
+        text, encoding = decode_text(data)
+        buffer = self.buffer_create(name, text.splitlines(), filename)
+        buffer.encoding = encoding
+        buffer.newline = detect_newline(text)
+        return buffer
+
+    def pad_show(self, pad_index:int, buffer: Buffer, row:int = 0, col:int = 0):
+        pad = self.pad_get(pad_index)
+        if pad is None:
+            return
+        if pad.buffer is not buffer:
+            pad.buffer = buffer
+            pad.buf_x = 0
+            pad.buf_y = 0
+            pad.cur_x = 0
+            pad.cur_y = 0
+            pad.sel_anchor = None
+        self.pad_goto(pad_index, row, col)
+
+    def pad_view(self, pad_index:int) -> PadView:
+        pad = self.pads[pad_index]
+        row, col = self.pad_cursor(pad_index)
+        return PadView(pad.buffer, row, col, pad.buf_y, pad.buf_x)
+
+    def pad_restore_view(self, pad_index:int, view: PadView):
+        pad = self.pad_get(pad_index)
+        if pad is None:
+            return
+        self.pad_show(pad_index, view.buffer)
+        pad.buf_y = min(view.buf_y, len(view.buffer) - 1)
+        pad.buf_x = view.buf_x
+        pad.cur_y = 0
+        pad.cur_x = 0
+        self.pad_goto(pad_index, view.row, view.col)
+
+    def pad_cursor(self, pad_index:int) -> tuple[int, int]:
+        pad = self.pads[pad_index]
+        return (pad.buf_y + pad.cur_y, pad.buf_x + pad.cur_x)
+
+    def pad_goto(self, pad_index:int, row:int, col:int):
+        pad = self.pads[pad_index]
+        row = min(max(row, 0), len(pad.buffer) - 1)
+        col = grapheme_floor(pad.buffer[row], min(max(col, 0), len(pad.buffer[row])))
+        if row < pad.buf_y:
+            pad.buf_y = row
+        elif row >= pad.buf_y + pad.height:
+            pad.buf_y = row - pad.height + 1
+        if col < pad.buf_x:
+            pad.buf_x = col
+        elif col >= pad.buf_x + pad.width:
+            pad.buf_x = col - pad.width + 1
+        pad.cur_y = row - pad.buf_y
+        pad.cur_x = col - pad.buf_x
+        self.pad_display(pad_index)
+
+    def pads_follow_edit(self, buffer: Buffer, start: int, removed: list[str], inserted: list[str], source: int = -1):
+        """Keep the cursors and views of the pads showing buffer, but source, on their text after lines start:start+len(removed) became inserted"""
+        same_rows = len(removed) == len(inserted)
+        for i, pad in enumerate(self.pads):
+            if i == source or pad.buffer is not buffer:
+                continue
+            row, col = self.pad_cursor(i)
+            if same_rows is True:
+                if start <= row < start + len(removed):
+                    self.pad_goto(i, row, shift_col(col, removed[row - start], inserted[row - start]))
+                continue
+            pad.buf_y = min(max(shift_row(pad.buf_y, start, len(removed), len(inserted)), 0), len(buffer) - 1)
+            pad.cur_y = 0
+            self.pad_goto(i, shift_row(row, start, len(removed), len(inserted)), col)
+
+    def pad_scroll_y(self, pad_index:int, dy:int):
+        """Scroll the view up or down, taking the cursor along where it would leave the view"""
+        pad = self.pads[pad_index]
+        row, col = self.pad_cursor(pad_index)
+        pad.buf_y = min(max(pad.buf_y + dy, 0), len(pad.buffer) - 1)
+        self.pad_goto(pad_index, min(max(row, pad.buf_y), pad.buf_y + pad.height - 1, len(pad.buffer) - 1), col)
+
+    def pad_recenter(self, pad_index:int):
+        """Scroll so that the cursor line is in the middle of the view"""
+        pad = self.pads[pad_index]
+        row, col = self.pad_cursor(pad_index)
+        pad.buf_y = max(0, row - pad.height // 2)
+        self.pad_goto(pad_index, row, col)
+
+    def pad_scroll_x(self, pad_index:int, dx:int):
+        """Scroll the view sideways without losing the cursor line, moving the cursor into the view"""
+        pad = self.pads[pad_index]
+        row, col = self.pad_cursor(pad_index)
+        pad.buf_x = min(max(pad.buf_x + dx, 0), len(pad.buffer[row]))
+        col = min(max(col, pad.buf_x), pad.buf_x + pad.width - 1, len(pad.buffer[row]))
+        pad.cur_x = col - pad.buf_x
+        self.pad_display(pad_index)
+
+    def text_get(self, pad_index:int, start: tuple[int, int], end: tuple[int, int]) -> str:
+        buffer = self.pads[pad_index].buffer
+        (r1, c1), (r2, c2) = start, end
+        if r1 == r2:
+            return buffer[r1][c1:c2]
+        return "\n".join([buffer[r1][c1:]] + buffer[r1+1:r2] + [buffer[r2][:c2]])
+
+    def text_delete(self, pad_index:int, start: tuple[int, int], end: tuple[int, int]):
+        buffer = self.pads[pad_index].buffer
+        (r1, c1), (r2, c2) = start, end
+        buffer[r1] = buffer[r1][:c1] + buffer[r2][c2:]
+        del buffer[r1+1:r2+1]
+        self.pad_goto(pad_index, r1, c1)
+
+    def text_insert(self, pad_index:int, pos: tuple[int, int], text: str) -> tuple[int, int]:
+        buffer = self.pads[pad_index].buffer
+        row, col = pos
+        line = buffer[row]
+        parts = text.split("\n")
+        if len(parts) == 1:
+            buffer[row] = line[:col] + text + line[col:]
+            return (row, col + len(text))
+        buffer[row] = line[:col] + parts[0]
+        buffer[row+1:row+1] = parts[1:]
+        last = row + len(parts) - 1
+        end_col = len(buffer[last])
+        buffer[last] += line[col:]
+        return (last, end_col)
+
+    def pad_get(self, padIndex: int) -> Pad | None:
+        if padIndex >=0 and padIndex<len(self.pads):
+            return self.pads[padIndex]
+        else:
+            self.log.error(f"Invalid padIndex: {padIndex}")
+            return None
+
+    def pad_display(self, pad_index:int, set_cursor:bool = True, update_from_buffer:bool=True):
+        if pad_index >= len(self.pads):
+            return
+        pad = self.pads[pad_index]
+        # self.repl.color_set(self.schema['fg'], self.schema['bg'])
+        if update_from_buffer is True:
+            for i in range(pad.height):
+                if i+pad.buf_y < len(pad.buffer):
+                    pad.screen[i] = pad.buffer[i+pad.buf_y][pad.buf_x:pad.buf_x+pad.width]
+                    pad.screen[i] += ' ' * (pad.width - len(pad.screen[i]))
+                else:
+                    pad.screen[i] = ' ' * pad.width
+        for i in range(pad.height):
+            self.pad_print_at(pad_index, pad.screen[i], i, 0)
+        if pad.left_border > 0:
+            # self.repl.color_set(self.schema['fg'], self.schema['lb'])
+            cursor = pad.buf_y + pad.cur_y if self.relative_numbers is True else None
+            for i in range(pad.height):
+                self.pad_print_at(pad_index, gutter_label(i + pad.buf_y, cursor, pad.buffer.line_offset()), i, 0, border=True)
+        if pad.bottom_border > 0:
+            # self.repl.color_set(self.schema['fg'], self.schema['bb'])
+            text, spans = status_format(self.status_format, self.status_fields(pad))
+            pad.status_spans = [StyleSpan(span.start + pad.left_border, span.end + pad.left_border, span.face) for span in spans]
+            for i in range(pad.height, pad.height+pad.bottom_border):
+                status_msg = ' ' * pad.left_border + text
+                gl = pad.left_border + pad.width
+                status_msg = status_msg[:gl]
+                status_msg += ' ' * (gl - len(status_msg))
+                self.pad_print_at(pad_index, status_msg, i, 0, border=True)
+        if set_cursor is True:
+            self.pad_print_at(pad_index, "", pad.cur_y, pad.cur_x)
+        # self.repl.canvas_render_show()
+
+    def pad_move(self, pad_id:int, dx:int | None = None, dy:int | None = None, x:int | None = None, y: int | None = None) -> bool:
+        changed: bool = False
+        if pad_id>= len(self.pads):
+            return changed
+        pad = self.pads[pad_id]
+        if x is None:
+            if dx is not None:
+                if dx < 0:
+                    if pad.cur_x + dx >=0:
+                        pad.cur_x += dx
+                        changed=True
+                    elif pad.buf_x + pad.cur_x + dx >= 0:
+                        pad.buf_x += dx
+                        if pad.buf_x < 0:
+                            pad.buf_x = 0
+                            pad.cur_x = 0
+                        changed = True
+                    else:
+                        pad.buf_x = 0
+                        pad.cur_x = 0
+                        changed = True
+                elif dx > 0:
+                    len_x = len(pad.buffer[pad.buf_y+pad.cur_y])
+                    if pad.buf_x + pad.cur_x < len_x:
+                        if pad.cur_x < pad.width:
+                            pad.cur_x += dx
+                        else:
+                            pad.buf_x += dx
+                        changed = True
+                    else:
+                        pass  # EOL, don't expand
+        else:
+            if x == -1:
+                len_x = len(pad.buffer[pad.buf_y+pad.cur_y])
+                len_w = len_x - pad.width
+                if len_w < 0:
+                    len_w = 0
+                pad.buf_x = len_w
+                pad.cur_x = len_x - pad.buf_x
+                changed = True
+            elif x == 0:
+                pad.buf_x = 0
+                pad.cur_x = 0
+                changed = True
+            else:
+                len_x = len(pad.buffer[pad.buf_y+pad.cur_y])
+                if x > len_x:
+                    x= len_x
+                if x <= pad.width:
+                    pad.buf_x = 0
+                    pad.cur_x = x
+                else:
+                    pad.buf_x = x
+                    pad.cur_x = 0
+                changed = True
+        if y is None:
+            if dy is not None:
+                if dy < 0:
+                    if pad.cur_y + dy >=0:
+                        pad.cur_y += dy
+                        changed=True
+                    elif pad.buf_y + pad.cur_y + dy >= 0:
+                        pad.buf_y += dy
+                        if pad.buf_y < 0:
+                            pad.buf_y = 0
+                            pad.cur_y = 0
+                        changed = True
+                    else:
+                        pad.buf_y = 0
+                        pad.cur_y = 0
+                        changed = True
+                elif dy > 0:
+                    if pad.buf_y + pad.cur_y < len(pad.buffer) - dy:
+                        if pad.cur_y < pad.height - 1:
+                            pad.cur_y += dy
+                        else:
+                            pad.buf_y += dy
+                        changed = True
+        else:
+            if y == -1:
+                len_y = len(pad.buffer)
+                len_h = len_y - pad.height
+                if len_h < 0:
+                    len_h = 0
+                pad.buf_y = len_h
+                pad.cur_y = len_y - pad.buf_y
+                changed = True
+            elif y == 0:
+                pad.buf_y = 0
+                pad.cur_y = 0
+                changed = True
+            else:
+                len_y = len(pad.buffer)
+                if y > len_y:
+                    y= len_y
+                if y <= pad.height:
+                    pad.buf_y = 0
+                    pad.cur_y = y
+                else:
+                    pad.buf_y = y
+                    pad.cur_y = 0
+                changed = True
+        len_x = len(pad.buffer[pad.buf_y+pad.cur_y])
+        delta = len_x - (pad.buf_x + pad.cur_x)
+        if delta < 0:
+            if pad.cur_x + delta >= 0:
+                pad.cur_x += delta
+            else:
+                pad.buf_x += delta
+                if pad.buf_x < 0:
+                    pad.buf_x = 0
+                    pad.cur_x = 0
+            changed = True
+        if pad.cur_x == pad.width:
+            pad.buf_x += 1
+            pad.cur_x -= 1
+            changed = True
+        while pad.cur_y >= pad.height:
+            pad.buf_y += 1
+            if pad.cur_y > 0:
+                pad.cur_y -= 1
+        if pad.cur_y >= pad.height:
+            self.message(f"Internal error: cursor row {pad.cur_y} outside pad")
+            pad.cur_y = pad.height - 1
+        return changed
+
+    def status_fields(self, pad: Pad) -> dict[str, str]:
+        """The fields of the status line; those that do not apply are empty, which drops the [ ] segments around them"""
+        buffer = pad.buffer
+        row = min(pad.cur_y + pad.buf_y, len(buffer) - 1)
+        narrowing = buffer.narrowing
+        settings = buffer.settings
+        errors = sum(1 for d in buffer.diagnostics if d.severity == 1)
+        warnings = len(buffer.diagnostics) - errors
+        return {
+            'name': buffer.name,
+            'ro': "[RO]" if buffer.read_only is True else "",
+            'narrowed': f"[Narrowed {narrowing.start + 1}-{narrowing.end}]" if narrowing is not None else "",
+            'row': str(row + buffer.line_offset()),
+            'col': str(grapheme_count(buffer[row][:pad.cur_x + pad.buf_x])),
+            'percent': f"{100 * (row + 1) // len(buffer)}%",
+            'lines': str(len(buffer)),
+            'ending': "HEX" if buffer.hex is True else line_ending_name(buffer.newline),
+            'indent': f"{'tabs' if settings.use_tabs is True else 'spaces'}:{settings.tab_width}",
+            'wrap': "wrap" if (pad.wrap if pad.wrap is not None else settings.wrap) is True else "",
+            'encoding': f"[{buffer.encoding}]" if buffer.encoding != 'utf-8' else "",
+            'errors': str(errors) if errors + warnings > 0 else "",
+            'warnings': str(warnings) if errors + warnings > 0 else "",
+            'changed': "[changed on disk]" if buffer.changed_on_disk is True else "",
+            'modified': "*" if buffer.modified() is True else "",
+            'branch': buffer.git_branch,
+            'mode': self.mode_label,
+            'pending': self.pending_keys,
+            }
+
+    def create_editor(self, buffer: Buffer, height: int, width:int = 0, offset_y:int =0, offset_x:int =0, color_theme: ColorTheme | None=None, line_no:bool=False, status_line:bool=False, debug:bool=False) -> int:
+        # tinp: InputEvent | None
+        left_border:int = 0
+        bottom_border:int = 0
+        if line_no is True:
+            left_border = line_number_width
+        if status_line is True:
+            bottom_border = 1
+        pad_id = self.pad_create(buffer, height, width, offset_y, offset_x, left_border, bottom_border, color_theme)
+        # self.repl.cursor_show()
+        self.editor_esc = False
+        return pad_id
+
+    def editor_event(self, pad_id: int, cmd: str, msg: str, debug: bool = False):
+        if debug is True:
+            hex_msg = f"{bytearray(msg, encoding='utf-8')}"
+            print(f"[{cmd},{msg},{hex_msg}]")
+            # self.input_queue.task_done()
+        else:
+            pad = self.pad_get(pad_id)
+            if pad is None:
+                self.message(f"Pad with id {pad_id} not found")
+                return
+            if cmd == "bsp":
+                if pad.cur_x + pad.buf_x > 0:
+                    line = pad.buffer[pad.buf_y+pad.cur_y]
+                    col = pad.buf_x + pad.cur_x
+                    start = grapheme_prev(line, col)
+                    pad.buffer[pad.buf_y+pad.cur_y] = line[:start] + line[col:]
+                    self.pad_goto(pad_id, pad.buf_y+pad.cur_y, start)
+                else:
+                    if pad.cur_y + pad.buf_y > 0:
+                        cur_idx = pad.cur_y+pad.buf_y
+                        cur_line = pad.buffer[cur_idx]
+                        _ = self.pad_move(pad_id, dy = -1)
+                        _ = self.pad_move(pad_id, x = -1)
+                        cur_idx_new = pad.cur_y+pad.buf_y
+                        pad.buffer[cur_idx_new] += cur_line
+                        del pad.buffer[cur_idx]
+                self.pad_display(pad_id)
+            elif cmd == 'exit':
+                self.editor_esc = True
+            elif cmd == "nl":
+                cur_ind = pad.cur_y+pad.buf_y
+                cur_pos = pad.cur_x + pad.buf_x
+                if cur_ind < len(pad.buffer):
+                    cur_line: str = pad.buffer[cur_ind]
+                else:
+                    self.message(f"Internal error: cursor line {cur_ind} outside buffer")
+                    return
+                left = cur_line[:cur_pos]
+                right = cur_line[cur_pos:]
+                pad.buffer[cur_ind]=left
+                if cur_ind == len(pad.buffer) -1:
+                    pad.buffer.append(right)
+                else:
+                    pad.buffer.insert(cur_ind+1, right)
+                _ = self.pad_move(pad_id, dy=1, x=0)
+                self.pad_display(pad_id)
+            elif cmd == "up":
+                _ = self.pad_move(pad_id, dy = -1)
+                self.pad_display(pad_id)
+            elif cmd == "down":
+                _ = self.pad_move(pad_id, dy = 1)
+                self.pad_display(pad_id)
+            elif cmd == "left":
+                row, col = self.pad_cursor(pad_id)
+                self.pad_goto(pad_id, row, grapheme_prev(pad.buffer[row], col))
+                self.pad_display(pad_id)
+            elif cmd == "right":
+                row, col = self.pad_cursor(pad_id)
+                self.pad_goto(pad_id, row, grapheme_next(pad.buffer[row], col))
+                self.pad_display(pad_id)
+            elif cmd == "home":
+                _ = self.pad_move(pad_id, x=0)
+                self.pad_display(pad_id)
+            elif cmd == "end":
+                _ = self.pad_move(pad_id, x= -1)
+                self.pad_display(pad_id)
+            elif cmd == "PgUp":
+                _ = self.pad_move(pad_id, dy = -pad.height)
+                self.pad_display(pad_id)
+            elif cmd == "PgDown":
+                _ = self.pad_move(pad_id, dy = pad.height)
+                self.pad_display(pad_id)
+            elif cmd == "Start":
+                _ = self.pad_move(pad_id, x=0, y=0)
+                self.pad_display(pad_id)
+            elif cmd == "End":
+                llen = len(pad.buffer) - 1
+                y = llen + pad.height
+                if y > llen:
+                    y = llen
+                _ = self.pad_move(pad_id, y=y)
+                _ = self.pad_move(pad_id, x= -1)
+                self.pad_display(pad_id)
+            elif cmd == "err":
+                self.message(f"{msg} [Illegal command in editor]")
+                return
+            elif cmd == "char":
+                cur_ind = pad.cur_y+pad.buf_y
+                cur_line = pad.buffer[cur_ind]
+                if ord(msg[0]) >= 32:
+                    left = cur_line[:pad.buf_x+pad.cur_x]
+                    right = cur_line[pad.buf_x+pad.cur_x:]
+                    pad.buffer[cur_ind] = left + msg + right
+                    _ = self.pad_move(pad_id, dx = 1)
+                self.pad_display(pad_id)
+            else:
+                self.message(f"Bad state: cmd={cmd}, msg={msg}")
+                return
+            # self.input_queue.task_done()
+                    
+            self.pad_display(pad_id, False)
+        return
+
+class Keymap:
+    """Maps key sequences like 'C-x 2' (keys separated by spaces) to (cmd, msg)"""
+    def __init__(self, name: str, bindings: dict[str, tuple[str, str]] | None = None):
+        self.log: logging.Logger = logging.getLogger("Keymap")
+        self.name: str = name
+        self.bindings: dict[str, tuple[str, str]] = {}
+        self.prefixes: set[str] = set()
+        if bindings is not None:
+            for keys, (cmd, msg) in bindings.items():
+                self.bind(keys, cmd, msg)
+
+    def bind(self, keys: str, cmd: str, msg: str = ""):
+        if keys in self.prefixes:
+            self.log.warning(f"Binding {keys} shadows longer sequences starting with it")
+        self.bindings[keys] = (cmd, msg)
+        seq = keys.split(" ")
+        for i in range(1, len(seq)):
+            self.prefixes.add(" ".join(seq[:i]))
+
+    def unbind(self, keys: str):
+        if keys in self.bindings:
+            del self.bindings[keys]
+            self.prefixes = set()
+            for k in self.bindings:
+                seq = k.split(" ")
+                for i in range(1, len(seq)):
+                    self.prefixes.add(" ".join(seq[:i]))
+
+    def is_prefix(self, keys: str) -> bool:
+        return keys in self.prefixes
+
+    def lookup(self, keys: str) -> tuple[str, str] | None:
+        return self.bindings.get(keys)
+
+    def keys_for(self, cmd: str) -> list[str]:
+        """Key sequences bound to cmd, shortest first"""
+        return sorted((keys for keys, binding in self.bindings.items() if binding[0] == cmd), key=lambda keys: (len(keys.split(" ")), keys))
+
+key_categories: list[tuple[str, set[str]]] = [  # the first with a word of the command's name wins
+    ("Help", {'describe', 'palette', 'messages', 'notifications', 'log', 'cheat'}),
+    ("Frames", {'frame', 'frames', 'split', 'enlarge', 'shrink', 'window', 'layout', 'golden', 'zen', 'minimap', 'follow', 'workspace'}),
+    ("Version control", {'hunk', 'diff', 'merge', 'git'}),
+    ("Files and buffers", {'file', 'files', 'buffer', 'save', 'revert', 'dired', 'recent', 'encoding', 'ending', 'read'}),
+    ("Search", {'grep', 'search', 'replace'}),
+    ("Code", {'lsp', 'diagnostic', 'diagnostics', 'outline', 'snippet', 'abbrev', 'repl', 'shell', 'spell', 'comment', 'format', 'build'}),
+    ("Editing", {'undo', 'redo', 'kill', 'yank', 'paste', 'delete', 'bsp', 'nl', 'insert', 'operator', 'visual', 'tab', 'indent', 'sort', 'reverse', 'unique', 'increment', 'decrement', 'rectangle'}),
+    ("Moving", {'motion', 'up', 'down', 'left', 'right', 'home', 'end', 'PgUp', 'PgDown', 'Start', 'End', 'scroll', 'recenter', 'jump', 'next', 'previous', 'bookmark', 'mark'}),
+    ("Display", {'toggle', 'zoom', 'mode', 'image', 'hex', 'export', 'narrow', 'widen', 'option'}),
+]
+
+def key_category(cmd: str) -> str:
+    words = set(cmd.split("-"))
+    for category, keywords in key_categories:
+        if len(words & keywords) > 0:
+            return category
+    return "Other"
+
+def cheat_sheet(entries: list[tuple[str, str, str]], pattern: str = "") -> list[str]:
+    """(keys, command and msg, doc) entries grouped under their categories, only those containing pattern"""
+    needle = pattern.lower()
+    width = max((len(keys) for keys, _, _ in entries), default=0)
+    cmd_width = max((len(cmd) for _, cmd, _ in entries), default=0)
+    groups: dict[str, list[str]] = {}
+    for keys, cmd, doc in sorted(entries, key=lambda entry: (entry[1], entry[0])):
+        if needle not in f"{keys} {cmd} {doc}".lower():
+            continue
+        groups.setdefault(key_category(cmd.split(" ")[0]), []).append(f"  {keys.ljust(width)}  {cmd.ljust(cmd_width)}  {doc}".rstrip())
+    lines: list[str] = []
+    for category in [category for category, _ in key_categories] + ["Other"]:
+        if category in groups:
+            lines += ([""] if len(lines) > 0 else []) + [category] + groups[category]
+    return lines if len(lines) > 0 else [f"No keys match {pattern}"]
+
+class KeySequence:
+    """Pending-keys state for multi-key sequences, dropped after a timeout"""
+    def __init__(self, timeout_ms: int = 1500):
+        self.pending: list[str] = []
+        self.timeout: float = timeout_ms / 1000.0
+        self.last_key_time: float = 0.0
+
+    def expire(self, now: float | None = None) -> bool:
+        if now is None:
+            now = time.monotonic()
+        if len(self.pending) > 0 and now - self.last_key_time > self.timeout:
+            self.pending = []
+            return True
+        return False
+
+    def feed(self, keymap: Keymap, key: str) -> tuple[KeyState, tuple[str, str] | None, str]:
+        """Returns the state after key, the binding if complete and the keys typed so far"""
+        _ = self.expire()
+        self.last_key_time = time.monotonic()
+        keys = " ".join(self.pending + [key])
+        if len(self.pending) > 0 and (key == 'Escape' or key == 'C-g'):
+            self.pending = []
+            return (KeyState.UNBOUND, None, keys)
+        if keymap.is_prefix(keys):
+            self.pending.append(key)
+            return (KeyState.PENDING, None, keys)
+        self.pending = []
+        binding = keymap.lookup(keys)
+        if binding is not None:
+            return (KeyState.BOUND, binding, keys)
+        return (KeyState.UNBOUND, None, keys)
+
+def default_keymap() -> Keymap:
+    return Keymap("default", {
+        'Return': ('nl', ''),
+        'Backspace': ('bsp', ''),
+        'Escape': ('keyboard-quit', ''),
+        'C-g': ('keyboard-quit', ''),
+        'C-l': ('recenter', ''),
+        'M-x': ('command-palette', ''),
+        'C-x C-f': ('find-file', ''),
+        'C-x C-r': ('recent-files', ''),
+        'C-x C-q': ('toggle-read-only', ''),
+        'C-SPC': ('set-mark', ''), 'C-u C-SPC': ('pop-mark', ''),
+        'C-c +': ('increment-number', ''), 'C-c -': ('decrement-number', ''),
+        'C-x C-s': ('save-buffer', ''),
+        'C-x =': ('describe-char', ''),
+        'C-h k': ('describe-key', ''), 'C-h f': ('describe-command', ''), 'C-h b': ('key-cheat-sheet', ''),
+        'C-x <': ('scroll-left', ''), 'C-x >': ('scroll-right', ''),
+        'C-x t w': ('toggle-whitespace', ''), 'C-x t s': ('toggle-spell-check', ''), 'C-x t f': ('toggle-frame-stats', ''),
+        'C-x t n': ('toggle-relative-numbers', ''), 'C-x t p': ('follow-mode', ''), 'C-x t l': ('toggle-scroll-lock', ''),
+        'C-x t 2': ('workspace-new', ''), 'C-x t 0': ('workspace-close', ''), 'C-x t o': ('workspace-next', ''), 'C-x t O': ('workspace-previous', ''),
+        'C-x t r': ('workspace-rename', ''), 'C-x t Return': ('workspace-switch', ''),
+        'M-$': ('spell-correct', ''),
+        'C-M-i': ('lsp-complete', ''), 'C-c h': ('lsp-hover', ''),
+        'M-.': ('lsp-definition', ''), 'M-?': ('lsp-references', ''), 'M-,': ('jump-back', ''), 'C-M-,': ('jump-forward', ''),
+        'C-c o': ('outline', ''),
+        'C-x v =': ('diff-with-file', ''), 'C-x v b': ('diff-buffers', ''),
+        'C-c ^ m': ('merge', ''), 'C-c ^ o': ('merge-take', 'ours'), 'C-c ^ t': ('merge-take', 'theirs'),
+        'C-c ^ b': ('merge-take', 'both'), 'C-c ^ a': ('merge-take', 'base'), 'C-c ^ n': ('merge-next', ''),
+        'C-c ^ p': ('merge-next', 'previous'), 'C-c ^ q': ('merge-quit', ''),
+        'C-c C-z': ('repl', ''), 'C-c C-c': ('repl-send', ''), 'C-c C-j': ('repl-send', 'line'), 'C-c C-b': ('repl-send', 'buffer'),
+        'M-|': ('shell-filter', ''),
+        'C-x d': ('dired', ''),
+        'C-x b': ('switch-buffer', ''),
+        'C-c s s': ('collab-share', ''), 'C-c s j': ('collab-join', ''), 'C-c s q': ('collab-leave', ''), 'C-c s p': ('collab-participants', ''),
+        'C-x v ]': ('next-hunk', ''), 'C-x v [': ('previous-hunk', ''), 'C-x v n': ('revert-hunk', ''),
+        'M-g n': ('next-diagnostic', ''), 'M-g p': ('previous-diagnostic', ''), 'M-g l': ('list-diagnostics', ''),
+        'M-i': ('tab-to-tab-stop', ''),
+        'M-/': ('snippet-expand', ''), 'C-x i s': ('snippet-insert', ''),
+        'C-x a l': ('define-abbrev', ''), 'C-x a g': ('define-global-abbrev', ''),
+        'M-;': ('toggle-comment', ''), 'C-x C-;': ('toggle-comment', ''),
+        'C-z': ('undo', ''), 'C-S-z': ('redo', ''), 'C-x u': ('undo-tree', ''),
+        'C-x n l': ('notifications', ''),
+        'C-x n d': ('dismiss-notifications', ''),
+        'C-x n n': ('narrow-to-region', ''), 'C-x n f': ('narrow-to-defun', ''), 'C-x n w': ('widen', ''),
+        'C-x r w': ('layout-save', ''),
+        'C-x r k': ('kill-rectangle', ''), 'C-x r y': ('yank-rectangle', ''), 'C-x r t': ('string-insert-rectangle', ''), 'C-x r o': ('open-rectangle', ''),
+        'C-x r m': ('bookmark-set', ''), 'C-x r b': ('bookmark-jump', ''), 'C-x r l': ('bookmark-list', ''),
+        'C-x r j': ('layout-restore', ''),
+        'M-s g': ('grep', ''),
+        'Up': ('up', ''),
+        'Down': ('down', ''),
+        'Left': ('left', ''),
+        'Right': ('right', ''),
+        'Home': ('home', ''),
+        'End': ('end', ''),
+        'PageUp': ('PgUp', ''),
+        'PageDown': ('PgDown', ''),
+        'C-Return': ('Start', ''),
+        'S-Return': ('End', ''),
+        'Tab': ('next-frame', ''),
+        'M-h': ('split', 'horizontal'),
+        'M-v': ('split', 'vertical'),
+        'M-=': ('enlarge', ''),
+        'M--': ('shrink', ''),
+        'M-c': ('delete-frame', ''),
+        'C-x 2': ('split', 'vertical'),
+        'C-x 3': ('split', 'horizontal'),
+        'C-x 0': ('delete-frame', ''),
+        'C-x 1': ('delete-other-frames', ''),
+        'C-x o': ('next-frame', ''),
+        'C-x Left': ('select-frame', 'left'),
+        'C-x Right': ('select-frame', 'right'),
+        'C-x Up': ('select-frame', 'up'),
+        'C-x Down': ('select-frame', 'down'),
+        'C-x S-Left': ('swap-frame', 'left'),
+        'C-x S-Right': ('swap-frame', 'right'),
+        'C-x S-Up': ('swap-frame', 'up'),
+        'C-x S-Down': ('swap-frame', 'down'),
+        'C-x M-Left': ('move-frame', 'left'),
+        'C-x M-Right': ('move-frame', 'right'),
+        'C-x M-Up': ('move-frame', 'up'),
+        'C-x M-Down': ('move-frame', 'down'),
+        'C-x w r': ('rotate-frames', ''),
+        'C-x w R': ('rotate-frames', 'ccw'),
+        'C-x w t': ('transpose-frames', ''),
+        'C-x w |': ('toggle-split-direction', ''),
+        'C-x w g': ('golden-ratio-mode', ''),
+        'C-x w z': ('zoom-frame', ''),
+        'C-x w f': ('jump-to-frame', ''),
+        'C-x w p': ('open-floating-frame', ''),
+        'C-=': ('font-zoom', 'in'), 'C-+': ('font-zoom', 'in'), 'C--': ('font-zoom', 'out'), 'C-0': ('font-zoom', 'reset'),
+        'C-x C-c': ('quit', ''),
+        })
+
+def text_key(text: str) -> str:
+    if text == " ":
+        return "SPC"
+    return text
+
+def key_text(key: str) -> str | None:
+    """Inverse of text_key(): the character a key inserts, or None for named keys"""
+    if key == "SPC":
+        return " "
+    if len(key) == 1:
+        return key
+    return None
+
+def toggle_comment(lines: list[str], start: str, end: str = "") -> list[str]:
+    """Uncomment if every non-blank line is commented, else comment all at their common indentation"""
+    def commented(line: str) -> bool:
+        body = line.strip()
+        return body.startswith(start) and (end == "" or body.endswith(end))
+
+    filled = [line for line in lines if line.strip() != ""]
+    if len(filled) == 0:
+        return lines
+    if all(commented(line) for line in filled):
+        result: list[str] = []
+        for line in lines:
+            if line.strip() == "":
+                result.append(line)
+                continue
+            indent = line[:len(line) - len(line.lstrip())]
+            body = line.strip()[len(start):]
+            if end != "":
+                body = body[:-len(end)]
+            if body.startswith(" "):
+                body = body[1:]
+            if end != "" and body.endswith(" "):
+                body = body[:-1]
+            result.append(indent + body)
+        return result
+    indent = min(len(line) - len(line.lstrip()) for line in filled)
+    return [line if line.strip() == "" else line[:indent] + start + " " + line[indent:] + (" " + end if end != "" else "") for line in lines]
+
+number_prefix_re = re.compile(r'^\s*([-+]?(?:\d+(?:\.\d*)?|\.\d+))')
+
+def sort_lines(lines: list[str], how: str = "") -> list[str]:
+    """Lines by their text, how 'case' ignoring case, 'numeric' by their leading number; those without one come first"""
+    if how == 'numeric':
+        def number(line: str) -> tuple[int, float]:
+            match = number_prefix_re.match(line)
+            return (0, 0.0) if match is None else (1, float(match.group(1)))
+
+        return sorted(lines, key=number)
+    if how == 'case':
+        return sorted(lines, key=str.casefold)
+    return sorted(lines)
+
+def unique_lines(lines: list[str]) -> list[str]:
+    """The first of each set of equal lines, in their order"""
+    return list(dict.fromkeys(lines))
+
+number_re = re.compile(r'0[xX][0-9a-fA-F]+|-?\d+')
+
+def increment_number(line: str, col: int, delta: int) -> tuple[str, int] | None:
+    """line with delta added to the number under or after col, and the column of its new last character, like vim's C-a;
+    decimal numbers keep their zero padding, a dash after a word character as in x-1 is no sign, 0x hex wraps as 64 bits"""
+    for match in number_re.finditer(line):
+        start, text = match.start(), match.group(0)
+        if text.startswith('-') and start > 0 and char_class(line[start - 1]) == 1:
+            start, text = start + 1, text[1:]
+        if match.end() <= col:
+            continue
+        if text[:2].lower() == '0x':
+            digits = text[2:]
+            upper = any(c.isupper() for c in digits)
+            new = text[:2] + format((int(digits, 16) + delta) % 2 ** 64, 'X' if upper is True else 'x').zfill(len(digits))
+        else:
+            value = int(text) + delta
+            digits = text.lstrip('-')
+            width = len(digits) if len(digits) > 1 and digits.startswith('0') else 0
+            new = ('-' if value < 0 else '') + str(abs(value)).zfill(width)
+        return (line[:start] + new + line[start + len(text):], start + len(new) - 1)
+    return None
+
+def defun_bounds(lines: list[str], row: int) -> tuple[int, int]:
+    """Rows start:end of the top-level definition around row: from the nearest unindented line at or above it, with
+    decorators before, to the next one; closing brackets at the line start don't count as unindented"""
+    def top(i: int) -> bool:
+        return lines[i] != "" and lines[i][0].isspace() is False and lines[i][0] not in "{}[]()"
+
+    start = row
+    while start > 0 and top(start) is False:
+        start -= 1
+    while start > 0 and top(start - 1) and lines[start - 1].startswith('@'):
+        start -= 1
+    end = start
+    while end < len(lines) - 1 and lines[end].startswith('@'):
+        end += 1
+    end += 1
+    while end < len(lines) and top(end) is False:
+        end += 1
+    while end > start + 1 and lines[end - 1].strip() == "":
+        end -= 1
+    return (start, end)
+
+def kill_rectangle(lines: list[str], c1: int, c2: int) -> tuple[list[str], list[str]]:
+    """The lines without columns c1 to c2 and what was there, padded with spaces where lines are shorter"""
+    return ([line[:c1] + line[c2:] for line in lines], [line[c1:c2].ljust(c2 - c1) for line in lines])
+
+def insert_rectangle(lines: list[str], col: int, rect: list[str]) -> list[str]:
+    """A line per string of rect, each inserted at col into the line there (padded to reach it) or into a new one"""
+    result: list[str] = []
+    for i, piece in enumerate(rect):
+        line = lines[i] if i < len(lines) else ""
+        result.append(line[:col].ljust(col) + piece + line[col:])
+    return result + lines[len(rect):]
+
+auto_pairs = {'(': ')', '[': ']', '{': '}', '"': '"', "'": "'", '`': '`'}
+
+def char_class(c: str) -> int:
+    if c.isspace():
+        return 0
+    if c.isalnum() or c == '_':
+        return 1
+    return 2
+
+class ModalEditor:
+    """vim-style normal/insert/visual layer on top of the keymaps, driving ReplEditor"""
+    def __init__(self, editor: ReplEditor, timeout_ms: int = 1500):
+        self.log: logging.Logger = logging.getLogger("ModalEditor")
+        self.editor: ReplEditor = editor
+        self.mode: Mode = Mode.NORMAL
+        self.count: str = ""
+        self.keys: KeySequence = KeySequence(timeout_ms)
+        self.fallback: Callable[[str, str], None] | None = None  # runs bindings that aren't modal commands
+        self.on_mode_change: Callable[[Mode, Mode], None] | None = None  # new and previous mode
+        self.on_jump: Callable[[int], None] | None = None  # with the pad, before the motions that jump: G and gg
+        self.counted: set[str] = {'increment-number', 'decrement-number'}  # fallback commands given an explicit count as msg
+        self.operator: str = ""
+        self.op_count: int = 1
+        self.normal_keymap: Keymap = Keymap("normal", {
+            'h': ('motion', 'left'), 'Left': ('motion', 'left'), 'Backspace': ('motion', 'left'),
+            'l': ('motion', 'right'), 'Right': ('motion', 'right'),
+            'j': ('motion', 'down'), 'Down': ('motion', 'down'), 'Return': ('motion', 'down'),
+            'k': ('motion', 'up'), 'Up': ('motion', 'up'),
+            'w': ('motion', 'word'), 'b': ('motion', 'back'),
+            '0': ('motion', 'bol'), 'Home': ('motion', 'bol'),
+            '$': ('motion', 'eol'), 'End': ('motion', 'eol'),
+            'G': ('motion', 'last'), 'g g': ('motion', 'first'), 'g c': ('toggle-comment', ''),
+            'd': ('operator', 'd'), 'y': ('operator', 'y'), 'c': ('operator', 'c'),
+            'x': ('delete-char', ''), 'p': ('paste', 'after'), 'P': ('paste', 'before'),
+            'i': ('insert', 'i'), 'a': ('insert', 'a'), 'I': ('insert', 'I'), 'A': ('insert', 'A'),
+            'o': ('insert', 'o'), 'O': ('insert', 'O'),
+            'v': ('visual', ''), 'Escape': ('escape', ''),
+            'u': ('undo', ''), 'C-r': ('redo', ''), 'SPC u': ('undo-tree', ''),
+            'SPC i s': ('snippet-insert', ''),
+            'z h': ('scroll-left', ''), 'z l': ('scroll-right', ''), 'SPC t w': ('toggle-whitespace', ''),
+            'SPC t s': ('toggle-spell-check', ''), 'SPC t n': ('toggle-relative-numbers', ''), 'SPC t p': ('follow-mode', ''), 'SPC t l': ('toggle-scroll-lock', ''),
+            'SPC Tab n': ('workspace-new', ''), 'SPC Tab d': ('workspace-close', ''), 'SPC Tab ]': ('workspace-next', ''), 'SPC Tab [': ('workspace-previous', ''),
+            'SPC Tab r': ('workspace-rename', ''), 'SPC Tab Tab': ('workspace-switch', ''), 'SPC Tab 1': ('workspace-switch', '1'), 'SPC Tab 2': ('workspace-switch', '2'),
+            'SPC Tab 3': ('workspace-switch', '3'), 'SPC Tab 4': ('workspace-switch', '4'), 'z =': ('spell-correct', ''),
+            'K': ('lsp-hover', ''), 'SPC c c': ('lsp-complete', ''),
+            'g d': ('lsp-definition', ''), 'g r': ('lsp-references', ''), 'C-t': ('jump-back', ''),
+            'C-a': ('increment-number', ''), 'g C-x': ('decrement-number', ''),  # C-x alone would hide the global C-x chords
+            'C-o': ('jump-back', ''), 'C-i': ('jump-forward', ''), 'm': ('set-mark', ''), '`': ('pop-mark', ''),
+            'SPC c o': ('outline', ''),
+            'SPC g d': ('diff-with-file', ''), 'SPC g b': ('diff-buffers', ''),
+            'SPC m m': ('merge', ''), 'SPC m o': ('merge-take', 'ours'), 'SPC m t': ('merge-take', 'theirs'),
+            'SPC m b': ('merge-take', 'both'), 'SPC m a': ('merge-take', 'base'), 'SPC m n': ('merge-next', ''),
+            'SPC m p': ('merge-next', 'previous'), 'SPC m q': ('merge-quit', ''),
+            'SPC r r': ('repl', ''), 'SPC r s': ('repl-send', ''), 'SPC r l': ('repl-send', 'line'), 'SPC r b': ('repl-send', 'buffer'),
+            'SPC |': ('shell-filter', ''),
+            'SPC R k': ('kill-rectangle', ''), 'SPC R y': ('yank-rectangle', ''), 'SPC R t': ('string-insert-rectangle', ''), 'SPC R o': ('open-rectangle', ''),
+            'SPC n n': ('narrow-to-region', ''), 'SPC n f': ('narrow-to-defun', ''), 'SPC n w': ('widen', ''),
+            'SPC B m': ('bookmark-set', ''), 'SPC B b': ('bookmark-jump', ''), 'SPC B l': ('bookmark-list', ''),
+            'SPC h k': ('describe-key', ''), 'SPC h f': ('describe-command', ''), 'SPC h b': ('key-cheat-sheet', ''),
+            'SPC f d': ('dired', ''),
+            'SPC C s': ('collab-share', ''), 'SPC C j': ('collab-join', ''), 'SPC C q': ('collab-leave', ''), 'SPC C p': ('collab-participants', ''),
+            '] c': ('next-hunk', ''), '[ c': ('previous-hunk', ''), 'SPC g r': ('revert-hunk', ''),
+            '] d': ('next-diagnostic', ''), '[ d': ('previous-diagnostic', ''), 'SPC c d': ('list-diagnostics', ''),
+            'SPC w v': ('split', 'horizontal'), 'SPC w s': ('split', 'vertical'),
+            'SPC w c': ('delete-frame', ''), 'SPC w w': ('next-frame', ''),
+            'SPC w h': ('select-frame', 'left'), 'SPC w l': ('select-frame', 'right'),
+            'SPC w k': ('select-frame', 'up'), 'SPC w j': ('select-frame', 'down'),
+            'SPC w H': ('swap-frame', 'left'), 'SPC w L': ('swap-frame', 'right'),
+            'SPC w K': ('swap-frame', 'up'), 'SPC w J': ('swap-frame', 'down'),
+            'SPC w m h': ('move-frame', 'left'), 'SPC w m l': ('move-frame', 'right'),
+            'SPC w m k': ('move-frame', 'up'), 'SPC w m j': ('move-frame', 'down'),
+            'SPC w r': ('rotate-frames', ''), 'SPC w R': ('rotate-frames', 'ccw'),
+            'SPC w t': ('transpose-frames', ''), 'SPC w |': ('toggle-split-direction', ''),
+            'SPC w g': ('golden-ratio-mode', ''), 'SPC w z': ('zoom-frame', ''),
+            'SPC w o': ('delete-other-frames', ''), 'SPC w f': ('jump-to-frame', ''),
+            'SPC w p': ('open-floating-frame', ''),
+            'SPC f f': ('find-file', ''), 'SPC f r': ('recent-files', ''), 'SPC f s': ('save-buffer', ''), 'SPC b b': ('switch-buffer', ''),
+            'SPC l s': ('layout-save', ''), 'SPC l l': ('layout-restore', ''), 'SPC s p': ('grep', ''),
+            })
+        self.set_mode(Mode.NORMAL)
+
+    def set_mode(self, mode: Mode, pad_id: int = -1):
+        previous = self.mode
+        self.mode = mode
+        self.editor.mode_label = mode.name
+        self.editor.cursor_block = mode != Mode.INSERT
+        pad = self.editor.pad_get(pad_id) if pad_id >= 0 else None
+        if pad is not None:
+            if mode == Mode.VISUAL:
+                pad.sel_anchor = self.editor.pad_cursor(pad_id)
+            else:
+                pad.sel_anchor = None
+            if mode == Mode.NORMAL:
+                row, col = self.editor.pad_cursor(pad_id)
+                self.goto(pad_id, row, col)
+        self.reset()
+        if mode != previous and self.on_mode_change is not None:
+            self.on_mode_change(mode, previous)
+
+    def reset(self):
+        self.count = ""
+        self.operator = ""
+        self.op_count = 1
+
+    def goto(self, pad_id: int, row: int, col: int):
+        buffer = self.editor.pads[pad_id].buffer
+        row = min(max(row, 0), len(buffer) - 1)
+        if self.mode != Mode.INSERT:
+            col = grapheme_floor(buffer[row], min(col, max(0, len(buffer[row]) - 1)))
+        self.editor.pad_goto(pad_id, row, col)
+
+    def word_forward(self, buffer: list[str], row: int, col: int) -> tuple[int, int]:
+        line = buffer[row]
+        if col < len(line) and char_class(line[col]) != 0:
+            cls = char_class(line[col])
+            while col < len(line) and char_class(line[col]) == cls:
+                col += 1
+        while True:
+            line = buffer[row]
+            while col < len(line) and char_class(line[col]) == 0:
+                col += 1
+            if col < len(line):
+                return (row, col)
+            if row + 1 >= len(buffer):
+                return (row, len(line))
+            row += 1
+            col = 0
+            if buffer[row] == "":
+                return (row, 0)
+
+    def word_backward(self, buffer: list[str], row: int, col: int) -> tuple[int, int]:
+        while True:
+            if col == 0:
+                if row == 0:
+                    return (0, 0)
+                row -= 1
+                col = len(buffer[row])
+                if col == 0:
+                    return (row, 0)
+            col -= 1
+            if char_class(buffer[row][col]) != 0:
+                break
+        cls = char_class(buffer[row][col])
+        while col > 0 and char_class(buffer[row][col-1]) == cls:
+            col -= 1
+        return (row, col)
+
+    def motion(self, pad_id: int, name: str, count: int, explicit_count: bool) -> tuple[int, int, bool, bool]:
+        """Returns target (row, col, linewise, inclusive) of a motion from the cursor"""
+        buffer = self.editor.pads[pad_id].buffer
+        row, col = self.editor.pad_cursor(pad_id)
+        if name == 'left':
+            for _ in range(count):
+                col = grapheme_prev(buffer[row], col)
+            return (row, col, False, False)
+        elif name == 'right':
+            for _ in range(count):
+                col = grapheme_next(buffer[row], col)
+            return (row, col, False, False)
+        elif name == 'down':
+            return (min(len(buffer) - 1, row + count), col, True, False)
+        elif name == 'up':
+            return (max(0, row - count), col, True, False)
+        elif name == 'bol':
+            return (row, 0, False, False)
+        elif name == 'eol':
+            row = min(len(buffer) - 1, row + count - 1)
+            return (row, len(buffer[row]), False, False)
+        elif name == 'first':
+            return ((count - 1) if explicit_count else 0, 0, True, False)
+        elif name == 'last':
+            return ((count - 1) if explicit_count else len(buffer) - 1, 0, True, False)
+        elif name == 'word':
+            line = buffer[row]
+            if self.operator == 'c' and col < len(line) and char_class(line[col]) != 0:
+                # cw changes to the end of the word, keeping the following whitespace
+                for _ in range(count):
+                    if col < len(line) and char_class(line[col]) == 0:
+                        while col < len(line) and char_class(line[col]) == 0:
+                            col += 1
+                    cls = char_class(line[col]) if col < len(line) else 0
+                    while col < len(line) and char_class(line[col]) == cls and cls != 0:
+                        col += 1
+                return (row, col, False, False)
+            start_row = row
+            for _ in range(count):
+                row, col = self.word_forward(buffer, row, col)
+            if self.operator != "" and row > start_row:
+                # like vim, an operator on w never eats the line break
+                row -= 1
+                col = len(buffer[row])
+            return (row, col, False, False)
+        elif name == 'back':
+            for _ in range(count):
+                row, col = self.word_backward(buffer, row, col)
+            return (row, col, False, False)
+        self.log.error(f"Unknown motion: {name}")
+        return (row, col, False, False)
+
+    def apply_operator(self, pad_id: int, operator: str, start: tuple[int, int], end: tuple[int, int], linewise: bool):
+        buffer = self.editor.pads[pad_id].buffer
+        if linewise is True:
+            r1, r2 = start[0], end[0]
+            self.editor.register = ("\n".join(buffer[r1:r2+1]) + "\n", True)
+            if operator == 'y':
+                self.goto(pad_id, r1, 0)
+                return
+            if operator == 'c':
+                buffer[r1:r2+1] = [""]
+                self.set_mode(Mode.INSERT)
+                self.goto(pad_id, r1, 0)
+                return
+            del buffer[r1:r2+1]
+            if len(buffer) == 0:
+                buffer.append("")
+            self.goto(pad_id, r1, 0)
+            return
+        self.editor.register = (self.editor.text_get(pad_id, start, end), False)
+        if operator == 'y':
+            self.goto(pad_id, start[0], start[1])
+            return
+        self.editor.text_delete(pad_id, start, end)
+        if operator == 'c':
+            self.set_mode(Mode.INSERT)
+        self.goto(pad_id, start[0], start[1])
+
+    def paste(self, pad_id: int, where: str, count: int):
+        text, linewise = self.editor.register
+        if text == "":
+            return
+        buffer = self.editor.pads[pad_id].buffer
+        row, col = self.editor.pad_cursor(pad_id)
+        if linewise is True:
+            lines = text[:-1].split("\n") * count
+            if where == 'after':
+                row += 1
+            buffer[row:row] = lines
+            self.goto(pad_id, row, 0)
+            return
+        if where == 'after' and len(buffer[row]) > 0:
+            col += 1
+        end = self.editor.text_insert(pad_id, (row, col), text * count)
+        self.goto(pad_id, end[0], end[1] - 1)
+
+    def enter_insert(self, pad_id: int, how: str):
+        buffer = self.editor.pads[pad_id].buffer
+        row, col = self.editor.pad_cursor(pad_id)
+        self.set_mode(Mode.INSERT, pad_id)
+        if how == 'a' and len(buffer[row]) > 0:
+            col += 1
+        elif how == 'I':
+            col = len(buffer[row]) - len(buffer[row].lstrip())
+        elif how == 'A':
+            col = len(buffer[row])
+        elif how == 'o':
+            buffer.insert(row + 1, "")
+            row, col = row + 1, 0
+        elif how == 'O':
+            buffer.insert(row, "")
+            col = 0
+        self.goto(pad_id, row, col)
+
+    def feed(self, pad_id: int, key: str) -> bool:
+        """Handle key in the current mode; False means the key falls through to the default keymap"""
+        if self.editor.pad_get(pad_id) is None:
+            return False
+        if self.mode == Mode.INSERT:
+            if key == 'Escape':
+                row, col = self.editor.pad_cursor(pad_id)
+                self.set_mode(Mode.NORMAL, pad_id)
+                self.goto(pad_id, row, grapheme_prev(self.editor.pads[pad_id].buffer[row], col))
+                return True
+            return False
+        if key.isdigit() and (key != '0' or self.count != "") and len(self.keys.pending) == 0:
+            self.count += key
+            return True
+        state, binding, keys = self.keys.feed(self.normal_keymap, key)
+        self.editor.pending_keys = " ".join(self.keys.pending)
+        if state == KeyState.PENDING:
+            return True
+        if binding is None:
+            self.reset()
+            # swallow unbound printable keys and aborted sequences
+            return key_text(key) is not None or " " in keys
+        cmd, msg = binding
+        explicit_count = self.count != ""
+        count = (int(self.count) if explicit_count else 1) * self.op_count
+        self.count = ""
+        if cmd == 'operator':
+            if self.mode == Mode.VISUAL:
+                sel = self.editor.pads[pad_id].selection()
+                if sel is not None:
+                    start, end = sel
+                    end = (end[0], min(end[1] + 1, len(self.editor.pads[pad_id].buffer[end[0]])))
+                    if msg == 'c':
+                        self.apply_operator(pad_id, msg, start, end, False)
+                        self.editor.pads[pad_id].sel_anchor = None
+                    else:
+                        self.set_mode(Mode.NORMAL, pad_id)
+                        self.apply_operator(pad_id, msg, start, end, False)
+                return True
+            if self.operator == msg:
+                # dd, yy, cc: operate on count lines
+                row, _ = self.editor.pad_cursor(pad_id)
+                last = min(len(self.editor.pads[pad_id].buffer) - 1, row + count - 1)
+                operator = self.operator
+                self.reset()
+                self.apply_operator(pad_id, operator, (row, 0), (last, 0), True)
+                return True
+            self.operator = msg
+            self.op_count = count
+            return True
+        if cmd == 'motion':
+            row, col, linewise, inclusive = self.motion(pad_id, msg, count, explicit_count)
+            if self.operator != "":
+                operator = self.operator
+                self.reset()
+                cursor = self.editor.pad_cursor(pad_id)
+                start, end = min(cursor, (row, col)), max(cursor, (row, col))
+                if inclusive is True:
+                    end = (end[0], end[1] + 1)
+                self.apply_operator(pad_id, operator, start, end, linewise)
+            else:
+                if msg in ('first', 'last') and self.on_jump is not None:
+                    self.on_jump(pad_id)
+                self.goto(pad_id, row, col)
+            return True
+        operator_pending = self.operator != ""
+        self.reset()
+        if operator_pending is True:
+            return True
+        if cmd == 'delete-char':
+            row, col = self.editor.pad_cursor(pad_id)
+            end = col
+            for _ in range(count):
+                end = grapheme_next(self.editor.pads[pad_id].buffer[row], end)
+            if end > col:
+                self.apply_operator(pad_id, 'd', (row, col), (row, end), False)
+        elif cmd == 'paste':
+            self.paste(pad_id, msg, count)
+        elif cmd == 'insert':
+            self.enter_insert(pad_id, msg)
+        elif cmd == 'visual':
+            if self.mode == Mode.VISUAL:
+                self.set_mode(Mode.NORMAL, pad_id)
+            else:
+                self.set_mode(Mode.VISUAL, pad_id)
+        elif cmd == 'escape':
+            self.set_mode(Mode.NORMAL, pad_id)
+        elif self.fallback is not None:
+            self.fallback(cmd, str(count) if explicit_count is True and cmd in self.counted else msg)
+        return True
+
+@dataclass
+class Command:
+    name: str
+    fn: Callable[[str], None]
+    doc: str
+
+class Commands:
+    def __init__(self):
+        self.log: logging.Logger = logging.getLogger("Commands")
+        self.commands: dict[str, Command] = {}
+
+    def register(self, name: str, fn: Callable[[str], None], doc: str):
+        if name in self.commands:
+            self.log.warning(f"Command {name} registered twice")
+        self.commands[name] = Command(name, fn, doc)
+
+    def get(self, name: str) -> Command | None:
+        return self.commands.get(name)
+
+    def names(self) -> list[str]:
+        return sorted(self.commands.keys())
+
+    def run(self, name: str, msg: str = "") -> bool:
+        command = self.commands.get(name)
+        if command is None:
+            self.log.error(f"Unknown command: {name}")
+            return False
+        command.fn(msg)
+        return True
+
+hook_events = ('buffer-opened', 'before-save', 'after-save', 'frame-created', 'frame-selected', 'mode-changed')
+
+class Hooks:
+    """Callbacks per editor event, run in the order they were added with the event's arguments as keywords"""
+    def __init__(self):
+        self.log: logging.Logger = logging.getLogger("Hooks")
+        self.callbacks: dict[str, list[Callable[..., None]]] = {event: [] for event in hook_events}
+
+    def add(self, event: str, callback: Callable[..., None]) -> bool:
+        if event not in self.callbacks:
+            self.log.error(f"Unknown event {event}, expected one of {', '.join(hook_events)}")
+            return False
+        self.callbacks[event].append(callback)
+        return True
+
+    def remove(self, event: str, callback: Callable[..., None]):
+        if callback in self.callbacks.get(event, []):
+            self.callbacks[event].remove(callback)
+
+    def emit(self, event: str, **args: object):
+        """A failing callback is logged and does not keep the others from running"""
+        for callback in list(self.callbacks[event]):
+            try:
+                callback(**args)
+            except Exception as e:
+                self.log.error(f"{event} hook {getattr(callback, '__name__', callback)} failed: {e}")
+
+def fuzzy_score(pattern: str, candidate: str) -> int | None:
+    """Subsequence match of pattern in candidate, higher is better, None if no match"""
+    p = pattern.lower()
+    c = candidate.lower()
+    score = 0
+    pi = 0
+    prev = -2
+    for ci, ch in enumerate(c):
+        if pi < len(p) and ch == p[pi]:
+            score += 1
+            if ci == prev + 1:
+                score += 5
+            if ci == 0 or c[ci-1] in " -_/.":
+                score += 3
+            prev = ci
+            pi += 1
+    if pi < len(p):
+        return None
+    return score * 100 - len(c)
+
+def fuzzy_filter(pattern: str, candidates: list[str]) -> list[str]:
+    """Matching candidates, best first; ties and an empty pattern keep the candidate order"""
+    if pattern == "":
+        return list(candidates)
+    scored: list[tuple[int, str]] = []
+    for candidate in candidates:
+        score = fuzzy_score(pattern, candidate)
+        if score is not None:
+            scored.append((score, candidate))
+    scored.sort(key=lambda sc: -sc[0])
+    return [candidate for _, candidate in scored]
+
+class Minibuffer:
+    """One-line input at the bottom of the window with fuzzy completion"""
+    def __init__(self):
+        self.log: logging.Logger = logging.getLogger("Minibuffer")
+        self.active: bool = False
+        self.prompt: str = ""
+        self.text: str = ""
+        self.candidates: list[str] = []
+        self.matches: list[str] = []
+        self.selected: int = 0
+        self.max_visible: int = 8
+        self.on_accept: Callable[[str], None] | None = None
+        self.annotate: Callable[[str], str] | None = None
+        self.on_change: Callable[[str], None] | None = None  # called with the text after each edit, for live filters
+        self.require_match: bool = True
+        self.message: str = ""  # echo area text shown while inactive
+        self.message_until: float = 0.0
+
+    def show_message(self, text: str, seconds: float):
+        self.message = text
+        self.message_until = time.monotonic() + seconds
+
+    def current_message(self) -> str:
+        if self.message != "" and time.monotonic() > self.message_until:
+            self.message = ""
+        return self.message
+
+    def start(self, prompt: str, candidates: list[str], on_accept: Callable[[str], None], annotate: Callable[[str], str] | None = None, require_match: bool = True,
+              on_change: Callable[[str], None] | None = None):
+        """With require_match False the typed text is accepted as is, candidates only complete"""
+        self.active = True
+        self.require_match = require_match
+        self.prompt = prompt
+        self.text = ""
+        self.candidates = candidates
+        self.on_accept = on_accept
+        self.annotate = annotate
+        self.on_change = on_change
+        self.update_matches()
+
+    def cancel(self):
+        self.active = False
+        self.on_accept = None
+        self.on_change = None
+        self.candidates = []
+        self.matches = []
+
+    def update_matches(self):
+        self.matches = fuzzy_filter(self.text, self.candidates)
+        self.selected = 0
+        if self.on_change is not None:
+            self.on_change(self.text)
+
+    def visible_matches(self) -> tuple[list[str], int]:
+        """Window of matches around the selection and the selection index inside it"""
+        first = max(0, self.selected - self.max_visible + 1)
+        return (self.matches[first:first + self.max_visible], self.selected - first)
+
+    def feed(self, key: str) -> bool:
+        if self.active is False:
+            return False
+        if key == 'Return':
+            if self.require_match is True and len(self.candidates) > 0 and len(self.matches) == 0:
+                return True  # completion is required and nothing matches
+            if self.require_match is False and self.text != "":
+                choice = self.text
+            else:
+                choice = self.matches[self.selected] if len(self.matches) > 0 else self.text
+            on_accept = self.on_accept
+            self.cancel()
+            if on_accept is not None:
+                on_accept(choice)
+        elif key == 'Escape' or key == 'C-g':
+            self.cancel()
+        elif key == 'Backspace':
+            self.text = self.text[:-1]
+            self.update_matches()
+        elif key == 'Tab':
+            if len(self.matches) > 0:
+                self.text = self.matches[self.selected]
+                self.update_matches()
+        elif key in ('Down', 'C-n'):
+            if len(self.matches) > 0:
+                self.selected = (self.selected + 1) % len(self.matches)
+        elif key in ('Up', 'C-p'):
+            if len(self.matches) > 0:
+                self.selected = (self.selected - 1) % len(self.matches)
+        else:
+            text = key_text(key)
+            if text is not None:
+                self.text += text
+                self.update_matches()
+        return True
+
+def find_project_root(path: str) -> str:
+    """Nearest parent directory containing .git or a .led.toml, or path itself"""
+    directory = os.path.abspath(path)
+    if os.path.isdir(directory) is False:
+        directory = os.path.dirname(directory)
+    probe = directory
+    while True:
+        if os.path.exists(os.path.join(probe, ".git")) or os.path.exists(os.path.join(probe, project_config_name)):
+            return probe
+        parent = os.path.dirname(probe)
+        if parent == probe:
+            return directory
+        probe = parent
+
+@dataclass
+class Hunk:
+    start: int  # buffer rows start:end replaced base rows base_start:base_end
+    end: int
+    base_start: int
+    base_end: int
+
+    def kind(self) -> str:
+        return 'added' if self.base_start == self.base_end else 'deleted' if self.start == self.end else 'modified'
+
+def diff_hunks(base: list[str], lines: list[str]) -> list[Hunk]:
+    matcher = difflib.SequenceMatcher(None, base, lines, autojunk=False)
+    return [Hunk(j1, j2, i1, i2) for tag, i1, i2, j1, j2 in matcher.get_opcodes() if tag != 'equal']
+
+def diff_align(a: list[str], b: list[str]) -> list[tuple[int | None, int | None, bool]]:
+    """Rows of a side-by-side view: line of a, line of b (None for filler) and whether the row differs"""
+    rows: list[tuple[int | None, int | None, bool]] = []
+    matcher = difflib.SequenceMatcher(None, a, b, autojunk=False)
+    for tag, i1, i2, j1, j2 in matcher.get_opcodes():
+        if tag == 'equal':
+            rows += [(i, j, False) for i, j in zip(range(i1, i2), range(j1, j2))]
+            continue
+        for k in range(max(i2 - i1, j2 - j1)):
+            rows.append((i1 + k if i1 + k < i2 else None, j1 + k if j1 + k < j2 else None, True))
+    return rows
+
+@dataclass
+class DiffView:
+    left: Buffer  # generated, aligned views
+    right: Buffer
+    sources: tuple[Buffer | None, Buffer | None]  # None for a file read from disk
+    rows: list[tuple[int | None, int | None, bool]]
+    left_frame: int
+    right_frame: int
+    origin: Buffer  # shown again when the view is closed
+
+    def changes(self) -> list[int]:
+        """First row of each changed region"""
+        return [i for i, (_, _, changed) in enumerate(self.rows) if changed is True and (i == 0 or self.rows[i - 1][2] is False)]
+
+@dataclass
+class Conflict:
+    start: int  # row of <<<<<<<
+    base: int  # row of ||||||| (diff3 style), -1 without a base section
+    middle: int  # row of =======
+    end: int  # row of >>>>>>>
+
+    def ours(self, lines: list[str]) -> list[str]:
+        return lines[self.start + 1:self.base if self.base >= 0 else self.middle]
+
+    def base_lines(self, lines: list[str]) -> list[str]:
+        return lines[self.base + 1:self.middle] if self.base >= 0 else []
+
+    def theirs(self, lines: list[str]) -> list[str]:
+        return lines[self.middle + 1:self.end]
+
+def find_conflicts(lines: list[str]) -> list[Conflict]:
+    conflicts: list[Conflict] = []
+    start = base = middle = -1
+    for row, line in enumerate(lines):
+        if line.startswith("<<<<<<<"):
+            start, base, middle = row, -1, -1
+        elif line.startswith("|||||||") and start >= 0 and middle < 0:
+            base = row
+        elif line.startswith("=======") and start >= 0 and middle < 0:
+            middle = row
+        elif line.startswith(">>>>>>>") and middle >= 0:
+            conflicts.append(Conflict(start, base, middle, row))
+            start = middle = -1
+    return conflicts
+
+def conflict_side(lines: list[str], conflicts: list[Conflict], side: str) -> tuple[list[str], list[tuple[int, int]]]:
+    """The text with every conflict resolved to one side, and the rows each resolution covers"""
+    result: list[str] = []
+    regions: list[tuple[int, int]] = []
+    pos = 0
+    for conflict in conflicts:
+        result += lines[pos:conflict.start]
+        chosen = conflict.ours(lines) if side == 'ours' else conflict.theirs(lines)
+        regions.append((len(result), len(result) + len(chosen)))
+        result += chosen
+        pos = conflict.end + 1
+    result += lines[pos:]
+    return result if len(result) > 0 else [""], regions
+
+@dataclass
+class MergeView:
+    buffer: Buffer  # the result, edited in place
+    ours: Buffer
+    theirs: Buffer
+    ours_frame: int
+    theirs_frame: int
+    result_frame: int
+    synced: tuple[int, int] = (-1, -1)  # undo version and cursor row the side views were last moved for
+
+def css_color(color: tuple[int, int, int, int]) -> str:
+    return f"rgb({color[0]},{color[1]},{color[2]})"
+
+def html_export(buffer: Buffer, theme: ColorTheme, first: int = 0, last: int = -1, numbers: bool = True) -> str:
+    """Rows first..last as a standalone page in the theme's colors, with row highlights and diagnostics underlined"""
+    last = len(buffer) - 1 if last < 0 else last
+    severities = {1: theme.error, 2: theme.warning, 3: theme.info, 4: theme.info}
+    width = len(str(last + 1))
+    rows: list[str] = []
+    for row in range(first, last + 1):
+        raw = buffer[row]
+        line = raw.expandtabs(buffer.settings.tab_width)
+        marks: list[tuple[int, int, int]] = []  # start, end, severity in expanded columns
+        for d in buffer.diagnostics:
+            if d.row <= row <= d.end_row:
+                start = len(raw[:d.col if row == d.row else 0].expandtabs(buffer.settings.tab_width))
+                end = len(raw[:d.end_col].expandtabs(buffer.settings.tab_width)) if row == d.end_row else len(line)
+                marks.append((start, max(end, start + 1), d.severity))
+        parts: list[str] = []
+        pos = 0
+        for start, end, severity in sorted(marks):
+            if start < pos:
+                continue
+            parts.append(html.escape(line[pos:start]))
+            parts.append(f'<span style="text-decoration: underline wavy {css_color(severities.get(severity, theme.info))}">{html.escape(line[start:end]) or " "}</span>')
+            pos = end
+        parts.append(html.escape(line[pos:]))
+        number = f'<span style="color: {css_color(theme.faint)}; user-select: none">{row + 1:>{width}} </span>' if numbers is True else ""
+        highlight = buffer.highlights.get(row)
+        style = f' style="background: {css_color(highlight)}"' if highlight is not None else ""
+        rows.append(f'<span class="line"{style}>{number}{"".join(parts) or " "}</span>')
+    return (f'<!DOCTYPE html>\n<html>\n<head>\n<meta charset="utf-8">\n<title>{html.escape(buffer.name)}</title>\n'
+            f'<style>\nbody {{ background: {css_color(theme.background)}; color: {css_color(theme.foreground)}; }}\n'
+            f'pre {{ font-family: "Iosevka", ui-monospace, monospace; line-height: 1.3; }}\n.line {{ display: block; }}\n</style>\n'
+            f'</head>\n<body>\n<pre>' + "".join(rows) + '</pre>\n</body>\n</html>\n')
+
+def git_index_lines(filename: str) -> list[str] | None:
+    """The file as staged in git, None if it is not tracked or git is missing"""
+    try:
+        result = subprocess.run(["git", "-C", os.path.dirname(os.path.abspath(filename)), "show", f":./{os.path.basename(filename)}"],
+                                capture_output=True, timeout=10)
+    except (OSError, subprocess.TimeoutExpired):
+        return None
+    if result.returncode != 0:
+        return None
+    lines = decode_text(result.stdout)[0].splitlines()
+    return lines if len(lines) > 0 else [""]
+
+def git_branch(filename: str) -> str:
+    """The branch checked out where the file is, HEAD if detached, "" outside git or without it"""
+    try:
+        result = subprocess.run(["git", "-C", os.path.dirname(os.path.abspath(filename)), "rev-parse", "--abbrev-ref", "HEAD"],
+                                capture_output=True, timeout=10)
+    except (OSError, subprocess.TimeoutExpired):
+        return ""
+    if result.returncode != 0:
+        return ""
+    return result.stdout.decode('utf-8', errors='replace').strip()
+
+def read_ignore_patterns(directory: str) -> list[str]:
+    path = os.path.join(directory, ".gitignore")
+    if os.path.exists(path) is False:
+        return []
+    try:
+        with open(path, 'r') as file:
+            lines = file.read().splitlines()
+    except OSError:
+        return []
+    return [line.strip() for line in lines if line.strip() != "" and line.strip().startswith('#') is False]
+
+def is_ignored(rel_path: str, is_dir: bool, rules: list[tuple[str, list[str]]]) -> bool:
+    """gitignore-style matching; rules are (base directory relative to root, patterns), last match wins"""
+    ignored = False
+    for base, patterns in rules:
+        if base != "" and not rel_path.startswith(base + "/"):
+            continue
+        local = rel_path[len(base) + 1:] if base != "" else rel_path
+        name = os.path.basename(local)
+        for pattern in patterns:
+            negate = pattern.startswith('!')
+            if negate is True:
+                pattern = pattern[1:]
+            if pattern.endswith('/'):
+                if is_dir is False:
+                    continue
+                pattern = pattern[:-1]
+            if '/' in pattern:
+                matched = fnmatch.fnmatchcase(local, pattern.lstrip('/'))
+            else:
+                matched = fnmatch.fnmatchcase(name, pattern)
+            if matched is True:
+                ignored = not negate
+    return ignored
+
+def project_files(root: str, exclude: list[str] = []) -> list[str]:
+    """Files below root, relative to it, without those .gitignore files or the exclude patterns leave out"""
+    files: list[str] = []
+    rules: list[tuple[str, list[str]]] = [("", ['.git/'] + exclude)]
+    for directory, dirs, names in os.walk(root):
+        rel_dir = os.path.relpath(directory, root)
+        rel_dir = "" if rel_dir == "." else rel_dir
+        patterns = read_ignore_patterns(directory)
+        if len(patterns) > 0:
+            rules.append((rel_dir, patterns))
+        dirs[:] = sorted(d for d in dirs if is_ignored(os.path.join(rel_dir, d), True, rules) is False)
+        for name in sorted(names):
+            rel_path = os.path.join(rel_dir, name)
+            if is_ignored(rel_path, False, rules) is False:
+                files.append(rel_path)
+    return files
+
+def grep_file(path: str, pattern: str, ignore_case: bool) -> list[tuple[int, str]]:
+    hits: list[tuple[int, str]] = []
+    try:
+        with open(path, 'rb') as file:
+            data = file.read()
+    except OSError:
+        return hits
+    if b'\0' in data[:1024]:
+        return hits  # binary
+    text = data.decode('utf-8', errors='replace')
+    needle = pattern.lower() if ignore_case else pattern
+    for line_no, line in enumerate(text.splitlines()):
+        if needle in (line.lower() if ignore_case else line):
+            hits.append((line_no, line))
+    return hits
+
+def grep_project(root: str, pattern: str, max_results: int = 10000, exclude: list[str] = []) -> list[str]:
+    """Literal search of all non-ignored files below root, smart-case, as 'path:line:text'"""
+    ignore_case = pattern == pattern.lower()
+    files = project_files(root, exclude)
+    results: list[str] = []
+    with concurrent.futures.ThreadPoolExecutor(max_workers=8) as pool:
+        for rel_path, hits in zip(files, pool.map(lambda f: grep_file(os.path.join(root, f), pattern, ignore_case), files)):
+            for line_no, line in hits:
+                results.append(f"{rel_path}:{line_no+1}:{line}")
+                if len(results) >= max_results:
+                    return results
+    return results
+
+grep_line_re = re.compile(r'^(.+?):(\d+):(?:(\d+):)?')
+
+class RecentFiles:
+    """Most recently opened files first, persisted as JSON in the state directory"""
+    def __init__(self, max_files: int = 50, path: str | None = None):
+        self.log: logging.Logger = logging.getLogger("RecentFiles")
+        self.max_files: int = max_files
+        self.path: str = path if path is not None else state_path("recent_files.json")
+        self.files: list[str] = []
+        self.load()
+
+    def load(self):
+        if os.path.exists(self.path) is False:
+            return
+        try:
+            with open(self.path, 'r') as file:
+                data = json.load(file)
+        except (OSError, json.JSONDecodeError) as e:
+            self.log.error(f"Cannot read {self.path}: {e}")
+            return
+        if isinstance(data, list):
+            self.files = [f for f in cast(list[object], data) if isinstance(f, str)][:self.max_files]
+
+    def save(self):
+        try:
+            os.makedirs(os.path.dirname(self.path), exist_ok=True)
+            with open(self.path, 'w') as file:
+                json.dump(self.files, file, indent=1)
+        except OSError as e:
+            self.log.error(f"Cannot write {self.path}: {e}")
+
+    def add(self, filename: str):
+        path = filename if parse_remote(filename) is not None else os.path.abspath(filename)
+        if path in self.files:
+            self.files.remove(path)
+        self.files.insert(0, path)
+        del self.files[self.max_files:]
+        self.save()
+
+    def existing(self) -> list[str]:
+        return [f for f in self.files if os.path.exists(f) or parse_remote(f) is not None]
+
+@dataclass
+class Bookmark:
+    filename: str
+    row: int
+    col: int
+    text: str  # the line it was set on, found again when lines were added or removed above it
+
+    def locate(self, lines: list[str]) -> int:
+        """The row of the bookmarked line, the nearest one with its text if it moved"""
+        if self.row < len(lines) and lines[self.row] == self.text:
+            return self.row
+        rows = [i for i, line in enumerate(lines) if line == self.text] if self.text.strip() != "" else []
+        if len(rows) == 0:
+            return min(self.row, max(0, len(lines) - 1))
+        return min(rows, key=lambda i: abs(i - self.row))
+
+class Bookmarks:
+    """Named positions in files, persisted as JSON in the state directory"""
+    def __init__(self, path: str | None = None):
+        self.log: logging.Logger = logging.getLogger("Bookmarks")
+        self.path: str = path if path is not None else state_path("bookmarks.json")
+        self.marks: dict[str, Bookmark] = {}
+        self.load()
+
+    def load(self):
+        if os.path.exists(self.path) is False:
+            return
+        try:
+            with open(self.path, 'r') as file:
+                data = json.load(file)
+        except (OSError, json.JSONDecodeError) as e:
+            self.log.error(f"Cannot read {self.path}: {e}")
+            return
+        if isinstance(data, dict) is False:
+            return
+        for name, mark in cast(dict[str, object], data).items():
+            try:
+                fields = cast(dict[str, object], mark)
+                self.marks[name] = Bookmark(str(fields['filename']), int(cast(int, fields['row'])), int(cast(int, fields['col'])), str(fields.get('text', "")))
+            except (KeyError, TypeError, ValueError, AttributeError):
+                self.log.warning(f"Ignoring invalid bookmark {name} in {self.path}")
+
+    def save(self):
+        try:
+            os.makedirs(os.path.dirname(self.path), exist_ok=True)
+            with open(self.path, 'w') as file:
+                json.dump({name: asdict(mark) for name, mark in self.marks.items()}, file, indent=1)
+        except OSError as e:
+            self.log.error(f"Cannot write {self.path}: {e}")
+
+    def set(self, name: str, mark: Bookmark):
+        self.marks[name] = mark
+        self.save()
+
+    def delete(self, name: str) -> bool:
+        if name not in self.marks:
+            return False
+        del self.marks[name]
+        self.save()
+        return True
+
+    def rename(self, name: str, new_name: str) -> bool:
+        if name not in self.marks or new_name in self.marks:
+            return False
+        self.marks[new_name] = self.marks.pop(name)
+        self.save()
+        return True
+
+    def names(self) -> list[str]:
+        return sorted(self.marks)
+
+    def describe(self, name: str) -> str:
+        mark = self.marks[name]
+        return f"{abbreviate_home(mark.filename)}:{mark.row + 1}"
+
+    def lines(self) -> list[str]:
+        """The *Bookmarks* listing, a header and then one line per bookmark in the order of names()"""
+        width = max((len(name) for name in self.marks), default=0)
+        return ["Return: jump, d: delete, R: rename, g: refresh"] + [f"{name.ljust(width)}  {self.describe(name)}  {self.marks[name].text.strip()}".rstrip() for name in self.names()]
+
+class UndoStore:
+    """Undo trees of files in the cache directory, one JSON file per path, valid while the file content matches"""
+    def __init__(self, directory: str | None = None):
+        self.log: logging.Logger = logging.getLogger("UndoStore")
+        self.directory: str = directory if directory is not None else cache_path("undo")
+
+    def path(self, filename: str) -> str:
+        return os.path.join(self.directory, hashlib.sha256(os.path.abspath(filename).encode('utf-8')).hexdigest() + ".json")
+
+    def save(self, buffer: Buffer):
+        if buffer.filename is None or buffer.undo is None or len(buffer.undo.nodes) == 1:
+            return
+        data = {'path': buffer.filename, 'content_hash': content_hash(buffer), 'tree': buffer.undo.to_dict()}
+        try:
+            os.makedirs(self.directory, exist_ok=True)
+            with open(self.path(buffer.filename), 'w') as file:
+                json.dump(data, file)
+        except OSError as e:
+            self.log.error(f"Cannot write undo history of {buffer.filename}: {e}")
+
+    def restore(self, buffer: Buffer) -> bool:
+        """Replace a fresh undo tree by the stored one if it was saved for the same content"""
+        if buffer.filename is None or buffer.undo is None or len(buffer.undo.nodes) > 1:
+            return False
+        path = self.path(buffer.filename)
+        if os.path.exists(path) is False:
+            return False
+        try:
+            with open(path, 'r') as file:
+                data = cast(dict[str, object], json.load(file))
+            if data.get('path') != buffer.filename or data.get('content_hash') != content_hash(buffer):
+                return False
+            buffer.undo = UndoTree.from_dict(cast(dict[str, object], data['tree']), buffer)
+        except (OSError, json.JSONDecodeError, KeyError, TypeError) as e:
+            self.log.error(f"Cannot read undo history of {buffer.filename}: {e}")
+            return False
+        return True
+
+def process_alive(pid: int) -> bool:
+    try:
+        os.kill(pid, 0)
+    except ProcessLookupError:
+        return False
+    except OSError:
+        return True  # exists, owned by someone else
+    return True
+
+class SwapStore:
+    """Unsaved contents of file buffers in the state directory, rewritten while they change and removed once saved,
+    so that edits survive a crash; one JSON file per path, like UndoStore"""
+    def __init__(self, directory: str | None = None):
+        self.log: logging.Logger = logging.getLogger("SwapStore")
+        self.directory: str = directory if directory is not None else state_path("swap")
+
+    def path(self, filename: str) -> str:
+        return os.path.join(self.directory, hashlib.sha256(os.path.abspath(filename).encode('utf-8')).hexdigest() + ".json")
+
+    def write(self, buffer: Buffer):
+        if buffer.filename is None:
+            return
+        data = {'path': buffer.filename, 'pid': os.getpid(), 'time': time.time(), 'disk_hash': buffer.disk_hash, 'lines': list(buffer)}
+        path = self.path(buffer.filename)
+        try:
+            os.makedirs(self.directory, exist_ok=True)
+            with open(path + ".tmp", 'w') as file:
+                json.dump(data, file)
+            os.replace(path + ".tmp", path)  # a crash while writing keeps the previous swap file
+        except OSError as e:
+            self.log.error(f"Cannot write swap file of {buffer.filename}: {e}")
+
+    def remove(self, filename: str):
+        try:
+            os.remove(self.path(filename))
+        except FileNotFoundError:
+            pass
+        except OSError as e:
+            self.log.error(f"Cannot remove swap file of {filename}: {e}")
+
+    def read(self, path: str) -> dict[str, object] | None:
+        """Contents of a swap file if a led that is no longer running left it"""
+        try:
+            with open(path, 'r') as file:
+                data = cast(dict[str, object], json.load(file))
+        except FileNotFoundError:
+            return None
+        except (OSError, json.JSONDecodeError) as e:
+            self.log.error(f"Cannot read swap file {path}: {e}")
+            return None
+        pid = data.get('pid')
+        if not isinstance(pid, int) or pid == os.getpid() or process_alive(pid) is True:
+            return None
+        return data
+
+    def leftover(self, filename: str) -> dict[str, object] | None:
+        data = self.read(self.path(filename))
+        return data if data is not None and data.get('path') == filename else None
+
+    def leftovers(self) -> list[str]:
+        """Files whose unsaved changes a crashed led left behind"""
+        try:
+            names = sorted(os.listdir(self.directory))
+        except OSError:
+            return []
+        paths: list[str] = []
+        for name in names:
+            if name.endswith(".json"):
+                data = self.read(os.path.join(self.directory, name))
+                if data is not None and isinstance(data.get('path'), str):
+                    paths.append(cast(str, data['path']))
+        return paths
+
+def file_stamp(path: str) -> tuple[float, int] | None:
+    """(mtime, size), None if the file doesn't exist"""
+    try:
+        st = os.stat(path)
+    except OSError:
+        return None
+    return (st.st_mtime, st.st_size)
+
+class FileWatcher:
+    """Polls the stamps of watched files in a thread and calls on_change(path) from there when one differs"""
+    def __init__(self, on_change: Callable[[str], None], interval_ms: int = 1000):
+        self.log: logging.Logger = logging.getLogger("FileWatcher")
+        self.on_change: Callable[[str], None] = on_change
+        self.interval: float = interval_ms / 1000.0
+        self.stamps: dict[str, tuple[float, int] | None] = {}
+        self.lock: threading.Lock = threading.Lock()
+        self.stopped: threading.Event = threading.Event()
+        self.thread: threading.Thread | None = None
+
+    def watch(self, path: str):
+        """Start watching path, or accept its current state as known (e.g. after writing it)"""
+        with self.lock:
+            self.stamps[path] = file_stamp(path)
+        if self.thread is None:
+            self.thread = threading.Thread(target=self.poll, daemon=True)
+            self.thread.start()
+
+    def unwatch(self, path: str):
+        with self.lock:
+            _ = self.stamps.pop(path, None)
+
+    def poll(self):
+        while self.stopped.wait(self.interval) is False:
+            with self.lock:
+                watched = list(self.stamps.items())
+            for path, stamp in watched:
+                current = file_stamp(path)
+                if current == stamp:
+                    continue
+                with self.lock:
+                    if path not in self.stamps:
+                        continue
+                    self.stamps[path] = current
+                self.on_change(path)
+
+    def stop(self):
+        self.stopped.set()
+
+@dataclass
+class Snippet:
+    trigger: str
+    body: str  # $1, ${1:default}; $0 is the final cursor position, repeated numbers are mirrors
+    description: str = ""
+
+snippet_field_re = re.compile(r'\$(\d+)|\$\{(\d+)(?::([^}]*))?\}')
+
+def parse_snippet(body: str) -> tuple[list[str], dict[int, list[tuple[int, int, int]]]]:
+    """Lines of the expanded body and the (line, start, end) spans of each tab stop, first occurrence first"""
+    lines = [""]
+    fields: dict[int, list[tuple[int, int, int]]] = {}
+    defaults: dict[int, str] = {}
+    pos = 0
+
+    def add_text(text: str):
+        parts = text.split("\n")
+        lines[-1] += parts[0]
+        lines.extend(parts[1:])
+
+    for match in snippet_field_re.finditer(body):
+        add_text(body[pos:match.start()])
+        pos = match.end()
+        num = int(match.group(1) if match.group(1) is not None else match.group(2))
+        default = match.group(3) if match.group(3) is not None else defaults.get(num, "")
+        _ = defaults.setdefault(num, default)
+        start = len(lines[-1])
+        lines[-1] += default
+        fields.setdefault(num, []).append((len(lines) - 1, start, start + len(default)))
+    add_text(body[pos:])
+    return lines, fields
+
+class SnippetLibrary:
+    """Snippets from snippets/<filetype>.toml in the config directory, snippets/all.toml for every file type"""
+    def __init__(self, directory: str | None = None):
+        self.log: logging.Logger = logging.getLogger("SnippetLibrary")
+        self.directory: str = directory if directory is not None else os.path.join(os.path.dirname(config_path()), "snippets")
+        self.loaded: dict[str, dict[str, Snippet]] = {}
+
+    def load(self, filetype: str) -> dict[str, Snippet]:
+        if filetype in self.loaded:
+            return self.loaded[filetype]
+        snippets: dict[str, Snippet] = {}
+        path = os.path.join(self.directory, f"{filetype}.toml")
+        if os.path.exists(path):
+            try:
+                with open(path, 'rb') as file:
+                    data = tomllib.load(file)
+            except (OSError, tomllib.TOMLDecodeError) as e:
+                self.log.error(f"Cannot read snippets {path}: {e}")
+                data = {}
+            for trigger, entry in data.items():
+                if isinstance(entry, str):
+                    snippets[trigger] = Snippet(trigger, entry)
+                elif isinstance(entry, dict) and isinstance(entry.get('body'), str):
+                    table = cast(dict[str, str], entry)
+                    snippets[trigger] = Snippet(trigger, table['body'], str(table.get('description', "")))
+                else:
+                    self.log.warning(f"Ignoring snippet {trigger} in {path}")
+        self.loaded[filetype] = snippets
+        return snippets
+
+    def snippets(self, filetype: str) -> dict[str, Snippet]:
+        """Snippets of the file type, overriding those for all types"""
+        result = dict(self.load("all"))
+        if filetype != "":
+            result.update(self.load(filetype))
+        return result
+
+prose_filetypes = ['', 'md', 'txt', 'rst', 'org']
+spell_word_re = re.compile(r"[^\W\d_]+(?:'[^\W\d_]+)?")
+string_literal_re = re.compile(r'"(?:[^"\\]|\\.)*"|\'(?:[^\'\\]|\\.)*\'')
+
+def spell_regions(line: str, settings: FileTypeSettings, filetype: str) -> list[tuple[int, int]]:
+    """Parts of a line worth spell checking: all of it for prose, else comments and string literals"""
+    if filetype in prose_filetypes:
+        return [(0, len(line))]
+    regions: list[tuple[int, int]] = []
+    code_end = len(line)
+    if settings.comment != "":
+        start = line.find(settings.comment)
+        if start >= 0:
+            end = line.find(settings.comment_end, start) if settings.comment_end != "" else -1
+            regions.append((start + len(settings.comment), end if end >= 0 else len(line)))
+            code_end = start
+    regions += [(m.start() + 1, m.end() - 1) for m in string_literal_re.finditer(line, 0, code_end)]
+    return regions
+
+class SpellChecker:
+    """Word list plus a personal dictionary; suggestions are the known words one or two edits away"""
+    def __init__(self, word_list: str, personal: str | None = None):
+        self.log: logging.Logger = logging.getLogger("SpellChecker")
+        self.personal_path: str = personal if personal is not None else os.path.join(os.path.dirname(config_path()), "dictionary.txt")
+        self.words: set[str] = set()
+        self.cache: dict[str, list[tuple[int, int]]] = {}
+        self.has_word_list: bool = self.read(word_list)
+        _ = self.read(self.personal_path)
+
+    def read(self, path: str) -> bool:
+        if os.path.exists(path) is False:
+            return False
+        try:
+            with open(path, 'r', errors='replace') as file:
+                self.words.update(w.strip().lower() for w in file if w.strip() != "")
+        except OSError as e:
+            self.log.error(f"Cannot read {path}: {e}")
+            return False
+        return True
+
+    def known(self, word: str) -> bool:
+        if len(word) < 2 or word.isupper() or any(c.isupper() for c in word[1:]):
+            return True  # acronyms and camelCase identifiers aren't prose
+        return word.lower() in self.words
+
+    def misspelled(self, line: str, settings: FileTypeSettings, filetype: str) -> list[tuple[int, int]]:
+        key = f"{filetype}\0{line}"
+        spans = self.cache.get(key)
+        if spans is None:
+            spans = []
+            for start, end in spell_regions(line, settings, filetype):
+                spans += [(m.start(), m.end()) for m in spell_word_re.finditer(line, start, end) if self.known(m.group(0)) is False]
+            if len(self.cache) > 10000:
+                self.cache.clear()
+            self.cache[key] = spans
+        return spans
+
+    def add(self, word: str):
+        self.words.add(word.lower())
+        self.cache.clear()
+        try:
+            os.makedirs(os.path.dirname(self.personal_path), exist_ok=True)
+            with open(self.personal_path, 'a') as file:
+                _ = file.write(word + "\n")
+        except OSError as e:
+            self.log.error(f"Cannot write {self.personal_path}: {e}")
+
+    def edits(self, word: str) -> set[str]:
+        letters = string.ascii_lowercase
+        splits = [(word[:i], word[i:]) for i in range(len(word) + 1)]
+        result = {a + b[1:] for a, b in splits if b}
+        result |= {a + b[1] + b[0] + b[2:] for a, b in splits if len(b) > 1}
+        result |= {a + c + b[1:] for a, b in splits if b for c in letters}
+        result |= {a + c + b for a, b in splits for c in letters}
+        return result
+
+    def suggestions(self, word: str, count: int = 9) -> list[str]:
+        lower = word.lower()
+        near = self.edits(lower)
+        found = sorted(w for w in near if w in self.words)
+        if len(found) == 0:
+            found = sorted({w for e in near for w in self.edits(e) if w in self.words})
+        found.sort(key=lambda w: (w[:1] != lower[:1], abs(len(w) - len(lower))))
+        if word[:1].isupper():
+            found = [w[:1].upper() + w[1:] for w in found]
+        return found[:count]
+
+class Abbrevs:
+    """Word -> expansion per file type ('all' for every type), kept as JSON in the config directory"""
+    def __init__(self, path: str | None = None):
+        self.log: logging.Logger = logging.getLogger("Abbrevs")
+        self.path: str = path if path is not None else os.path.join(os.path.dirname(config_path()), "abbrevs.json")
+        self.tables: dict[str, dict[str, str]] = {}
+        self.load()
+
+    def load(self):
+        if os.path.exists(self.path) is False:
+            return
+        try:
+            with open(self.path, 'r') as file:
+                data = json.load(file)
+        except (OSError, json.JSONDecodeError) as e:
+            self.log.error(f"Cannot read {self.path}: {e}")
+            return
+        if isinstance(data, dict):
+            for filetype, table in cast(dict[str, object], data).items():
+                if isinstance(table, dict):
+                    self.tables[filetype] = {k: v for k, v in cast(dict[str, object], table).items() if isinstance(v, str)}
+
+    def save(self):
+        try:
+            os.makedirs(os.path.dirname(self.path), exist_ok=True)
+            with open(self.path, 'w') as file:
+                json.dump(self.tables, file, indent=1)
+        except OSError as e:
+            self.log.error(f"Cannot write {self.path}: {e}")
+
+    def define(self, filetype: str, word: str, expansion: str):
+        self.tables.setdefault(filetype, {})[word] = expansion
+        self.save()
+
+    def lookup(self, filetype: str, word: str) -> str | None:
+        """Expansion of word, file type first; a capitalized word capitalizes its expansion"""
+        for table in (self.tables.get(filetype, {}), self.tables.get('all', {})):
+            if word in table:
+                return table[word]
+            expansion = table.get(word.lower())
+            if expansion is not None and word[:1].isupper() and word[1:].islower():
+                return expansion[:1].upper() + expansion[1:]
+        return None
+
+@dataclass
+class SnippetSession:
+    """An expanded snippet whose tab stops are being filled in"""
+    buffer: Buffer
+    order: list[int]  # tab stop numbers in visiting order, 0 last
+    spans: dict[int, list[list[int]]]  # [row, start, end] per occurrence, the first is edited, the others mirror it
+    index: int = 0
+    pending: bool = False  # the next typed character replaces the placeholder
+
+def path_uri(path: str) -> str:
+    return pathlib.Path(os.path.abspath(path)).as_uri()
+
+def uri_path(uri: str) -> str:
+    return urllib.parse.unquote(urllib.parse.urlparse(uri).path)
+
+def utf16_col(line: str, col: int) -> int:
+    """LSP columns count UTF-16 code units"""
+    return len(line[:col].encode('utf-16-le')) // 2
+
+def col_from_utf16(line: str, units: int) -> int:
+    n = 0
+    for i, c in enumerate(line):
+        if n >= units:
+            return i
+        n += 2 if ord(c) > 0xFFFF else 1
+    return len(line)
+
+lsp_language_ids = {'py': 'python', 'rs': 'rust', 'c': 'c', 'h': 'c', 'go': 'go', 'js': 'javascript', 'ts': 'typescript'}
+
+severity_names = {1: "error", 2: "warning", 3: "info", 4: "hint"}
+
+@dataclass
+class Diagnostic:
+    row: int
+    col: int
+    end_row: int
+    end_col: int
+    severity: int  # 1 error, 2 warning, 3 information, 4 hint
+    message: str
+    source: str = ""
+    tags: tuple[int, ...] = ()  # 1 unnecessary, 2 deprecated
+
+class ReplProcess:
+    """An interpreter reading stdin; its stdout and stderr are handed to post() in chunks as they arrive"""
+    def __init__(self, command: list[str], directory: str, post: Callable[[Callable[[], None]], None]):
+        self.log: logging.Logger = logging.getLogger("ReplProcess")
+        self.command: list[str] = command
+        self.directory: str = directory
+        self.post: Callable[[Callable[[], None]], None] = post
+        self.process: subprocess.Popen[bytes] | None = None
+        self.on_output: Callable[[str], None] | None = None
+        self.on_exit: Callable[[], None] | None = None
+
+    def start(self) -> bool:
+        try:
+            self.process = subprocess.Popen(self.command, cwd=self.directory, stdin=subprocess.PIPE, stdout=subprocess.PIPE, stderr=subprocess.STDOUT)
+        except OSError as e:
+            self.log.error(f"Cannot start {self.command}: {e}")
+            return False
+        threading.Thread(target=self.read_loop, daemon=True).start()
+        return True
+
+    def send(self, text: str):
+        if self.process is None or self.process.stdin is None:
+            return
+        try:
+            _ = self.process.stdin.write(text.encode('utf-8'))
+            self.process.stdin.flush()
+        except OSError as e:
+            self.log.warning(f"{self.command[0]}: {e}")
+
+    def read_loop(self):
+        process = self.process
+        if process is None or process.stdout is None:
+            return
+        decoder = codecs.getincrementaldecoder('utf-8')(errors='replace')
+        while True:
+            data = os.read(process.stdout.fileno(), 4096)
+            if data == b"":
+                break
+            text = decoder.decode(data).replace("\r\n", "\n").replace("\r", "")
+            if self.on_output is not None and text != "":
+                on_output = self.on_output
+                self.post(lambda text=text: on_output(text))
+        if self.on_exit is not None:
+            self.post(self.on_exit)
+
+    def stop(self):
+        if self.process is None:
+            return
+        self.process.terminate()
+        try:
+            _ = self.process.wait(timeout=1)
+        except subprocess.TimeoutExpired:
+            self.process.kill()
+        self.process = None
+
+@dataclass
+class ReplSession:
+    process: ReplProcess
+    buffer: Buffer
+    input_start: tuple[int, int]  # text from here to the end is the input not yet sent
+
+CharId = tuple[int, int]  # Lamport counter and site, ordered by the counter first
+
+def char_id(value: object) -> CharId | None:
+    """CharId from its JSON form, None for null or anything else"""
+    if isinstance(value, list) and len(cast(list[object], value)) == 2:
+        counter, site = cast(list[object], value)
+        if isinstance(counter, int) and isinstance(site, int):
+            return (counter, site)
+    return None
+
+def text_offset(lines: list[str], row: int, col: int) -> int:
+    return sum(len(line) + 1 for line in lines[:row]) + col
+
+def offset_position(text: str, offset: int) -> tuple[int, int]:
+    row = text.count("\n", 0, offset)
+    return row, offset - (text.rfind("\n", 0, offset) + 1)
+
+@dataclass
+class CrdtChar:
+    id: CharId
+    origin: CharId | None  # the character it was typed after, None at the start
+    char: str
+    deleted: bool = False
+
+class Rga:
+    """Replicated growable array of characters: an insert names the character it follows, concurrent inserts after
+    the same character are ordered by descending id and deletes leave tombstones, so sites that applied the same
+    operations in any causal order hold the same text"""
+    def __init__(self, site: int, text: str = ""):
+        self.site: int = site
+        self.clock: int = 0
+        self.chars: list[CrdtChar] = []
+        self.index: dict[CharId, int] = {}  # position in chars, rebuilt when inserts make it stale
+        self.index_valid: bool = True
+        self.last: tuple[CharId, int] | None = None  # last insert, typed runs continue after it without a rebuild
+        origin = None
+        for c in text:
+            self.clock += 1
+            char = CrdtChar((self.clock, site), origin, c)
+            self.index[char.id] = len(self.chars)
+            self.chars.append(char)
+            origin = char.id
+
+    def to_list(self) -> list[list[object]]:
+        return [[c.id[0], c.id[1], *(c.origin if c.origin is not None else (0, 0)), c.char, c.deleted] for c in self.chars]
+
+    @classmethod
+    def from_list(cls, site: int, data: list[list[object]]) -> 'Rga':
+        rga = cls(site)
+        for counter, char_site, o_counter, o_site, c, deleted in data:
+            origin = (cast(int, o_counter), cast(int, o_site))
+            rga.chars.append(CrdtChar((cast(int, counter), cast(int, char_site)), origin if origin != (0, 0) else None, cast(str, c), deleted is True))
+            rga.clock = max(rga.clock, cast(int, counter))
+        rga.index_valid = False
+        return rga
+
+    def position(self, char: CharId) -> int | None:
+        if self.last is not None and self.last[0] == char:
+            return self.last[1]
+        if self.index_valid is False:
+            self.index = {c.id: i for i, c in enumerate(self.chars)}
+            self.index_valid = True
+        return self.index.get(char)
+
+    def text(self) -> str:
+        return "".join(c.char for c in self.chars if c.deleted is False)
+
+    def id_before(self, offset: int) -> CharId | None:
+        """Id of the visible character before text offset, None at the start"""
+        visible = [c.id for c in self.chars if c.deleted is False]
+        if offset <= 0 or len(visible) == 0:
+            return None
+        return visible[min(offset, len(visible)) - 1]
+
+    def offset_after(self, char: CharId | None) -> int:
+        """Text offset right after the character, which may have been deleted since"""
+        i = self.position(char) if char is not None else None
+        if i is None:
+            return 0
+        return sum(1 for c in self.chars[:i + 1] if c.deleted is False)
+
+    def edit(self, old: str, new: str) -> list[list[object]]:
+        """Apply the change from old, the current text, to new; the operations are for the other sites"""
+        start = 0
+        limit = min(len(old), len(new))
+        while start < limit and old[start] == new[start]:
+            start += 1
+        end = 0
+        while end < limit - start and old[len(old) - 1 - end] == new[len(new) - 1 - end]:
+            end += 1
+        visible = [i for i, c in enumerate(self.chars) if c.deleted is False]
+        ops: list[list[object]] = []
+        for i in visible[start:len(old) - end]:
+            self.chars[i].deleted = True
+            ops.append(['d', *self.chars[i].id])
+        origin = self.chars[visible[start - 1]].id if start > 0 else None
+        pos = visible[start - 1] + 1 if start > 0 else 0
+        for k, c in enumerate(new[start:len(new) - end]):
+            self.clock += 1
+            char = CrdtChar((self.clock, self.site), origin, c)
+            self.chars.insert(pos + k, char)
+            ops.append(['i', self.clock, self.site, *(origin if origin is not None else (0, 0)), c])
+            origin = char.id
+            self.index_valid = False
+            self.last = None
+        return ops
+
+    def apply(self, op: list[object]) -> bool:
+        """Integrate an operation of another site; False if it refers to a character not seen yet"""
+        char = (cast(int, op[1]), cast(int, op[2]))
+        if op[0] == 'd':
+            i = self.position(char)
+            if i is None:
+                return False
+            self.chars[i].deleted = True
+            return True
+        self.clock = max(self.clock, char[0])
+        if self.position(char) is not None:
+            return True
+        origin = (cast(int, op[3]), cast(int, op[4]))
+        pos = 0
+        if origin != (0, 0):
+            i = self.position(origin)
+            if i is None:
+                return False
+            pos = i + 1
+        while pos < len(self.chars) and self.chars[pos].id > char:
+            pos += 1
+        self.chars.insert(pos, CrdtChar(char, origin if origin != (0, 0) else None, cast(str, op[5])))
+        self.index_valid = False
+        self.last = (char, pos)
+        return True
+
+class CollabConnection:
+    """Socket to one participant carrying newline-delimited JSON messages; on_message and on_close run on the reader thread"""
+    def __init__(self, sock: socket.socket, on_message: Callable[['CollabConnection', dict[str, object]], None], on_close: Callable[['CollabConnection'], None]):
+        self.log: logging.Logger = logging.getLogger("CollabConnection")
+        self.sock: socket.socket = sock
+        self.on_message: Callable[['CollabConnection', dict[str, object]], None] = on_message
+        self.on_close: Callable[['CollabConnection'], None] = on_close
+        self.site: int = 0  # the participant's site, 0 until the host welcomed it
+        self.lock: threading.Lock = threading.Lock()
+
+    def start(self):
+        threading.Thread(target=self.read_loop, daemon=True).start()
+
+    def send(self, message: dict[str, object]):
+        data = (json.dumps(message) + "\n").encode('utf-8')
+        with self.lock:
+            try:
+                self.sock.sendall(data)
+            except OSError as e:
+                self.log.warning(f"Cannot send to site {self.site}: {e}")
+
+    def read_loop(self):
+        try:
+            with self.sock.makefile('rb') as file:
+                for line in file:
+                    try:
+                        message = json.loads(line)
+                    except json.JSONDecodeError as e:
+                        self.log.warning(f"Ignoring invalid message from site {self.site}: {e}")
+                        continue
+                    if isinstance(message, dict):
+                        self.on_message(self, cast(dict[str, object], message))
+        except OSError:
+            pass
+        self.on_close(self)
+
+    def close(self):
+        try:
+            self.sock.shutdown(socket.SHUT_RDWR)
+        except OSError:
+            pass
+        self.sock.close()
+
+@dataclass
+class CollabPeer:
+    name: str
+    color: tuple[int, int, int, int]
+    cursor: CharId | None = None  # character before the cursor, None at the start
+    anchor: CharId | None = None
+    selecting: bool = False
+    placed: bool = False  # False until the first cursor message
+
+class CollabSession:
+    """A buffer shared with other led instances; the host relays each message to the other participants"""
+    def __init__(self, buffer: Buffer, rga: Rga, token: str, server: socket.socket | None = None):
+        self.buffer: Buffer = buffer
+        self.rga: Rga = rga  # site 0 while a joining instance waits for the host's welcome
+        self.token: str = token
+        self.server: socket.socket | None = server  # listening socket of the host
+        self.connections: list[CollabConnection] = []  # the participants for the host, the host for the others
+        self.peers: dict[int, CollabPeer] = {}  # by site
+        self.next_site: int = 2
+        self.text: str = rga.text()  # buffer text at the last sync
+        self.version: int = buffer.undo.version if buffer.undo is not None else 0
+        self.cursor_at: tuple[int, int, int, tuple[int, int] | None] | None = None  # version, row, col and anchor last sent
+
+    def broadcast(self, message: dict[str, object], exclude: CollabConnection | None = None):
+        for connection in self.connections:
+            if connection is not exclude:
+                connection.send(message)
+
+symbol_kinds = {2: "module", 5: "class", 6: "method", 7: "property", 8: "field", 9: "constructor", 10: "enum",
+                11: "interface", 12: "function", 13: "variable", 14: "constant", 23: "struct", 26: "type"}
+
+@dataclass
+class OutlineSymbol:
+    name: str
+    kind: str
+    row: int
+    col: int  # utf-16 units when from a language server
+    children: list['OutlineSymbol'] = field(default_factory=list)
+    collapsed: bool = False
+
+def lsp_outline(result: object) -> list[OutlineSymbol]:
+    """Tree of a documentSymbol result, DocumentSymbol[] nests by itself, SymbolInformation[] by containerName"""
+    items = cast(list[dict[str, object]], result if isinstance(result, list) else [])
+    roots: list[OutlineSymbol] = []
+    by_name: dict[str, OutlineSymbol] = {}
+    for item in items:
+        kind = symbol_kinds.get(cast(int, item.get('kind', 0)), "")
+        if 'location' in item:
+            start = cast(dict[str, dict[str, dict[str, int]]], item['location'])['range']['start']
+            symbol = OutlineSymbol(cast(str, item['name']), kind, start['line'], start['character'])
+            parent = by_name.get(cast(str, item.get('containerName', "")))
+            (parent.children if parent is not None else roots).append(symbol)
+            by_name[symbol.name] = symbol
+        else:
+            start = cast(dict[str, dict[str, int]], item.get('selectionRange', item.get('range', {}))).get('start', {})
+            symbol = OutlineSymbol(cast(str, item['name']), kind, start.get('line', 0), start.get('character', 0), lsp_outline(item.get('children', [])))
+            roots.append(symbol)
+    return sorted(roots, key=lambda symbol: symbol.row)
+
+outline_re = re.compile(r'^(\s*)(?:pub\s+|async\s+|export\s+)*(def|class|fn|func|function|struct|enum|trait|impl|interface)\s+(\w+)')
+
+def regex_outline(lines: list[str]) -> list[OutlineSymbol]:
+    """Outline from definition keywords, nested by indentation, for buffers without a language server"""
+    roots: list[OutlineSymbol] = []
+    stack: list[tuple[int, OutlineSymbol]] = []
+    for row, line in enumerate(lines):
+        match = outline_re.match(line)
+        if match is None:
+            continue
+        indent = len(match.group(1).expandtabs())
+        symbol = OutlineSymbol(match.group(3), match.group(2), row, match.start(3))
+        while len(stack) > 0 and stack[-1][0] >= indent:
+            _ = stack.pop()
+        (stack[-1][1].children if len(stack) > 0 else roots).append(symbol)
+        stack.append((indent, symbol))
+    return roots
+
+def flatten_outline(symbols: list[OutlineSymbol], depth: int = 0) -> tuple[list[str], list[OutlineSymbol]]:
+    """Visible outline lines and the symbol shown on each"""
+    lines: list[str] = []
+    shown: list[OutlineSymbol] = []
+    for symbol in symbols:
+        marker = "  " if len(symbol.children) == 0 else "▸ " if symbol.collapsed is True else "▾ "
+        lines.append(f"{'  ' * depth}{marker}{symbol.name}  {symbol.kind}")
+        shown.append(symbol)
+        if symbol.collapsed is False:
+            sub_lines, sub_shown = flatten_outline(symbol.children, depth + 1)
+            lines += sub_lines
+            shown += sub_shown
+    return lines, shown
+
+class LspClient:
+    """One language server process spoken to with JSON-RPC over stdio; replies and notifications are handed to post() for the main loop"""
+    def __init__(self, command: list[str], root: str, post: Callable[[Callable[[], None]], None]):
+        self.log: logging.Logger = logging.getLogger("LspClient")
+        self.command: list[str] = command
+        self.root: str = root
+        self.post: Callable[[Callable[[], None]], None] = post
+        self.process: subprocess.Popen[bytes] | None = None
+        self.next_id: int = 1
+        self.pending: dict[int, Callable[[object], None]] = {}  # request id -> result handler, main loop only
+        self.lock: threading.Lock = threading.Lock()
+        self.initialized: bool = False
+        self.queued: list[dict[str, object]] = []  # sent once the server answered initialize
+        self.capabilities: dict[str, object] = {}
+        self.on_notification: Callable[[str, dict[str, object]], None] | None = None
+
+    def start(self) -> bool:
+        if shutil.which(self.command[0]) is None:
+            return False
+        try:
+            self.process = subprocess.Popen(self.command, cwd=self.root, stdin=subprocess.PIPE, stdout=subprocess.PIPE, stderr=subprocess.DEVNULL)
+        except OSError as e:
+            self.log.error(f"Cannot start {self.command}: {e}")
+            return False
+        threading.Thread(target=self.read_loop, daemon=True).start()
+        self.write({'jsonrpc': '2.0', 'id': 0, 'method': 'initialize', 'params': {
+            'processId': os.getpid(), 'rootUri': path_uri(self.root),
+            'capabilities': {'textDocument': {
+                'synchronization': {'didSave': True},
+                'completion': {'completionItem': {'snippetSupport': False}},
+                'hover': {'contentFormat': ['plaintext', 'markdown']},
+                'publishDiagnostics': {'tagSupport': {'valueSet': [1, 2]}},
+                'definition': {}, 'references': {}, 'documentSymbol': {'hierarchicalDocumentSymbolSupport': True}}}}})
+        self.pending[0] = self.on_initialized
+        return True
+
+    def on_initialized(self, result: object):
+        if isinstance(result, dict):
+            self.capabilities = cast(dict[str, object], cast(dict[str, object], result).get('capabilities', {}))
+        self.initialized = True
+        self.write({'jsonrpc': '2.0', 'method': 'initialized', 'params': {}})
+        for message in self.queued:
+            self.write(message)
+        self.queued = []
+
+    def write(self, message: dict[str, object]):
+        if self.process is None or self.process.stdin is None:
+            return
+        body = json.dumps(message).encode('utf-8')
+        try:
+            with self.lock:
+                _ = self.process.stdin.write(f"Content-Length: {len(body)}\r\n\r\n".encode('ascii') + body)
+                self.process.stdin.flush()
+        except OSError as e:
+            self.log.error(f"Language server {self.command[0]} is gone: {e}")
+            self.process = None
+
+    def send(self, message: dict[str, object]):
+        if self.initialized is True:
+            self.write(message)
+        else:
+            self.queued.append(message)
+
+    def request(self, method: str, params: dict[str, object], on_result: Callable[[object], None]):
+        request_id = self.next_id
+        self.next_id += 1
+        self.pending[request_id] = on_result
+        self.send({'jsonrpc': '2.0', 'id': request_id, 'method': method, 'params': params})
+
+    def notify(self, method: str, params: dict[str, object]):
+        self.send({'jsonrpc': '2.0', 'method': method, 'params': params})
+
+    def read_loop(self):
+        process = self.process
+        if process is None or process.stdout is None:
+            return
+        stream = process.stdout
+        while True:
+            length = -1
+            while True:
+                header = stream.readline()
+                if header == b"":
+                    return
+                header = header.strip()
+                if header == b"":
+                    break
+                name, _, value = header.partition(b":")
+                if name.lower() == b"content-length":
+                    try:
+                        length = int(value)
+                    except ValueError:
+                        self.log.error(f"Bad Content-Length from {self.command[0]}: {value!r}")
+            if length < 0:
+                continue
+            try:
+                message = cast(dict[str, object], json.loads(stream.read(length)))
+            except ValueError as e:  # JSONDecodeError, or UnicodeDecodeError for bytes that are not UTF-8
+                self.log.error(f"Bad message from {self.command[0]}: {e}")
+                continue
+            self.post(lambda message=message: self.handle(message))
+
+    def handle(self, message: dict[str, object]):
+        if 'id' in message and 'method' not in message:
+            handler = self.pending.pop(cast(int, message['id']), None)
+            if 'error' in message:
+                self.log.warning(f"{self.command[0]}: {message['error']}")
+            elif handler is not None:
+                handler(message.get('result'))
+        elif 'method' in message:
+            if 'id' in message:
+                # requests from the server (configuration, progress): answer empty
+                self.write({'jsonrpc': '2.0', 'id': message['id'], 'result': None})
+            elif self.on_notification is not None:
+                self.on_notification(cast(str, message['method']), cast(dict[str, object], message.get('params', {})))
+
+    def stop(self):
+        if self.process is None:
+            return
+        self.write({'jsonrpc': '2.0', 'id': self.next_id, 'method': 'shutdown'})
+        self.write({'jsonrpc': '2.0', 'method': 'exit'})
+        try:
+            _ = self.process.wait(timeout=1)
+        except subprocess.TimeoutExpired:
+            self.process.kill()
+        self.process = None
+
+def abbreviate_home(path: str) -> str:
+    home = os.path.expanduser("~")
+    if path.startswith(home + os.sep):
+        return "~" + path[len(home):]
+    return path
+
+log_format = "%(asctime)s %(levelname)-7s %(name)s [%(threadName)s] %(message)s"
+
+class LogRing(logging.Handler):
+    """The most recent log records, formatted, for the *log* buffer"""
+    def __init__(self, size: int = 2000):
+        super().__init__()
+        self.lines: collections.deque[str] = collections.deque(maxlen=size)
+        self.setFormatter(logging.Formatter(log_format, "%H:%M:%S"))
+
+    def emit(self, record: logging.LogRecord):
+        self.lines.extend(self.format(record).splitlines())
+
+def setup_logging(verbose: bool = False, path: str | None = None) -> LogRing:
+    """Log to the state directory's led.log (info, or debug if verbose), warnings also to stderr, everything to a LogRing"""
+    path = path if path is not None else state_path("led.log")
+    root = logging.getLogger()
+    root.setLevel(logging.DEBUG if verbose is True else logging.INFO)
+    stderr = logging.StreamHandler()
+    stderr.setLevel(logging.DEBUG if verbose is True else logging.WARNING)
+    stderr.setFormatter(logging.Formatter(log_format))
+    root.addHandler(stderr)
+    ring = LogRing()
+    root.addHandler(ring)
+    try:
+        os.makedirs(os.path.dirname(path), exist_ok=True)
+        file = logging.handlers.RotatingFileHandler(path, maxBytes=1 << 20, backupCount=1, encoding='utf-8')
+        file.setFormatter(logging.Formatter(log_format))
+        root.addHandler(file)
+    except OSError as e:
+        logging.getLogger("Log").warning(f"Cannot write {path}: {e}")
+    return ring
+
+class FrameStats:
+    """Frames per second, time per main loop stage and draw calls, summed up each second for the profiling overlay"""
+    def __init__(self):
+        self.start: float = time.monotonic()
+        self.frames: int = 0  # presented in the current second
+        self.stages: dict[str, float] = {}  # seconds spent in the current second
+        self.copies: int = 0  # glyph and texture copies
+        self.lines: int = 0  # canvas lines redrawn
+        self.summary: list[str] = ["measuring..."]
+
+    def add(self, stage: str, seconds: float):
+        self.stages[stage] = self.stages.get(stage, 0.0) + seconds
+
+    def deadline(self) -> float:
+        return self.start + 1.0
+
+    def update(self, now: float | None = None) -> bool:
+        """Replace the summary once a second has passed, True if it did"""
+        if now is None:
+            now = time.monotonic()
+        if now < self.deadline():
+            return False
+        elapsed = now - self.start
+        per_frame = max(self.frames, 1)
+        self.summary = [f"{self.frames / elapsed:5.1f} fps"] + [f"{stage:<8}{1000 * seconds / per_frame:6.2f} ms" for stage, seconds in self.stages.items()]
+        self.summary += [f"copies  {self.copies // per_frame:6d}", f"lines   {self.lines // per_frame:6d}"]
+        self.start, self.frames, self.stages, self.copies, self.lines = now, 0, {}, 0, 0
+        return True
+
+class WakeQueue[T](queue.Queue[T]):
+    """Queue filled by worker threads that also wakes the main loop from waiting for SDL events"""
+    def __init__(self):
+        super().__init__()
+        self.wake: Callable[[], None] = lambda: None
+
+    def put(self, item: T, block: bool = True, timeout: float | None = None):
+        super().put(item, block, timeout)
+        self.wake()
+
+@dataclass
+class Notification:
+    source: str
+    text: str
+    level: str  # info, warning or error
+    posted: float  # time.time() for display
+    until: float = 0.0  # time.monotonic() the toast disappears
+
+class Notifications:
+    """Messages from background tasks; post() may be called from any thread, the rest from the main loop"""
+    def __init__(self, timeout_ms: int = 6000, max_toasts: int = 5):
+        self.log: logging.Logger = logging.getLogger("Notifications")
+        self.timeout: float = timeout_ms / 1000.0
+        self.max_toasts: int = max_toasts
+        self.incoming: WakeQueue[Notification] = WakeQueue()
+        self.toasts: list[Notification] = []
+        self.history: list[Notification] = []
+
+    def post(self, source: str, text: str, level: str = "info"):
+        self.incoming.put(Notification(source, text, level, time.time()))
+
+    def update(self) -> bool:
+        """Take over posted notifications and retire expired toasts; True if the toasts changed"""
+        changed = False
+        while self.incoming.empty() is False:
+            notification = self.incoming.get_nowait()
+            notification.until = time.monotonic() + self.timeout
+            self.log.info(f"{notification.source}: {notification.text}")
+            self.toasts.append(notification)
+            changed = True
+        now = time.monotonic()
+        while len(self.toasts) > 0 and (self.toasts[0].until < now or len(self.toasts) > self.max_toasts):
+            self.history.append(self.toasts.pop(0))
+            changed = True
+        return changed
+
+    def dismiss(self):
+        self.history += self.toasts
+        self.toasts = []
+
+    def lines(self) -> list[str]:
+        return [f"{time.strftime('%H:%M:%S', time.localtime(n.posted))} {n.level:<7} {n.source}: {n.text}" for n in self.history + self.toasts]
+
+@dataclass
+class LayoutSnapshot:
+    layout: FrameLayout
+    views: dict[int, PadView]  # by leaf frame id
+
+def layout_tree(frames: Frames, id: int, leaf: Callable[[Frame], dict[str, object]]) -> dict[str, object]:
+    """The frames below id as nested tables of a layout file: splits with direction, ratio, first and second, leaves as leaf() describes them"""
+    fr = frames.get(id)
+    if fr is None:
+        return {}
+    if fr.c_lu != 0 and fr.c_rd != 0:
+        return {'split': 'vertical' if fr.direction == Direction.VERTICAL else 'horizontal', 'ratio': round(fr.ratio, 4),
+                'first': layout_tree(frames, fr.c_lu, leaf), 'second': layout_tree(frames, fr.c_rd, leaf)}
+    return leaf(fr)
+
+def layout_check(node: object, depth: int = 0) -> str:
+    """What is wrong with a layout file's tree, "" if nothing"""
+    if isinstance(node, dict) is False:
+        return "a frame must be a table"
+    table = cast(dict[str, object], node)
+    if 'split' not in table:
+        for key in ('file', 'buffer'):
+            if key in table and isinstance(table[key], str) is False:
+                return f"{key} must be a string"
+        row = table.get('row', 0)
+        if isinstance(row, int) is False or isinstance(row, bool) or cast(int, row) < 0:
+            return f"row must be a line number, not {row}"
+        return ""
+    if table['split'] not in ('horizontal', 'vertical'):
+        return f"split must be horizontal or vertical, not {table['split']}"
+    ratio = table.get('ratio', 0.5)
+    if isinstance(ratio, (int, float)) is False or isinstance(ratio, bool) or not 0 < cast(float, ratio) < 1:
+        return f"ratio must be between 0 and 1, not {ratio}"
+    if depth >= 16:
+        return "splits are nested too deep"
+    for key in ('first', 'second'):
+        if key not in table:
+            return f"a split needs a {key} frame"
+        error = layout_check(table[key], depth + 1)
+        if error != "":
+            return error
+    return ""
+
+def load_layout(path: str) -> tuple[dict[str, object], str]:
+    """The root frame of a layout file, JSON or else TOML for .toml; or the error"""
+    try:
+        with open(path, 'rb') as file:
+            data = cast(object, tomllib.load(file) if path.endswith(".toml") else json.load(file))
+    except (OSError, ValueError) as e:  # JSONDecodeError and TOMLDecodeError are ValueErrors
+        return {}, str(e)
+    root = cast(dict[str, object], data).get('root') if isinstance(data, dict) else None
+    error = layout_check(root) if root is not None else "there is no root frame"
+    return (cast(dict[str, object], root), "") if error == "" else ({}, error)
+
+def layout_text(root: dict[str, object], path: str) -> str:
+    """A layout file load_layout() reads back: JSON, or TOML for .toml"""
+    if path.endswith(".toml") is False:
+        return json.dumps({'root': root}, indent=2) + "\n"
+
+    def table(name: str, node: dict[str, object]) -> list[str]:
+        lines = [f"[{name}]"]
+        for key, value in node.items():
+            if isinstance(value, bool):
+                lines.append(f"{key} = {'true' if value is True else 'false'}")
+            elif isinstance(value, (int, float)):
+                lines.append(f"{key} = {value!r}")
+            elif isinstance(value, str):
+                lines.append(f"{key} = {json.dumps(value, ensure_ascii=False).replace(chr(0x7f), '\\u007f')}")  # TOML has no surrogate pair escapes
+        for key, value in node.items():
+            if isinstance(value, dict):
+                lines += [""] + table(f"{name}.{key}", cast(dict[str, object], value))
+        return lines
+
+    return "\n".join(table('root', root)) + "\n"
+
+def layout_build(frames: Frames, id: int, node: dict[str, object]) -> list[tuple[int, dict[str, object]]]:
+    """Split leaf frame id as the checked tree says; the leaf frames made with their tables, first to last"""
+    if 'split' not in node:
+        return [(id, node)]
+    _ = frames.split(id, Direction.VERTICAL if node['split'] == 'vertical' else Direction.HORIZONTAL)
+    fr = frames.get(id)
+    assert fr is not None
+    fr.ratio = float(cast(float, node.get('ratio', 0.5)))
+    first, second = fr.c_lu, fr.c_rd
+    return layout_build(frames, first, cast(dict[str, object], node['first'])) + layout_build(frames, second, cast(dict[str, object], node['second']))
+
+@dataclass
+class Workspace:
+    """An independent frame layout, one tab of the tab bar; the layout of the one shown is Led.frames"""
+    name: str
+    snapshot: LayoutSnapshot | None = None  # while another one is shown
+    zoomed: LayoutSnapshot | None = None  # its zoom-frame state
+
+class Workspaces:
+    """The workspaces of a window and which one is shown; the buffers are shared, each has its own frames"""
+    def __init__(self):
+        self.items: list[Workspace] = [Workspace("1")]
+        self.current: int = 0
+
+    def __len__(self) -> int:
+        return len(self.items)
+
+    def shown(self) -> Workspace:
+        return self.items[self.current]
+
+    def names(self) -> list[str]:
+        return [ws.name for ws in self.items]
+
+    def labels(self) -> list[str]:
+        """Number and name, as chosen from in the minibuffer"""
+        return [label.strip() for _, label in tab_labels(self.names())]
+
+    def find(self, key: str) -> int | None:
+        """The workspace of a number, name or label"""
+        labels = self.labels()
+        return next((i for i, ws in enumerate(self.items) if key in (ws.name, labels[i], str(i + 1))), None)
+
+    def add(self) -> int:
+        """A new workspace at the end, named by the first free number"""
+        names = set(self.names())
+        n = len(self.items) + 1
+        while str(n) in names:
+            n += 1
+        self.items.append(Workspace(str(n)))
+        return len(self.items) - 1
+
+    def remove(self, index: int) -> bool:
+        """Delete a workspace that is not shown"""
+        if index == self.current or not 0 <= index < len(self.items):
+            return False
+        del self.items[index]
+        if self.current > index:
+            self.current -= 1
+        return True
+
+    def rename(self, index: int, name: str) -> bool:
+        """False if the name is empty or another workspace has it"""
+        name = name.strip()
+        if name == "" or any(ws.name == name for i, ws in enumerate(self.items) if i != index):
+            return False
+        self.items[index].name = name
+        return True
+
+def tab_labels(names: list[str]) -> list[tuple[int, str]]:
+    """The tabs of the tab bar and the column each starts at, numbered for switching"""
+    tabs: list[tuple[int, str]] = []
+    col = 0
+    for i, name in enumerate(names):
+        label = f" {i + 1} " if name == str(i + 1) else f" {i + 1}:{name} "
+        tabs.append((col, label))
+        col += len(label) + 1
+    return tabs
+
+def tab_at(tabs: list[tuple[int, str]], col: int) -> int | None:
+    return next((i for i, (start, label) in enumerate(tabs) if start <= col < start + len(label)), None)
+
+def client_files(args: list[str]) -> list[tuple[str, int, int]]:
+    """Absolute paths with the row and column to show, from file names each optionally preceded by +line[:col]"""
+    files: list[tuple[str, int, int]] = []
+    row, col = 0, 0
+    for arg in args:
+        m = re.fullmatch(r'\+(\d+)(?::(\d+))?', arg)
+        if m is not None:
+            row, col = int(m.group(1)) - 1, int(m.group(2) or 1) - 1
+            continue
+        files.append((arg if parse_remote(arg) is not None else os.path.abspath(arg), max(row, 0), max(col, 0)))
+        row, col = 0, 0
+    return files
+
+def client_send(files: list[tuple[str, int, int]], path: str | None = None) -> bool:
+    """Have the running led open the files; False if none is serving"""
+    path = path if path is not None else server_socket_path()
+    try:
+        with socket.socket(socket.AF_UNIX) as sock:
+            sock.settimeout(10)
+            sock.connect(path)
+            sock.sendall((json.dumps({'files': files}) + "\n").encode('utf-8'))
+            with sock.makefile('rb') as file:
+                _ = file.readline()
+    except OSError:
+        return False
+    return True
@@ -0,0 +1,494 @@
+import os
+import json
+import logging
+import tempfile
+import unittest
+
+from led_core import (Bookmark, Bookmarks, Buffer, Commands, Content, Direction, EditorConfig, EditorError, ErrorKind, FrameStyle, Frames, Heading, JumpList,
+    KeySequence, Keymap, LogRing, MarkRing, ModalEditor, Narrowing, PadView, ReplEditor, SwapStore, UndoTree, Workspaces, cheat_sheet, default_keymap,
+    defun_bounds, filetype_detect, find_project_root, fuzzy_filter, grapheme_count, grapheme_next, hex_lines, hex_parse, increment_number, insert_rectangle,
+    kill_rectangle, layout_build, layout_check, layout_text, layout_tree, load_config, load_layout, parse_remote, project_config, project_files, shift_col,
+    shift_row, sort_lines, status_format, tab_at, tab_labels, unique_lines)
+
+def leaves(frames: Frames) -> list[tuple[int, int, int, int]]:
+    """Geometry of the frames that show something, in tree order"""
+    return [(fr.x, fr.y, fr.wx, fr.hy) for fr in frames.win_frames()[0]]
+
+class TestFrames(unittest.TestCase):
+    def test_split_halves_the_frame(self):
+        frames = Frames()
+        self.assertTrue(frames.split(direction=Direction.HORIZONTAL))
+        frames.geometry(0, 0, 800, 600)
+        self.assertEqual(leaves(frames), [(0, 0, 400, 600), (400, 0, 400, 600)])
+
+    def test_nested_split(self):
+        frames = Frames()
+        _ = frames.split(direction=Direction.HORIZONTAL)
+        _ = frames.split(direction=Direction.VERTICAL)
+        frames.geometry(0, 0, 800, 600)
+        self.assertEqual(sorted(leaves(frames)), [(0, 0, 400, 300), (0, 300, 400, 300), (400, 0, 400, 600)])
+
+    def test_gap_between_neighbors(self):
+        frames = Frames()
+        frames.style = FrameStyle(gap=10)
+        _ = frames.split(direction=Direction.HORIZONTAL)
+        frames.geometry(0, 0, 800, 600)
+        self.assertEqual(leaves(frames), [(0, 0, 395, 600), (405, 0, 395, 600)])
+
+    def test_frame_options_override_the_pad(self):
+        frames, editor = Frames(), ReplEditor()
+        _ = frames.split(direction=Direction.HORIZONTAL)
+        frames.geometry(0, 0, 800, 600)
+        left = frames.win_frames()[0][0]
+        left.line_numbers, left.wrap = False, True
+        editor.sync_pads(frames, lambda _zoom: (8, 16))
+        pads = [editor.pads[fr.pad_id] for fr in frames.win_frames()[0]]
+        self.assertEqual([pad.left_border for pad in pads], [0, 6])
+        self.assertEqual([pad.wrap for pad in pads], [True, None])
+        self.assertEqual(left.options()['line-numbers'], 'off')
+        self.assertTrue(frames.split(left.id, Direction.VERTICAL))
+        self.assertEqual([fr.line_numbers for fr in frames.win_frames()[0]].count(False), 2)  # both halves of the left one
+
+    def test_fresh_starts_a_single_new_frame(self):
+        frames = Frames()
+        _ = frames.split(direction=Direction.HORIZONTAL)
+        ids = {fr.id for fr in frames.frames}
+        new = frames.fresh(3)
+        self.assertNotIn(new, ids)
+        self.assertEqual([(fr.id, fr.pad_id) for fr in frames.win_frames()[0]], [(new, 3)])
+
+    def test_layout_tree_round_trip(self):
+        tree: dict[str, object] = {'split': 'horizontal', 'ratio': 0.3, 'first': {'file': "a.py"},
+                                   'second': {'split': 'vertical', 'ratio': 0.5, 'first': {'buffer': "*scratch*"}, 'second': {'file': "b.py", 'row': 4}}}
+        self.assertEqual(layout_check(tree), "")
+        frames = Frames()
+        leaves = layout_build(frames, frames.root_id, tree)
+        self.assertEqual([leaf for _, leaf in leaves], [{'file': "a.py"}, {'buffer': "*scratch*"}, {'file': "b.py", 'row': 4}])
+        entries = dict(leaves)
+        self.assertEqual(layout_tree(frames, frames.root_id, lambda fr: entries[fr.id]), tree)
+
+    def test_layout_file_errors(self):
+        with tempfile.TemporaryDirectory() as directory:
+            path = os.path.join(directory, "team.toml")
+            with open(path, 'w') as file:
+                _ = file.write('[root]\nsplit = "diagonal"\n')
+            self.assertEqual(load_layout(path), ({}, "split must be horizontal or vertical, not diagonal"))
+            with open(path, 'w') as file:
+                _ = file.write('[root]\nsplit = "vertical"\nratio = 0.25\n[root.first]\nfile = "a"\n[root.second]\nrow = 2\n')
+            self.assertEqual(load_layout(path)[0]['ratio'], 0.25)
+        self.assertEqual(layout_check({'split': 'vertical', 'ratio': 2, 'first': {}, 'second': {}}), "ratio must be between 0 and 1, not 2")
+
+    def test_layout_file_round_trip(self):
+        tree: dict[str, object] = {'split': 'vertical', 'ratio': 0.25, 'first': {'file': 'notes \U0001F600 "b"\x7f.md', 'row': 3, 'active': True}, 'second': {}}
+        with tempfile.TemporaryDirectory() as directory:
+            for name in ("team.json", "team.toml"):
+                path = os.path.join(directory, name)
+                with open(path, 'w', encoding='utf-8') as file:
+                    _ = file.write(layout_text(tree, path))
+                self.assertEqual(load_layout(path), (tree, ""))
+
+    def test_tab_labels(self):
+        tabs = tab_labels(["1", "notes", "3"])
+        self.assertEqual(tabs, [(0, " 1 "), (4, " 2:notes "), (14, " 3 ")])
+        self.assertEqual([tab_at(tabs, col) for col in (0, 3, 5, 14, 30)], [0, None, 1, 2, None])
+
+    def test_split_keeps_the_first_half_active(self):
+        frames = Frames()
+        root = frames.root_id
+        _ = frames.split()
+        self.assertEqual(frames.active_id, frames.frames[0].c_lu)
+        self.assertNotEqual(frames.active_id, root)
+
+    def test_delete_gives_the_space_back(self):
+        frames = Frames()
+        _ = frames.split(direction=Direction.HORIZONTAL)
+        self.assertTrue(frames.delete())
+        frames.geometry(0, 0, 800, 600)
+        self.assertEqual(leaves(frames), [(0, 0, 800, 600)])
+
+    def test_root_cannot_be_deleted(self):
+        frames = Frames()
+        self.assertFalse(frames.delete())
+        self.assertEqual(len(frames.frames), 1)
+
+    def test_neighbor(self):
+        frames = Frames()
+        _ = frames.split(direction=Direction.HORIZONTAL)
+        frames.geometry(0, 0, 800, 600)
+        left, right = frames.win_frames()[0]
+        self.assertEqual(frames.neighbor(left.id, Heading.RIGHT), right.id)
+        self.assertIsNone(frames.neighbor(left.id, Heading.LEFT))
+
+class TestEditing(unittest.TestCase):
+    def setUp(self):
+        self.editor = ReplEditor()
+        self.buffer = self.editor.buffer_create("test", ["hello world", "second"])
+        self.pad = self.editor.create_editor(self.buffer, 10, 40)
+
+    def test_insert_within_a_line(self):
+        self.assertEqual(self.editor.text_insert(self.pad, (0, 5), ","), (0, 6))
+        self.assertEqual(list(self.buffer), ["hello, world", "second"])
+
+    def test_insert_lines(self):
+        self.assertEqual(self.editor.text_insert(self.pad, (0, 5), "\nnew\n"), (2, 0))
+        self.assertEqual(list(self.buffer), ["hello", "new", " world", "second"])
+
+    def test_get_and_delete_across_lines(self):
+        self.assertEqual(self.editor.text_get(self.pad, (0, 6), (1, 3)), "world\nsec")
+        self.editor.text_delete(self.pad, (0, 6), (1, 3))
+        self.assertEqual(list(self.buffer), ["hello ond"])
+
+    def test_scroll_takes_the_cursor_along(self):
+        buffer = self.editor.buffer_create("long", [str(i) for i in range(100)])
+        pad = self.editor.create_editor(buffer, 10, 40)
+        self.editor.pad_scroll_y(pad, 20)
+        self.assertEqual(self.editor.pads[pad].buf_y, 20)
+        self.assertEqual(self.editor.pad_cursor(pad), (20, 0))
+        self.editor.pad_goto(pad, 50, 0)
+        self.editor.pad_recenter(pad)
+        self.assertEqual(self.editor.pads[pad].buf_y, 45)
+
+    def test_other_views_follow_an_edit(self):
+        buffer = self.editor.buffer_create("long", [str(i) for i in range(100)])
+        pad, other = self.editor.create_editor(buffer, 10, 40), self.editor.create_editor(buffer, 10, 40)
+        self.editor.pad_scroll_y(other, 50)
+        self.editor.pad_goto(other, 55, 1)
+        buffer[10:10] = ["a", "b", "c"]
+        self.editor.pads_follow_edit(buffer, 10, [], ["a", "b", "c"], pad)
+        self.assertEqual(self.editor.pads[other].buf_y, 53)
+        self.assertEqual(self.editor.pad_cursor(other), (58, 1))
+        self.assertEqual(self.editor.pad_cursor(pad), (0, 0))
+        removed = buffer[50:60]
+        del buffer[50:60]
+        self.editor.pads_follow_edit(buffer, 50, removed, [], pad)
+        self.assertEqual(self.editor.pad_cursor(other), (50, 1))
+        buffer[50] = "x" + buffer[50]
+        self.editor.pads_follow_edit(buffer, 50, ["50"], ["x50"], pad)
+        self.assertEqual(self.editor.pad_cursor(other), (50, 2))
+        self.assertEqual([shift_col(col, "abcd", "aXYd") for col in (0, 1, 2, 3, 4)], [0, 1, 2, 3, 4])
+        self.assertEqual([shift_col(col, "abcd", "abd") for col in (1, 2, 3, 4)], [1, 2, 2, 3])
+        self.assertEqual([shift_row(row, 4, 2, 1) for row in (3, 4, 5, 6)], [3, 4, 4, 5])
+
+    def test_relative_line_numbers(self):
+        buffer = self.editor.buffer_create("long", [str(i) for i in range(100)])
+        pad = self.editor.create_editor(buffer, 10, 40, line_no=True)
+        self.editor.relative_numbers = True
+        self.editor.pad_goto(pad, 3, 0)
+        gutters = [line[:self.editor.pads[pad].left_border] for line in self.editor.pads[pad].canvas[:5]]
+        self.assertEqual(gutters, ["    3 ", "    2 ", "    1 ", "  3   ", "    1 "])
+
+class TestNarrowing(unittest.TestCase):
+    def test_region_follows_base_edits(self):
+        base = Buffer(["a", "b", "c", "d", "e"], "base")
+        narrowing = Narrowing(base, 2, 4, ["c", "d"], list(base))
+        expected = [(["new", "a", "b", "c", "d", "e"], (3, 5)),  # a line above
+                    (["new", "a", "c", "d", "e"], (2, 4)),  # one deleted above
+                    (["new", "a", "c", "d1", "d2", "e"], (2, 5)),  # the last narrowed line split
+                    (["new", "a", "c", "d1", "d2", "e", "f"], (2, 5)),  # below
+                    (["d2", "e", "f"], (0, 1))]  # deleted across the start
+        for lines, region in expected:
+            base[:] = lines
+            narrowing.follow_base()
+            self.assertEqual((narrowing.start, narrowing.end), region)
+
+class TestLines(unittest.TestCase):
+    def test_sort_orders(self):
+        lines = ["10 b", "b", "-2 a", "A", "9"]
+        self.assertEqual(sort_lines(lines), ["-2 a", "10 b", "9", "A", "b"])
+        self.assertEqual(sort_lines(lines, 'numeric'), ["b", "A", "-2 a", "9", "10 b"])
+        self.assertEqual(sort_lines(["b", "A", "a"], 'case'), ["A", "a", "b"])
+
+    def test_increment_number(self):
+        self.assertEqual(increment_number("x = 9;", 0, 1), ("x = 10;", 5))
+        self.assertEqual(increment_number("x = 9;", 6, 1), None)
+        self.assertEqual(increment_number("at -3", 3, 5), ("at 2", 3))
+        self.assertEqual(increment_number("v-1", 0, 1), ("v-2", 2))
+        self.assertEqual(increment_number("007", 1, 1), ("008", 2))
+        self.assertEqual(increment_number("0x0fF", 0, 1), ("0x100", 4))
+        self.assertEqual(increment_number("0x0", 0, -1), ("0xffffffffffffffff", 17))
+
+    def test_rectangles(self):
+        lines, killed = kill_rectangle(["abcdef", "ab", "abcdef"], 1, 4)
+        self.assertEqual((lines, killed), (["aef", "a", "aef"], ["bcd", "b  ", "bcd"]))
+        self.assertEqual(insert_rectangle(["aef", "a"], 3, ["XY", "XY", "XY"]), ["aefXY", "a  XY", "   XY"])
+        self.assertEqual(insert_rectangle(["one", "two", "three"], 0, ["> "]), ["> one", "two", "three"])
+
+    def test_defun_bounds(self):
+        lines = ["import os", "", "@cache", "def f():", "    x = 1", "", "    return x", "", "", "int main()", "{", "    return 0;", "}", "x = 2"]
+        self.assertEqual(defun_bounds(lines, 4), (2, 7))
+        self.assertEqual(defun_bounds(lines, 2), (2, 7))
+        self.assertEqual(defun_bounds(lines, 11), (9, 13))
+        self.assertEqual(defun_bounds(lines, 13), (13, 14))
+
+    def test_unique_keeps_the_first(self):
+        self.assertEqual(unique_lines(["a", "b", "a", "c", "b"]), ["a", "b", "c"])
+
+class TestUndoTree(unittest.TestCase):
+    def test_undo_and_redo(self):
+        lines = ["a"]
+        undo = UndoTree(lines)
+        lines.append("b")
+        undo.record(lines, (0, 0), (1, 1))
+        self.assertEqual(undo.undo(lines), (0, 0))
+        self.assertEqual(lines, ["a"])
+        self.assertEqual(undo.redo(lines), (1, 1))
+        self.assertEqual(lines, ["a", "b"])
+
+    def test_editing_after_undo_keeps_the_old_branch(self):
+        lines = ["a"]
+        undo = UndoTree(lines)
+        lines[0] = "b"
+        undo.record(lines, (0, 0), (0, 1))
+        _ = undo.undo(lines)
+        lines[0] = "c"
+        undo.record(lines, (0, 0), (0, 1))
+        self.assertEqual(len(undo.nodes[0].children), 2)
+
+    def test_nothing_to_undo(self):
+        self.assertIsNone(UndoTree(["a"]).undo(["a"]))
+
+class TestBuffer(unittest.TestCase):
+    def test_never_empty(self):
+        self.assertEqual(list(Buffer([], "empty")), [""])
+
+    def test_generated_buffers_have_no_undo(self):
+        self.assertIsNone(Buffer(["x"], "gen", undo=False).undo)
+
+class TestLoading(unittest.TestCase):
+    def test_new_file_is_one_empty_line(self):
+        with tempfile.TemporaryDirectory() as directory:
+            self.assertEqual(Content().load_text(os.path.join(directory, "new.txt")), ([""], 'utf-8', '\n'))
+
+    def test_unreadable_file_is_an_error(self):
+        with tempfile.TemporaryDirectory() as directory:
+            with self.assertRaises(EditorError) as caught:
+                _ = Content().load_text(directory)
+            self.assertEqual(caught.exception.kind, ErrorKind.IO)
+
+class TestProject(unittest.TestCase):
+    def test_project_config_merges_allowed_entries(self):
+        with tempfile.TemporaryDirectory() as root:
+            with open(os.path.join(root, ".led.toml"), 'w') as file:
+                _ = file.write('build_command = "make"\nexclude = ["target/"]\nmodal_editing = true\n[filetypes.py]\ntab_width = 2\n')
+            config = EditorConfig()
+            merged = project_config(config, root)
+            self.assertEqual((merged.build_command, merged.exclude, merged.filetypes['py'].tab_width), ("make", ["target/"], 2))
+            self.assertIs(merged.modal_editing, False)
+            self.assertEqual(config.filetypes['py'].tab_width, 4)
+            self.assertIs(project_config(config, os.path.join(root, "none")), config)
+
+    def test_user_config_sets_entries_after_its_keys(self):
+        with tempfile.TemporaryDirectory() as directory:
+            path = os.path.join(directory, "config.toml")
+            with open(path, 'w') as file:
+                _ = file.write('[keys]\n"C-c b" = "build"\n[filetypes.py]\ntab_width = 2\n')
+            config = load_config(path)
+            self.assertEqual((config.keys, config.filetypes['py'].tab_width), ({'C-c b': 'build'}, 2))
+
+    def test_led_toml_marks_the_root_and_exclude_skips(self):
+        with tempfile.TemporaryDirectory() as root:
+            os.makedirs(os.path.join(root, "src"))
+            os.makedirs(os.path.join(root, "target"))
+            for name in (".led.toml", "src/a.py", "target/b.py"):
+                with open(os.path.join(root, name), 'w') as file:
+                    _ = file.write("")
+            self.assertEqual(find_project_root(os.path.join(root, "src", "a.py")), root)
+            self.assertEqual(project_files(root, ["target/"]), [".led.toml", os.path.join("src", "a.py")])
+
+class TestFileTypes(unittest.TestCase):
+    def test_content_decides_without_a_known_extension(self):
+        config = EditorConfig()
+        self.assertEqual(filetype_detect(config, "/bin/tool", ["#!/usr/bin/env -S python3 -u", "pass"]), 'py')
+        self.assertEqual(filetype_detect(config, "run", ["#!/bin/bash"]), 'sh')
+        self.assertEqual(filetype_detect(config, "feed.rss", ['<?xml version="1.0"?>', "<rss/>"]), 'xml')
+        self.assertEqual(filetype_detect(config, "script.rb", ["#!/bin/sh"]), 'sh')
+        self.assertEqual(filetype_detect(config, "notes.rb", ["puts 1"]), 'rb')
+        self.assertEqual(filetype_detect(config, "a.py", ["#!/bin/sh"]), 'py')
+
+    def test_modelines_win(self):
+        config = EditorConfig()
+        self.assertEqual(filetype_detect(config, "a.txt", ["# -*- mode: python; coding: utf-8 -*-"]), 'py')
+        self.assertEqual(filetype_detect(config, "build", ["#!/bin/sh", "-*- Makefile -*-"]), 'Makefile')
+        self.assertEqual(filetype_detect(config, "conf", ["x"] * 20 + ["# vim: set ts=4 ft=toml :"]), 'toml')
+        self.assertEqual(filetype_detect(config, "conf", ["# vim:ft=sh"]), 'sh')
+        self.assertEqual(filetype_detect(config, "a.txt", ["# vim: ft=nonsense"]), 'txt')
+
+    def test_associations_override_detection(self):
+        config = EditorConfig(associations={'*.tpl': 'html', 'Dockerfile.*': 'sh', 'conf/*.txt': 'toml'})
+        self.assertEqual(filetype_detect(config, "/srv/page.tpl", ["#!/bin/sh"]), 'html')
+        self.assertEqual(filetype_detect(config, "/srv/Dockerfile.dev", []), 'sh')
+        self.assertEqual(filetype_detect(config, "/srv/conf/a.txt", ["# vim: ft=py"]), 'toml')
+        self.assertEqual(filetype_detect(config, "/srv/a.txt", []), 'txt')
+
+class TestLogRing(unittest.TestCase):
+    def test_keeps_the_latest_lines(self):
+        ring = LogRing(2)
+        log = logging.getLogger("TestLogRing")
+        log.addHandler(ring)
+        log.warning("one")
+        log.warning("two\nthree")
+        log.removeHandler(ring)
+        self.assertEqual([line.split()[-1] for line in ring.lines], ["two", "three"])
+
+class TestSwapStore(unittest.TestCase):
+    def test_only_files_of_ended_sessions_are_left_over(self):
+        with tempfile.TemporaryDirectory() as directory:
+            swap = SwapStore(directory)
+            buffer = Buffer(["unsaved"], "a.txt", os.path.join(directory, "a.txt"))
+            assert buffer.filename is not None
+            swap.write(buffer)
+            self.assertIsNone(swap.leftover(buffer.filename))  # this session's own
+            with open(swap.path(buffer.filename), 'r') as file:
+                data = json.load(file)
+            data['pid'] = 2 ** 22 + 1  # above any pid_max
+            with open(swap.path(buffer.filename), 'w') as file:
+                json.dump(data, file)
+            leftover = swap.leftover(buffer.filename)
+            assert leftover is not None
+            self.assertEqual(leftover['lines'], ["unsaved"])
+            self.assertEqual(swap.leftovers(), [buffer.filename])
+            swap.remove(buffer.filename)
+            self.assertEqual(swap.leftovers(), [])
+
+class TestJumpList(unittest.TestCase):
+    def test_back_and_forward_like_a_browser(self):
+        a, b = Buffer(["a"] * 10, "a"), Buffer(["b"] * 10, "b")
+        jumps = JumpList()
+        jumps.push(PadView(a, 1, 0, 0, 0))
+        jumps.push(PadView(b, 5, 0, 0, 0))
+        back = jumps.back(PadView(a, 9, 0, 0, 0))
+        assert back is not None
+        self.assertEqual((back.buffer is b, back.row), (True, 5))
+        back = jumps.back(back)
+        assert back is not None
+        self.assertEqual((back.buffer is a, back.row), (True, 1))
+        self.assertIsNone(jumps.back(back))
+        forward = jumps.forward()
+        assert forward is not None
+        self.assertEqual(forward.row, 5)
+        jumps.push(forward)  # a new jump drops the places ahead
+        self.assertIsNone(jumps.forward())
+        self.assertEqual([view.row for view in jumps.jumps], [1, 5])
+
+class TestMarkRing(unittest.TestCase):
+    def test_pop_cycles_through_the_marks(self):
+        ring = MarkRing(max_marks=3)
+        for row in range(4):
+            ring.push((row, 0))
+        ring.push((3, 0))
+        self.assertEqual([ring.pop(), ring.pop(), ring.pop(), ring.pop()], [(3, 0), (2, 0), (1, 0), (3, 0)])
+        self.assertIsNone(MarkRing().pop())
+
+class TestBookmarks(unittest.TestCase):
+    def test_kept_across_sessions(self):
+        with tempfile.TemporaryDirectory() as directory:
+            path = os.path.join(directory, "bookmarks.json")
+            Bookmarks(path).set("main", Bookmark("/src/a.py", 3, 4, "def main():"))
+            marks = Bookmarks(path)
+            self.assertEqual(marks.marks, {"main": Bookmark("/src/a.py", 3, 4, "def main():")})
+            self.assertTrue(marks.rename("main", "entry"))
+            self.assertEqual(Bookmarks(path).names(), ["entry"])
+
+    def test_found_again_after_lines_moved(self):
+        mark = Bookmark("/src/a.py", 1, 0, "target")
+        self.assertEqual(mark.locate(["x", "target"]), 1)
+        self.assertEqual(mark.locate(["x", "y", "z", "target", "target"]), 3)
+        self.assertEqual(mark.locate(["x"]), 0)
+
+class TestStatusFormat(unittest.TestCase):
+    def test_fields_and_segments(self):
+        fields = {'name': "a.py", 'ro': "", 'row': "3", 'errors': "1", 'mode': ""}
+        text, _ = status_format("{name}[ {ro}] {row}[  E:{errors}][  -- {mode} --] {nope} \\[x\\]", fields)
+        self.assertEqual(text, "a.py 3  E:1 {nope} [x]")
+
+    def test_face_sections(self):
+        text, spans = status_format("{@status-name}{name}{@} at {@pos}{row}[ {@hidden}{ro}]:{col}{@}", {'name': "ab", 'row': "7", 'ro': "", 'col': "2"})
+        self.assertEqual(text, "ab at 7:2")
+        self.assertEqual([(s.start, s.end, s.face) for s in spans], [(0, 2, 'status-name'), (6, 9, 'pos')])
+
+    def test_default_matches_the_editor(self):
+        editor = ReplEditor()
+        buffer = editor.buffer_create("notes.txt", ["one", "two"])
+        pad = editor.create_editor(buffer, 5, 60, line_no=True, status_line=True)
+        buffer.read_only = True
+        editor.pad_goto(pad, 1, 2)
+        self.assertTrue(editor.pads[pad].canvas[4].startswith(" " * 6 + "notes.txt [RO] (1,2)  LF  spaces:4  "))
+        self.assertEqual(editor.pads[pad].status_spans[0].face, 'status-name')
+
+class TestWorkspaces(unittest.TestCase):
+    def test_add_rename_find_remove(self):
+        workspaces = Workspaces()
+        self.assertEqual(workspaces.add(), 1)
+        self.assertTrue(workspaces.rename(1, " notes "))
+        self.assertFalse(workspaces.rename(0, "notes"))
+        self.assertEqual(workspaces.labels(), ["1", "2:notes"])
+        self.assertEqual([workspaces.find(key) for key in ("notes", "2:notes", "2", "1", "x")], [1, 1, 1, 0, None])
+        self.assertEqual(workspaces.add(), 2)
+        workspaces.current = 2
+        self.assertFalse(workspaces.remove(2))
+        self.assertTrue(workspaces.remove(0))
+        self.assertEqual((workspaces.names(), workspaces.current), (["notes", "3"], 1))
+        self.assertEqual(workspaces.add(), 2)
+        self.assertEqual(workspaces.names()[2], "4")
+
+class TestCommands(unittest.TestCase):
+    def test_run_passes_the_message(self):
+        commands = Commands()
+        got: list[str] = []
+        commands.register('echo', got.append, "Remember the message")
+        self.assertTrue(commands.run('echo', "hi"))
+        self.assertEqual(got, ["hi"])
+
+    def test_unknown_command(self):
+        self.assertFalse(Commands().run('nope'))
+
+class TestModal(unittest.TestCase):
+    def test_global_chords_pass_normal_mode(self):
+        editor = ReplEditor()
+        pad = editor.create_editor(editor.buffer_create("test", ["x = 1"]), 10, 40)
+        modal, keys = ModalEditor(editor), KeySequence()
+        results = [(modal.feed(pad, key), keys.feed(default_keymap(), key)[1]) for key in ('C-x', 'C-c')]
+        self.assertEqual(results, [(False, None), (False, ('quit', ''))])
+
+class TestKeymap(unittest.TestCase):
+    def test_prefix_and_lookup(self):
+        keymap = Keymap("test")
+        keymap.bind('C-x C-s', 'save')
+        self.assertTrue(keymap.is_prefix('C-x'))
+        self.assertEqual(keymap.lookup('C-x C-s'), ('save', ''))
+        self.assertIsNone(keymap.lookup('C-x'))
+
+    def test_keys_for_a_command(self):
+        keymap = Keymap("test", {'C-x C-s': ('save', ''), 'C-s': ('save', ''), 'C-f': ('find', '')})
+        self.assertEqual(keymap.keys_for('save'), ['C-s', 'C-x C-s'])
+        self.assertEqual(keymap.keys_for('quit'), [])
+
+    def test_cheat_sheet_groups_and_filters(self):
+        entries = [('C-x 2', 'split vertical', 'Split the active frame'), ('C-s', 'save-buffer', 'Write the buffer'), ('u', 'undo', '')]
+        self.assertEqual(cheat_sheet(entries), ['Frames', '  C-x 2  split vertical  Split the active frame', '',
+                                                'Files and buffers', '  C-s    save-buffer     Write the buffer', '', 'Editing', '  u      undo'])
+        self.assertEqual(cheat_sheet(entries, 'WRITE'), ['Files and buffers', '  C-s    save-buffer     Write the buffer'])
+        self.assertEqual(cheat_sheet(entries, 'nothing'), ['No keys match nothing'])
+
+class TestText(unittest.TestCase):
+    def test_combining_marks_stay_with_their_base(self):
+        text = "e\u0301x"
+        self.assertEqual(grapheme_next(text, 0), 2)
+        self.assertEqual(grapheme_count(text), 2)
+
+    def test_hex_round_trip(self):
+        data = bytes(range(40))
+        self.assertEqual(hex_parse(hex_lines(data)), data)
+
+    def test_fuzzy_filter(self):
+        self.assertEqual(fuzzy_filter("fo", ["bar", "foo", "f_o"]), ["foo", "f_o"])
+
+    def test_parse_remote(self):
+        remote = parse_remote("ssh://me@host:2222/etc/hosts")
+        self.assertIsNotNone(remote)
+        assert remote is not None
+        self.assertEqual((remote.host, remote.port, remote.path), ("me@host", 2222, "/etc/hosts"))
+        self.assertIsNone(parse_remote("/local/file"))
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,81 @@
+import unittest
+
+import sdl2  # pyright: ignore[reportMissingTypeStubs]
+
+from led import KeyInput
+
+ALTGR_X11 = sdl2.KMOD_MODE
+ALTGR_WINDOWS = sdl2.KMOD_LCTRL | sdl2.KMOD_RALT
+
+def keys(events: list[tuple[object, ...]]) -> list[str]:
+    """Key names for SDL events, ('down', sym, name, modifiers) or ('text', text), in one batch each unless split by 'flush'"""
+    key_input = KeyInput()
+    result: list[str] = []
+    for event in events:
+        if event[0] == 'down':
+            result += key_input.key_down(*event[1:])  # pyright: ignore[reportArgumentType]
+        elif event[0] == 'text':
+            result += key_input.text_input(str(event[1]))
+        else:
+            result += key_input.flush()
+    return result + key_input.flush()
+
+class TestUsLayout(unittest.TestCase):
+    def test_letters_come_from_text_input(self):
+        self.assertEqual(keys([('down', ord('a'), "A", 0), ('text', "a"), ('down', ord('b'), "B", sdl2.KMOD_LSHIFT), ('text', "B")]), ["a", "B"])
+
+    def test_space_is_spc(self):
+        self.assertEqual(keys([('down', ord(' '), "Space", 0), ('text', " ")]), ["SPC"])
+
+    def test_control_and_alt_chords(self):
+        self.assertEqual(keys([('down', ord('x'), "X", sdl2.KMOD_LCTRL), ('down', ord('f'), "F", sdl2.KMOD_LCTRL)]), ["C-x", "C-f"])
+        self.assertEqual(keys([('down', ord('x'), "X", sdl2.KMOD_LALT)]), ["M-x"])
+
+    def test_named_keys(self):
+        self.assertEqual(keys([('down', sdl2.SDLK_RETURN, "Return", 0), ('down', sdl2.SDLK_LEFT, "Left", 0)]), ["Return", "Left"])
+
+    def test_right_alt_without_text_is_meta(self):
+        self.assertEqual(keys([('down', ord('x'), "X", sdl2.KMOD_RALT), ('flush',)]), ["M-x"])
+
+class TestGermanLayout(unittest.TestCase):
+    def test_umlaut_keys_insert_once(self):
+        self.assertEqual(keys([('down', ord('ä'), "Ä", 0), ('text', "ä"), ('down', ord('ß'), "ß", 0), ('text', "ß")]), ["ä", "ß"])
+
+    def test_dead_acute_then_e(self):
+        self.assertEqual(keys([('down', ord('´'), "´", 0), ('down', ord('e'), "E", 0), ('text', "é")]), ["é"])
+
+    def test_dead_circumflex_then_space(self):
+        self.assertEqual(keys([('down', ord('^'), "^", 0), ('down', ord(' '), "Space", 0), ('text', "^")]), ["^"])
+
+    def test_dead_key_without_keycode(self):
+        self.assertEqual(keys([('down', 0, "", 0), ('down', ord('o'), "O", 0), ('text', "ô")]), ["ô"])
+
+    def test_altgr_q_is_at_sign(self):
+        self.assertEqual(keys([('down', ord('q'), "Q", ALTGR_X11), ('text', "@")]), ["@"])
+        self.assertEqual(keys([('down', ord('q'), "Q", sdl2.KMOD_RALT), ('text', "@")]), ["@"])
+
+    def test_control_umlaut_is_a_chord(self):
+        self.assertEqual(keys([('down', ord('ö'), "Ö", sdl2.KMOD_LCTRL)]), ["C-ö"])
+
+class TestFrenchLayout(unittest.TestCase):
+    def test_accented_keys(self):
+        self.assertEqual(keys([('down', ord('é'), "É", 0), ('text', "é"), ('down', ord('è'), "È", 0), ('text', "è")]), ["é", "è"])
+
+    def test_dead_diaeresis(self):
+        self.assertEqual(keys([('down', ord('^'), "^", sdl2.KMOD_LSHIFT), ('down', ord('e'), "E", 0), ('text', "ë")]), ["ë"])
+
+    def test_windows_altgr_zero_is_at_sign(self):
+        self.assertEqual(keys([('down', ord('à'), "À", ALTGR_WINDOWS), ('text', "@")]), ["@"])
+
+class TestCompose(unittest.TestCase):
+    def test_compose_quote_e(self):
+        self.assertEqual(keys([('down', 0, "", 0), ('down', ord("'"), "'", 0), ('down', ord('e'), "E", 0), ('text', "é")]), ["é"])
+
+    def test_compose_into_several_characters(self):
+        self.assertEqual(keys([('down', 0, "", 0), ('down', ord('o'), "O", 0), ('down', ord('e'), "E", 0), ('text', "œ")]), ["œ"])
+
+    def test_held_chord_is_released_by_the_next_key(self):
+        self.assertEqual(keys([('down', ord('x'), "X", sdl2.KMOD_RALT), ('down', sdl2.SDLK_RETURN, "Return", 0)]), ["M-x", "Return"])
+
+if __name__ == "__main__":
+    _ = unittest.main()